@@ -0,0 +1,89 @@
+//! Full-chain validation, balance replay and serialization of a chain of
+//! `BLOCKS` blocks (2,000 by default): `cargo bench --bench chain`.
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+
+use mini_block::export::{self, ExportFormat};
+use mini_block::{Amount, Blockchain, ChainParams, ManualClock, Mempool, Miner, Transaction};
+
+const ACCOUNTS: [&str; 4] = ["alice", "bob", "carol", "dave"];
+
+/// A chain whose blocks each carry a few transfers between the accounts,
+/// with no proof of work so it builds quickly.
+fn build_chain() -> Blockchain {
+    let blocks: usize = std::env::var("BLOCKS").ok().and_then(|blocks| blocks.parse().ok()).unwrap_or(2_000);
+    let mut params = ChainParams {
+        initial_difficulty: 0,
+        retarget_interval: 0,
+        ..ChainParams::default()
+    };
+    for account in ACCOUNTS {
+        params.genesis_allocations.insert(account.to_string(), Amount::from_coins(1_000_000));
+    }
+    let clock = ManualClock::new(1_800_000_000_000);
+    let mut chain = Blockchain::with_params(params).unwrap();
+    chain.set_miner(Miner::new(1).with_clock(clock.clone()));
+    let mut mempool = Mempool::new();
+    for height in 1..blocks {
+        for (i, &from) in ACCOUNTS.iter().enumerate() {
+            let to = ACCOUNTS[(i + height) % ACCOUNTS.len()];
+            let sequence = chain.next_sequence(&mempool, from).unwrap();
+            let tx =
+                Transaction::new(from, to, Amount::from_coins(10)).with_fee(Amount::from_coins(1)).with_sequence(sequence);
+            chain.submit_transaction(&mut mempool, tx).unwrap();
+        }
+        clock.advance(1000);
+        chain.mine_pending(&mut mempool, ACCOUNTS.len(), "miner").unwrap();
+    }
+    chain
+}
+
+fn validation(c: &mut Criterion) {
+    let chain = build_chain();
+    let mut group = c.benchmark_group("validation");
+    group.sample_size(10);
+    let mut thread_counts = vec![1];
+    if rayon::current_num_threads() > 1 {
+        thread_counts.push(rayon::current_num_threads());
+    }
+    for threads in thread_counts {
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+        group.bench_with_input(BenchmarkId::new("full", threads), &pool, |b, pool| {
+            b.iter(|| pool.install(|| chain.validate_full()).unwrap())
+        });
+    }
+    chain.validate().unwrap();
+    group.bench_function("incremental", |b| b.iter(|| chain.validate().unwrap()));
+    group.finish();
+}
+
+fn replay(c: &mut Criterion) {
+    let built = build_chain();
+    // Rebuilt from its blocks the chain has no snapshot, so every block is replayed.
+    let chain = Blockchain::from_blocks(built.blocks().to_vec(), built.params().clone()).unwrap();
+    let mut group = c.benchmark_group("replay");
+    group.sample_size(20);
+    group.bench_function("balances", |b| b.iter(|| chain.balances()));
+    group.bench_function("utxo_set", |b| b.iter(|| chain.utxo_set().unwrap()));
+    group.finish();
+}
+
+fn serialization(c: &mut Criterion) {
+    let chain = build_chain();
+    let dir = tempfile::tempdir().unwrap();
+    let mut group = c.benchmark_group("serialization");
+    group.sample_size(10);
+    for format in [ExportFormat::Json, ExportFormat::Cbor, ExportFormat::Bincode, ExportFormat::Csv] {
+        let path = dir.path().join(format!("chain.{}", format));
+        group.bench_with_input(BenchmarkId::new("export", format), &format, |b, &format| {
+            b.iter(|| export::export(&chain, &path, format).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("import", format), &format, |b, &format| {
+            b.iter(|| export::import(&path, format).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, validation, replay, serialization);
+criterion_main!(benches);
@@ -0,0 +1,48 @@
+//! Hash rate and time to mine a block at several difficulties:
+//! `cargo bench --bench mining`.
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use std::hint::black_box;
+
+use mini_block::{BlockHeader, Miner, Target};
+
+fn header(index: u64, difficulty: usize) -> BlockHeader {
+    let bits = Target::from_leading_zeros(difficulty).to_bits();
+    BlockHeader::new(index, 1_800_000_000_000, "ab".repeat(32), "cd".repeat(32), bits)
+}
+
+fn hashing(c: &mut Criterion) {
+    let mut group = c.benchmark_group("hashing");
+    group.throughput(Throughput::Elements(1));
+    let header = header(1, 1);
+    group.bench_function("compute_hash", |b| b.iter(|| black_box(&header).compute_hash()));
+    let hasher = header.hasher();
+    let mut nonce = 0u64;
+    group.bench_function("nonce", |b| {
+        b.iter(|| {
+            nonce = nonce.wrapping_add(1);
+            hasher.hash(black_box(nonce))
+        })
+    });
+    group.finish();
+}
+
+fn mining(c: &mut Criterion) {
+    let mut group = c.benchmark_group("mining");
+    group.sample_size(20);
+    let miner = Miner::new(1);
+    for difficulty in 1..=4 {
+        // A new index each time, so the nonce found varies as it would.
+        let mut index = 0;
+        group.bench_with_input(BenchmarkId::from_parameter(difficulty), &difficulty, |b, &difficulty| {
+            b.iter(|| {
+                index += 1;
+                miner.mine(header(index, difficulty)).unwrap()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, hashing, mining);
+criterion_main!(benches);
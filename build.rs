@@ -0,0 +1,11 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/mini_block.proto");
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("no bundled protoc for this platform");
+        // SAFETY: build scripts are single-threaded.
+        unsafe { std::env::set_var("PROTOC", protoc) };
+        tonic_build::compile_protos("proto/mini_block.proto").expect("failed to compile proto/mini_block.proto");
+    }
+}
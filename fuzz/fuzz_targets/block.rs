@@ -0,0 +1,20 @@
+//! A block as a peer or the block store hands it over: decoded, checked
+//! and connected to a regtest chain, whose zero difficulty lets any nonce
+//! through to the later checks.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mini_block::compact::CompactBlock;
+use mini_block::{Block, Blockchain, ChainProfile};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(block) = serde_json::from_slice::<Block>(data) else { return };
+    let _ = (block.compute_hash(), block.merkle_root(), block.has_valid_merkle_root(), block.size());
+    let compact = CompactBlock::new(&block);
+    let _ = compact.reconstruct(None).into_block();
+
+    let mut chain = Blockchain::with_params(ChainProfile::Regtest.params()).expect("regtest chain");
+    if chain.accept_block(block).is_ok() {
+        let _ = chain.validate_full();
+    }
+});
@@ -0,0 +1,19 @@
+//! A corrupt or hostile chain file, read in each export format and
+//! validated.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mini_block::ExportFormat;
+use mini_block::export;
+
+fuzz_target!(|data: &[u8]| {
+    let dir = tempfile::tempdir().expect("temporary directory");
+    let path = dir.path().join("chain");
+    std::fs::write(&path, data).expect("write chain file");
+    for format in [ExportFormat::Json, ExportFormat::Cbor, ExportFormat::Bincode, ExportFormat::Csv] {
+        if let Ok(chain) = export::import(&path, format) {
+            let _ = chain.validate();
+            let _ = chain.utxo_set();
+        }
+    }
+});
@@ -0,0 +1,51 @@
+//! A line from a peer: decoded as a message, with what it carries handled
+//! the way a node or light client does.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mini_block::network::Message;
+use mini_block::{Amount, Blockchain, ChainProfile, HeaderChain, Mempool, Transaction};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(message) = serde_json::from_slice::<Message>(data) else { return };
+    let params = ChainProfile::Regtest.params();
+    let mut chain = Blockchain::with_params(params.clone()).expect("regtest chain");
+    let mut mempool = Mempool::new();
+    match message {
+        Message::NewBlock(block) => {
+            let _ = chain.accept_block(block);
+        }
+        Message::Blocks(blocks) => {
+            for block in blocks {
+                let _ = chain.accept_block(block);
+            }
+        }
+        Message::CompactBlock(compact) => {
+            let _ = compact.hash();
+            // Nothing is pending, so stand-ins fill the rest, as a peer might
+            // send the wrong transactions.
+            let mut partial = compact.reconstruct(Some(&mempool));
+            let missing = partial.missing().len();
+            if partial.fill(vec![Transaction::new("alice", "bob", Amount::from_coins(1)); missing]).is_ok()
+                && let Some(block) = partial.into_block()
+            {
+                let _ = chain.accept_block(block);
+            }
+        }
+        Message::Headers(headers) => {
+            let _ = HeaderChain::new(params).expect("regtest headers").extend(headers);
+        }
+        Message::Proof(Some(proof)) => {
+            let _ = HeaderChain::new(params).expect("regtest headers").verify(&proof);
+        }
+        Message::Transactions(transactions) | Message::BlockTransactions { transactions, .. } => {
+            for tx in transactions {
+                let _ = chain.submit_transaction(&mut mempool, tx);
+            }
+        }
+        Message::GetHeaders(locator) => {
+            let _ = chain.headers_after(&locator, 10);
+        }
+        _ => {}
+    }
+});
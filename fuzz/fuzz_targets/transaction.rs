@@ -0,0 +1,20 @@
+//! A transaction as a peer relays it or a client submits it: decoded,
+//! queued on a regtest chain and, if accepted, mined.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mini_block::{Amount, Blockchain, ChainProfile, Mempool, Transaction};
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(tx) = serde_json::from_slice::<Transaction>(data) else { return };
+    let _ = (tx.hash(), tx.size(), tx.cost(), tx.is_final(1, 0));
+
+    let mut params = ChainProfile::Regtest.params();
+    params.genesis_allocations.insert(tx.sender().to_string(), Amount::from_coins(1_000));
+    let Ok(mut chain) = Blockchain::with_params(params) else { return };
+    let mut mempool = Mempool::new();
+    if chain.submit_transaction(&mut mempool, tx).is_ok() {
+        let _ = chain.mine_pending(&mut mempool, 10, "miner");
+        let _ = chain.validate_full();
+    }
+});
@@ -0,0 +1,78 @@
+use ed25519_dalek::VerifyingKey;
+use sha2::{Digest, Sha256};
+
+use crate::error::{BlockchainError, Result};
+
+/// Bytes of the public key hash carried by an address.
+pub const HASH_LEN: usize = 20;
+const CHECKSUM_LEN: usize = 4;
+/// Shortest string that could be an encoded address; anything shorter is a
+/// plain account name.
+const MIN_ENCODED_LEN: usize = 25;
+
+fn checksum(data: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let hash = Sha256::digest(Sha256::digest(data));
+    let mut checksum = [0u8; CHECKSUM_LEN];
+    checksum.copy_from_slice(&hash[..CHECKSUM_LEN]);
+    checksum
+}
+
+/// Base58Check encoding of `version || payload || checksum`, where the
+/// checksum is the first four bytes of the double SHA-256 of the rest.
+pub fn encode(version: u8, payload: &[u8]) -> String {
+    let mut data = Vec::with_capacity(1 + payload.len() + CHECKSUM_LEN);
+    data.push(version);
+    data.extend_from_slice(payload);
+    let checksum = checksum(&data);
+    data.extend_from_slice(&checksum);
+    bs58::encode(data).into_string()
+}
+
+/// Decodes a Base58Check string into its version byte and payload.
+pub fn decode(address: &str) -> Result<(u8, Vec<u8>)> {
+    let data = bs58::decode(address)
+        .into_vec()
+        .map_err(|_| BlockchainError::Validation(format!("address {} is not valid base58", address)))?;
+    if data.len() < 1 + CHECKSUM_LEN {
+        return Err(BlockchainError::Validation(format!("address {} is too short", address)));
+    }
+    let (body, check) = data.split_at(data.len() - CHECKSUM_LEN);
+    if checksum(body) != check {
+        return Err(BlockchainError::Validation(format!(
+            "address {} has a bad checksum (mistyped?)",
+            address
+        )));
+    }
+    Ok((body[0], body[1..].to_vec()))
+}
+
+/// Address of a public key: the first 20 bytes of its SHA-256 hash, encoded
+/// under the network's version byte.
+pub fn from_public_key(version: u8, key: &VerifyingKey) -> String {
+    encode(version, &Sha256::digest(key.as_bytes())[..HASH_LEN])
+}
+
+/// Whether `name` has the shape of an encoded address (long enough and only
+/// base58 characters) rather than being a plain account name like `alice`.
+pub fn looks_encoded(name: &str) -> bool {
+    name.len() >= MIN_ENCODED_LEN && bs58::decode(name).into_vec().is_ok()
+}
+
+/// Accepts plain account names, and encoded addresses only if their checksum,
+/// version and length are right.
+pub fn validate(name: &str, version: u8) -> Result<()> {
+    if !looks_encoded(name) {
+        return Ok(());
+    }
+    let (found, payload) = decode(name)?;
+    if found != version {
+        return Err(BlockchainError::Validation(format!(
+            "address {} is for another network (version {}, expected {})",
+            name, found, version
+        )));
+    }
+    if payload.len() != HASH_LEN {
+        return Err(BlockchainError::Validation(format!("address {} has the wrong length", name)));
+    }
+    Ok(())
+}
@@ -0,0 +1,206 @@
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::{BlockchainError, Result};
+
+/// Decimal places of an [`Amount`].
+pub const DECIMALS: usize = 8;
+
+/// Base units in one coin.
+pub const COIN: u64 = 100_000_000;
+
+/// A quantity of coins (or of an asset), kept as a whole number of base
+/// units, [`COIN`] of them to the coin. Arithmetic on amounts is checked:
+/// it fails rather than wrapping.
+///
+/// Amounts are written in coins with up to [`DECIMALS`] decimal places,
+/// like `12.5`. Human-readable formats carry them that way, as strings, and
+/// also read whole numbers of coins, so a config file can say `reward = 50`;
+/// binary formats carry the base units.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Amount(u64);
+
+impl Amount {
+    pub const ZERO: Amount = Amount(0);
+    pub const MAX: Amount = Amount(u64::MAX);
+
+    pub const fn from_units(units: u64) -> Self {
+        Amount(units)
+    }
+
+    /// Whole coins, which never overflow from a `u32`.
+    pub const fn from_coins(coins: u32) -> Self {
+        Amount(coins as u64 * COIN)
+    }
+
+    pub const fn units(self) -> u64 {
+        self.0
+    }
+
+    pub const fn is_zero(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn checked_add(self, other: Amount) -> Option<Amount> {
+        self.0.checked_add(other.0).map(Amount)
+    }
+
+    pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+        self.0.checked_sub(other.0).map(Amount)
+    }
+
+    pub fn checked_mul(self, factor: u64) -> Option<Amount> {
+        self.0.checked_mul(factor).map(Amount)
+    }
+
+    pub fn saturating_add(self, other: Amount) -> Amount {
+        Amount(self.0.saturating_add(other.0))
+    }
+
+    pub fn saturating_sub(self, other: Amount) -> Amount {
+        Amount(self.0.saturating_sub(other.0))
+    }
+
+    /// The total of `amounts`, or `None` if it overflows.
+    pub fn checked_sum(amounts: impl IntoIterator<Item = Amount>) -> Option<Amount> {
+        amounts.into_iter().try_fold(Amount::ZERO, Amount::checked_add)
+    }
+
+    /// Like [`Amount::checked_add`], failing with a validation error that
+    /// names `what` was being added up.
+    pub fn try_add(self, other: Amount, what: &str) -> Result<Amount> {
+        self.checked_add(other).ok_or_else(|| overflow(what))
+    }
+
+    /// Like [`Amount::checked_sum`], failing with a validation error that
+    /// names `what` was being added up.
+    pub fn try_sum(amounts: impl IntoIterator<Item = Amount>, what: &str) -> Result<Amount> {
+        Amount::checked_sum(amounts).ok_or_else(|| overflow(what))
+    }
+}
+
+fn overflow(what: &str) -> BlockchainError {
+    BlockchainError::Validation(format!("{} overflows the largest amount", what))
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (coins, units) = (self.0 / COIN, self.0 % COIN);
+        if units == 0 {
+            return write!(f, "{}", coins);
+        }
+        let fraction = format!("{:0width$}", units, width = DECIMALS);
+        write!(f, "{}.{}", coins, fraction.trim_end_matches('0'))
+    }
+}
+
+/// Parses coins with up to [`DECIMALS`] decimal places, such as `12`,
+/// `12.5` or `0.00000001`.
+impl FromStr for Amount {
+    type Err = BlockchainError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = |why: &str| BlockchainError::Validation(format!("invalid amount {:?}: {}", s, why));
+        let (coins, fraction) = s.split_once('.').unwrap_or((s, ""));
+        if coins.is_empty() && fraction.is_empty() {
+            return Err(invalid("expected a number of coins such as 12.5"));
+        }
+        let digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+        if !digits(coins) || !digits(fraction) || (s.contains('.') && fraction.is_empty()) {
+            return Err(invalid("expected a number of coins such as 12.5"));
+        }
+        if fraction.len() > DECIMALS {
+            return Err(invalid(&format!("at most {} decimal places", DECIMALS)));
+        }
+        let coins: u64 = if coins.is_empty() { 0 } else { coins.parse().map_err(|_| invalid("too large"))? };
+        let units: u64 = format!("{:0<width$}", fraction, width = DECIMALS).parse().unwrap_or(0);
+        coins
+            .checked_mul(COIN)
+            .and_then(|base| base.checked_add(units))
+            .map(Amount)
+            .ok_or_else(|| invalid("too large"))
+    }
+}
+
+impl Serialize for Amount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.collect_str(self)
+        } else {
+            serializer.serialize_u64(self.0)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Amount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(CoinsVisitor)
+        } else {
+            u64::deserialize(deserializer).map(Amount)
+        }
+    }
+}
+
+/// Reads coins written as a decimal string or a whole number.
+struct CoinsVisitor;
+
+impl Visitor<'_> for CoinsVisitor {
+    type Value = Amount;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an amount of coins, such as \"12.5\" or 12")
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> std::result::Result<Amount, E> {
+        value.parse().map_err(E::custom)
+    }
+
+    fn visit_u64<E: de::Error>(self, coins: u64) -> std::result::Result<Amount, E> {
+        coins.checked_mul(COIN).map(Amount).ok_or_else(|| E::custom(format!("{} coins is too large", coins)))
+    }
+
+    fn visit_i64<E: de::Error>(self, coins: i64) -> std::result::Result<Amount, E> {
+        let coins = u64::try_from(coins).map_err(|_| E::custom("amounts can't be negative"))?;
+        self.visit_u64(coins)
+    }
+}
+
+/// Amounts as decimal strings only, for formats such as CSV that would
+/// otherwise read `12.5` as a lossy float:
+/// `#[serde(with = "crate::amount::decimal")]`.
+pub mod decimal {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::Amount;
+
+    pub fn serialize<S: Serializer>(amount: &Amount, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(amount)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Amount, D::Error> {
+        String::deserialize(deserializer)?.trim().parse().map_err(serde::de::Error::custom)
+    }
+
+    /// The same for optional amounts, which CSV leaves empty.
+    pub mod option {
+        use serde::{Deserialize, Deserializer, Serializer};
+
+        use super::Amount;
+
+        pub fn serialize<S: Serializer>(amount: &Option<Amount>, serializer: S) -> Result<S::Ok, S::Error> {
+            match amount {
+                Some(amount) => serializer.collect_str(amount),
+                None => serializer.serialize_none(),
+            }
+        }
+
+        pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Amount>, D::Error> {
+            Option::<String>::deserialize(deserializer)?
+                .map(|amount| amount.trim().parse().map_err(serde::de::Error::custom))
+                .transpose()
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use crate::amount::Amount;
+use crate::block::Block;
+use crate::blockchain::Blockchain;
+use crate::error::Result;
+use crate::transaction::Transaction;
+
+/// Starts the memo of an anchoring transaction; the anchored digest follows.
+pub const MEMO_PREFIX: &[u8] = b"anchor:sha256:";
+
+/// The SHA-256 digest of the file at `path`.
+pub fn hash_file(path: impl AsRef<Path>) -> Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().into())
+}
+
+/// A transaction of nothing from `sender` to itself whose memo records
+/// `digest`, so the block it is mined in proves the digest existed by then.
+pub fn transaction(sender: impl Into<String>, digest: [u8; 32]) -> Transaction {
+    let sender = sender.into();
+    Transaction::new(sender.clone(), sender, Amount::ZERO).with_memo([MEMO_PREFIX, &digest].concat())
+}
+
+/// The digest `tx` anchors, if it is an anchoring transaction.
+pub fn digest_of(tx: &Transaction) -> Option<[u8; 32]> {
+    tx.memo()?.strip_prefix(MEMO_PREFIX)?.try_into().ok()
+}
+
+/// The earliest main-chain block anchoring `digest`, and the transaction in
+/// it that does. Anchors in pruned blocks can't be found.
+pub fn find<'a>(chain: &'a Blockchain, digest: &[u8; 32]) -> Option<(&'a Block, &'a Transaction)> {
+    chain.iter().find_map(|block| {
+        block
+            .transactions()
+            .iter()
+            .find(|tx| digest_of(tx).as_ref() == Some(digest))
+            .map(|tx| (block, tx))
+    })
+}
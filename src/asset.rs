@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::amount::Amount;
+use crate::error::{BlockchainError, Result};
+
+/// Longest asset identifier accepted.
+pub const MAX_NAME_LEN: usize = 16;
+
+/// A token circulating alongside the native coin, created by an issuance
+/// transaction and moved by account-model transfers naming it. Fees are
+/// always paid in the native coin.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Asset {
+    /// Address of the first issuance, the only one allowed to issue more.
+    pub issuer: String,
+    /// Total issued so far.
+    pub supply: Amount,
+    pub balances: BTreeMap<String, Amount>,
+}
+
+impl Asset {
+    pub fn balance_of(&self, address: &str) -> Amount {
+        self.balances.get(address).copied().unwrap_or_default()
+    }
+}
+
+/// Checks that `name` is 1 to [`MAX_NAME_LEN`] ASCII letters, digits or dashes.
+pub fn validate_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.len() > MAX_NAME_LEN {
+        return Err(BlockchainError::Validation(format!(
+            "asset name {:?} must be 1 to {} characters",
+            name, MAX_NAME_LEN
+        )));
+    }
+    if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(BlockchainError::Validation(format!(
+            "asset name {:?} may only contain ASCII letters, digits and dashes",
+            name
+        )));
+    }
+    Ok(())
+}
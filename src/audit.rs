@@ -0,0 +1,179 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::amount::Amount;
+use crate::blockchain::Blockchain;
+use crate::error::{BlockchainError, Result};
+use crate::transaction::Transaction;
+use crate::validation::{Check, Violation};
+
+/// What an address received and gave up over the whole chain.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Flows {
+    /// Payments, block rewards and allocations received.
+    pub received: Amount,
+    /// Amounts sent, not counting fees.
+    pub sent: Amount,
+    pub fees: Amount,
+}
+
+impl Flows {
+    pub fn balance(&self) -> Amount {
+        self.received.saturating_sub(self.sent.saturating_add(self.fees))
+    }
+}
+
+/// An asset's books: what was issued and who holds it at the tip.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AssetFlows {
+    pub issuer: String,
+    pub issued: Amount,
+    pub holdings: BTreeMap<String, Amount>,
+}
+
+impl AssetFlows {
+    pub fn supply(&self) -> Amount {
+        self.holdings.values().fold(Amount::ZERO, |total, held| total.saturating_add(*held))
+    }
+}
+
+/// The chain's books, from [`audit`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AuditReport {
+    pub blocks: u64,
+    /// Coins allocated by the genesis block.
+    pub allocated: Amount,
+    /// Coins minted as block rewards, not counting the fees coinbases pass on.
+    pub issued: Amount,
+    pub fees: Amount,
+    /// Sum of every balance at the tip.
+    pub supply: Amount,
+    pub addresses: BTreeMap<String, Flows>,
+    pub assets: BTreeMap<String, AssetFlows>,
+    pub violations: Vec<Violation>,
+}
+
+impl AuditReport {
+    /// Whether value was conserved: no block created coins outside its
+    /// coinbase, the supply is exactly what was allocated and issued, and so
+    /// is the supply of every asset.
+    pub fn is_balanced(&self) -> bool {
+        self.violations.is_empty()
+            && Some(self.supply) == self.allocated.checked_add(self.issued)
+            && self.assets.values().all(|asset| asset.supply() == asset.issued)
+    }
+}
+
+/// Replays the chain as a double-entry ledger: every transfer debits its
+/// sender the amount plus fee and credits the receiver the amount and the
+/// miner the fee, leaving each block's coinbase as the only source of new
+/// coins. A block is flagged if a sender spends more than they hold or its
+/// coinbase mints more than the block reward plus fees (or, at genesis, the
+/// configured allocations). Assets are kept on separate books, where only
+/// their issuer's issuances create value and a transfer is flagged if it
+/// sends more than the sender holds. Totals saturate rather than overflow,
+/// which leaves the books unbalanced. A pruned chain can't be audited.
+pub fn audit(chain: &Blockchain) -> Result<AuditReport> {
+    if let Some(height) = chain.pruned_height() {
+        return Err(BlockchainError::Validation(format!(
+            "cannot audit a chain pruned up to block #{}",
+            height
+        )));
+    }
+    let params = chain.params();
+    let allocations = params.genesis_allocations.values().fold(Amount::ZERO, |total, amount| total.saturating_add(*amount));
+    let mut report = AuditReport::default();
+    for block in chain {
+        let index = block.index();
+        let (mut minted, mut fees) = (Amount::ZERO, Amount::ZERO);
+        for tx in block.transactions() {
+            let amount = tx.amount();
+            if tx.is_coinbase() {
+                minted = minted.saturating_add(amount);
+            } else {
+                let sender = report.addresses.entry(tx.sender().to_string()).or_default();
+                let held = sender.balance();
+                let cost = tx.cost().unwrap_or(Amount::MAX);
+                if held < cost {
+                    report.violations.push(
+                        Violation::new(index, Check::Conservation, format!("{} spends more than they hold", tx.sender()))
+                            .expected(format!("at most {}", held))
+                            .actual(cost),
+                    );
+                }
+                if tx.asset().is_none() {
+                    sender.sent = sender.sent.saturating_add(tx.total_amount().unwrap_or(Amount::MAX));
+                }
+                sender.fees = sender.fees.saturating_add(tx.fee());
+                fees = fees.saturating_add(tx.fee());
+            }
+            match tx.asset() {
+                Some(asset) => audit_asset(&mut report, index, asset, tx),
+                None => {
+                    for (receiver, amount) in tx.recipients() {
+                        let receiver = report.addresses.entry(receiver.to_string()).or_default();
+                        receiver.received = receiver.received.saturating_add(amount);
+                    }
+                }
+            }
+        }
+
+        let allowed = if index == 0 {
+            allocations
+        } else {
+            params.block_reward_at(index).saturating_add(fees)
+        };
+        if minted > allowed {
+            let message = if index == 0 {
+                "genesis block mints more than its allocations"
+            } else {
+                "coinbase mints more than the block reward plus fees"
+            };
+            report.violations.push(
+                Violation::new(index, Check::Conservation, message)
+                    .expected(format!("at most {}", allowed))
+                    .actual(minted),
+            );
+        }
+        if index == 0 {
+            report.allocated = report.allocated.saturating_add(minted);
+        } else {
+            report.issued = report.issued.saturating_add(minted.saturating_sub(fees));
+        }
+        report.fees = report.fees.saturating_add(fees);
+        report.blocks += 1;
+    }
+    report.supply = report.addresses.values().fold(Amount::ZERO, |total, flows| total.saturating_add(flows.balance()));
+    Ok(report)
+}
+
+fn audit_asset(report: &mut AuditReport, index: u64, name: &str, tx: &Transaction) {
+    let asset = report.assets.entry(name.to_string()).or_insert_with(|| AssetFlows {
+        issuer: tx.sender().to_string(),
+        ..AssetFlows::default()
+    });
+    let amount = tx.amount();
+    if tx.is_issue() {
+        if asset.issuer != tx.sender() {
+            report.violations.push(
+                Violation::new(index, Check::Conservation, format!("{} issues asset {}", tx.sender(), name))
+                    .expected(format!("issuer {}", asset.issuer))
+                    .actual(tx.sender()),
+            );
+        }
+        asset.issued = asset.issued.saturating_add(amount);
+    } else {
+        let held = asset.holdings.entry(tx.sender().to_string()).or_default();
+        if *held < amount {
+            let message = format!("{} sends more {} than they hold", tx.sender(), name);
+            report.violations.push(
+                Violation::new(index, Check::Conservation, message)
+                    .expected(format!("at most {}", held))
+                    .actual(amount),
+            );
+        }
+        *held = held.saturating_sub(amount);
+    }
+    let receiver = asset.holdings.entry(tx.receiver().to_string()).or_default();
+    *receiver = receiver.saturating_add(amount);
+}
@@ -0,0 +1,113 @@
+use std::sync::Arc;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::{info, warn};
+
+use crate::block::Block;
+use crate::blockchain::Blockchain;
+use crate::error::Result;
+use crate::mempool::DEFAULT_BATCH_SIZE;
+use crate::network::SharedChain;
+use crate::rpc::SharedMempool;
+use crate::sync::{lock, read};
+
+type BlockHook = Arc<dyn Fn(&Blockchain) + Send + Sync>;
+
+/// Mines a block on a shared chain at a fixed interval, so a demo or test
+/// network moves on without anyone running `mine`.
+///
+/// By default a block is only mined when transactions are pending; with
+/// [`AutoMiner::with_empty_blocks`] one is mined every interval regardless.
+#[derive(Clone)]
+pub struct AutoMiner {
+    chain: SharedChain,
+    mempool: SharedMempool,
+    miner: String,
+    interval: Duration,
+    empty_blocks: bool,
+    on_block: BlockHook,
+}
+
+impl AutoMiner {
+    /// A scheduler whose blocks pay `miner`, mined every `interval`.
+    pub fn new(chain: SharedChain, mempool: SharedMempool, miner: impl Into<String>, interval: Duration) -> Self {
+        AutoMiner {
+            chain,
+            mempool,
+            miner: miner.into(),
+            interval,
+            empty_blocks: false,
+            on_block: Arc::new(|_| {}),
+        }
+    }
+
+    /// Also mines blocks when nothing is pending, as regtest nodes do.
+    pub fn with_empty_blocks(mut self, empty_blocks: bool) -> Self {
+        self.empty_blocks = empty_blocks;
+        self
+    }
+
+    /// Registers a callback run (with the chain locked) after each block is
+    /// added, e.g. to persist or broadcast it.
+    pub fn with_block_hook(mut self, hook: impl Fn(&Blockchain) + Send + Sync + 'static) -> Self {
+        self.on_block = Arc::new(hook);
+        self
+    }
+
+    /// Mines one block now, unless nothing is pending and empty blocks are
+    /// off.
+    pub fn tick(&self) -> Result<Option<Block>> {
+        if !self.empty_blocks && lock(&self.mempool).is_empty() {
+            return Ok(None);
+        }
+        let block = Blockchain::mine_shared(&self.chain, &self.mempool, DEFAULT_BATCH_SIZE, &self.miner)?;
+        (self.on_block)(&read(&self.chain));
+        Ok(Some(block))
+    }
+
+    /// Mines every interval in a background thread until the returned
+    /// handle is stopped or dropped.
+    pub fn start(self) -> AutoMining {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(self.interval) {
+                match self.tick() {
+                    Ok(Some(block)) => {
+                        info!(index = block.index(), transactions = block.transactions().len(), "auto-mined block")
+                    }
+                    Ok(None) => {}
+                    Err(err) => warn!(%err, "auto-mining failed"),
+                }
+            }
+        });
+        AutoMining {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
+
+/// A running [`AutoMiner`]; dropping it stops the miner after any block in
+/// progress.
+#[derive(Debug)]
+pub struct AutoMining {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl AutoMining {
+    /// Stops the miner, waiting for any block in progress.
+    pub fn stop(self) {
+        drop(self);
+    }
+}
+
+impl Drop for AutoMining {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// Penalty points at which a peer is disconnected and banned.
+pub const BAN_THRESHOLD: u32 = 100;
+/// How long a ban lasts.
+pub const BAN_DURATION: Duration = Duration::from_secs(60 * 60);
+
+/// Ways a peer can misbehave.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Offense {
+    /// Sent a block that failed validation.
+    InvalidBlock,
+    /// Sent headers that don't chain or lack their proof of work.
+    InvalidHeaders,
+    /// Sent a line that isn't a message.
+    MalformedMessage,
+    /// Sent a message longer than [`MAX_MESSAGE_BYTES`](crate::network::MAX_MESSAGE_BYTES).
+    OversizedMessage,
+    /// Sent a message it may not send now, such as a second handshake.
+    UnexpectedMessage,
+    /// Sent more messages in a second than [`MAX_MESSAGE_RATE`](crate::network::MAX_MESSAGE_RATE).
+    Flooding,
+}
+
+impl Offense {
+    pub fn penalty(self) -> u32 {
+        match self {
+            Offense::InvalidBlock | Offense::InvalidHeaders => 50,
+            Offense::MalformedMessage | Offense::OversizedMessage | Offense::UnexpectedMessage => 20,
+            Offense::Flooding => 10,
+        }
+    }
+}
+
+impl fmt::Display for Offense {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Offense::InvalidBlock => "invalid block",
+            Offense::InvalidHeaders => "invalid headers",
+            Offense::MalformedMessage => "malformed message",
+            Offense::OversizedMessage => "oversized message",
+            Offense::UnexpectedMessage => "unexpected message",
+            Offense::Flooding => "flooding",
+        })
+    }
+}
+
+/// Misbehavior scores and bans, by IP address so that reconnecting from
+/// another port starts neither over. A peer whose score reaches the
+/// threshold is banned for the ban duration, after which it starts again
+/// from zero.
+#[derive(Debug)]
+pub struct BanList {
+    scores: HashMap<IpAddr, u32>,
+    banned: HashMap<IpAddr, Instant>,
+    threshold: u32,
+    duration: Duration,
+}
+
+impl Default for BanList {
+    fn default() -> Self {
+        BanList::new(BAN_THRESHOLD, BAN_DURATION)
+    }
+}
+
+impl BanList {
+    pub fn new(threshold: u32, duration: Duration) -> Self {
+        BanList {
+            scores: HashMap::new(),
+            banned: HashMap::new(),
+            threshold,
+            duration,
+        }
+    }
+
+    /// Adds the penalty for `offense` to `ip`'s score, banning it if that
+    /// reaches the threshold. Returns whether it is now banned.
+    pub fn punish(&mut self, ip: IpAddr, offense: Offense) -> bool {
+        let score = self.scores.entry(ip).or_default();
+        *score += offense.penalty();
+        if *score < self.threshold {
+            return false;
+        }
+        self.scores.remove(&ip);
+        self.banned.insert(ip, Instant::now() + self.duration);
+        true
+    }
+
+    pub fn score(&self, ip: IpAddr) -> u32 {
+        self.scores.get(&ip).copied().unwrap_or(0)
+    }
+
+    /// Whether `ip` is banned, forgetting its ban if it has run out.
+    pub fn is_banned(&mut self, ip: IpAddr) -> bool {
+        match self.banned.get(&ip) {
+            Some(until) if *until > Instant::now() => true,
+            Some(_) => {
+                self.banned.remove(&ip);
+                false
+            }
+            None => false,
+        }
+    }
+
+    /// The banned addresses and how long each ban has left.
+    pub fn bans(&self) -> Vec<(IpAddr, Duration)> {
+        let now = Instant::now();
+        let mut bans: Vec<_> = self
+            .banned
+            .iter()
+            .filter(|(_, until)| **until > now)
+            .map(|(ip, until)| (*ip, *until - now))
+            .collect();
+        bans.sort();
+        bans
+    }
+}
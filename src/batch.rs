@@ -0,0 +1,102 @@
+use serde::Deserialize;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use crate::amount::{self, Amount};
+use crate::error::{BlockchainError, Result};
+
+/// One transfer read from a batch file, with the arguments of the `add`
+/// command.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BatchEntry {
+    pub sender: String,
+    pub receiver: String,
+    pub amount: Amount,
+    #[serde(default)]
+    pub fee: Amount,
+}
+
+/// A [`BatchEntry`] as a CSV row, whose amounts are read as decimal text
+/// rather than as floats.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CsvEntry {
+    sender: String,
+    receiver: String,
+    #[serde(with = "amount::decimal")]
+    amount: Amount,
+    #[serde(default, with = "amount::decimal")]
+    fee: Amount,
+}
+
+impl From<CsvEntry> for BatchEntry {
+    fn from(entry: CsvEntry) -> Self {
+        BatchEntry {
+            sender: entry.sender,
+            receiver: entry.receiver,
+            amount: entry.amount,
+            fee: entry.fee,
+        }
+    }
+}
+
+/// Reads the transfers listed in `path`: a JSON array of objects if it ends
+/// in `.json`, otherwise CSV with a `sender,receiver,amount[,fee]` header.
+/// Each entry comes with its line (CSV) or position in the array (JSON),
+/// counting from 1, and fails on its own if it can't be parsed.
+pub fn read_batch(path: impl AsRef<Path>) -> Result<Vec<(u64, Result<BatchEntry>)>> {
+    let path = path.as_ref();
+    let reader = BufReader::new(File::open(path)?);
+    if path.extension().is_some_and(|ext| ext == "json") {
+        let values: Vec<serde_json::Value> = serde_json::from_reader(reader)?;
+        return Ok(values
+            .into_iter()
+            .zip(1..)
+            .map(|(value, position)| (position, serde_json::from_value(value).map_err(BlockchainError::from)))
+            .collect());
+    }
+
+    // Flexible, so rows may leave out a trailing fee.
+    let mut csv = csv::ReaderBuilder::new().trim(csv::Trim::All).flexible(true).from_reader(reader);
+    let headers = csv.headers().map_err(csv_error)?.clone();
+    let mut entries = Vec::new();
+    for record in csv.records() {
+        let entry = match record {
+            Ok(record) if record.len() > headers.len() => (
+                line_of(record.position()),
+                Err(BlockchainError::Encoding(format!(
+                    "CSV: expected at most {} fields, found {}",
+                    headers.len(),
+                    record.len()
+                ))),
+            ),
+            Ok(record) => {
+                // Match fields to only as many columns as the row has, so
+                // the ones left out take their defaults.
+                let columns: csv::StringRecord = headers.iter().take(record.len()).collect();
+                let entry: Result<CsvEntry> = record.deserialize(Some(&columns)).map_err(|err| match err.kind() {
+                    csv::ErrorKind::Deserialize { err, .. } => match err.field().and_then(|i| columns.get(i as usize)) {
+                        Some(column) => BlockchainError::Encoding(format!("CSV: {}: {}", column, err.kind())),
+                        None => BlockchainError::Encoding(format!("CSV: {}", err.kind())),
+                    },
+                    _ => csv_error(err),
+                });
+                (line_of(record.position()), entry.map(BatchEntry::from))
+            }
+            Err(err) if matches!(err.kind(), csv::ErrorKind::Io(_)) => return Err(csv_error(err)),
+            Err(err) => (line_of(err.position()), Err(csv_error(err))),
+        };
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+fn line_of(position: Option<&csv::Position>) -> u64 {
+    position.map_or(0, csv::Position::line)
+}
+
+fn csv_error(err: csv::Error) -> BlockchainError {
+    BlockchainError::Encoding(format!("CSV: {}", err))
+}
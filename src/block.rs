@@ -0,0 +1,454 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::OnceLock;
+
+use crate::amount::Amount;
+use crate::error::Result;
+use crate::hash::{HashAlgorithm, Hasher};
+use crate::merkle::{self, MerkleProof};
+use crate::miner::Miner;
+use crate::target::Target;
+use crate::transaction::Transaction;
+
+/// Version of blocks mined before any [`Upgrade`](crate::params::Upgrade).
+pub const INITIAL_BLOCK_VERSION: u32 = 1;
+
+/// The fields covered by a block's proof of work. Transactions are committed
+/// to only through `merkle_root`, so a header can be checked without its body.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockHeader {
+    /// Which consensus rules the block follows; see [`ChainParams::block_version`](crate::ChainParams::block_version).
+    #[serde(default = "initial_version", skip_serializing_if = "is_initial_version")]
+    version: u32,
+    index: u64,
+    timestamp: u128,
+    merkle_root: String,
+    previous_hash: String,
+    nonce: u64,
+    bits: u32,
+    #[serde(default, skip_serializing_if = "HashAlgorithm::is_default")]
+    algorithm: HashAlgorithm,
+}
+
+impl BlockHeader {
+    /// A header with nonce 0, ready to be mined.
+    pub fn new(index: u64, timestamp: u128, merkle_root: String, previous_hash: String, bits: u32) -> Self {
+        BlockHeader {
+            version: INITIAL_BLOCK_VERSION,
+            index,
+            timestamp,
+            merkle_root,
+            previous_hash,
+            nonce: 0,
+            bits,
+            algorithm: HashAlgorithm::default(),
+        }
+    }
+
+    /// The header hashed with `algorithm` rather than SHA-256.
+    pub fn with_algorithm(mut self, algorithm: HashAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    pub fn with_version(mut self, version: u32) -> Self {
+        self.version = version;
+        self
+    }
+
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    pub fn timestamp(&self) -> u128 {
+        self.timestamp
+    }
+
+    pub fn merkle_root(&self) -> &str {
+        &self.merkle_root
+    }
+
+    pub fn previous_hash(&self) -> &str {
+        &self.previous_hash
+    }
+
+    pub fn nonce(&self) -> u64 {
+        self.nonce
+    }
+
+    pub fn set_timestamp(&mut self, timestamp: u128) {
+        self.timestamp = timestamp;
+    }
+
+    pub fn set_nonce(&mut self, nonce: u64) {
+        self.nonce = nonce;
+    }
+
+    /// The proof-of-work target in compact form.
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// The value the block hash must not exceed.
+    pub fn target(&self) -> Target {
+        Target::from_bits(self.bits)
+    }
+
+    pub fn algorithm(&self) -> HashAlgorithm {
+        self.algorithm
+    }
+
+    /// Hash of the header fields, in hex.
+    pub fn compute_hash(&self) -> String {
+        hex::encode(self.hash_bytes())
+    }
+
+    /// Hash of the header fields.
+    pub fn hash_bytes(&self) -> [u8; 32] {
+        self.hasher().hash(self.nonce)
+    }
+
+    /// The header hashed up to its nonce; see [`HeaderHasher`].
+    pub fn hasher(&self) -> HeaderHasher {
+        let mut hasher = self.algorithm.hasher();
+        // SHA-256 headers leave the algorithm out, keeping their old hashes.
+        if !self.algorithm.is_default() {
+            hasher.update(&[self.algorithm.id()]);
+        }
+        // Likewise for headers of the initial version.
+        if self.version != INITIAL_BLOCK_VERSION {
+            hasher.update(&self.version.to_be_bytes());
+        }
+        hasher.update(&self.index.to_be_bytes());
+        hasher.update(&self.timestamp.to_be_bytes());
+        hasher.update(&self.bits.to_be_bytes());
+        for field in [&self.merkle_root, &self.previous_hash] {
+            hasher.update(&(field.len() as u64).to_be_bytes());
+            hasher.update(field.as_bytes());
+        }
+        HeaderHasher(hasher)
+    }
+
+    /// Expected number of hashes needed to mine this header.
+    pub fn work(&self) -> u128 {
+        self.target().work()
+    }
+}
+
+fn initial_version() -> u32 {
+    INITIAL_BLOCK_VERSION
+}
+
+fn is_initial_version(version: &u32) -> bool {
+    *version == INITIAL_BLOCK_VERSION
+}
+
+/// A header's hash state before its nonce, which is hashed last, so trying
+/// another nonce only hashes those 8 bytes without rebuilding the rest.
+pub struct HeaderHasher(Box<dyn Hasher>);
+
+impl HeaderHasher {
+    /// Hash of the header with `nonce` in place of its own.
+    pub fn hash(&self, nonce: u64) -> [u8; 32] {
+        self.0.finish_with(&nonce.to_be_bytes())
+    }
+}
+
+impl fmt::Debug for HeaderHasher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HeaderHasher").finish_non_exhaustive()
+    }
+}
+
+/// What a block's transactions add up to, worked out once per block and
+/// kept when it is pruned.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockTotals {
+    /// Paid out by the coinbase (or, at genesis, allocated): the subsidy
+    /// plus the fees.
+    pub coinbase: Amount,
+    pub fees: Amount,
+    /// Native coins paid to receivers, not counting coinbases, fees or change.
+    pub transferred: Amount,
+    /// Serialized size in bytes, before any pruning.
+    pub size: usize,
+}
+
+impl BlockTotals {
+    /// Newly minted coins: what the coinbase pays beyond the fees.
+    pub fn subsidy(&self) -> Amount {
+        self.coinbase.saturating_sub(self.fees)
+    }
+}
+
+/// A header, its hash, and the body of transactions it commits to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "FlatBlock", into = "FlatBlock")]
+pub struct Block {
+    header: BlockHeader,
+    hash: String,
+    transactions: Vec<Transaction>,
+    /// Whether the transactions were discarded; see [`Block::prune`].
+    pruned: bool,
+    /// Worked out the first time they are asked for, since the size takes
+    /// serializing the whole block.
+    totals: OnceLock<BlockTotals>,
+}
+
+/// Serialized form of a block, with the header fields inline so stored
+/// blocks keep the layout they had before the header was split out. (Serde's
+/// `flatten` cannot carry the `u128` timestamp.)
+#[derive(Serialize, Deserialize)]
+struct FlatBlock {
+    index: u64,
+    timestamp: u128,
+    transactions: Vec<Transaction>,
+    merkle_root: String,
+    previous_hash: String,
+    hash: String,
+    nonce: u64,
+    bits: u32,
+    #[serde(default, skip_serializing_if = "HashAlgorithm::is_default")]
+    algorithm: HashAlgorithm,
+    #[serde(default = "initial_version", skip_serializing_if = "is_initial_version")]
+    version: u32,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pruned: bool,
+    /// Only kept for pruned blocks, whose totals can't be worked out again.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    totals: Option<BlockTotals>,
+}
+
+impl From<FlatBlock> for Block {
+    fn from(flat: FlatBlock) -> Self {
+        Block {
+            header: BlockHeader {
+                version: flat.version,
+                index: flat.index,
+                timestamp: flat.timestamp,
+                merkle_root: flat.merkle_root,
+                previous_hash: flat.previous_hash,
+                nonce: flat.nonce,
+                bits: flat.bits,
+                algorithm: flat.algorithm,
+            },
+            hash: flat.hash,
+            transactions: flat.transactions,
+            pruned: flat.pruned,
+            // Blocks pruned before totals were kept have none to show.
+            totals: flat.totals.or(flat.pruned.then(BlockTotals::default)).map(OnceLock::from).unwrap_or_default(),
+        }
+    }
+}
+
+impl From<Block> for FlatBlock {
+    fn from(block: Block) -> Self {
+        let totals = block.pruned.then(|| *block.totals());
+        FlatBlock {
+            index: block.header.index,
+            timestamp: block.header.timestamp,
+            transactions: block.transactions,
+            merkle_root: block.header.merkle_root,
+            previous_hash: block.header.previous_hash,
+            hash: block.hash,
+            nonce: block.header.nonce,
+            bits: block.header.bits,
+            algorithm: block.header.algorithm,
+            version: block.header.version,
+            pruned: block.pruned,
+            totals,
+        }
+    }
+}
+
+/// A block complete but for its nonce, e.g. for a miner in another process
+/// to search for; see [`Blockchain::block_template`](crate::Blockchain::block_template).
+#[derive(Debug, Clone)]
+pub struct BlockTemplate {
+    header: BlockHeader,
+    transactions: Vec<Transaction>,
+}
+
+impl BlockTemplate {
+    pub fn new(header: BlockHeader, transactions: Vec<Transaction>) -> Self {
+        BlockTemplate { header, transactions }
+    }
+
+    pub fn header(&self) -> &BlockHeader {
+        &self.header
+    }
+
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    /// The block with `nonce`, whether or not its hash meets the target.
+    pub fn complete(&self, nonce: u64) -> Block {
+        let mut header = self.header.clone();
+        header.set_nonce(nonce);
+        let hash = header.compute_hash();
+        Block::from_parts(header, hash, self.transactions.clone())
+    }
+}
+
+impl Block {
+    pub fn new(index: u64, transactions: Vec<Transaction>, previous_hash: String, bits: u32) -> Result<Self> {
+        Block::mine_with(&Miner::default(), index, transactions, previous_hash, bits)
+    }
+
+    /// Builds and mines a block using the given miner's thread pool, stamped
+    /// with the time on the miner's clock.
+    pub fn mine_with(
+        miner: &Miner,
+        index: u64,
+        transactions: Vec<Transaction>,
+        previous_hash: String,
+        bits: u32,
+    ) -> Result<Self> {
+        Block::mine_at(miner, index, miner.clock().now_millis()?, transactions, previous_hash, bits)
+    }
+
+    /// Like [`Block::mine_with`] but with a caller-chosen timestamp, so the
+    /// result is fully deterministic (used for genesis blocks).
+    pub fn mine_at(
+        miner: &Miner,
+        index: u64,
+        timestamp: u128,
+        transactions: Vec<Transaction>,
+        previous_hash: String,
+        bits: u32,
+    ) -> Result<Self> {
+        let merkle_root = merkle::merkle_root(&transactions);
+        let header = BlockHeader::new(index, timestamp, merkle_root, previous_hash, bits);
+        let (header, hash) = miner.mine(header)?;
+        Ok(Block::from_parts(header, hash, transactions))
+    }
+
+    /// Reassembles a block from its parts as they were stored, trusting
+    /// `hash`; validation will catch a mismatch.
+    pub fn from_parts(header: BlockHeader, hash: String, transactions: Vec<Transaction>) -> Self {
+        Block {
+            header,
+            hash,
+            transactions,
+            pruned: false,
+            totals: OnceLock::new(),
+        }
+    }
+
+    fn compute_totals(&self) -> BlockTotals {
+        let mut totals = BlockTotals {
+            size: self.size(),
+            ..BlockTotals::default()
+        };
+        for tx in &self.transactions {
+            let amount = tx.total_amount().unwrap_or(Amount::MAX);
+            if tx.is_coinbase() {
+                totals.coinbase = totals.coinbase.saturating_add(amount);
+            } else {
+                totals.fees = totals.fees.saturating_add(tx.fee());
+                if tx.asset().is_none() {
+                    totals.transferred = totals.transferred.saturating_add(amount);
+                }
+            }
+        }
+        totals
+    }
+
+    /// Discards the transactions, keeping the header and hash. A pruned block
+    /// can still be linked and have its proof of work checked, but its
+    /// effect on balances must come from elsewhere.
+    pub fn prune(&mut self) {
+        self.totals();
+        self.transactions = Vec::new();
+        self.pruned = true;
+    }
+
+    pub fn is_pruned(&self) -> bool {
+        self.pruned
+    }
+
+    pub fn totals(&self) -> &BlockTotals {
+        self.totals.get_or_init(|| self.compute_totals())
+    }
+
+    pub fn header(&self) -> &BlockHeader {
+        &self.header
+    }
+
+    pub fn version(&self) -> u32 {
+        self.header.version
+    }
+
+    pub fn index(&self) -> u64 {
+        self.header.index
+    }
+
+    pub fn timestamp(&self) -> u128 {
+        self.header.timestamp
+    }
+
+    /// The block body, empty if the block was pruned.
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    pub fn merkle_root(&self) -> &str {
+        &self.header.merkle_root
+    }
+
+    /// Builds an inclusion proof for the transaction at `tx_index`, which a
+    /// light client can check against `merkle_root` alone.
+    pub fn merkle_proof(&self, tx_index: usize) -> Option<MerkleProof> {
+        MerkleProof::build(&self.transactions, tx_index)
+    }
+
+    /// Whether the stored Merkle root matches the block's transactions.
+    pub fn has_valid_merkle_root(&self) -> bool {
+        self.header.merkle_root == merkle::merkle_root(&self.transactions)
+    }
+
+    pub fn previous_hash(&self) -> &str {
+        &self.header.previous_hash
+    }
+
+    pub fn hash(&self) -> &str {
+        &self.hash
+    }
+
+    pub fn nonce(&self) -> u64 {
+        self.header.nonce
+    }
+
+    /// The proof-of-work target in compact form.
+    pub fn bits(&self) -> u32 {
+        self.header.bits
+    }
+
+    pub fn target(&self) -> Target {
+        self.header.target()
+    }
+
+    /// Recomputes the hash from the block's header, ignoring the stored `hash`.
+    pub fn compute_hash(&self) -> String {
+        self.header.compute_hash()
+    }
+
+    pub fn work(&self) -> u128 {
+        self.header.work()
+    }
+
+    /// Serialized size in bytes, as limited by the chain's maximum block size.
+    pub fn size(&self) -> usize {
+        serde_json::to_vec(self).map_or(0, |bytes| bytes.len())
+    }
+
+    /// Whether the stored hash meets the header's target.
+    pub fn meets_target(&self) -> bool {
+        self.header.target().is_met_by(&self.hash)
+    }
+}
@@ -0,0 +1,1976 @@
+use ed25519_dalek::VerifyingKey;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::Path;
+use std::slice;
+use std::sync::{Mutex, RwLock};
+use std::sync::mpsc::Receiver;
+use tracing::{debug, debug_span, info, warn};
+
+use crate::address;
+use crate::amount::Amount;
+use crate::block::{Block, BlockHeader, BlockTemplate};
+use crate::consensus::{Consensus, ConsensusKind};
+use crate::error::{BlockchainError, Result};
+use crate::events::{ChainEvent, EventBus, NodeEvent, Subscription};
+use crate::file;
+use crate::index::ChainIndex;
+use crate::light::TxProof;
+use crate::mempool::{self, Mempool};
+use crate::merkle;
+use crate::metrics::Metrics;
+use crate::miner::{Miner, TIMESTAMP_REFRESH};
+use crate::params::ChainParams;
+use crate::script::Script;
+use crate::sigcache::SignatureCache;
+use crate::state::ChainState;
+use crate::store::ChainStore;
+use crate::sync::{lock, read, write};
+use crate::transaction::{Transaction, describe_lock_time};
+use crate::utxo::UtxoSet;
+use crate::validation::{Check, TxCheck, TxError, ValidationReport, Violation};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Blockchain {
+    blocks: Vec<Block>,
+    #[serde(default)]
+    params: ChainParams,
+    #[serde(skip)]
+    miner: Miner,
+    /// Valid blocks that are not on the main chain, keyed by hash.
+    #[serde(skip)]
+    side_blocks: HashMap<String, Block>,
+    #[serde(skip)]
+    events: EventBus,
+    #[serde(skip)]
+    metrics: Metrics,
+    /// Known-good block hashes by height; see [`Blockchain::set_checkpoints`].
+    #[serde(skip)]
+    checkpoints: BTreeMap<u64, String>,
+    /// State as of the last pruned block, if the chain was pruned.
+    #[serde(skip)]
+    base: Option<ChainState>,
+    /// See [`Blockchain::set_prune_depth`].
+    #[serde(skip)]
+    prune_depth: Option<u64>,
+    /// State as of a recent block, so balances and unspent outputs are
+    /// replayed from there rather than from genesis.
+    #[serde(skip)]
+    snapshot: Option<ChainState>,
+    /// See [`Blockchain::set_snapshot_interval`].
+    #[serde(skip)]
+    snapshot_interval: u64,
+    /// See [`Blockchain::validated_height`].
+    #[serde(skip)]
+    validated: Mutex<Option<Validated>>,
+    /// Lookups over `blocks`, updated whenever a block joins or leaves them.
+    #[serde(skip)]
+    index: ChainIndex,
+    /// See [`Blockchain::signature_cache`].
+    #[serde(skip)]
+    signatures: SignatureCache,
+}
+
+/// The consensus engine and miner that seal a block, so a block can be
+/// mined without holding the chain.
+struct Sealer {
+    consensus: Box<dyn Consensus>,
+    miner: Miner,
+}
+
+impl Sealer {
+    fn seal(&self, header: BlockHeader, transactions: Vec<Transaction>) -> Result<Block> {
+        let (header, hash) = self.consensus.seal(&self.miner, header)?;
+        Ok(Block::from_parts(header, hash, transactions))
+    }
+
+    fn seal_template(&self, template: BlockTemplate) -> Result<Block> {
+        self.seal(template.header().clone(), template.transactions().to_vec())
+    }
+}
+
+/// The last block a validation pass accepted, with the ledger as of it, so
+/// the next pass only checks the blocks after it.
+#[derive(Debug)]
+struct Validated {
+    height: u64,
+    hash: String,
+    ledger: Ledger,
+}
+
+/// What validation checks each transaction against: the unspent outputs and
+/// native-coin balances left by the blocks before it.
+#[derive(Debug, Clone, Default)]
+struct Ledger {
+    utxos: UtxoSet,
+    balances: HashMap<String, Amount>,
+}
+
+impl Ledger {
+    fn apply_transaction(&mut self, tx: &Transaction) {
+        self.utxos.apply_transaction(tx);
+        apply_balance(&mut self.balances, tx);
+    }
+
+    /// Checks that the sender of `tx` can afford it from their balance, less
+    /// the rewards `immature` says they may not spend yet.
+    fn check_balance(&self, tx: &Transaction, immature: &HashMap<&str, Amount>) -> std::result::Result<(), TxError> {
+        if tx.is_coinbase() {
+            return Ok(());
+        }
+        let balance = self.balances.get(tx.sender()).copied().unwrap_or_default();
+        let available = balance.saturating_sub(immature.get(tx.sender()).copied().unwrap_or_default());
+        let cost = tx.cost().map_err(|err| TxError::from_error(TxCheck::Fee, err))?;
+        if cost > available {
+            return Err(TxError::new(
+                TxCheck::Balance,
+                format!(
+                    "insufficient balance: {} has {} available but tried to spend {}",
+                    tx.sender(),
+                    available,
+                    cost
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// How far past the last pruned block the prune depth must reach before the
+/// chain is pruned again, so stores are not rewritten after every block.
+const PRUNE_BATCH: u64 = 100;
+
+const DEFAULT_SNAPSHOT_INTERVAL: u64 = 100;
+
+/// Replays the transactions of `blocks` to compute native-coin address
+/// balances.
+pub(crate) fn balances_of(blocks: &[Block]) -> HashMap<String, Amount> {
+    let mut balances = HashMap::new();
+    apply_balances(&mut balances, blocks);
+    balances
+}
+
+/// Validation rejects blocks whose senders overspend; should an unvalidated
+/// block do so anyway, the totals saturate.
+pub(crate) fn apply_balances(balances: &mut HashMap<String, Amount>, blocks: &[Block]) {
+    for tx in blocks.iter().flat_map(|block| block.transactions()) {
+        apply_balance(balances, tx);
+    }
+}
+
+fn apply_balance(balances: &mut HashMap<String, Amount>, tx: &Transaction) {
+    if !tx.is_coinbase() {
+        let sender = balances.entry(tx.sender().to_string()).or_default();
+        *sender = sender.saturating_sub(tx.cost().unwrap_or(Amount::MAX));
+    }
+    if tx.asset().is_none() {
+        for (receiver, amount) in tx.recipients() {
+            let receiver = balances.entry(receiver.to_string()).or_default();
+            *receiver = receiver.saturating_add(amount);
+        }
+    }
+}
+
+/// The coinbases among `blocks` that block `next` may not spend yet; see
+/// [`ChainParams::coinbase_maturity`].
+fn immature_rewards(blocks: &[Block], next: u64, maturity: u64) -> impl Iterator<Item = &Transaction> {
+    blocks
+        .iter()
+        .rev()
+        .take_while(move |block| block.index() > 0 && next < block.index() + maturity)
+        .filter_map(|block| block.transactions().first())
+        .filter(|tx| tx.is_coinbase())
+}
+
+/// The [`Blockchain::locator`] of a chain of `blocks`.
+pub(crate) fn locator_of(blocks: &[Block]) -> Vec<String> {
+    let mut locator = Vec::new();
+    let mut height = blocks.len() - 1;
+    let mut step = 1;
+    loop {
+        locator.push(blocks[height].hash().to_string());
+        if height == 0 {
+            return locator;
+        }
+        if locator.len() >= 10 {
+            step *= 2;
+        }
+        height = height.saturating_sub(step);
+    }
+}
+
+/// Median timestamp of the last `span` of `blocks`.
+pub(crate) fn median_time_past(blocks: &[Block], span: usize) -> u128 {
+    let start = blocks.len().saturating_sub(span.max(1));
+    median(blocks[start..].iter().map(Block::timestamp).collect())
+}
+
+/// Median of `timestamps`, or 0 if there are none.
+fn median(mut timestamps: Vec<u128>) -> u128 {
+    timestamps.sort_unstable();
+    timestamps.get(timestamps.len() / 2).copied().unwrap_or(0)
+}
+
+/// Sum of the expected hashes behind every block, saturating.
+pub(crate) fn total_work(blocks: &[Block]) -> u128 {
+    blocks.iter().fold(0u128, |work, block| work.saturating_add(block.work()))
+}
+
+impl Blockchain {
+    pub fn new() -> Result<Self> {
+        Blockchain::with_params(ChainParams::default())
+    }
+
+    pub fn with_params(params: ChainParams) -> Result<Self> {
+        let mut blockchain = Blockchain::from_parts(Vec::new(), params, ChainIndex::default());
+        blockchain.create_genesis_block()?;
+        Ok(blockchain)
+    }
+
+    /// Loads the chain held by `store`, or starts a new one (and writes its
+    /// genesis block) if the store is empty.
+    pub fn open_store(store: &mut dyn ChainStore) -> Result<Self> {
+        let params = store.params()?.unwrap_or_default();
+        if store.is_empty()? {
+            let blockchain = Blockchain::with_params(params)?;
+            blockchain.persist(store)?;
+            return Ok(blockchain);
+        }
+        let blocks = store.load_blocks()?;
+        let mut index = store.index()?.unwrap_or_default();
+        if index.catch_up(&blocks) {
+            store.save_index(&index)?;
+        }
+        let mut blockchain = Blockchain::from_parts(blocks, params, index);
+        if let Some(state) = store.state()? {
+            match blockchain.block_by_index(state.height) {
+                Some(block) if block.hash() == state.hash => blockchain.base = Some(state),
+                _ => {
+                    return Err(BlockchainError::Storage(format!(
+                        "saved state at height {} does not match the stored blocks",
+                        state.height
+                    )));
+                }
+            }
+        }
+        // A snapshot of a block since rolled back is of no use.
+        blockchain.snapshot = store
+            .snapshot()?
+            .filter(|state| blockchain.block_by_index(state.height).is_some_and(|block| block.hash() == state.hash));
+        Ok(blockchain)
+    }
+
+    /// Like [`Blockchain::open_store`], but a new chain starts from `params`
+    /// and a store holding a chain with different parameters is refused.
+    pub fn open_store_with(store: &mut dyn ChainStore, params: ChainParams) -> Result<Self> {
+        if !store.is_empty()? && store.params()?.unwrap_or_default() != params {
+            return Err(BlockchainError::Validation(format!(
+                "store holds a chain with different parameters than {}",
+                params.chain_id
+            )));
+        }
+        store.set_params(&params)?;
+        Blockchain::open_store(store)
+    }
+
+    /// Wraps already-built blocks, e.g. ones read from an export, without
+    /// validating them.
+    pub fn from_blocks(blocks: Vec<Block>, params: ChainParams) -> Result<Self> {
+        if blocks.is_empty() {
+            return Err(BlockchainError::Validation("chain has no genesis block".to_string()));
+        }
+        let index = ChainIndex::build(&blocks);
+        Ok(Blockchain::from_parts(blocks, params, index))
+    }
+
+    fn from_parts(blocks: Vec<Block>, params: ChainParams, index: ChainIndex) -> Self {
+        Blockchain {
+            index,
+            blocks,
+            params,
+            miner: Miner::default(),
+            side_blocks: HashMap::new(),
+            events: EventBus::new(),
+            metrics: Metrics::new(),
+            checkpoints: BTreeMap::new(),
+            base: None,
+            prune_depth: None,
+            snapshot: None,
+            snapshot_interval: DEFAULT_SNAPSHOT_INTERVAL,
+            validated: Mutex::new(None),
+            signatures: SignatureCache::default(),
+        }
+    }
+
+    /// Brings `store` in line with this chain: blocks the store already has
+    /// are kept, any that were replaced are truncated, and new ones appended.
+    pub fn persist(&self, store: &mut dyn ChainStore) -> Result<()> {
+        let stored = store.len()?;
+        if stored == 0 {
+            store.set_params(&self.params)?;
+        }
+        let mut keep = stored.min(self.blocks.len() as u64);
+        while keep > 0 {
+            let ours = &self.blocks[keep as usize - 1];
+            match store.block_by_height(keep - 1)? {
+                Some(theirs) if theirs.hash() == ours.hash() => break,
+                _ => keep -= 1,
+            }
+        }
+        if keep < stored {
+            store.truncate(keep)?;
+        }
+        for block in self.blocks.iter().skip(keep as usize) {
+            store.append_block(block)?;
+        }
+        if keep < stored || keep < self.blocks.len() as u64 {
+            store.save_index(&self.index)?;
+        }
+        // The snapshot is taken as its block is added, so it is new to the
+        // store if that block is.
+        if let Some(snapshot) = &self.snapshot
+            && snapshot.height >= keep
+        {
+            store.save_snapshot(snapshot)?;
+        }
+        if let Some(base) = &self.base
+            && store.state()?.is_none_or(|state| state.hash != base.hash)
+        {
+            store.prune(base)?;
+        }
+        Ok(())
+    }
+
+    fn create_genesis_block(&mut self) -> Result<()> {
+        let genesis_block = self.seal_block(
+            0,
+            self.params.genesis_timestamp,
+            self.genesis_transactions(),
+            self.genesis_parent_hash(),
+        )?;
+        self.index.connect(&genesis_block);
+        self.blocks.push(genesis_block);
+        Ok(())
+    }
+
+    /// Builds the next block on the tip and has the consensus engine seal it.
+    fn seal_block(
+        &self,
+        index: u64,
+        timestamp: u128,
+        transactions: Vec<Transaction>,
+        previous_hash: String,
+    ) -> Result<Block> {
+        let header = self.next_header(index, timestamp, &transactions, previous_hash);
+        self.sealer(index).seal(header, transactions)
+    }
+
+    /// What seals the block at `index`, apart from the chain.
+    fn sealer(&self, index: u64) -> Sealer {
+        let mut miner = self.miner.clone().with_metrics(self.metrics.clone());
+        // The genesis block must keep the timestamp its parameters fix.
+        if index > 0 {
+            miner = miner.with_timestamp_refresh(TIMESTAMP_REFRESH);
+        }
+        Sealer {
+            consensus: self.consensus(),
+            miner,
+        }
+    }
+
+    /// The unsealed header of the next block on the tip.
+    fn next_header(
+        &self,
+        index: u64,
+        timestamp: u128,
+        transactions: &[Transaction],
+        previous_hash: String,
+    ) -> BlockHeader {
+        let bits = self.consensus().next_bits(&self.blocks);
+        BlockHeader::new(index, timestamp, merkle::merkle_root(transactions), previous_hash, bits)
+            .with_algorithm(self.params.hash_algorithm)
+            .with_version(self.params.block_version(index))
+    }
+
+    /// Coinbase-style transactions crediting the genesis allocations.
+    fn genesis_transactions(&self) -> Vec<Transaction> {
+        self.params
+            .genesis_allocations
+            .iter()
+            .map(|(address, &amount)| Transaction::coinbase(address.as_str(), amount, 0))
+            .collect()
+    }
+
+    /// The genesis block's previous hash commits to the chain ID, so networks
+    /// with otherwise identical genesis settings still have distinct chains.
+    fn genesis_parent_hash(&self) -> String {
+        format!("{:x}", Sha256::digest(self.params.chain_id.as_bytes()))
+    }
+
+    pub fn blocks(&self) -> &[Block] {
+        &self.blocks
+    }
+
+    pub fn params(&self) -> &ChainParams {
+        &self.params
+    }
+
+    pub fn miner(&self) -> &Miner {
+        &self.miner
+    }
+
+    /// Replaces the miner used for new blocks, e.g. to change its thread count.
+    pub fn set_miner(&mut self, miner: Miner) {
+        self.miner = miner;
+    }
+
+    /// Receives an event whenever a block is mined or received, a transaction
+    /// is queued, or the chain reorganizes.
+    pub fn subscribe(&self) -> Receiver<NodeEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn event_bus(&self) -> &EventBus {
+        &self.events
+    }
+
+    /// Runs `callback` with each block that extends the main chain, mined
+    /// here or received. Blocks a reorganization connects go to
+    /// [`on_reorg`](Self::on_reorg) callbacks instead. As with
+    /// [`EventBus::on_event`], the callback runs with the chain locked.
+    pub fn on_block_added(&self, callback: impl Fn(&Block) + Send + Sync + 'static) -> Subscription {
+        self.events.on_event(move |event| {
+            if let NodeEvent::BlockMined(block) | NodeEvent::BlockReceived(block) = event {
+                callback(block);
+            }
+        })
+    }
+
+    /// Runs `callback` with the blocks rolled back and then connected
+    /// whenever the main chain switches to a branch with more work.
+    pub fn on_reorg(&self, callback: impl Fn(&[Block], &[Block]) + Send + Sync + 'static) -> Subscription {
+        self.events.on_event(move |event| {
+            if let NodeEvent::ChainReorged { rolled_back, connected } = event {
+                callback(rolled_back, connected);
+            }
+        })
+    }
+
+    /// Runs `callback` with the ID and contents of each transaction accepted
+    /// into a mempool.
+    pub fn on_tx_accepted(&self, callback: impl Fn(&str, &Transaction) + Send + Sync + 'static) -> Subscription {
+        self.events.on_event(move |event| {
+            if let NodeEvent::TransactionQueued { txid, transaction } = event {
+                callback(txid, transaction);
+            }
+        })
+    }
+
+    /// Runs `callback` with the ID of each replacement accepted into a
+    /// mempool, the ID of the transaction it replaced, and the replacement.
+    pub fn on_tx_replaced(&self, callback: impl Fn(&str, &str, &Transaction) + Send + Sync + 'static) -> Subscription {
+        self.events.on_event(move |event| {
+            if let NodeEvent::TransactionReplaced { txid, replaced, transaction } = event {
+                callback(txid, replaced, transaction);
+            }
+        })
+    }
+
+    /// Replaces the event bus, e.g. to keep subscribers when swapping in a
+    /// different chain.
+    pub fn set_event_bus(&mut self, events: EventBus) {
+        self.events = events;
+    }
+
+    /// Signatures this chain has found valid, which checks of transactions
+    /// and blocks look up before verifying any.
+    pub fn signature_cache(&self) -> &SignatureCache {
+        &self.signatures
+    }
+
+    /// Counters updated as this chain mines and validates blocks.
+    pub fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// Replaces the metrics, e.g. to keep them when swapping in a different
+    /// chain.
+    pub fn set_metrics(&mut self, metrics: Metrics) {
+        self.metrics = metrics;
+    }
+
+    /// Trusts the blocks up to the highest checkpoint the main chain reaches:
+    /// validation only checks that they hash and link correctly, and the
+    /// chain never reorganizes below it. A block at a checkpoint's height
+    /// must have its hash.
+    pub fn set_checkpoints(&mut self, checkpoints: BTreeMap<u64, String>) {
+        // Blocks trusted under the old checkpoints may not have been fully checked.
+        self.clear_validation_cache();
+        self.checkpoints = checkpoints;
+    }
+
+    pub fn checkpoints(&self) -> &BTreeMap<u64, String> {
+        &self.checkpoints
+    }
+
+    /// Height of the highest checkpoint at or below the tip.
+    fn checkpoint_height(&self) -> Option<u64> {
+        let tip = self.latest_block().index();
+        self.checkpoints.range(..=tip).next_back().map(|(&height, _)| height)
+    }
+
+    /// Refuses to switch to a chain that forks off below the last checkpoint,
+    /// i.e. shares only our first `common` blocks.
+    fn check_reorg_depth(&self, common: usize) -> Result<()> {
+        match (self.checkpoint_height(), self.pruned_height()) {
+            (Some(height), _) if (common as u64) <= height => Err(BlockchainError::Validation(format!(
+                "chain forks below the checkpoint at height {}",
+                height
+            ))),
+            (_, Some(height)) if (common as u64) <= height => Err(BlockchainError::Validation(format!(
+                "chain forks below the pruned block at height {}",
+                height
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// Discards the transactions of blocks more than `depth` below the tip,
+    /// keeping their headers and the state they leave behind. A pruned chain
+    /// can no longer reorganize below that state or serve the discarded
+    /// blocks. Refused under proof of stake, which elects validators by
+    /// replaying the whole chain.
+    pub fn set_prune_depth(&mut self, depth: Option<u64>) -> Result<()> {
+        if depth.is_some() && matches!(self.params.consensus, ConsensusKind::ProofOfStake { .. }) {
+            return Err(BlockchainError::Validation(
+                "a proof-of-stake chain cannot be pruned".to_string(),
+            ));
+        }
+        self.prune_depth = depth;
+        self.prune()
+    }
+
+    pub fn prune_depth(&self) -> Option<u64> {
+        self.prune_depth
+    }
+
+    /// Height of the last pruned block, if any.
+    pub fn pruned_height(&self) -> Option<u64> {
+        self.base.as_ref().map(|base| base.height)
+    }
+
+    /// Prunes the blocks more than the prune depth below the tip, if that
+    /// reaches at least [`PRUNE_BATCH`] blocks past the last pruned one.
+    fn prune(&mut self) -> Result<()> {
+        let Some(height) = self.prune_depth.and_then(|depth| self.height().checked_sub(depth)) else {
+            return Ok(());
+        };
+        if self.pruned_height().is_some_and(|pruned| height < pruned + PRUNE_BATCH) {
+            return Ok(());
+        }
+        let through = &self.blocks[..=height as usize];
+        let state = ChainState {
+            height,
+            hash: through[height as usize].hash().to_string(),
+            utxos: self.utxos_through(through)?,
+            balances: self.balances_through(through),
+        };
+        for block in &mut self.blocks[..=height as usize] {
+            block.prune();
+        }
+        debug!(height, "pruned chain");
+        self.base = Some(state);
+        Ok(())
+    }
+
+    /// Snapshots the state every `interval` blocks (100 by default), which
+    /// [`Blockchain::persist`] saves and [`Blockchain::open_store`] restores.
+    /// 0 disables snapshots.
+    pub fn set_snapshot_interval(&mut self, interval: u64) {
+        self.snapshot_interval = interval;
+    }
+
+    /// Height of the snapshot balances are replayed from, if any.
+    pub fn snapshot_height(&self) -> Option<u64> {
+        self.snapshot.as_ref().map(|snapshot| snapshot.height)
+    }
+
+    /// Snapshots the state at the tip if it is at least the snapshot interval
+    /// past the last snapshot (or the pruned block).
+    fn take_snapshot(&mut self) -> Result<()> {
+        let since = self.replay_start(&self.blocks).map_or(0, |state| state.height);
+        if self.snapshot_interval == 0 || self.height() < since + self.snapshot_interval {
+            return Ok(());
+        }
+        let snapshot = ChainState {
+            height: self.height(),
+            hash: self.latest_block().hash().to_string(),
+            utxos: self.utxo_set()?,
+            balances: self.balances(),
+        };
+        debug!(height = snapshot.height, "took snapshot");
+        self.snapshot = Some(snapshot);
+        Ok(())
+    }
+
+    /// Prunes and snapshots the chain as configured, after blocks were added.
+    fn update_state(&mut self) {
+        if let Err(err) = self.prune().and_then(|()| self.take_snapshot()) {
+            warn!(%err, "failed to update the chain state");
+        }
+    }
+
+    /// Counts `err` in the metrics if it means something was invalid.
+    fn count_failure(&self, err: &BlockchainError) {
+        if matches!(err, BlockchainError::Validation(_)) {
+            self.metrics.add_validation_failure();
+        }
+    }
+
+    /// Compact target required of the next block appended to the tip.
+    pub fn next_bits(&self) -> u32 {
+        self.consensus().next_bits(&self.blocks)
+    }
+
+    /// The consensus engine selected by the chain parameters.
+    pub fn consensus(&self) -> Box<dyn Consensus> {
+        self.params.consensus.engine(&self.params)
+    }
+
+    /// Address that must produce the next block, if the consensus engine
+    /// restricts it (proof of stake).
+    pub fn next_producer(&self) -> Result<Option<String>> {
+        self.consensus().next_producer(&self.blocks)
+    }
+
+    pub fn latest_block(&self) -> &Block {
+        self.blocks.last().expect("chain always contains a genesis block")
+    }
+
+    /// Index of the tip; 0 for a chain holding only its genesis block.
+    pub fn height(&self) -> u64 {
+        self.latest_block().index()
+    }
+
+    /// Main-chain blocks from genesis to tip.
+    pub fn iter(&self) -> std::slice::Iter<'_, Block> {
+        self.blocks.iter()
+    }
+
+    pub fn block_by_index(&self, index: u64) -> Option<&Block> {
+        usize::try_from(index).ok().and_then(|index| self.blocks.get(index))
+    }
+
+    /// The main-chain block with this hash.
+    pub fn block_by_hash(&self, hash: &str) -> Option<&Block> {
+        self.main_chain_height_of(hash).map(|height| &self.blocks[height])
+    }
+
+    /// Hashes of main-chain blocks from the tip back to genesis, dense near
+    /// the tip and exponentially sparser further back, so a peer can find
+    /// where its chain diverges from ours in one round trip.
+    pub fn locator(&self) -> Vec<String> {
+        locator_of(&self.blocks)
+    }
+
+    /// Up to `max` headers of the main-chain blocks that follow the first
+    /// block of `locator` we have, or from genesis if we have none of them.
+    pub fn headers_after(&self, locator: &[String], max: usize) -> Vec<BlockHeader> {
+        let start = locator
+            .iter()
+            .find_map(|hash| self.main_chain_height_of(hash))
+            .map_or(0, |height| height + 1);
+        self.blocks.iter().skip(start).take(max).map(|block| block.header().clone()).collect()
+    }
+
+    /// Total work of the main chain up to and including the block with this hash.
+    pub fn work_through(&self, hash: &str) -> Option<u128> {
+        self.main_chain_height_of(hash)
+            .map(|height| total_work(&self.blocks[..=height]))
+    }
+
+    /// The confirmed transaction with this ID and the block containing it.
+    pub fn get_transaction(&self, txid: &str) -> Option<(&Block, &Transaction)> {
+        let location = self.index.transaction(txid)?;
+        let block = &self.blocks[location.height as usize];
+        block.transactions().get(location.position).map(|tx| (block, tx))
+    }
+
+    /// A confirmed transaction with the proof that its block includes it,
+    /// for a light client that only has the block's header.
+    pub fn transaction_proof(&self, txid: &str) -> Option<TxProof> {
+        let (block, transaction) = self.get_transaction(txid)?;
+        Some(TxProof {
+            block_hash: block.hash().to_string(),
+            transaction: transaction.clone(),
+            proof: block.merkle_proof(self.index.transaction(txid)?.position)?,
+        })
+    }
+
+    /// Block, transaction and address lookups over the main chain.
+    pub fn index(&self) -> &ChainIndex {
+        &self.index
+    }
+
+    /// Rebuilds the index from the blocks and saves it to `store`, e.g. if
+    /// the saved one was damaged. The transactions of pruned blocks are
+    /// gone, so they drop out of a rebuilt index.
+    pub fn reindex(&mut self, store: &mut dyn ChainStore) -> Result<()> {
+        self.index = ChainIndex::build(&self.blocks);
+        store.save_index(&self.index)
+    }
+
+    /// Confirmed transactions sending to or from `address`, oldest first,
+    /// each with the block that contains it. Transactions in pruned blocks
+    /// are left out.
+    pub fn transactions_for_address<'a>(
+        &'a self,
+        address: &'a str,
+    ) -> impl Iterator<Item = (&'a Block, &'a Transaction)> + 'a {
+        self.index.address(address).iter().filter_map(|location| {
+            let block = &self.blocks[location.height as usize];
+            block.transactions().get(location.position).map(|tx| (block, tx))
+        })
+    }
+
+    /// Mines a block paying the block reward plus the transactions' fees to
+    /// `miner`, followed by `transactions`. Under proof of stake `miner` must
+    /// be the elected validator.
+    pub fn add_block(&mut self, miner: &str, transactions: Vec<Transaction>) -> Result<()> {
+        let template = self.template_for(miner, transactions)?;
+        let new_block = self.sealer(template.header().index()).seal_template(template)?;
+        self.push_mined(new_block);
+        Ok(())
+    }
+
+    /// Appends a block sealed from a template built on the current tip.
+    fn push_mined(&mut self, block: Block) {
+        debug!(index = block.index(), hash = %block.hash(), "mined block");
+        self.index.connect(&block);
+        self.blocks.push(block.clone());
+        self.events.publish(NodeEvent::BlockMined(block));
+        self.update_state();
+    }
+
+    /// What [`Blockchain::mine_pending`] would mine, for a miner elsewhere to
+    /// find the nonce of and hand back to [`Blockchain::accept_block`]. Only
+    /// proof-of-work chains have use for templates.
+    pub fn block_template(&self, mempool: &Mempool, max: usize, miner: &str) -> Result<BlockTemplate> {
+        if self.params.consensus != ConsensusKind::ProofOfWork {
+            return Err(BlockchainError::Mining("block templates need a proof-of-work chain".to_string()));
+        }
+        self.template_for(miner, self.pending_batch(mempool, max, miner)?)
+    }
+
+    /// The next block paying `miner` and holding `transactions`, checked
+    /// against the block limits but not yet sealed.
+    fn template_for(&self, miner: &str, transactions: Vec<Transaction>) -> Result<BlockTemplate> {
+        address::validate(miner, self.params.address_version)?;
+        if let Some(producer) = self.next_producer()?
+            && producer != miner
+        {
+            return Err(BlockchainError::Validation(format!(
+                "{} is not the validator for block #{} ({} is)",
+                miner,
+                self.blocks.len(),
+                producer
+            )));
+        }
+        let previous_block = self.latest_block();
+        let new_index = previous_block.index() + 1;
+        let reward = self
+            .coinbase_value(new_index, &transactions)
+            .ok_or_else(|| BlockchainError::Validation("block reward plus fees overflows".to_string()))?;
+        let mut block_transactions = Vec::with_capacity(transactions.len() + 1);
+        block_transactions.push(Transaction::coinbase(miner, reward, new_index));
+        block_transactions.extend(transactions);
+        let previous_hash = previous_block.hash().to_string();
+        let timestamp = self.next_timestamp()?;
+
+        // Refuse before mining rather than produce a block nobody accepts.
+        let candidate = self.unsealed_block(new_index, timestamp, block_transactions);
+        let mut violations = Vec::new();
+        self.check_limits(&candidate, &mut violations);
+        if let Some(violation) = violations.into_iter().next() {
+            return Err(BlockchainError::Validation(violation.to_string()));
+        }
+
+        let transactions = candidate.transactions().to_vec();
+        let header = self.next_header(new_index, timestamp, &transactions, previous_hash);
+        Ok(BlockTemplate::new(header, transactions))
+    }
+
+    /// Timestamp for the next block mined: now by the miner's clock, unless
+    /// that is not after the median of recent blocks.
+    fn next_timestamp(&self) -> Result<u128> {
+        let now = self.miner.clock().now_millis()?;
+        // Stay after the median of recent blocks even if they were mined
+        // within the same millisecond or our clock is slightly behind theirs.
+        let timestamp = now.max(self.median_time_past(&self.blocks) + 1);
+        if timestamp > now + u128::from(self.params.max_future_block_time_ms) {
+            return Err(BlockchainError::Mining(
+                "the clock is too far behind the chain's recent blocks to mine the next one".to_string(),
+            ));
+        }
+        Ok(timestamp)
+    }
+
+    /// A block with placeholder hashes and the longest possible nonce, so its
+    /// size is an upper bound on the size of the same block once sealed.
+    fn unsealed_block(&self, index: u64, timestamp: u128, transactions: Vec<Transaction>) -> Block {
+        let placeholder = "0".repeat(64);
+        let mut header = BlockHeader::new(index, timestamp, placeholder.clone(), placeholder.clone(), self.next_bits())
+            .with_algorithm(self.params.hash_algorithm)
+            .with_version(self.params.block_version(index));
+        header.set_nonce(u64::MAX);
+        Block::from_parts(header, placeholder, transactions)
+    }
+
+    /// Bytes left for transactions in the next block paying `miner`, after
+    /// the header, the coinbase (at its largest amount) and one separator per
+    /// transaction.
+    fn transaction_budget(&self, miner: &str) -> Result<usize> {
+        let coinbase = Transaction::coinbase(miner, Amount::MAX, self.blocks.len() as u64);
+        let empty = self.unsealed_block(self.blocks.len() as u64, self.miner.clock().now_millis()?, vec![coinbase]);
+        Ok(self
+            .params
+            .max_block_size
+            .saturating_sub(empty.size() + self.params.max_block_transactions))
+    }
+
+    /// What a coinbase may claim for the block at `index` holding
+    /// `transactions`: the block reward at that height plus their fees, or
+    /// `None` if that overflows.
+    fn coinbase_value(&self, index: u64, transactions: &[Transaction]) -> Option<Amount> {
+        let reward = self.params.block_reward_at(index);
+        Amount::checked_sum(std::iter::once(reward).chain(transactions.iter().map(Transaction::fee)))
+    }
+
+    /// Replays every confirmed transaction to compute address balances.
+    pub fn balances(&self) -> HashMap<String, Amount> {
+        self.balances_through(&self.blocks)
+    }
+
+    /// Balances after `blocks`, which start like the main chain, replayed
+    /// from the latest saved state they include.
+    fn balances_through(&self, blocks: &[Block]) -> HashMap<String, Amount> {
+        match self.replay_start(blocks) {
+            Some(state) => {
+                let mut balances = state.balances.clone();
+                apply_balances(&mut balances, &blocks[state.height as usize + 1..]);
+                balances
+            }
+            None => balances_of(blocks),
+        }
+    }
+
+    /// The later of the pruned state and the snapshot, if taken at a block
+    /// in `blocks`.
+    fn replay_start(&self, blocks: &[Block]) -> Option<&ChainState> {
+        [self.base.as_ref(), self.snapshot.as_ref()]
+            .into_iter()
+            .flatten()
+            .filter(|state| blocks.get(state.height as usize).is_some_and(|block| block.hash() == state.hash))
+            .max_by_key(|state| state.height)
+    }
+
+    /// The balance `address` had as of the last pruned block, or 0 if the
+    /// chain was never pruned.
+    pub fn pruned_balance_of(&self, address: &str) -> Amount {
+        self.base.as_ref().and_then(|state| state.balances.get(address)).copied().unwrap_or_default()
+    }
+
+    pub fn balance_of(&self, address: &str) -> Amount {
+        self.balances().get(address).copied().unwrap_or_default()
+    }
+
+    /// The coinbase rewards `address` was paid that the next block may not
+    /// spend yet; see [`ChainParams::coinbase_maturity`].
+    pub fn immature_balance_of(&self, address: &str) -> Amount {
+        let rewards = immature_rewards(&self.blocks, self.height() + 1, self.params.coinbase_maturity)
+            .filter(|tx| tx.receiver() == address)
+            .map(Transaction::amount);
+        Amount::checked_sum(rewards).unwrap_or(Amount::MAX)
+    }
+
+    /// The balance of `address` less its [immature](Blockchain::immature_balance_of)
+    /// rewards: what its transactions may spend.
+    pub fn spendable_balance_of(&self, address: &str) -> Amount {
+        self.balance_of(address).saturating_sub(self.immature_balance_of(address))
+    }
+
+    /// How much of each asset `address` holds at the tip.
+    pub fn asset_balances(&self, address: &str) -> Result<BTreeMap<String, Amount>> {
+        Ok(self.utxo_set()?.asset_balances(address))
+    }
+
+    /// Queues a transaction after checking it is not already confirmed or
+    /// pending, its addresses are well formed, it uses the sender's next
+    /// sequence number, and the sender can afford it, taking into account
+    /// what they are already spending in the mempool.
+    pub fn submit_transaction(&self, mempool: &mut Mempool, tx: Transaction) -> Result<()> {
+        self.queue_transaction(mempool, tx, self.height())
+            .inspect_err(|err| self.count_failure(err))
+    }
+
+    /// Runs every check [`Blockchain::submit_transaction`] would on `tx`,
+    /// against the chain and the transactions pending in `mempool`, without
+    /// changing either, so a wallet can tell whether a transaction will be
+    /// accepted before broadcasting it.
+    pub fn validate_transaction(&self, mempool: &Mempool, tx: &Transaction) -> std::result::Result<(), TxError> {
+        self.check_pending(mempool, tx)?;
+        mempool.check_room(tx).map_err(|err| TxError::from_error(TxCheck::Fee, err))
+    }
+
+    /// Like [`Blockchain::submit_transaction`], for a transaction saved by an
+    /// earlier run that was first queued at height `queued_at`, so it still
+    /// expires when it would have.
+    pub fn restore_transaction(&self, mempool: &mut Mempool, tx: Transaction, queued_at: u64) -> Result<()> {
+        let expiry = mempool.limits().expiry_blocks;
+        if expiry > 0 && self.height().saturating_sub(queued_at) >= expiry {
+            return Err(BlockchainError::Validation(format!(
+                "transaction {} expired while pending",
+                tx.hash()
+            )));
+        }
+        self.queue_transaction(mempool, tx, queued_at.min(self.height()))
+    }
+
+    fn queue_transaction(&self, mempool: &mut Mempool, tx: Transaction, queued_at: u64) -> Result<()> {
+        mempool.expire(self.height());
+        self.check_pending(mempool, &tx)?;
+        let txid = tx.hash();
+        match mempool.replaceable_by(&tx).map(Transaction::hash) {
+            Some(replaced) => {
+                mempool.replace(&replaced, tx.clone(), queued_at)?;
+                debug!(%txid, %replaced, "replaced pending transaction");
+                self.events.publish(NodeEvent::TransactionReplaced {
+                    txid,
+                    replaced,
+                    transaction: tx,
+                });
+            }
+            None => {
+                mempool.insert(tx.clone(), queued_at)?;
+                debug!(%txid, "queued transaction");
+                self.events.publish(NodeEvent::TransactionQueued { txid, transaction: tx });
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that `tx` may join the transactions pending in `mempool`, or
+    /// replace the one there with its sequence number; see
+    /// [`mempool::check_replacement`].
+    fn check_pending(&self, mempool: &Mempool, tx: &Transaction) -> std::result::Result<(), TxError> {
+        if tx.is_coinbase() {
+            return Err(TxError::new(TxCheck::Coinbase, "coinbase transactions can only be created by mining"));
+        }
+        if tx.height().is_some() {
+            return Err(TxError::new(TxCheck::Coinbase, "only coinbase transactions commit to a height"));
+        }
+        let txid = tx.hash();
+        if self.get_transaction(&txid).is_some() {
+            return Err(TxError::new(
+                TxCheck::Duplicate,
+                format!("transaction {} is already in the chain", txid),
+            ));
+        }
+        if mempool.contains(&txid) {
+            return Err(TxError::new(TxCheck::Duplicate, format!("transaction {} is already pending", txid)));
+        }
+        for address in std::iter::once(tx.sender()).chain(tx.recipients().map(|(receiver, _)| receiver)) {
+            address::validate(address, self.params.address_version)
+                .map_err(|err| TxError::from_error(TxCheck::Address, err))?;
+        }
+        if tx.size() >= self.params.max_block_size {
+            return Err(TxError::new(
+                TxCheck::Size,
+                format!(
+                    "transaction of {} bytes can never fit in a block of at most {} bytes",
+                    tx.size(),
+                    self.params.max_block_size
+                ),
+            ));
+        }
+        tx.check_memo().map_err(|err| TxError::from_error(TxCheck::Memo, err))?;
+        let policy = mempool.limits();
+        policy.check_fee_rate(tx).map_err(|err| TxError::from_error(TxCheck::Fee, err))?;
+        policy.check_dust(tx).map_err(|err| TxError::from_error(TxCheck::Dust, err))?;
+        let state = |err| TxError::from_error(TxCheck::State, err);
+        let replacing = mempool.replaceable_by(tx);
+        if let Some(original) = replacing {
+            mempool::check_replacement(original, tx).map_err(|err| TxError::from_error(TxCheck::Fee, err))?;
+        }
+        if tx.is_sequenced() {
+            let expected = match replacing {
+                Some(original) => original.sequence(),
+                None => self.next_sequence(mempool, tx.sender()).map_err(state)?,
+            };
+            if tx.sequence() != expected {
+                return Err(TxError::new(
+                    TxCheck::Sequence,
+                    format!(
+                        "transaction from {} has sequence {}, expected {}",
+                        tx.sender(),
+                        tx.sequence(),
+                        expected
+                    ),
+                ));
+            }
+        }
+        if let Some(asset) = tx.asset() {
+            let replaced = replacing
+                .filter(|original| original.asset() == Some(asset) && !original.is_issue())
+                .map_or(Amount::ZERO, Transaction::amount);
+            let pending = mempool.pending_asset_outgoing(tx.sender(), asset).saturating_sub(replaced);
+            self.utxo_set().map_err(state)?.check_asset(tx, pending)?;
+        }
+        if !tx.inputs().is_empty() {
+            let utxos = self.utxo_set().map_err(state)?;
+            utxos.check_transaction_with(tx, &self.params.chain_id, &self.signatures)?;
+            utxos.check_maturity(tx, self.height() + 1, self.params.coinbase_maturity)?;
+            if let Some(input) = tx.inputs().iter().find(|input| mempool.is_spent(input)) {
+                return Err(TxError::new(
+                    TxCheck::Inputs,
+                    format!(
+                        "output {}:{} is already spent by a pending transaction",
+                        input.txid, input.vout
+                    ),
+                ));
+            }
+        }
+        let replaced = replacing.map_or(Amount::ZERO, |original| original.cost().unwrap_or(Amount::MAX));
+        let available = self
+            .spendable_balance_of(tx.sender())
+            .saturating_sub(mempool.pending_outgoing(tx.sender()).saturating_sub(replaced));
+        let cost = tx.cost().map_err(|err| TxError::from_error(TxCheck::Fee, err))?;
+        if cost > available {
+            return Err(TxError::new(
+                TxCheck::Balance,
+                format!(
+                    "insufficient balance: {} has {} available but tried to spend {}",
+                    tx.sender(),
+                    available,
+                    cost
+                ),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Drops the pending transactions the chain no longer allows, such as
+    /// ones a block from a peer confirmed or conflicts with, and returns
+    /// them.
+    pub fn drop_invalid_pending(&self, mempool: &mut Mempool) -> Vec<Transaction> {
+        mempool.expire(self.height());
+        let mut valid = Mempool::with_limits(*mempool.limits());
+        let mut dropped = Vec::new();
+        for (tx, queued_at) in mempool.entries() {
+            let kept = self
+                .check_pending(&valid, tx)
+                .map_err(BlockchainError::from)
+                .and_then(|()| valid.insert(tx.clone(), queued_at));
+            if let Err(err) = kept {
+                debug!(txid = %tx.hash(), %err, "dropping pending transaction");
+                dropped.push(tx.clone());
+            }
+        }
+        mempool.remove_batch(&dropped);
+        dropped
+    }
+
+    /// The sequence number `sender`'s next account-model transaction must
+    /// use, counting the ones already pending in `mempool`.
+    pub fn next_sequence(&self, mempool: &Mempool, sender: &str) -> Result<u64> {
+        Ok(self.utxo_set()?.next_sequence(sender) + mempool.pending_sequenced(sender))
+    }
+
+    /// Unspent outputs of the main chain.
+    pub fn utxo_set(&self) -> Result<UtxoSet> {
+        self.utxos_through(&self.blocks)
+    }
+
+    /// Unspent outputs after `blocks`, which start like the main chain,
+    /// replayed from the latest saved state they include.
+    fn utxos_through(&self, blocks: &[Block]) -> Result<UtxoSet> {
+        match self.replay_start(blocks) {
+            Some(state) => {
+                let mut utxos = state.utxos.clone();
+                for block in &blocks[state.height as usize + 1..] {
+                    utxos.apply_block(block, &self.params.chain_id)?;
+                }
+                Ok(utxos)
+            }
+            None => UtxoSet::from_blocks(blocks, &self.params.chain_id),
+        }
+    }
+
+    /// The ledger validation checks the block after `blocks` against.
+    fn ledger_through(&self, blocks: &[Block]) -> Result<Ledger> {
+        Ok(Ledger {
+            utxos: self.utxos_through(blocks)?,
+            balances: self.balances_through(blocks),
+        })
+    }
+
+    /// Builds a UTXO-style transaction by selecting the sender's largest
+    /// unspent outputs (skipping any already spent in the mempool, any
+    /// locked by a script, and rewards too recent to spend) until they cover
+    /// `amount + fee`, returning the excess as change.
+    pub fn build_utxo_transaction(
+        &self,
+        mempool: &Mempool,
+        sender: &str,
+        receiver: &str,
+        amount: Amount,
+        fee: Amount,
+    ) -> Result<Transaction> {
+        self.build_spending(mempool, sender, receiver, amount, fee, |_| false)
+    }
+
+    /// Like [`Blockchain::build_utxo_transaction`], but also selects outputs
+    /// locked to `key` with [`Script::pay_to_key`], whose inputs must then
+    /// be signed, e.g. with [`UnlockedWallet::sign`](crate::UnlockedWallet::sign).
+    pub fn build_transaction_for_key(
+        &self,
+        mempool: &Mempool,
+        sender: &str,
+        key: &VerifyingKey,
+        receiver: &str,
+        amount: Amount,
+        fee: Amount,
+    ) -> Result<Transaction> {
+        let lock = Script::pay_to_key(key);
+        self.build_spending(mempool, sender, receiver, amount, fee, |script| *script == lock)
+    }
+
+    /// Like [`Blockchain::build_utxo_transaction`], but also selects outputs
+    /// locked to any key with [`Script::pay_to_key`], for a builder without
+    /// the keys, such as an online machine preparing a transaction for an
+    /// offline one to sign; see [`TransactionFile`](crate::offline::TransactionFile).
+    pub fn build_unsigned_transaction(
+        &self,
+        mempool: &Mempool,
+        sender: &str,
+        receiver: &str,
+        amount: Amount,
+        fee: Amount,
+    ) -> Result<Transaction> {
+        self.build_spending(mempool, sender, receiver, amount, fee, Script::is_pay_to_key)
+    }
+
+    /// Builds a transaction spending the sender's unlocked outputs and the
+    /// locked ones `unlockable` accepts. Change below the mempool's dust
+    /// limit goes to the miner instead.
+    fn build_spending(
+        &self,
+        mempool: &Mempool,
+        sender: &str,
+        receiver: &str,
+        amount: Amount,
+        fee: Amount,
+        unlockable: impl Fn(&Script) -> bool,
+    ) -> Result<Transaction> {
+        let needed = amount.try_add(fee, "amount plus fee")?;
+        let utxos = self.utxo_set()?;
+        let mut candidates: Vec<_> = utxos
+            .outputs_for(sender)
+            .filter(|(outpoint, output)| output.script.as_ref().is_none_or(&unlockable) && !mempool.is_spent(outpoint))
+            .filter(|(outpoint, _)| utxos.is_mature(outpoint, self.height() + 1, self.params.coinbase_maturity))
+            .collect();
+        candidates.sort_by(|(a_point, a), (b_point, b)| {
+            b.amount
+                .cmp(&a.amount)
+                .then_with(|| (&a_point.txid, a_point.vout).cmp(&(&b_point.txid, b_point.vout)))
+        });
+
+        let mut inputs = Vec::new();
+        let mut total = Amount::ZERO;
+        for (outpoint, output) in candidates {
+            if total >= needed {
+                break;
+            }
+            inputs.push(outpoint.clone());
+            total = total.try_add(output.amount, "unspent outputs")?;
+        }
+        if total < needed {
+            return Err(BlockchainError::Validation(format!(
+                "insufficient unspent outputs: {} has {} spendable but tried to spend {}",
+                sender, total, needed
+            )));
+        }
+        let mut change = total.saturating_sub(needed);
+        let mut fee = fee;
+        if change < mempool.limits().dust_limit {
+            fee = fee.saturating_add(change);
+            change = Amount::ZERO;
+        }
+        Ok(Transaction::spending(sender, receiver, amount, inputs, change).with_fee(fee))
+    }
+
+    /// Mines the (up to `max`) highest fee-rate pending transactions that fit
+    /// within the block limits into a single new block and removes them from the mempool. Returns how many
+    /// were included. Transactions whose lock time has not passed stay
+    /// pending, and ones past the mempool's expiry are dropped.
+    pub fn mine_pending(&mut self, mempool: &mut Mempool, max: usize, miner: &str) -> Result<usize> {
+        let batch = self.pending_batch(mempool, max, miner)?;
+        self.add_block(miner, batch.clone())?;
+        mempool.remove_batch(&batch);
+        mempool.expire(self.height());
+        Ok(batch.len())
+    }
+
+    /// Like [`Blockchain::mine_pending`], for a chain other threads share:
+    /// the block is built under the read lock and sealed under no lock, so
+    /// queries go on being answered while it is mined, and the write lock is
+    /// only taken to append it. Should another block extend the chain in the
+    /// meantime, the block is built again on the new tip. Returns the block.
+    pub fn mine_shared(chain: &RwLock<Blockchain>, mempool: &Mutex<Mempool>, max: usize, miner: &str) -> Result<Block> {
+        loop {
+            let (template, sealer, batch) = {
+                let blockchain = read(chain);
+                let batch = blockchain.pending_batch(&lock(mempool), max, miner)?;
+                let template = blockchain.template_for(miner, batch.clone())?;
+                let sealer = blockchain.sealer(template.header().index());
+                (template, sealer, batch)
+            };
+            let block = sealer.seal_template(template)?;
+            let mut blockchain = write(chain);
+            if block.previous_hash() != blockchain.latest_block().hash() {
+                debug!(index = block.index(), "chain moved on while mining; rebuilding the block");
+                continue;
+            }
+            blockchain.push_mined(block.clone());
+            let mut mempool = lock(mempool);
+            mempool.remove_batch(&batch);
+            mempool.expire(blockchain.height());
+            return Ok(block);
+        }
+    }
+
+    /// The pending transactions the next block paying `miner` would mine.
+    fn pending_batch(&self, mempool: &Mempool, max: usize, miner: &str) -> Result<Vec<Transaction>> {
+        let max = max.min(self.params.max_block_transactions.saturating_sub(1));
+        let (height, timestamp) = (self.blocks.len() as u64, self.next_timestamp()?);
+        Ok(mempool.peek_batch_where(max, self.transaction_budget(miner)?, |tx| tx.is_final(height, timestamp)))
+    }
+
+    /// Checks that the genesis block matches the chain parameters, and every
+    /// later block's hash, proof of work and target, its link to the
+    /// previous block, that it starts with exactly one coinbase paying the
+    /// block reward plus fees, that it stays within the block limits, that
+    /// it never spends an output twice, and that no sender spends more than
+    /// their balance.
+    ///
+    /// Blocks up to the last checkpoint are only checked to hash, link and
+    /// match their checkpoints; see [`Blockchain::set_checkpoints`].
+    ///
+    /// Only blocks after the last one a previous pass accepted are checked;
+    /// see [`Blockchain::validate_full`]. Hashes, Merkle roots and links are
+    /// checked across threads, the rest block by block.
+    pub fn validate(&self) -> Result<()> {
+        let Some((start, mut ledger)) = self.resume_validation() else {
+            return Ok(());
+        };
+        let _span = debug_span!("validate", from = start, blocks = self.blocks.len()).entered();
+        let trusted = self.checkpoint_height();
+        let headers = self.check_headers(start);
+        for (i, header) in (start..).zip(headers) {
+            let violations = self.check_main_chain_block(i, header, trusted, &mut ledger);
+            if let Some(violation) = violations.into_iter().next() {
+                debug!(index = i, %violation, "chain is invalid");
+                return Err(BlockchainError::Validation(violation.to_string()));
+            }
+        }
+        self.mark_validated(ledger);
+        Ok(())
+    }
+
+    /// Like [`Blockchain::validate`], but checks every block again.
+    pub fn validate_full(&self) -> Result<()> {
+        self.clear_validation_cache();
+        self.validate()
+    }
+
+    /// Height of the last block a validation pass accepted, which later
+    /// passes start after. Cleared if that block leaves the main chain.
+    pub fn validated_height(&self) -> Option<u64> {
+        lock(&self.validated).as_ref().map(|validated| validated.height)
+    }
+
+    /// Forgets which blocks were validated, so the next pass checks them all.
+    pub fn clear_validation_cache(&self) {
+        *lock(&self.validated) = None;
+    }
+
+    /// Where the next validation pass starts, and the ledger before that
+    /// block, or `None` if every block was already validated.
+    fn resume_validation(&self) -> Option<(usize, Ledger)> {
+        let mut validated = lock(&self.validated);
+        match validated.as_ref() {
+            Some(last) if last.height == self.height() && self.latest_block().hash() == last.hash => None,
+            Some(last) if self.block_by_index(last.height).is_some_and(|block| block.hash() == last.hash) => {
+                Some((last.height as usize + 1, last.ledger.clone()))
+            }
+            _ => {
+                *validated = None;
+                Some((0, Ledger::default()))
+            }
+        }
+    }
+
+    /// Records that every block through the tip passed, leaving `ledger`.
+    fn mark_validated(&self, ledger: Ledger) {
+        let tip = self.latest_block();
+        *lock(&self.validated) = Some(Validated {
+            height: tip.index(),
+            hash: tip.hash().to_string(),
+            ledger,
+        });
+    }
+
+    /// Runs [`Blockchain::check_header`] on every main-chain block from
+    /// `start` on, in parallel.
+    fn check_headers(&self, start: usize) -> Vec<Vec<Violation>> {
+        (start..self.blocks.len())
+            .into_par_iter()
+            .map(|i| self.check_header(&self.blocks[i], &self.blocks[..i]))
+            .collect()
+    }
+
+    /// Finishes checking main-chain block `i`, whose header checks found
+    /// `header`. Blocks up to the `trusted` height are only applied to `ledger`.
+    fn check_main_chain_block(
+        &self,
+        i: usize,
+        mut header: Vec<Violation>,
+        trusted: Option<u64>,
+        ledger: &mut Ledger,
+    ) -> Vec<Violation> {
+        let (block, ancestors) = (&self.blocks[i], &self.blocks[..i]);
+        if trusted.is_some_and(|height| block.index() <= height) {
+            for tx in block.transactions() {
+                ledger.apply_transaction(tx);
+            }
+        } else {
+            header.extend(self.check_contents(block, ancestors, ledger));
+        }
+        // The pruned blocks left nothing to replay; carry on from the state.
+        if let Some(base) = &self.base
+            && block.index() == base.height
+        {
+            ledger.utxos = base.utxos.clone();
+            ledger.balances = base.balances.clone();
+        }
+        header
+    }
+
+    /// Checks a pruned block lies within the saved state, and that the block
+    /// the state was taken at is the one it names.
+    fn check_pruned(&self, block: &Block, violations: &mut Vec<Violation>) {
+        let index = block.index();
+        match &self.base {
+            Some(base) if index == base.height && block.hash() != base.hash => violations.push(
+                Violation::new(index, Check::Pruned, "block does not match the saved state")
+                    .expected(&base.hash)
+                    .actual(block.hash()),
+            ),
+            Some(base) if index <= base.height => {}
+            _ if block.is_pruned() => {
+                violations.push(Violation::new(index, Check::Pruned, "block was pruned and no saved state covers it"));
+            }
+            _ => {}
+        }
+    }
+
+    fn check_checkpoint(&self, block: &Block, violations: &mut Vec<Violation>) {
+        if let Some(expected) = self.checkpoints.get(&block.index())
+            && block.hash() != expected
+        {
+            violations.push(
+                Violation::new(block.index(), Check::Checkpoint, "block does not match the checkpoint")
+                    .expected(expected)
+                    .actual(block.hash()),
+            );
+        }
+    }
+
+    /// Runs the same checks as [`Blockchain::validate`] but keeps going after
+    /// a failure, reporting every violation in the blocks it checks.
+    pub fn validate_detailed(&self) -> ValidationReport {
+        let Some((start, mut ledger)) = self.resume_validation() else {
+            return ValidationReport::default();
+        };
+        let trusted = self.checkpoint_height();
+        let mut report = ValidationReport::default();
+        let headers = self.check_headers(start);
+        for (i, header) in (start..).zip(headers) {
+            report.violations.extend(self.check_main_chain_block(i, header, trusted, &mut ledger));
+            report.blocks_checked += 1;
+        }
+        if report.is_valid() {
+            self.mark_validated(ledger);
+        }
+        report
+    }
+
+    /// Cheap checks on `headers`, a run that follows a block on our main
+    /// chain: each links to the one before at the next height, uses the
+    /// chain's hash algorithm and an active block version, meets its own
+    /// proof-of-work target and any checkpoint, and is stamped after the
+    /// median of the blocks before it but not too far ahead of our clock.
+    ///
+    /// None of this needs block bodies, so a bogus chain can be turned away
+    /// before any are downloaded. Bodies still get every check of
+    /// [`Blockchain::validate`] once they arrive.
+    pub fn validate_headers(&self, headers: &[BlockHeader]) -> Result<()> {
+        self.validate_headers_after(&[], headers)
+    }
+
+    /// Like [`Blockchain::validate_headers`] for `headers` continuing
+    /// `checked`, a run that already passed.
+    pub(crate) fn validate_headers_after(&self, checked: &[BlockHeader], headers: &[BlockHeader]) -> Result<()> {
+        let Some(first) = checked.first().or(headers.first()) else {
+            return Ok(());
+        };
+        let height = self.main_chain_height_of(first.previous_hash()).ok_or_else(|| {
+            BlockchainError::Validation(format!("header #{} does not build on our chain", first.index()))
+        })?;
+        let span = self.params.median_time_span.max(1);
+        let ancestors = &self.blocks[height.saturating_sub(span - 1)..=height];
+        let mut recent: VecDeque<u128> = ancestors.iter().map(Block::timestamp).collect();
+        recent.extend(checked.iter().map(BlockHeader::timestamp));
+        let (mut parent_hash, mut parent_index) = match checked.last() {
+            Some(last) => (last.compute_hash(), last.index()),
+            None => (self.blocks[height].hash().to_string(), height as u64),
+        };
+        let ahead = u128::from(self.params.max_future_block_time_ms);
+        let latest = self.miner.clock().now_millis().ok().map(|now| now + ahead);
+        for header in headers {
+            while recent.len() > span {
+                recent.pop_front();
+            }
+            let hash = header.compute_hash();
+            let median = median(recent.iter().copied().collect());
+            let parent = (parent_hash.as_str(), parent_index);
+            if let Some(violation) = self.check_linked_header(header, &hash, parent, median, latest) {
+                return Err(BlockchainError::Validation(violation.to_string()));
+            }
+            recent.push_back(header.timestamp());
+            parent_hash = hash;
+            parent_index = header.index();
+        }
+        Ok(())
+    }
+
+    /// The first rule `header`, hashing to `hash`, breaks as the successor of
+    /// `parent` (its hash and index), given the `median` timestamp of the
+    /// blocks before it and the `latest` timestamp our clock allows.
+    fn check_linked_header(
+        &self,
+        header: &BlockHeader,
+        hash: &str,
+        (parent_hash, parent_index): (&str, u64),
+        median: u128,
+        latest: Option<u128>,
+    ) -> Option<Violation> {
+        let index = header.index();
+        if header.previous_hash() != parent_hash || index != parent_index + 1 {
+            return Some(Violation::new(index, Check::Link, "header does not follow the one before it"));
+        }
+        let version = self.params.block_version(index);
+        if header.version() < version {
+            return Some(
+                Violation::new(index, Check::Version, "header is of a version from before an active upgrade")
+                    .expected(format!("at least {}", version))
+                    .actual(header.version()),
+            );
+        }
+        if header.algorithm() != self.params.hash_algorithm {
+            return Some(
+                Violation::new(index, Check::Hash, "header is hashed with another algorithm than the chain")
+                    .expected(self.params.hash_algorithm)
+                    .actual(header.algorithm()),
+            );
+        }
+        if !header.target().is_met_by(hash) {
+            return Some(Violation::new(index, Check::ProofOfWork, "header does not meet its proof-of-work target"));
+        }
+        if header.timestamp() <= median {
+            return Some(
+                Violation::new(index, Check::Timestamp, "timestamp is not after the median of recent blocks")
+                    .expected(format!("after {}", median))
+                    .actual(header.timestamp()),
+            );
+        }
+        if let Some(latest) = latest
+            && header.timestamp() > latest
+        {
+            return Some(
+                Violation::new(index, Check::Timestamp, "timestamp is too far in the future")
+                    .expected(format!("at most {}", latest))
+                    .actual(header.timestamp()),
+            );
+        }
+        match self.checkpoints.get(&index) {
+            Some(expected) if expected != hash => Some(
+                Violation::new(index, Check::Checkpoint, "header does not match the checkpoint")
+                    .expected(expected)
+                    .actual(hash),
+            ),
+            _ => None,
+        }
+    }
+
+    /// Validates `block` as the successor of `ancestors`, which leave
+    /// `ledger`; on success `ledger` is updated with the block.
+    fn validate_block(&self, block: &Block, ancestors: &[Block], ledger: &mut Ledger) -> Result<()> {
+        match self.check_block(block, ancestors, ledger).into_iter().next() {
+            Some(violation) => Err(BlockchainError::Validation(violation.to_string())),
+            None => Ok(()),
+        }
+    }
+
+    /// Collects every rule `block` breaks as the successor of `ancestors`.
+    /// Transactions that pass are applied to `ledger` even if others fail,
+    /// so later blocks can still be checked against it.
+    fn check_block(&self, block: &Block, ancestors: &[Block], ledger: &mut Ledger) -> Vec<Violation> {
+        let mut violations = self.check_header(block, ancestors);
+        violations.extend(self.check_contents(block, ancestors, ledger));
+        violations
+    }
+
+    /// The checks that only look at `block` and the stored hash of its
+    /// parent: its height, hash, Merkle root, link and any checkpoint. Each
+    /// block's are independent of the rest, so they run in parallel. These
+    /// are all the checks left for blocks below a checkpoint.
+    fn check_header(&self, block: &Block, ancestors: &[Block]) -> Vec<Violation> {
+        let index = block.index();
+        let mut violations = Vec::new();
+        if index != ancestors.len() as u64 {
+            violations.push(
+                Violation::new(index, Check::Height, "block is at the wrong height")
+                    .expected(ancestors.len())
+                    .actual(index),
+            );
+        }
+
+        let version = self.params.block_version(index);
+        if block.version() < version {
+            violations.push(
+                Violation::new(index, Check::Version, "block is of a version from before an active upgrade")
+                    .expected(format!("at least {}", version))
+                    .actual(block.version()),
+            );
+        }
+        if block.header().algorithm() != self.params.hash_algorithm {
+            violations.push(
+                Violation::new(index, Check::Hash, "block is hashed with another algorithm than the chain")
+                    .expected(self.params.hash_algorithm)
+                    .actual(block.header().algorithm()),
+            );
+        }
+        let hash = block.compute_hash();
+        if block.hash() != hash {
+            violations.push(
+                Violation::new(index, Check::Hash, "stored hash does not match the header")
+                    .expected(hash)
+                    .actual(block.hash()),
+            );
+        }
+
+        // A pruned block's transactions are gone, so only its header is
+        // checked; the saved state stands in for their effects.
+        self.check_pruned(block, &mut violations);
+        let merkle_root = merkle::merkle_root(block.transactions());
+        if !block.is_pruned() && block.merkle_root() != merkle_root {
+            violations.push(
+                Violation::new(index, Check::MerkleRoot, "Merkle root does not match the transactions")
+                    .expected(merkle_root)
+                    .actual(block.merkle_root()),
+            );
+        }
+
+        match ancestors.last() {
+            Some(previous) if block.previous_hash() != previous.hash() => violations.push(
+                Violation::new(index, Check::Link, format!("does not link to block #{}", previous.index()))
+                    .expected(previous.hash())
+                    .actual(block.previous_hash()),
+            ),
+            Some(_) => {}
+            None => self.check_genesis(block, &mut violations),
+        }
+        self.check_checkpoint(block, &mut violations);
+        violations
+    }
+
+    /// The checks that depend on the blocks before `block`: its target,
+    /// consensus rules, timestamp, coinbase, limits and transactions, which
+    /// are applied to `ledger` as they pass. Every sender must afford what
+    /// they spend, account-model or not, without their immature rewards.
+    fn check_contents(&self, block: &Block, ancestors: &[Block], ledger: &mut Ledger) -> Vec<Violation> {
+        let index = block.index();
+        let mut violations = Vec::new();
+        let consensus = self.consensus();
+        let expected_bits = consensus.next_bits(ancestors);
+        if block.bits() != expected_bits {
+            violations.push(
+                Violation::new(index, Check::Difficulty, "wrong proof-of-work target")
+                    .expected(format!("{:08x}", expected_bits))
+                    .actual(format!("{:08x}", block.bits())),
+            );
+        }
+
+        consensus.check_block(block, ancestors, &mut violations);
+
+        if !ancestors.is_empty() {
+            self.check_timestamp(block, ancestors, &mut violations);
+            if !block.is_pruned() {
+                self.check_coinbase(block, &mut violations);
+                self.check_limits(block, &mut violations);
+            }
+        }
+
+        let utxos = &ledger.utxos;
+        utxos.verify_signatures(block.transactions(), &self.params.chain_id, &self.signatures);
+        // The block's own coinbase is as immature as any.
+        let maturity = self.params.coinbase_maturity;
+        let mut immature: HashMap<&str, Amount> = HashMap::new();
+        let rewards = immature_rewards(ancestors, index, maturity)
+            .chain(immature_rewards(slice::from_ref(block), index, maturity));
+        for tx in rewards {
+            let reward = immature.entry(tx.receiver()).or_default();
+            *reward = reward.saturating_add(tx.amount());
+        }
+        for (position, tx) in block.transactions().iter().enumerate() {
+            if !tx.is_final(index, block.timestamp()) {
+                violations.push(Violation::new(
+                    index,
+                    Check::Transaction,
+                    format!("transaction {} is locked until {}", position, describe_lock_time(tx.lock_time())),
+                ));
+                continue;
+            }
+            let utxos = &ledger.utxos;
+            let checked = utxos
+                .check_transaction_with(tx, &self.params.chain_id, &self.signatures)
+                .and_then(|()| utxos.check_maturity(tx, index, self.params.coinbase_maturity))
+                .and_then(|()| ledger.check_balance(tx, &immature));
+            match checked {
+                Ok(()) => ledger.apply_transaction(tx),
+                Err(err) => violations.push(Violation::new(
+                    index,
+                    Check::Transaction,
+                    format!("transaction {}: {}", position, err),
+                )),
+            }
+        }
+        violations
+    }
+
+    /// Total work of the main chain, computed from each block's target. Fork
+    /// choice follows the chain with the most work rather than the most blocks.
+    pub fn cumulative_work(&self) -> u128 {
+        total_work(&self.blocks)
+    }
+
+    /// Whether a block with this hash is on the main chain or a side chain.
+    pub fn knows_block(&self, hash: &str) -> bool {
+        self.side_blocks.contains_key(hash) || self.block_by_hash(hash).is_some()
+    }
+
+    /// The block with this hash, on the main chain or a side chain.
+    pub fn find_block(&self, hash: &str) -> Option<&Block> {
+        self.block_by_hash(hash).or_else(|| self.side_blocks.get(hash))
+    }
+
+    fn main_chain_height_of(&self, hash: &str) -> Option<usize> {
+        self.index.height_of(hash).map(|height| height as usize)
+    }
+
+    /// Validates a block received from elsewhere. A block extending the tip is
+    /// appended; a block extending any other known block is kept on a side
+    /// chain, and if that side chain now has more work than the main chain
+    /// the chain reorganizes onto it.
+    pub fn accept_block(&mut self, block: Block) -> Result<Vec<ChainEvent>> {
+        let _span = debug_span!("accept_block", index = block.index(), hash = %block.hash()).entered();
+        let events = self.connect_block(block).inspect_err(|err| {
+            warn!(%err, "rejected block");
+            self.count_failure(err);
+        })?;
+        self.publish_received(&events);
+        Ok(events)
+    }
+
+    fn connect_block(&mut self, block: Block) -> Result<Vec<ChainEvent>> {
+        if self.knows_block(block.hash()) {
+            return Ok(Vec::new());
+        }
+        if block.previous_hash() == self.latest_block().hash() {
+            self.validate_block(&block, &self.blocks, &mut self.ledger_through(&self.blocks)?)?;
+            self.index.connect(&block);
+            self.blocks.push(block.clone());
+            self.update_state();
+            return Ok(vec![ChainEvent::BlockConnected(block)]);
+        }
+
+        // Walk back through side-chain blocks to where the branch forks off.
+        let mut branch = vec![block];
+        let fork_height = loop {
+            let parent = branch.last().expect("branch is never empty").previous_hash();
+            if let Some(height) = self.main_chain_height_of(parent) {
+                break height;
+            }
+            match self.side_blocks.get(parent) {
+                Some(side) => branch.push(side.clone()),
+                None => {
+                    return Err(BlockchainError::Validation(format!(
+                        "block #{} has an unknown parent",
+                        branch[0].index()
+                    )));
+                }
+            }
+        };
+        branch.reverse();
+        self.check_reorg_depth(fork_height + 1)?;
+
+        let mut candidate = self.blocks[..=fork_height].to_vec();
+        let mut ledger = self.ledger_through(&candidate)?;
+        for block in &branch {
+            self.validate_block(block, &candidate, &mut ledger)?;
+            candidate.push(block.clone());
+        }
+
+        let new_block = branch.last().expect("branch is never empty").clone();
+        if total_work(&candidate) <= self.cumulative_work() {
+            self.side_blocks.insert(new_block.hash().to_string(), new_block.clone());
+            return Ok(vec![ChainEvent::SideBlockStored(new_block)]);
+        }
+        let events = self.reorganize(candidate, fork_height + 1);
+        self.update_state();
+        Ok(events)
+    }
+
+    /// Switches the main chain to `candidate`, which shares our first
+    /// `common` blocks, moving the blocks it replaces to the side chains.
+    fn reorganize(&mut self, candidate: Vec<Block>, common: usize) -> Vec<ChainEvent> {
+        let mut events = Vec::new();
+        let rolled_back = self.blocks.split_off(common);
+        info!(
+            fork = common,
+            rolled_back = rolled_back.len(),
+            connected = candidate.len().saturating_sub(common),
+            "reorganizing chain"
+        );
+        for block in rolled_back.into_iter().rev() {
+            self.index.disconnect(&block);
+            self.side_blocks.insert(block.hash().to_string(), block.clone());
+            events.push(ChainEvent::BlockRolledBack(block));
+        }
+        for block in candidate.into_iter().skip(common) {
+            self.side_blocks.remove(block.hash());
+            self.index.connect(&block);
+            self.blocks.push(block.clone());
+            events.push(ChainEvent::BlockConnected(block));
+        }
+        events
+    }
+
+    /// Adopts `blocks` if they form a valid chain from the same genesis block
+    /// with more cumulative work than ours, returning the resulting events
+    /// (empty if our chain was kept). Its headers are checked first, with
+    /// [`Blockchain::validate_headers`], and only then its bodies.
+    pub fn replace_chain(&mut self, blocks: Vec<Block>) -> Result<Vec<ChainEvent>> {
+        if blocks.is_empty() || total_work(&blocks) <= self.cumulative_work() {
+            return Ok(Vec::new());
+        }
+        if blocks[0].hash() != self.blocks[0].hash() {
+            return Err(BlockchainError::Validation(
+                "candidate chain has a different genesis block".to_string(),
+            ));
+        }
+        // Turn away chains whose headers alone give them away before
+        // replaying any transactions.
+        let headers: Vec<BlockHeader> = blocks[1..].iter().map(|block| block.header().clone()).collect();
+        self.validate_headers(&headers).inspect_err(|err| self.count_failure(err))?;
+        let index = ChainIndex::build(&blocks);
+        let mut candidate = Blockchain::from_parts(blocks, self.params.clone(), index);
+        // Judge timestamps by our clock, and reuse the signatures we checked.
+        candidate.miner = self.miner.clone();
+        candidate.signatures = self.signatures.clone();
+        candidate.validate().inspect_err(|err| self.count_failure(err))?;
+        let common = self
+            .blocks
+            .iter()
+            .zip(candidate.blocks.iter())
+            .take_while(|(ours, theirs)| ours.hash() == theirs.hash())
+            .count();
+        self.check_reorg_depth(common)?;
+        let events = self.reorganize(candidate.blocks, common);
+        self.update_state();
+        self.publish_received(&events);
+        Ok(events)
+    }
+
+    fn publish_received(&self, events: &[ChainEvent]) {
+        for event in NodeEvent::from_chain_events(events) {
+            self.events.publish(event);
+        }
+    }
+
+    /// Checks a genesis block was derived from this chain's parameters.
+    fn check_genesis(&self, block: &Block, violations: &mut Vec<Violation>) {
+        let parent = self.genesis_parent_hash();
+        if block.previous_hash() != parent {
+            violations.push(
+                Violation::new(
+                    0,
+                    Check::Genesis,
+                    format!("genesis block belongs to a chain other than {}", self.params.chain_id),
+                )
+                .expected(parent)
+                .actual(block.previous_hash()),
+            );
+        }
+        if block.timestamp() != self.params.genesis_timestamp {
+            violations.push(
+                Violation::new(0, Check::Genesis, "wrong genesis timestamp")
+                    .expected(self.params.genesis_timestamp)
+                    .actual(block.timestamp()),
+            );
+        }
+        let merkle_root = merkle::merkle_root(&self.genesis_transactions());
+        if block.merkle_root() != merkle_root {
+            violations.push(
+                Violation::new(0, Check::Genesis, "genesis block does not match the configured allocations")
+                    .expected(merkle_root)
+                    .actual(block.merkle_root()),
+            );
+        }
+    }
+
+    /// Median timestamp of the last `median_time_span` of `blocks`, or 0 if
+    /// there are none.
+    fn median_time_past(&self, blocks: &[Block]) -> u128 {
+        median_time_past(blocks, self.params.median_time_span)
+    }
+
+    /// Checks the block is later than the median of the blocks before it and
+    /// not too far ahead of our clock.
+    fn check_timestamp(&self, block: &Block, ancestors: &[Block], violations: &mut Vec<Violation>) {
+        let index = block.index();
+        let median = self.median_time_past(ancestors);
+        if block.timestamp() <= median {
+            violations.push(
+                Violation::new(index, Check::Timestamp, "timestamp is not after the median of recent blocks")
+                    .expected(format!("after {}", median))
+                    .actual(block.timestamp()),
+            );
+        }
+        if let Ok(now) = self.miner.clock().now_millis() {
+            let latest = now + u128::from(self.params.max_future_block_time_ms);
+            if block.timestamp() > latest {
+                violations.push(
+                    Violation::new(index, Check::Timestamp, "timestamp is too far in the future")
+                        .expected(format!("at most {}", latest))
+                        .actual(block.timestamp()),
+                );
+            }
+        }
+    }
+
+    /// Checks the block's transaction count and serialized size against the
+    /// chain's limits.
+    fn check_limits(&self, block: &Block, violations: &mut Vec<Violation>) {
+        let index = block.index();
+        let count = block.transactions().len();
+        if count > self.params.max_block_transactions {
+            violations.push(
+                Violation::new(index, Check::Size, "block has too many transactions")
+                    .expected(format!("at most {}", self.params.max_block_transactions))
+                    .actual(count),
+            );
+        }
+        let size = block.size();
+        if size > self.params.max_block_size {
+            violations.push(
+                Violation::new(index, Check::Size, "block is too large")
+                    .expected(format!("at most {} bytes", self.params.max_block_size))
+                    .actual(format!("{} bytes", size)),
+            );
+        }
+    }
+
+    fn check_coinbase(&self, block: &Block, violations: &mut Vec<Violation>) {
+        let index = block.index();
+        match block.transactions().first() {
+            Some(coinbase) if coinbase.is_coinbase() => {
+                if !coinbase.inputs().is_empty() || !coinbase.change().is_zero() {
+                    violations.push(Violation::new(index, Check::Coinbase, "coinbase spends outputs"));
+                }
+                if !coinbase.payments().is_empty() {
+                    violations.push(Violation::new(index, Check::Coinbase, "coinbase pays more than one receiver"));
+                }
+                if coinbase.height() != Some(index) {
+                    let actual = coinbase.height().map_or("none".to_string(), |height| height.to_string());
+                    violations.push(
+                        Violation::new(index, Check::Coinbase, "coinbase commits to the wrong height")
+                            .expected(index)
+                            .actual(actual),
+                    );
+                }
+                match self.coinbase_value(index, &block.transactions()[1..]) {
+                    Some(expected) if coinbase.amount() != expected => violations.push(
+                        Violation::new(index, Check::Coinbase, "coinbase does not pay the block reward plus fees")
+                            .expected(expected)
+                            .actual(coinbase.amount()),
+                    ),
+                    Some(_) => {}
+                    None => violations.push(Violation::new(index, Check::Coinbase, "transaction fees overflow")),
+                }
+            }
+            _ => violations.push(Violation::new(
+                index,
+                Check::Coinbase,
+                "block does not start with a coinbase transaction",
+            )),
+        }
+        if block.transactions().iter().skip(1).any(Transaction::is_coinbase) {
+            violations.push(Violation::new(
+                index,
+                Check::Coinbase,
+                "block contains more than one coinbase transaction",
+            ));
+        }
+    }
+
+    pub fn is_chain_valid(&self) -> bool {
+        self.validate().is_ok()
+    }
+
+    /// Writes the chain to `path` as JSON, replacing the file atomically and
+    /// keeping its previous contents in `<path>.bak`.
+    pub fn save_to_file(&self, path: impl AsRef<Path>) -> Result<()> {
+        let json = serde_json::to_vec_pretty(&self)?;
+        file::replace_with_backup(path.as_ref(), &json)
+    }
+
+    /// Reads a chain written by [`Blockchain::save_to_file`], or its backup
+    /// if the file itself is unreadable.
+    pub fn load_from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let bc: Blockchain = file::read_with_backup(path.as_ref(), |data| Ok(serde_json::from_slice(data)?))?;
+        Blockchain::from_blocks(bc.blocks, bc.params)
+    }
+}
+
+impl<'a> IntoIterator for &'a Blockchain {
+    type Item = &'a Block;
+    type IntoIter = std::slice::Iter<'a, Block>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
@@ -0,0 +1,240 @@
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+use serde_json::{Value, json};
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use crate::amount::Amount;
+use crate::block::Block;
+use crate::error::{BlockchainError, Result};
+use crate::events::NodeEvent;
+use crate::fee::FeeEstimate;
+use crate::transaction::Transaction;
+use crate::validation::TxCheck;
+
+/// A transaction of a block, as listed by `GET /block/{index}/transactions`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlockTransaction {
+    pub txid: String,
+    pub transaction: Transaction,
+}
+
+/// An address's balances, from `GET /balance/{address}`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Account {
+    pub address: String,
+    pub balance: Amount,
+    pub assets: BTreeMap<String, Amount>,
+    /// The sequence number the address's next transaction must use.
+    pub next_sequence: u64,
+}
+
+/// A transaction looked up by `GET /transaction/{txid}`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TransactionStatus {
+    pub transaction: Transaction,
+    /// Height of the block containing it, if confirmed.
+    #[serde(default)]
+    pub block: Option<u64>,
+    pub confirmed: bool,
+}
+
+/// The answer to `POST /transaction`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Submitted {
+    pub txid: String,
+    /// Transactions in the mempool after this one was queued.
+    pub pending: usize,
+}
+
+/// The answer to `POST /transaction/validate`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Validated {
+    pub txid: String,
+    pub valid: bool,
+    /// The check an invalid transaction fails, and why.
+    #[serde(default)]
+    pub check: Option<TxCheck>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// Typed methods for the endpoints of a node's HTTP API (see
+/// [`RpcServer`](crate::rpc::RpcServer) and its OpenAPI document), for Rust
+/// programs talking to a running node. Each call makes one connection.
+/// Error statuses come back as [`BlockchainError::Rpc`].
+#[derive(Debug, Clone)]
+pub struct HttpClient {
+    addr: String,
+    timeout: Option<Duration>,
+}
+
+impl HttpClient {
+    /// A client of the node serving its HTTP API at `addr`, as HOST:PORT.
+    pub fn new(addr: impl Into<String>) -> Self {
+        HttpClient {
+            addr: addr.into(),
+            timeout: None,
+        }
+    }
+
+    /// Gives up on a request once the node has been silent for `timeout`.
+    /// Mining can take a while, so there is no limit by default.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Every main-chain block, from genesis to tip.
+    pub fn chain(&self) -> Result<Vec<Block>> {
+        self.call("GET", "/chain", None)
+    }
+
+    pub fn block(&self, index: u64) -> Result<Block> {
+        self.call("GET", &format!("/block/{}", index), None)
+    }
+
+    pub fn block_transactions(&self, index: u64) -> Result<Vec<BlockTransaction>> {
+        self.call("GET", &format!("/block/{}/transactions", index), None)
+    }
+
+    pub fn balance(&self, address: &str) -> Result<Account> {
+        self.call("GET", &format!("/balance/{}", address), None)
+    }
+
+    /// A confirmed or pending transaction.
+    pub fn transaction(&self, txid: &str) -> Result<TransactionStatus> {
+        self.call("GET", &format!("/transaction/{}", txid), None)
+    }
+
+    /// Queues `tx` in the node's mempool.
+    pub fn submit_transaction(&self, tx: &Transaction) -> Result<Submitted> {
+        self.call("POST", "/transaction", Some(json!(tx)))
+    }
+
+    /// Asks whether the node would queue `tx`, without queuing it.
+    pub fn validate_transaction(&self, tx: &Transaction) -> Result<Validated> {
+        self.call("POST", "/transaction/validate", Some(json!(tx)))
+    }
+
+    /// Has the node mine up to `count` pending transactions (its default
+    /// batch if `None`) into a block paying `miner`, and returns the block.
+    pub fn mine(&self, miner: &str, count: Option<usize>) -> Result<Block> {
+        let mut request = json!({ "miner": miner });
+        if let Some(count) = count {
+            request["count"] = json!(count);
+        }
+        self.call("POST", "/mine", Some(request))
+    }
+
+    /// A fee suggested for confirming within `blocks` blocks.
+    pub fn fee_estimate(&self, blocks: u64) -> Result<FeeEstimate> {
+        self.call("GET", &format!("/fee-estimate/{}", blocks), None)
+    }
+
+    /// The node's metrics in the Prometheus text format.
+    pub fn metrics(&self) -> Result<String> {
+        let (status, mut reader) = self.open("GET", "/metrics", None)?;
+        let mut text = String::new();
+        reader.read_to_string(&mut text)?;
+        check(status, text.as_bytes())?;
+        Ok(text)
+    }
+
+    /// The node's OpenAPI document.
+    pub fn openapi(&self) -> Result<Value> {
+        self.call("GET", "/openapi.json", None)
+    }
+
+    /// The node's events as they happen, until it goes away. The read
+    /// timeout, if any, also applies between events.
+    pub fn events(&self) -> Result<Events> {
+        let (status, mut reader) = self.open("GET", "/events", None)?;
+        if status != 200 {
+            let mut body = Vec::new();
+            reader.read_to_end(&mut body)?;
+            check(status, &body)?;
+        }
+        Ok(Events { reader })
+    }
+
+    fn call<T: DeserializeOwned>(&self, method: &str, path: &str, body: Option<Value>) -> Result<T> {
+        let (status, mut reader) = self.open(method, path, body)?;
+        let mut body = Vec::new();
+        reader.read_to_end(&mut body)?;
+        check(status, &body)?;
+        Ok(serde_json::from_slice(&body)?)
+    }
+
+    /// Sends a request and reads the response's status line and headers,
+    /// leaving the body to be read.
+    fn open(&self, method: &str, path: &str, body: Option<Value>) -> Result<(u16, BufReader<TcpStream>)> {
+        let mut stream = TcpStream::connect(&self.addr)?;
+        stream.set_read_timeout(self.timeout)?;
+        stream.set_write_timeout(self.timeout)?;
+        let body = body.map(|body| serde_json::to_vec(&body)).transpose()?.unwrap_or_default();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            method,
+            path,
+            self.addr,
+            body.len()
+        )?;
+        stream.write_all(&body)?;
+
+        let mut reader = BufReader::new(stream);
+        let mut status_line = String::new();
+        reader.read_line(&mut status_line)?;
+        let status = status_line
+            .split_whitespace()
+            .nth(1)
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(|| BlockchainError::Encoding(format!("not an HTTP response: {:?}", status_line.trim_end())))?;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+                break;
+            }
+        }
+        Ok((status, reader))
+    }
+}
+
+/// Turns an error status into [`BlockchainError::Rpc`] with the message the
+/// node gave.
+fn check(status: u16, body: &[u8]) -> Result<()> {
+    if status == 200 {
+        return Ok(());
+    }
+    let message = serde_json::from_slice::<Value>(body)
+        .ok()
+        .and_then(|body| body["error"].as_str().map(str::to_string))
+        .unwrap_or_else(|| String::from_utf8_lossy(body).trim().to_string());
+    Err(BlockchainError::Rpc { status, message })
+}
+
+/// The stream of [`HttpClient::events`].
+pub struct Events {
+    reader: BufReader<TcpStream>,
+}
+
+impl Iterator for Events {
+    type Item = Result<NodeEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let mut line = String::new();
+            match self.reader.read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) => {}
+                Err(err) => return Some(Err(err.into())),
+            }
+            if let Some(data) = line.trim_end().strip_prefix("data:") {
+                return Some(serde_json::from_str(data.trim_start()).map_err(Into::into));
+            }
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{BlockchainError, Result};
+
+/// Source of block timestamps, in milliseconds since the Unix epoch.
+/// [`SystemClock`] is used unless a [`Miner`](crate::Miner) is given another,
+/// e.g. a [`ManualClock`] to make tests and simulations deterministic.
+pub trait Clock: fmt::Debug + Send + Sync {
+    fn now_millis(&self) -> Result<u128>;
+}
+
+/// The operating system's wall clock.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_millis(&self) -> Result<u128> {
+        Ok(SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|_| BlockchainError::Mining("system clock is before the Unix epoch".to_string()))?
+            .as_millis())
+    }
+}
+
+/// A clock that only moves when told to. Clones share the same time.
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock(Arc<AtomicU64>);
+
+impl ManualClock {
+    pub fn new(millis: u64) -> Self {
+        ManualClock(Arc::new(AtomicU64::new(millis)))
+    }
+
+    pub fn set(&self, millis: u64) {
+        self.0.store(millis, Ordering::Relaxed);
+    }
+
+    pub fn advance(&self, millis: u64) {
+        self.0.fetch_add(millis, Ordering::Relaxed);
+    }
+}
+
+impl Clock for ManualClock {
+    fn now_millis(&self) -> Result<u128> {
+        Ok(u128::from(self.0.load(Ordering::Relaxed)))
+    }
+}
@@ -0,0 +1,124 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::block::{Block, BlockHeader};
+use crate::error::{BlockchainError, Result};
+use crate::mempool::Mempool;
+use crate::transaction::Transaction;
+
+/// Hex digits of a transaction ID kept as its short ID: eight bytes, enough
+/// that a collision within one mempool is unlikely. One that happens makes
+/// the reconstructed block's Merkle root wrong, and the full block is
+/// fetched instead.
+pub const SHORT_ID_LEN: usize = 16;
+
+pub fn short_id(txid: &str) -> &str {
+    &txid[..SHORT_ID_LEN.min(txid.len())]
+}
+
+/// A block announced by its header and the short IDs of its transactions,
+/// which a peer that relays transactions mostly has pending already.
+/// Coinbases, which no mempool holds, are sent whole.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompactBlock {
+    pub header: BlockHeader,
+    /// Short ID of each transaction, in block order; empty where the
+    /// transaction is prefilled.
+    pub short_ids: Vec<String>,
+    /// Transactions sent whole, by position in the block.
+    pub prefilled: Vec<(usize, Transaction)>,
+}
+
+impl CompactBlock {
+    pub fn new(block: &Block) -> Self {
+        let mut short_ids = Vec::with_capacity(block.transactions().len());
+        let mut prefilled = Vec::new();
+        for (position, tx) in block.transactions().iter().enumerate() {
+            if tx.is_coinbase() {
+                short_ids.push(String::new());
+                prefilled.push((position, tx.clone()));
+            } else {
+                short_ids.push(short_id(&tx.hash()).to_string());
+            }
+        }
+        CompactBlock {
+            header: block.header().clone(),
+            short_ids,
+            prefilled,
+        }
+    }
+
+    pub fn hash(&self) -> String {
+        self.header.compute_hash()
+    }
+
+    /// Fills in the block's transactions from the prefilled ones and those
+    /// pending in `mempool`, leaving gaps for the rest.
+    pub fn reconstruct(&self, mempool: Option<&Mempool>) -> PartialBlock {
+        let mut slots = vec![None; self.short_ids.len()];
+        for (position, tx) in &self.prefilled {
+            if let Some(slot) = slots.get_mut(*position) {
+                *slot = Some(tx.clone());
+            }
+        }
+        if let Some(mempool) = mempool {
+            let wanted: HashMap<&str, usize> = self
+                .short_ids
+                .iter()
+                .enumerate()
+                .filter(|(position, id)| !id.is_empty() && slots[*position].is_none())
+                .map(|(position, id)| (id.as_str(), position))
+                .collect();
+            for tx in mempool.iter() {
+                if let Some(position) = wanted.get(short_id(&tx.hash())) {
+                    slots[*position] = Some(tx.clone());
+                }
+            }
+        }
+        PartialBlock {
+            header: self.header.clone(),
+            slots,
+        }
+    }
+}
+
+/// A compact block being reassembled.
+#[derive(Debug, Clone)]
+pub struct PartialBlock {
+    header: BlockHeader,
+    slots: Vec<Option<Transaction>>,
+}
+
+impl PartialBlock {
+    pub fn hash(&self) -> String {
+        self.header.compute_hash()
+    }
+
+    /// Positions of the transactions still missing.
+    pub fn missing(&self) -> Vec<usize> {
+        self.slots.iter().enumerate().filter(|(_, slot)| slot.is_none()).map(|(position, _)| position).collect()
+    }
+
+    /// Fills the gaps, in order, with the transactions a peer sent for them.
+    pub fn fill(&mut self, transactions: Vec<Transaction>) -> Result<()> {
+        let missing = self.missing();
+        if transactions.len() != missing.len() {
+            return Err(BlockchainError::Validation(format!(
+                "expected {} missing transactions, got {}",
+                missing.len(),
+                transactions.len()
+            )));
+        }
+        for (position, tx) in missing.into_iter().zip(transactions) {
+            self.slots[position] = Some(tx);
+        }
+        Ok(())
+    }
+
+    /// The block, once no transaction is missing.
+    pub fn into_block(self) -> Option<Block> {
+        let hash = self.hash();
+        let transactions = self.slots.into_iter().collect::<Option<Vec<_>>>()?;
+        Some(Block::from_parts(self.header, hash, transactions))
+    }
+}
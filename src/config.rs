@@ -0,0 +1,146 @@
+use serde::{Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::amount::Amount;
+use crate::error::{BlockchainError, Result};
+use crate::mempool::MempoolLimits;
+use crate::params::MAX_DIFFICULTY;
+use crate::profile::ChainProfile;
+
+/// Name of the configuration file looked for in the data directory.
+pub const CONFIG_FILE: &str = "config.toml";
+
+/// Where a node keeps its chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// An append-only log file; see [`LogStore`](crate::LogStore).
+    #[default]
+    Log,
+    /// A sled database; see [`SledStore`](crate::SledStore).
+    Sled,
+}
+
+impl FromStr for StorageBackend {
+    type Err = String;
+
+    fn from_str(name: &str) -> std::result::Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "log" => Ok(StorageBackend::Log),
+            "sled" => Ok(StorageBackend::Sled),
+            _ => Err(format!("unknown storage backend {} (expected log or sled)", name)),
+        }
+    }
+}
+
+impl fmt::Display for StorageBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            StorageBackend::Log => "log",
+            StorageBackend::Sled => "sled",
+        })
+    }
+}
+
+/// Node settings read from `config.toml`. Every field is optional, and
+/// command-line flags and environment variables take precedence.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Network to join unless a genesis file is given.
+    pub chain: Option<ChainProfile>,
+    /// Leading zero hex digits required of block hashes, overriding the
+    /// genesis file or the default chain.
+    pub difficulty: Option<usize>,
+    /// Block reward, overriding the genesis file or the default chain.
+    pub reward: Option<Amount>,
+    /// Port to accept peer connections on.
+    pub listen: Option<u16>,
+    /// Peers to connect to at startup.
+    pub peers: Vec<String>,
+    /// Public keys of the only peers to talk to, for a private network;
+    /// any peer may connect if empty.
+    pub allow_peers: Vec<String>,
+    /// Port the HTTP API is served on.
+    pub rpc_port: Option<u16>,
+    pub storage: Option<StorageBackend>,
+    /// Mining threads; one per CPU if unset.
+    pub threads: Option<usize>,
+    /// Genesis file, relative to the data directory.
+    pub genesis: Option<PathBuf>,
+    /// Wallet file, relative to the data directory.
+    pub wallet: Option<PathBuf>,
+    /// Known-good block hashes by height, e.g. `[checkpoints]` with
+    /// `1000 = "00ab..."`; see [`Blockchain::set_checkpoints`](crate::Blockchain::set_checkpoints).
+    #[serde(deserialize_with = "height_keys")]
+    pub checkpoints: BTreeMap<u64, String>,
+    /// Discard the transactions of blocks this far below the tip; see
+    /// [`Blockchain::set_prune_depth`](crate::Blockchain::set_prune_depth).
+    pub prune: Option<u64>,
+    /// The `[mempool]` table: how many transactions, and bytes of them, may
+    /// wait to be mined, and for how many blocks, and this node's relay
+    /// policy, e.g.
+    ///
+    /// ```toml
+    /// [mempool]
+    /// max_transactions = 10000
+    /// max_bytes = 10000000
+    /// expiry_blocks = 1000
+    /// # Refuse transactions paying under 10 base units per byte...
+    /// min_fee_rate = 10
+    /// # ...or creating outputs (change included) worth less than this.
+    /// dust_limit = "0.00001"
+    /// ```
+    ///
+    /// The policy only decides what this node queues and relays; blocks
+    /// holding transactions it would refuse are still valid.
+    pub mempool: MempoolLimits,
+}
+
+/// TOML table keys are always strings, so heights are parsed from them.
+fn height_keys<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<BTreeMap<u64, String>, D::Error> {
+    BTreeMap::<String, String>::deserialize(deserializer)?
+        .into_iter()
+        .map(|(height, hash)| match height.parse() {
+            Ok(height) => Ok((height, hash)),
+            Err(_) => Err(serde::de::Error::custom(format!("checkpoint height {} is not a number", height))),
+        })
+        .collect()
+}
+
+impl Config {
+    /// Reads a configuration file, or returns the defaults if it does not exist.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let data = match fs::read_to_string(path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(err) => return Err(err.into()),
+        };
+        let config: Config =
+            toml::from_str(&data).map_err(|err| BlockchainError::Validation(format!("invalid config file: {}", err)))?;
+        if config.difficulty.is_some_and(|difficulty| difficulty > MAX_DIFFICULTY) {
+            return Err(BlockchainError::Validation(format!(
+                "difficulty cannot exceed {}",
+                MAX_DIFFICULTY
+            )));
+        }
+        Ok(config)
+    }
+}
+
+/// The data directory used when none is given: `%APPDATA%\mini-block` on
+/// Windows, `~/.mini-block` elsewhere, or the current directory if the home
+/// directory is unknown.
+pub fn default_data_dir() -> PathBuf {
+    let home = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(|dir| PathBuf::from(dir).join("mini-block"))
+    } else {
+        std::env::var_os("HOME").map(|dir| PathBuf::from(dir).join(".mini-block"))
+    };
+    home.unwrap_or_else(|| PathBuf::from("."))
+}
@@ -0,0 +1,212 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+use crate::amount::Amount;
+use crate::block::{Block, BlockHeader};
+use crate::blockchain;
+use crate::error::{BlockchainError, Result};
+use crate::miner::Miner;
+use crate::params::ChainParams;
+use crate::target::Target;
+use crate::validation::{Check, Violation};
+
+/// Which consensus engine a chain runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConsensusKind {
+    #[default]
+    ProofOfWork,
+    /// Addresses holding at least `min_stake` are validators, and each block
+    /// is produced by one of them chosen in proportion to its balance.
+    ProofOfStake { min_stake: Amount },
+}
+
+impl ConsensusKind {
+    /// The engine for a chain with `params`.
+    pub fn engine(&self, params: &ChainParams) -> Box<dyn Consensus> {
+        match *self {
+            ConsensusKind::ProofOfWork => Box::new(ProofOfWork::new(params)),
+            ConsensusKind::ProofOfStake { min_stake } => Box::new(ProofOfStake::new(min_stake)),
+        }
+    }
+}
+
+/// Decides who may produce the next block and what makes it acceptable.
+pub trait Consensus {
+    /// Compact target (see [`Target::to_bits`]) the block after `ancestors` must declare.
+    fn next_bits(&self, ancestors: &[Block]) -> u32;
+
+    /// The only address allowed to produce the block after `ancestors`, or
+    /// `None` if anyone may.
+    fn next_producer(&self, ancestors: &[Block]) -> Result<Option<String>>;
+
+    /// Finishes a header for the block, returning it with its hash.
+    fn seal(&self, miner: &Miner, header: BlockHeader) -> Result<(BlockHeader, String)>;
+
+    /// Adds the engine's violations by `block` as the successor of `ancestors`.
+    fn check_block(&self, block: &Block, ancestors: &[Block], violations: &mut Vec<Violation>);
+}
+
+/// Retargeting never moves the target by more than this factor at once.
+const MAX_RETARGET_FACTOR: u128 = 4;
+
+/// Nakamoto-style proof of work with periodic retargeting.
+#[derive(Debug, Clone)]
+pub struct ProofOfWork {
+    initial_target: Target,
+    /// The easiest target retargeting may reach: one leading zero digit.
+    limit: Target,
+    target_block_time_ms: u64,
+    retarget_interval: u64,
+}
+
+impl ProofOfWork {
+    pub fn new(params: &ChainParams) -> Self {
+        ProofOfWork {
+            initial_target: Target::from_leading_zeros(params.initial_difficulty),
+            limit: Target::from_leading_zeros(1),
+            target_block_time_ms: params.target_block_time_ms,
+            retarget_interval: params.retarget_interval,
+        }
+    }
+}
+
+impl Consensus for ProofOfWork {
+    /// Retargets every `retarget_interval` blocks, scaling the target by how
+    /// long the last interval actually took relative to the target time
+    /// (at most 4x either way).
+    fn next_bits(&self, ancestors: &[Block]) -> u32 {
+        let Some(last) = ancestors.last() else {
+            return self.initial_target.to_bits();
+        };
+        let interval = self.retarget_interval;
+        let height = ancestors.len() as u64;
+        if interval == 0 || !height.is_multiple_of(interval) {
+            return last.bits();
+        }
+
+        // The genesis timestamp is fixed by the chain parameters rather than
+        // by when it was mined, so it is never used as a sample.
+        let start = ancestors.len().saturating_sub(interval as usize + 1).max(1);
+        let gaps = ancestors.len().saturating_sub(1 + start) as u128;
+        let expected = u128::from(self.target_block_time_ms) * gaps;
+        if expected == 0 {
+            return last.bits();
+        }
+        let first = &ancestors[start];
+        let actual = last
+            .timestamp()
+            .saturating_sub(first.timestamp())
+            .clamp(expected / MAX_RETARGET_FACTOR, expected * MAX_RETARGET_FACTOR);
+        last.target().scale(actual, expected, self.limit).to_bits()
+    }
+
+    fn next_producer(&self, _ancestors: &[Block]) -> Result<Option<String>> {
+        Ok(None)
+    }
+
+    fn seal(&self, miner: &Miner, header: BlockHeader) -> Result<(BlockHeader, String)> {
+        miner.mine(header)
+    }
+
+    fn check_block(&self, block: &Block, _ancestors: &[Block], violations: &mut Vec<Violation>) {
+        if !block.meets_target() {
+            violations.push(
+                Violation::new(block.index(), Check::ProofOfWork, "hash does not meet the proof-of-work target")
+                    .expected(format!("at most {}", block.target()))
+                    .actual(block.hash()),
+            );
+        }
+    }
+}
+
+/// Proof of stake with deterministic leader selection: the producer of each
+/// block is drawn from the validators, weighted by balance, using the hash of
+/// the parent block as the random seed. Blocks need no work: their target is
+/// [`Target::MAX`].
+///
+/// Blocks are not signed yet, so the producer is identified by the receiver
+/// of the block's coinbase; a block crediting anyone else is rejected.
+#[derive(Debug, Clone)]
+pub struct ProofOfStake {
+    min_stake: Amount,
+}
+
+impl ProofOfStake {
+    pub fn new(min_stake: Amount) -> Self {
+        ProofOfStake { min_stake }
+    }
+
+    /// Balances of the addresses eligible to produce the block after `ancestors`.
+    pub fn validators(&self, ancestors: &[Block]) -> BTreeMap<String, Amount> {
+        blockchain::balances_of(ancestors)
+            .into_iter()
+            .filter(|(_, stake)| !stake.is_zero() && *stake >= self.min_stake)
+            .collect()
+    }
+
+    fn leader(&self, ancestors: &[Block]) -> Option<String> {
+        let parent = ancestors.last()?;
+        let validators = self.validators(ancestors);
+        // Stakes are added up in base units as u128, which no sum of u64s overflows.
+        let total: u128 = validators.values().map(|stake| u128::from(stake.units())).sum();
+        if total == 0 {
+            return None;
+        }
+        let seed = Sha256::digest(format!("{}{}", parent.hash(), ancestors.len()));
+        let mut ticket = u128::from_be_bytes(seed[..16].try_into().expect("SHA-256 digest has 32 bytes")) % total;
+        for (address, stake) in validators {
+            let stake = u128::from(stake.units());
+            if ticket < stake {
+                return Some(address);
+            }
+            ticket -= stake;
+        }
+        unreachable!("ticket is below the total stake")
+    }
+}
+
+impl Consensus for ProofOfStake {
+    fn next_bits(&self, _ancestors: &[Block]) -> u32 {
+        Target::MAX.to_bits()
+    }
+
+    fn next_producer(&self, ancestors: &[Block]) -> Result<Option<String>> {
+        if ancestors.is_empty() {
+            return Ok(None);
+        }
+        match self.leader(ancestors) {
+            Some(leader) => Ok(Some(leader)),
+            None => Err(BlockchainError::Validation(format!(
+                "no address holds the minimum stake of {}",
+                self.min_stake
+            ))),
+        }
+    }
+
+    fn seal(&self, _miner: &Miner, header: BlockHeader) -> Result<(BlockHeader, String)> {
+        let hash = header.compute_hash();
+        Ok((header, hash))
+    }
+
+    fn check_block(&self, block: &Block, ancestors: &[Block], violations: &mut Vec<Violation>) {
+        if ancestors.is_empty() {
+            return;
+        }
+        let producer = block.transactions().first().map_or("none", |coinbase| coinbase.receiver());
+        match self.leader(ancestors) {
+            Some(leader) if leader != producer => violations.push(
+                Violation::new(block.index(), Check::Producer, "block was not produced by the elected validator")
+                    .expected(leader)
+                    .actual(producer),
+            ),
+            Some(_) => {}
+            None => violations.push(Violation::new(
+                block.index(),
+                Check::Producer,
+                "no validator was eligible to produce the block",
+            )),
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::error::{BlockchainError, Result};
+
+/// Name of a daemon's control socket in its data directory.
+pub const SOCKET_FILE: &str = "mini-block.sock";
+
+/// How long a daemon waits for a connected client to send its command.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A command for a running daemon, sent by `mini-block ctl` as one line of
+/// JSON per connection.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ControlRequest {
+    /// The command line, as words, e.g. `["balance", "alice"]`.
+    pub args: Vec<String>,
+    /// Whether to answer as `--json` would.
+    #[serde(default)]
+    pub json: bool,
+    /// The wallet password, for commands that need one.
+    #[serde(default)]
+    pub password: Option<String>,
+}
+
+/// A daemon's answer to a [`ControlRequest`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ControlResponse {
+    /// What the command printed.
+    pub output: String,
+    pub ok: bool,
+}
+
+/// The daemon's end of its control socket, which only the user running it
+/// may connect to. The socket file is removed when this is dropped.
+#[derive(Debug)]
+pub struct ControlSocket {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl ControlSocket {
+    /// Listens on `path`, replacing a socket left there by a daemon that is
+    /// gone. Fails if a daemon is still listening on it.
+    pub fn bind(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        if path.exists() {
+            if UnixStream::connect(&path).is_ok() {
+                return Err(BlockchainError::Validation(format!(
+                    "a daemon is already running on {}",
+                    path.display()
+                )));
+            }
+            fs::remove_file(&path)?;
+        }
+        let listener = UnixListener::bind(&path)?;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+        Ok(ControlSocket { listener, path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Waits for a client's command, returning it with the connection to
+    /// answer on. Clients that hang up without one, such as another daemon
+    /// checking whether this one runs, are skipped.
+    pub fn accept(&self) -> Result<(ControlRequest, ControlReply)> {
+        loop {
+            let (stream, _) = self.listener.accept()?;
+            stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+            let mut line = String::new();
+            if BufReader::new(&stream).read_line(&mut line)? == 0 {
+                continue;
+            }
+            let request = serde_json::from_str(&line)?;
+            return Ok((request, ControlReply { stream }));
+        }
+    }
+}
+
+impl Drop for ControlSocket {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Where the answer to an accepted [`ControlRequest`] goes.
+#[derive(Debug)]
+pub struct ControlReply {
+    stream: UnixStream,
+}
+
+impl ControlReply {
+    pub fn send(mut self, response: &ControlResponse) -> Result<()> {
+        let mut line = serde_json::to_vec(response)?;
+        line.push(b'\n');
+        self.stream.write_all(&line)?;
+        Ok(())
+    }
+}
+
+/// Sends `request` to the daemon listening on `path` and waits for its
+/// answer, for as long as the command takes.
+pub fn send(path: &Path, request: &ControlRequest) -> Result<ControlResponse> {
+    let mut stream = UnixStream::connect(path).map_err(|err| {
+        BlockchainError::Io(std::io::Error::new(
+            err.kind(),
+            format!("no daemon is listening on {} ({})", path.display(), err),
+        ))
+    })?;
+    let mut line = serde_json::to_vec(request)?;
+    line.push(b'\n');
+    stream.write_all(&line)?;
+    let mut answer = String::new();
+    BufReader::new(&stream).read_line(&mut answer)?;
+    if answer.is_empty() {
+        return Err(BlockchainError::Validation("the daemon hung up without answering".to_string()));
+    }
+    Ok(serde_json::from_str(&answer)?)
+}
@@ -0,0 +1,143 @@
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+
+use crate::block::{Block, BlockHeader};
+use crate::blockchain::Blockchain;
+use crate::error::{BlockchainError, Result};
+
+/// Most headers sent in one `Headers` reply; a full reply means the peer
+/// may have more.
+pub const MAX_HEADERS: usize = 2000;
+
+/// Most block bodies requested from a peer at once.
+pub const BLOCK_BATCH: usize = 16;
+
+/// Progress of catching up with a peer: its headers are fetched first, then
+/// the bodies of the blocks we lack, a batch at a time.
+#[derive(Debug, Default)]
+pub(crate) enum Download {
+    #[default]
+    Idle,
+    Headers {
+        peer: SocketAddr,
+        headers: Vec<BlockHeader>,
+    },
+    Blocks {
+        peer: SocketAddr,
+        queue: VecDeque<String>,
+        requested: Vec<String>,
+    },
+}
+
+/// What to ask the sync peer for next.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Request {
+    Headers(Vec<String>),
+    Blocks(Vec<String>),
+}
+
+impl Download {
+    pub(crate) fn peer(&self) -> Option<SocketAddr> {
+        match self {
+            Download::Idle => None,
+            Download::Headers { peer, .. } | Download::Blocks { peer, .. } => Some(*peer),
+        }
+    }
+
+    /// Whether blocks `peer` sends are the bodies this download asked for.
+    pub(crate) fn awaits_blocks_from(&self, peer: SocketAddr) -> bool {
+        matches!(self, Download::Blocks { peer: from, .. } if *from == peer)
+    }
+
+    /// Starts syncing from `peer` unless a download is already under way.
+    pub(crate) fn start(&mut self, peer: SocketAddr, chain: &Blockchain) -> Option<Request> {
+        if !matches!(self, Download::Idle) {
+            return None;
+        }
+        *self = Download::Headers {
+            peer,
+            headers: Vec::new(),
+        };
+        Some(Request::Headers(chain.locator()))
+    }
+
+    /// Takes a batch of headers from `peer`, failing if they don't pass
+    /// [`Blockchain::validate_headers`]. Once the peer has sent them
+    /// all, downloads their bodies only if they lead to more work than our
+    /// chain has.
+    pub(crate) fn on_headers(
+        &mut self,
+        peer: SocketAddr,
+        received: Vec<BlockHeader>,
+        chain: &Blockchain,
+    ) -> Result<Option<Request>> {
+        let Download::Headers { peer: from, headers } = self else {
+            return Ok(None);
+        };
+        if *from != peer {
+            return Ok(None);
+        }
+        let full = received.len() >= MAX_HEADERS;
+        chain.validate_headers_after(headers, &received)?;
+        headers.extend(received);
+        if full {
+            let last = headers.last().expect("a full batch is not empty");
+            return Ok(Some(Request::Headers(vec![last.compute_hash()])));
+        }
+
+        let Some(first) = headers.first() else {
+            *self = Download::Idle;
+            return Ok(None);
+        };
+        let base = chain.work_through(first.previous_hash()).unwrap_or_default();
+        let work = headers.iter().fold(base, |work, header| work.saturating_add(header.work()));
+        if work <= chain.cumulative_work() {
+            *self = Download::Idle;
+            return Ok(None);
+        }
+        let queue = headers
+            .iter()
+            .map(BlockHeader::compute_hash)
+            .filter(|hash| !chain.knows_block(hash))
+            .collect();
+        *self = Download::Blocks {
+            peer,
+            queue,
+            requested: Vec::new(),
+        };
+        Ok(self.next_batch())
+    }
+
+    /// Checks that `blocks` from `peer` are the ones requested, in order, and
+    /// returns them for the caller to connect.
+    pub(crate) fn on_blocks(&mut self, peer: SocketAddr, blocks: Vec<Block>) -> Result<Vec<Block>> {
+        let Download::Blocks { peer: from, requested, .. } = self else {
+            return Ok(Vec::new());
+        };
+        if *from != peer {
+            return Ok(Vec::new());
+        }
+        let hashes: Vec<&str> = blocks.iter().map(Block::hash).collect();
+        if hashes != *requested {
+            return Err(BlockchainError::Validation(
+                "peer did not send the requested blocks".to_string(),
+            ));
+        }
+        requested.clear();
+        Ok(blocks)
+    }
+
+    /// Requests the next batch of bodies, or finishes the download.
+    pub(crate) fn next_batch(&mut self) -> Option<Request> {
+        let Download::Blocks { queue, requested, .. } = self else {
+            return None;
+        };
+        if queue.is_empty() {
+            *self = Download::Idle;
+            return None;
+        }
+        let take = queue.len().min(BLOCK_BATCH);
+        *requested = queue.drain(..take).collect();
+        Some(Request::Blocks(requested.clone()))
+    }
+}
@@ -0,0 +1,73 @@
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum BlockchainError {
+    Io(io::Error),
+    Serialization(serde_json::Error),
+    Storage(String),
+    Validation(String),
+    Mining(String),
+    Wallet(String),
+    /// A chain export could not be encoded or decoded.
+    Encoding(String),
+    /// Mining was stopped through the miner's [`CancelToken`](crate::miner::CancelToken).
+    Cancelled,
+    /// A node's HTTP API answered with an error status.
+    Rpc { status: u16, message: String },
+}
+
+pub type Result<T> = std::result::Result<T, BlockchainError>;
+
+impl fmt::Display for BlockchainError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockchainError::Io(err) => write!(f, "I/O error: {}", err),
+            BlockchainError::Serialization(err) => write!(f, "serialization error: {}", err),
+            BlockchainError::Storage(msg) => write!(f, "storage error: {}", msg),
+            BlockchainError::Validation(msg) => write!(f, "validation failed: {}", msg),
+            BlockchainError::Mining(msg) => write!(f, "mining failed: {}", msg),
+            BlockchainError::Wallet(msg) => write!(f, "wallet error: {}", msg),
+            BlockchainError::Encoding(msg) => write!(f, "encoding error: {}", msg),
+            BlockchainError::Cancelled => write!(f, "mining was cancelled"),
+            BlockchainError::Rpc { status, message } => write!(f, "node answered {}: {}", status, message),
+        }
+    }
+}
+
+impl std::error::Error for BlockchainError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BlockchainError::Io(err) => Some(err),
+            BlockchainError::Serialization(err) => Some(err),
+            BlockchainError::Storage(_)
+            | BlockchainError::Validation(_)
+            | BlockchainError::Mining(_)
+            | BlockchainError::Wallet(_)
+            | BlockchainError::Encoding(_)
+            | BlockchainError::Cancelled
+            | BlockchainError::Rpc { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for BlockchainError {
+    fn from(err: io::Error) -> Self {
+        BlockchainError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for BlockchainError {
+    fn from(err: serde_json::Error) -> Self {
+        BlockchainError::Serialization(err)
+    }
+}
+
+impl From<sled::Error> for BlockchainError {
+    fn from(err: sled::Error) -> Self {
+        match err {
+            sled::Error::Io(err) => BlockchainError::Io(err),
+            other => BlockchainError::Storage(other.to_string()),
+        }
+    }
+}
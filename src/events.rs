@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+use crate::block::Block;
+use crate::sync::lock;
+use crate::transaction::Transaction;
+
+/// Changes to the main chain reported by [`Blockchain::accept_block`] and
+/// [`Blockchain::replace_chain`], in the order they were applied.
+///
+/// [`Blockchain::accept_block`]: crate::Blockchain::accept_block
+/// [`Blockchain::replace_chain`]: crate::Blockchain::replace_chain
+#[derive(Debug, Clone)]
+pub enum ChainEvent {
+    /// A block became part of the main chain.
+    BlockConnected(Block),
+    /// A block was removed from the tip during a reorganization; its
+    /// transactions are no longer confirmed.
+    BlockRolledBack(Block),
+    /// A valid block was stored on a side chain that has less work than the
+    /// main chain.
+    SideBlockStored(Block),
+}
+
+/// Notifications pushed to subscribers of a chain's [`EventBus`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data")]
+pub enum NodeEvent {
+    /// This node mined (or, under proof of stake, produced) a block.
+    BlockMined(Block),
+    /// A block from elsewhere extended the main chain.
+    BlockReceived(Block),
+    /// A transaction was accepted into the mempool.
+    TransactionQueued { txid: String, transaction: Transaction },
+    /// A transaction took the place of a pending one from the same sender
+    /// with the same sequence number, `replaced`, by paying a higher fee.
+    TransactionReplaced {
+        txid: String,
+        replaced: String,
+        transaction: Transaction,
+    },
+    /// The main chain switched to a branch with more work.
+    ChainReorged {
+        rolled_back: Vec<Block>,
+        connected: Vec<Block>,
+    },
+}
+
+impl NodeEvent {
+    /// Summarizes what accepting blocks from elsewhere did to the main chain.
+    pub(crate) fn from_chain_events(events: &[ChainEvent]) -> Vec<NodeEvent> {
+        let mut rolled_back = Vec::new();
+        let mut connected = Vec::new();
+        for event in events {
+            match event {
+                ChainEvent::BlockRolledBack(block) => rolled_back.push(block.clone()),
+                ChainEvent::BlockConnected(block) => connected.push(block.clone()),
+                ChainEvent::SideBlockStored(_) => {}
+            }
+        }
+        if rolled_back.is_empty() {
+            connected.into_iter().map(NodeEvent::BlockReceived).collect()
+        } else {
+            vec![NodeEvent::ChainReorged { rolled_back, connected }]
+        }
+    }
+}
+
+type Callback = Arc<dyn Fn(&NodeEvent) + Send + Sync>;
+
+/// Identifies a callback registered with [`EventBus::on_event`], to remove
+/// it again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Subscription(u64);
+
+/// Fans events out to any number of subscribers, each holding its own
+/// channel or callback. Clones share the same subscribers.
+#[derive(Clone, Default)]
+pub struct EventBus {
+    subscribers: Arc<Mutex<Vec<Sender<NodeEvent>>>>,
+    callbacks: Arc<Mutex<Vec<(Subscription, Callback)>>>,
+    next_subscription: Arc<AtomicU64>,
+}
+
+impl fmt::Debug for EventBus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventBus")
+            .field("subscribers", &lock(&self.subscribers).len())
+            .field("callbacks", &lock(&self.callbacks).len())
+            .finish()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        EventBus::default()
+    }
+
+    /// A channel receiving every event published from now on. Dropping the
+    /// receiver unsubscribes.
+    pub fn subscribe(&self) -> Receiver<NodeEvent> {
+        let (sender, receiver) = mpsc::channel();
+        lock(&self.subscribers).push(sender);
+        receiver
+    }
+
+    /// Runs `callback` on every event published from now on, on the
+    /// publishing thread, before [`publish`](Self::publish) returns. Events
+    /// are published with the chain locked, so the callback must not lock it.
+    /// Pass the returned subscription to [`remove`](Self::remove) to stop.
+    pub fn on_event(&self, callback: impl Fn(&NodeEvent) + Send + Sync + 'static) -> Subscription {
+        let subscription = Subscription(self.next_subscription.fetch_add(1, Ordering::Relaxed));
+        lock(&self.callbacks).push((subscription, Arc::new(callback)));
+        subscription
+    }
+
+    /// Removes a callback, returning whether it was registered.
+    pub fn remove(&self, subscription: Subscription) -> bool {
+        let mut callbacks = lock(&self.callbacks);
+        let before = callbacks.len();
+        callbacks.retain(|(registered, _)| *registered != subscription);
+        callbacks.len() < before
+    }
+
+    pub fn publish(&self, event: NodeEvent) {
+        // Run outside the lock, so callbacks may register or remove others.
+        let callbacks: Vec<Callback> = lock(&self.callbacks).iter().map(|(_, callback)| callback.clone()).collect();
+        for callback in callbacks {
+            callback(&event);
+        }
+        lock(&self.subscribers).retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
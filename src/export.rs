@@ -0,0 +1,450 @@
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::amount::Amount;
+use crate::block::{Block, BlockHeader, INITIAL_BLOCK_VERSION};
+use crate::blockchain::Blockchain;
+use crate::error::{BlockchainError, Result};
+use crate::file;
+use crate::hash::HashAlgorithm;
+use crate::params::ChainParams;
+use crate::script::Script;
+use crate::transaction::{Payment, Transaction};
+use crate::utxo::OutPoint;
+
+/// File formats a chain can be exported to and imported from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// The same layout as [`Blockchain::save_to_file`].
+    Json,
+    Cbor,
+    Bincode,
+    /// One row per transaction (or per empty block), for spreadsheets. Chain
+    /// parameters are not included.
+    Csv,
+}
+
+impl ExportFormat {
+    /// Guesses the format from a file extension, defaulting to JSON.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("cbor") => ExportFormat::Cbor,
+            Some("bin" | "bincode") => ExportFormat::Bincode,
+            Some("csv") => ExportFormat::Csv,
+            _ => ExportFormat::Json,
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(ExportFormat::Json),
+            "cbor" => Ok(ExportFormat::Cbor),
+            "bincode" => Ok(ExportFormat::Bincode),
+            "csv" => Ok(ExportFormat::Csv),
+            _ => Err(format!("unknown format {} (expected json, cbor, bincode or csv)", s)),
+        }
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ExportFormat::Json => "json",
+            ExportFormat::Cbor => "cbor",
+            ExportFormat::Bincode => "bincode",
+            ExportFormat::Csv => "csv",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Writes `chain` to `path` in `format`, replacing the file atomically. A
+/// pruned chain cannot be exported, since the file could not be validated
+/// without the discarded blocks.
+pub fn export(chain: &Blockchain, path: impl AsRef<Path>, format: ExportFormat) -> Result<()> {
+    if let Some(height) = chain.pruned_height() {
+        return Err(BlockchainError::Validation(format!(
+            "cannot export a chain pruned up to block #{}",
+            height
+        )));
+    }
+    let path = path.as_ref();
+    if format == ExportFormat::Json {
+        return chain.save_to_file(path);
+    }
+    let mut writer = Vec::new();
+    match format {
+        ExportFormat::Json => unreachable!("handled above"),
+        ExportFormat::Cbor => ciborium::into_writer(&Archive::of(chain), &mut writer).map_err(encoding("CBOR"))?,
+        ExportFormat::Bincode => bincode::serialize_into(&mut writer, &Archive::of(chain)).map_err(encoding("bincode"))?,
+        ExportFormat::Csv => {
+            let mut csv = csv::Writer::from_writer(&mut writer);
+            for row in chain.iter().flat_map(CsvRow::of_block) {
+                csv.serialize(row).map_err(encoding("CSV"))?;
+            }
+            csv.flush()?;
+        }
+    }
+    file::write_atomic(path, &writer)
+}
+
+/// Reads a chain exported with [`export`]. The chain is not validated.
+/// CSV files carry no chain parameters, so those chains get the defaults.
+pub fn import(path: impl AsRef<Path>, format: ExportFormat) -> Result<Blockchain> {
+    let path = path.as_ref();
+    if format == ExportFormat::Json {
+        return Blockchain::load_from_file(path);
+    }
+    let file = File::open(path)?;
+    let len = file.metadata()?.len();
+    let reader = BufReader::new(file);
+    let archive: Archive = match format {
+        ExportFormat::Json => unreachable!("handled above"),
+        ExportFormat::Cbor => ciborium::from_reader(reader).map_err(encoding("CBOR"))?,
+        // The options `bincode::serialize_into` writes with, limited to the
+        // file's size so that a corrupt length can't ask for more memory.
+        ExportFormat::Bincode => bincode::options()
+            .with_fixint_encoding()
+            .allow_trailing_bytes()
+            .with_limit(len)
+            .deserialize_from(reader)
+            .map_err(encoding("bincode"))?,
+        ExportFormat::Csv => {
+            let mut csv = csv::Reader::from_reader(reader);
+            let rows = csv
+                .deserialize()
+                .collect::<std::result::Result<Vec<CsvRow>, _>>()
+                .map_err(encoding("CSV"))?;
+            Archive {
+                params: ChainParams::default(),
+                blocks: CsvRow::into_blocks(rows)?,
+            }
+        }
+    };
+    Blockchain::from_blocks(archive.blocks.into_iter().map(Block::from).collect(), archive.params)
+}
+
+fn encoding<E: fmt::Display>(format: &'static str) -> impl Fn(E) -> BlockchainError {
+    move |err| BlockchainError::Encoding(format!("{}: {}", format, err))
+}
+
+/// Chain layout for the binary formats. Every field is always written, since
+/// bincode cannot skip fields the way the JSON layout does.
+#[derive(Serialize, Deserialize)]
+struct Archive {
+    params: ChainParams,
+    blocks: Vec<ArchivedBlock>,
+}
+
+impl Archive {
+    fn of(chain: &Blockchain) -> Self {
+        Archive {
+            params: chain.params().clone(),
+            blocks: chain.iter().map(ArchivedBlock::from).collect(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ArchivedBlock {
+    index: u64,
+    timestamp: u128,
+    merkle_root: String,
+    previous_hash: String,
+    nonce: u64,
+    bits: u32,
+    algorithm: HashAlgorithm,
+    version: u32,
+    hash: String,
+    transactions: Vec<ArchivedTransaction>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ArchivedTransaction {
+    sender: String,
+    receiver: String,
+    amount: Amount,
+    payments: Vec<Payment>,
+    inputs: Vec<OutPoint>,
+    change: Amount,
+    fee: Amount,
+    height: Option<u64>,
+    sequence: u64,
+    lock_time: u64,
+    asset: Option<String>,
+    issue: bool,
+    memo: Option<Vec<u8>>,
+    lock: Option<Script>,
+    unlocks: Vec<Script>,
+}
+
+fn header_of(block: &ArchivedBlock) -> BlockHeader {
+    let mut header = BlockHeader::new(
+        block.index,
+        block.timestamp,
+        block.merkle_root.clone(),
+        block.previous_hash.clone(),
+        block.bits,
+    )
+    .with_algorithm(block.algorithm)
+    .with_version(block.version);
+    header.set_nonce(block.nonce);
+    header
+}
+
+impl From<&Block> for ArchivedBlock {
+    fn from(block: &Block) -> Self {
+        ArchivedBlock {
+            index: block.index(),
+            timestamp: block.timestamp(),
+            merkle_root: block.merkle_root().to_string(),
+            previous_hash: block.previous_hash().to_string(),
+            nonce: block.nonce(),
+            bits: block.bits(),
+            algorithm: block.header().algorithm(),
+            version: block.version(),
+            hash: block.hash().to_string(),
+            transactions: block.transactions().iter().map(ArchivedTransaction::from).collect(),
+        }
+    }
+}
+
+impl From<ArchivedBlock> for Block {
+    fn from(block: ArchivedBlock) -> Self {
+        let header = header_of(&block);
+        let transactions = block.transactions.into_iter().map(Transaction::from).collect();
+        Block::from_parts(header, block.hash, transactions)
+    }
+}
+
+impl From<&Transaction> for ArchivedTransaction {
+    fn from(tx: &Transaction) -> Self {
+        ArchivedTransaction {
+            sender: tx.sender().to_string(),
+            receiver: tx.receiver().to_string(),
+            amount: tx.amount(),
+            payments: tx.payments().to_vec(),
+            inputs: tx.inputs().to_vec(),
+            change: tx.change(),
+            fee: tx.fee(),
+            height: tx.height(),
+            sequence: tx.sequence(),
+            lock_time: tx.lock_time(),
+            asset: tx.asset().map(str::to_string),
+            issue: tx.is_issue(),
+            memo: tx.memo().map(<[u8]>::to_vec),
+            lock: tx.lock().cloned(),
+            unlocks: tx.unlocks().to_vec(),
+        }
+    }
+}
+
+impl From<ArchivedTransaction> for Transaction {
+    fn from(tx: ArchivedTransaction) -> Self {
+        Transaction::from_parts(
+            tx.sender,
+            tx.receiver,
+            tx.amount,
+            tx.payments,
+            tx.inputs,
+            tx.change,
+            tx.fee,
+            tx.height,
+            tx.sequence,
+            tx.lock_time,
+            tx.asset,
+            tx.issue,
+            tx.memo,
+            tx.lock,
+            tx.unlocks,
+        )
+    }
+}
+
+/// A CSV row: the block's header fields, then one transaction. Blocks with no
+/// transactions get a single row with the transaction columns left empty.
+#[derive(Serialize, Deserialize)]
+struct CsvRow {
+    block: u64,
+    timestamp: u128,
+    merkle_root: String,
+    previous_hash: String,
+    nonce: u64,
+    bits: u32,
+    #[serde(default)]
+    algorithm: Option<HashAlgorithm>,
+    #[serde(default)]
+    version: Option<u32>,
+    hash: String,
+    sender: Option<String>,
+    receiver: Option<String>,
+    #[serde(with = "crate::amount::decimal::option")]
+    amount: Option<Amount>,
+    /// Further receivers as `receiver:amount` separated by spaces.
+    #[serde(default)]
+    payments: Option<String>,
+    /// Spent outputs as `txid:vout` separated by spaces.
+    inputs: Option<String>,
+    #[serde(with = "crate::amount::decimal::option")]
+    change: Option<Amount>,
+    #[serde(with = "crate::amount::decimal::option")]
+    fee: Option<Amount>,
+    height: Option<u64>,
+    sequence: Option<u64>,
+    #[serde(default)]
+    lock_time: Option<u64>,
+    #[serde(default)]
+    asset: Option<String>,
+    #[serde(default)]
+    issue: Option<bool>,
+    /// Hex-encoded.
+    #[serde(default)]
+    memo: Option<String>,
+    #[serde(default)]
+    lock: Option<Script>,
+    /// Unlocking scripts separated by `;`.
+    #[serde(default)]
+    unlocks: Option<String>,
+}
+
+impl CsvRow {
+    fn of_block(block: &Block) -> Vec<CsvRow> {
+        let row = |tx: Option<&Transaction>| CsvRow {
+            block: block.index(),
+            timestamp: block.timestamp(),
+            merkle_root: block.merkle_root().to_string(),
+            previous_hash: block.previous_hash().to_string(),
+            nonce: block.nonce(),
+            bits: block.bits(),
+            algorithm: Some(block.header().algorithm()),
+            version: Some(block.version()),
+            hash: block.hash().to_string(),
+            sender: tx.map(|tx| tx.sender().to_string()),
+            receiver: tx.map(|tx| tx.receiver().to_string()),
+            amount: tx.map(Transaction::amount),
+            payments: tx.map(|tx| {
+                let payments: Vec<String> = tx
+                    .payments()
+                    .iter()
+                    .map(|payment| format!("{}:{}", payment.receiver, payment.amount))
+                    .collect();
+                payments.join(" ")
+            }),
+            inputs: tx.map(|tx| {
+                let inputs: Vec<String> = tx
+                    .inputs()
+                    .iter()
+                    .map(|input| format!("{}:{}", input.txid, input.vout))
+                    .collect();
+                inputs.join(" ")
+            }),
+            change: tx.map(Transaction::change),
+            fee: tx.map(Transaction::fee),
+            height: tx.and_then(Transaction::height),
+            sequence: tx.map(Transaction::sequence),
+            lock_time: tx.map(Transaction::lock_time),
+            asset: tx.and_then(Transaction::asset).map(str::to_string),
+            issue: tx.map(Transaction::is_issue),
+            memo: tx.and_then(Transaction::memo).map(hex::encode),
+            lock: tx.and_then(Transaction::lock).cloned(),
+            unlocks: tx.map(|tx| {
+                let unlocks: Vec<String> = tx.unlocks().iter().map(Script::to_string).collect();
+                unlocks.join(";")
+            }),
+        };
+        if block.transactions().is_empty() {
+            return vec![row(None)];
+        }
+        block.transactions().iter().map(|tx| row(Some(tx))).collect()
+    }
+
+    /// Groups consecutive rows of the same block back into blocks.
+    fn into_blocks(rows: Vec<CsvRow>) -> Result<Vec<ArchivedBlock>> {
+        let mut blocks: Vec<ArchivedBlock> = Vec::new();
+        for row in rows {
+            let transaction = row.transaction()?;
+            match blocks.last_mut() {
+                Some(block) if block.index == row.block && block.hash == row.hash => {
+                    block.transactions.extend(transaction);
+                }
+                _ => blocks.push(ArchivedBlock {
+                    index: row.block,
+                    timestamp: row.timestamp,
+                    merkle_root: row.merkle_root,
+                    previous_hash: row.previous_hash,
+                    nonce: row.nonce,
+                    bits: row.bits,
+                    algorithm: row.algorithm.unwrap_or_default(),
+                    version: row.version.unwrap_or(INITIAL_BLOCK_VERSION),
+                    hash: row.hash,
+                    transactions: transaction.into_iter().collect(),
+                }),
+            }
+        }
+        Ok(blocks)
+    }
+
+    fn transaction(&self) -> Result<Option<ArchivedTransaction>> {
+        let Some(sender) = &self.sender else {
+            return Ok(None);
+        };
+        let mut payments = Vec::new();
+        for payment in self.payments.as_deref().unwrap_or_default().split_whitespace() {
+            let (receiver, amount) = payment
+                .rsplit_once(':')
+                .and_then(|(receiver, amount)| Some((receiver, amount.parse().ok()?)))
+                .ok_or_else(|| BlockchainError::Encoding(format!("CSV: invalid payment {}", payment)))?;
+            payments.push(Payment {
+                receiver: receiver.to_string(),
+                amount,
+            });
+        }
+        let mut inputs = Vec::new();
+        for input in self.inputs.as_deref().unwrap_or_default().split_whitespace() {
+            let (txid, vout) = input
+                .rsplit_once(':')
+                .and_then(|(txid, vout)| Some((txid, vout.parse().ok()?)))
+                .ok_or_else(|| BlockchainError::Encoding(format!("CSV: invalid input {}", input)))?;
+            inputs.push(OutPoint {
+                txid: txid.to_string(),
+                vout,
+            });
+        }
+        let unlocks = match self.unlocks.as_deref() {
+            None | Some("") => Vec::new(),
+            Some(unlocks) => unlocks.split(';').map(str::parse).collect::<Result<_>>()?,
+        };
+        let memo = self
+            .memo
+            .as_deref()
+            .map(hex::decode)
+            .transpose()
+            .map_err(|err| BlockchainError::Encoding(format!("CSV: invalid memo: {}", err)))?;
+        Ok(Some(ArchivedTransaction {
+            sender: sender.clone(),
+            receiver: self.receiver.clone().unwrap_or_default(),
+            amount: self.amount.unwrap_or_default(),
+            payments,
+            inputs,
+            change: self.change.unwrap_or_default(),
+            fee: self.fee.unwrap_or_default(),
+            height: self.height,
+            sequence: self.sequence.unwrap_or_default(),
+            lock_time: self.lock_time.unwrap_or_default(),
+            asset: self.asset.clone(),
+            issue: self.issue.unwrap_or_default(),
+            memo,
+            lock: self.lock.clone(),
+            unlocks,
+        }))
+    }
+}
@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+
+use crate::address;
+use crate::amount::Amount;
+use crate::blockchain::Blockchain;
+use crate::mempool::{DEFAULT_BATCH_SIZE, Mempool};
+use crate::transaction::Transaction;
+
+/// Recent blocks [`estimate_fee`] learns from.
+pub const FEE_HISTORY_BLOCKS: usize = 24;
+/// Confirmation target used when none is asked for.
+pub const DEFAULT_TARGET_BLOCKS: u64 = 2;
+/// How sure, judging by recent blocks, an estimate is to confirm in time.
+const CONFIDENCE: f64 = 0.85;
+
+/// A suggested fee, from [`estimate_fee`]. Rates are in base units per
+/// serialized byte.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeEstimate {
+    /// Blocks within which a transaction paying the estimate should confirm.
+    pub target_blocks: u64,
+    pub fee_rate: u64,
+    /// The rate applied to a transaction of `typical_size` bytes.
+    pub fee: Amount,
+    /// Median size of recently confirmed transactions.
+    pub typical_size: usize,
+    /// Lowest rate that would have made it into enough of the recent blocks.
+    pub history_rate: u64,
+    /// Rate needed to outbid the transactions pending ahead of a new one.
+    pub mempool_rate: u64,
+    pub blocks_sampled: usize,
+    pub pending: usize,
+}
+
+impl FeeEstimate {
+    /// The suggested fee for a transaction of `size` bytes.
+    pub fn fee_for(&self, size: usize) -> Amount {
+        Amount::from_units(self.fee_rate.saturating_mul(size as u64))
+    }
+}
+
+/// Compares `tx`'s fee rate with `other`'s without dividing.
+fn compare_rates(tx: &Transaction, other: &Transaction) -> Ordering {
+    let a = u128::from(tx.fee().units()) * other.size().max(1) as u128;
+    let b = u128::from(other.fee().units()) * tx.size().max(1) as u128;
+    a.cmp(&b)
+}
+
+/// Suggests a fee rate for a transaction to confirm within `target_blocks`
+/// blocks (at least one), taking the highest of the mempool's minimum fee
+/// rate and two others:
+///
+/// - from the last [`FEE_HISTORY_BLOCKS`] blocks, the lowest rate that got
+///   into enough of them to confirm in time with 85% confidence. A block
+///   with room for more transactions took any rate.
+/// - from the mempool, the rate that outbids every pending transaction but
+///   those filling the target's blocks, assuming blocks of
+///   [`DEFAULT_BATCH_SIZE`] transactions as miners build by default.
+pub fn estimate_fee(chain: &Blockchain, mempool: &Mempool, target_blocks: u64) -> FeeEstimate {
+    let target = target_blocks.max(1);
+    let mut sizes = Vec::new();
+    let mut marginal_rates = Vec::new();
+    let recent = chain.blocks().iter().skip(1).rev().filter(|block| !block.is_pruned()).take(FEE_HISTORY_BLOCKS);
+    for block in recent {
+        let transactions: Vec<&Transaction> = block.transactions().iter().filter(|tx| !tx.is_coinbase()).collect();
+        sizes.extend(transactions.iter().map(|tx| tx.size()));
+        let lowest = transactions.iter().map(|tx| tx.fee().units().div_ceil(tx.size().max(1) as u64)).min();
+        marginal_rates.push(match transactions.len() < DEFAULT_BATCH_SIZE {
+            true => 0,
+            false => lowest.unwrap_or(0),
+        });
+    }
+    let blocks_sampled = marginal_rates.len();
+    marginal_rates.sort_unstable();
+    // A block takes a rate with the chance p that a recent one did, so one
+    // of the next `target` blocks does with 1 - (1 - p)^target.
+    let history_rate = marginal_rates
+        .iter()
+        .enumerate()
+        .find(|&(i, rate)| {
+            let taken = marginal_rates[i..].iter().take_while(|other| *other == rate).count() + i;
+            let missed = 1.0 - taken as f64 / blocks_sampled as f64;
+            1.0 - missed.powi(target.min(i32::MAX as u64) as i32) >= CONFIDENCE
+        })
+        .map_or(0, |(_, rate)| *rate);
+
+    let mut pending: Vec<&Transaction> = mempool.iter().collect();
+    pending.sort_by(|a, b| compare_rates(b, a));
+    let slots = (target as usize).saturating_mul(DEFAULT_BATCH_SIZE);
+    let mempool_rate =
+        pending.get(slots.saturating_sub(1)).map_or(0, |last| last.fee().units() / last.size().max(1) as u64 + 1);
+
+    sizes.sort_unstable();
+    let typical_size = match sizes.get(sizes.len() / 2) {
+        Some(size) => *size,
+        None => {
+            let sample = address::encode(chain.params().address_version, &[0; address::HASH_LEN]);
+            let payment = Transaction::new(sample.as_str(), sample.as_str(), Amount::from_coins(1));
+            payment.with_fee(Amount::from_coins(1)).size()
+        }
+    };
+    let mut estimate = FeeEstimate {
+        target_blocks: target,
+        fee_rate: history_rate.max(mempool_rate).max(mempool.limits().min_fee_rate),
+        fee: Amount::ZERO,
+        typical_size,
+        history_rate,
+        mempool_rate,
+        blocks_sampled,
+        pending: pending.len(),
+    };
+    estimate.fee = estimate.fee_for(typical_size);
+    estimate
+}
@@ -0,0 +1,61 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+use crate::error::Result;
+
+/// Appended to a file's name for the copy of its previous contents kept by
+/// [`replace_with_backup`].
+pub(crate) const BACKUP_SUFFIX: &str = ".bak";
+
+/// `path` with `suffix` appended to its file name.
+pub(crate) fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Replaces the contents of `path` with `bytes` by writing them to a
+/// temporary file next to it, syncing that to disk and renaming it over
+/// `path`, so a crash leaves either the old contents or the new, never a mix.
+pub(crate) fn write_atomic(path: &Path, bytes: &[u8]) -> Result<()> {
+    let temp = with_suffix(path, ".tmp");
+    let mut file = File::create(&temp)?;
+    file.write_all(bytes)?;
+    file.sync_all()?;
+    drop(file);
+    fs::rename(&temp, path)?;
+    // The rename itself is only durable once the directory is synced.
+    #[cfg(unix)]
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        File::open(dir)?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Like [`write_atomic`], but first keeps what `path` held as its backup,
+/// replacing the previous backup.
+pub(crate) fn replace_with_backup(path: &Path, bytes: &[u8]) -> Result<()> {
+    if path.exists() {
+        write_atomic(&with_suffix(path, BACKUP_SUFFIX), &fs::read(path)?)?;
+    }
+    write_atomic(path, bytes)
+}
+
+/// Reads and parses `path`, falling back to its backup if that fails and
+/// there is one. The original error is returned if neither can be used.
+pub(crate) fn read_with_backup<T>(path: &Path, parse: impl Fn(&[u8]) -> Result<T>) -> Result<T> {
+    let err = match fs::read(path).map_err(Into::into).and_then(|bytes| parse(&bytes)) {
+        Ok(value) => return Ok(value),
+        Err(err) => err,
+    };
+    let backup = with_suffix(path, BACKUP_SUFFIX);
+    match fs::read(&backup).map_err(Into::into).and_then(|bytes| parse(&bytes)) {
+        Ok(value) => {
+            warn!(path = %path.display(), %err, "file is unreadable; using its backup");
+            Ok(value)
+        }
+        Err(_) => Err(err),
+    }
+}
@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use crate::amount::Amount;
+use crate::consensus::ConsensusKind;
+use crate::error::{BlockchainError, Result};
+use crate::hash::HashAlgorithm;
+use crate::block::INITIAL_BLOCK_VERSION;
+use crate::params::{ChainParams, MAX_DIFFICULTY, Upgrade};
+
+/// A network's genesis state, read from a `genesis.toml` or `genesis.json`
+/// file. Fields left out keep the default chain's values.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenesisConfig {
+    /// Name of the network; part of the genesis block, so chains with
+    /// different IDs never share blocks.
+    pub chain_id: String,
+    /// Genesis timestamp in milliseconds since the Unix epoch.
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+    /// Leading zero hex digits required of block hashes until the first retarget.
+    #[serde(default)]
+    pub difficulty: Option<usize>,
+    #[serde(default)]
+    pub block_reward: Option<Amount>,
+    /// Blocks between halvings of the block reward; it never halves unless set.
+    #[serde(default)]
+    pub halving_interval: Option<u64>,
+    /// Version byte of encoded addresses, so each network's addresses differ.
+    #[serde(default)]
+    pub address_version: Option<u8>,
+    /// Balances credited to addresses by the genesis block.
+    #[serde(default)]
+    pub allocations: BTreeMap<String, Amount>,
+    /// Consensus engine; proof of work unless set.
+    #[serde(default)]
+    pub consensus: ConsensusKind,
+    /// Most transactions per block, coinbase included.
+    #[serde(default)]
+    pub max_block_transactions: Option<usize>,
+    /// Largest serialized block, in bytes.
+    #[serde(default)]
+    pub max_block_size: Option<usize>,
+    /// Blocks whose median timestamp each new block's must exceed.
+    #[serde(default)]
+    pub median_time_span: Option<usize>,
+    /// How far in the future, in milliseconds, a block's timestamp may be.
+    #[serde(default)]
+    pub max_future_block_time_ms: Option<u64>,
+    /// Algorithm block headers are hashed with; SHA-256 unless set.
+    #[serde(default)]
+    pub hash_algorithm: HashAlgorithm,
+    /// Consensus upgrades and the heights they activate at.
+    #[serde(default)]
+    pub upgrades: Vec<Upgrade>,
+    /// Blocks a coinbase reward must wait before it may be spent.
+    #[serde(default)]
+    pub coinbase_maturity: Option<u64>,
+}
+
+impl GenesisConfig {
+    /// Reads a genesis file, parsed as TOML if its extension is `.toml` and
+    /// as JSON otherwise.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let data = fs::read_to_string(path)?;
+        let config: GenesisConfig = if path.extension().is_some_and(|ext| ext == "toml") {
+            toml::from_str(&data).map_err(|err| BlockchainError::Validation(format!("invalid genesis file: {}", err)))?
+        } else {
+            serde_json::from_str(&data)?
+        };
+        if config.difficulty.is_some_and(|difficulty| difficulty > MAX_DIFFICULTY) {
+            return Err(BlockchainError::Validation(format!(
+                "genesis difficulty cannot exceed {}",
+                MAX_DIFFICULTY
+            )));
+        }
+        if config.median_time_span == Some(0) {
+            return Err(BlockchainError::Validation(
+                "the median time span must cover at least one block".to_string(),
+            ));
+        }
+        if config.max_block_transactions == Some(0) {
+            return Err(BlockchainError::Validation(
+                "blocks must be allowed at least one transaction for the coinbase".to_string(),
+            ));
+        }
+        if let Some(upgrade) = config.upgrades.iter().find(|upgrade| upgrade.version <= INITIAL_BLOCK_VERSION) {
+            return Err(BlockchainError::Validation(format!(
+                "the upgrade at height {} must raise the block version above {}",
+                upgrade.height, INITIAL_BLOCK_VERSION
+            )));
+        }
+        if let ConsensusKind::ProofOfStake { min_stake } = config.consensus
+            && !config.allocations.values().any(|&amount| !amount.is_zero() && amount >= min_stake)
+        {
+            return Err(BlockchainError::Validation(format!(
+                "a proof-of-stake genesis needs an allocation of at least {} to start a validator",
+                min_stake
+            )));
+        }
+        Ok(config)
+    }
+
+    /// Chain parameters for this network.
+    pub fn params(&self) -> ChainParams {
+        let defaults = ChainParams::default();
+        ChainParams {
+            chain_id: self.chain_id.clone(),
+            genesis_timestamp: self.timestamp.map_or(defaults.genesis_timestamp, u128::from),
+            initial_difficulty: self.difficulty.unwrap_or(defaults.initial_difficulty),
+            block_reward: self.block_reward.unwrap_or(defaults.block_reward),
+            halving_interval: self.halving_interval.unwrap_or(defaults.halving_interval),
+            address_version: self.address_version.unwrap_or(defaults.address_version),
+            genesis_allocations: self.allocations.clone(),
+            consensus: self.consensus,
+            max_block_transactions: self.max_block_transactions.unwrap_or(defaults.max_block_transactions),
+            max_block_size: self.max_block_size.unwrap_or(defaults.max_block_size),
+            median_time_span: self.median_time_span.unwrap_or(defaults.median_time_span),
+            max_future_block_time_ms: self.max_future_block_time_ms.unwrap_or(defaults.max_future_block_time_ms),
+            hash_algorithm: self.hash_algorithm,
+            upgrades: self.upgrades.clone(),
+            coinbase_maturity: self.coinbase_maturity.unwrap_or(defaults.coinbase_maturity),
+            ..defaults
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use std::net::{SocketAddr, TcpListener, ToSocketAddrs};
+use std::sync::Arc;
+use std::thread;
+use tonic::transport::Server;
+use tonic::transport::server::TcpIncoming;
+use tonic::{Code, Request, Response, Status};
+use tracing::{debug, error};
+
+use crate::amount::Amount;
+use crate::block::Block;
+use crate::blockchain::Blockchain;
+use crate::error::{BlockchainError, Result};
+use crate::hash::HashAlgorithm;
+use crate::mempool::DEFAULT_BATCH_SIZE;
+use crate::network::SharedChain;
+use crate::rpc::SharedMempool;
+use crate::sync::{lock, read};
+use crate::transaction::{Payment, Transaction};
+use crate::utxo::OutPoint;
+
+/// Types and services generated from `proto/mini_block.proto`.
+pub mod proto {
+    tonic::include_proto!("mini_block.v1");
+}
+
+use proto::get_block_request::Block as BlockKey;
+use proto::node_server::{Node, NodeServer};
+
+type BlockHook = Arc<dyn Fn(&Blockchain) + Send + Sync>;
+type Reply<T> = std::result::Result<Response<T>, Status>;
+
+/// gRPC counterpart of [`RpcServer`](crate::rpc::RpcServer), serving the
+/// `Node` service of `proto/mini_block.proto` so clients in other languages
+/// can be generated from the schema.
+#[derive(Clone)]
+pub struct GrpcServer {
+    chain: SharedChain,
+    mempool: SharedMempool,
+    on_block: BlockHook,
+}
+
+impl GrpcServer {
+    pub fn new(chain: SharedChain, mempool: SharedMempool) -> Self {
+        GrpcServer {
+            chain,
+            mempool,
+            on_block: Arc::new(|_| {}),
+        }
+    }
+
+    /// Registers a callback run (with the chain locked) after `Mine` adds a
+    /// block, e.g. to persist or broadcast it.
+    pub fn with_block_hook(mut self, hook: impl Fn(&Blockchain) + Send + Sync + 'static) -> Self {
+        self.on_block = Arc::new(hook);
+        self
+    }
+
+    /// Serves requests on `addr` from a background thread running its own
+    /// async runtime.
+    pub fn listen(&self, addr: impl ToSocketAddrs) -> Result<SocketAddr> {
+        let listener = TcpListener::bind(addr)?;
+        let local = listener.local_addr()?;
+        listener.set_nonblocking(true)?;
+        let runtime = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+        let service = NodeServer::new(self.clone());
+        thread::spawn(move || {
+            let served = runtime.block_on(async move {
+                let listener = tokio::net::TcpListener::from_std(listener)?;
+                let incoming = TcpIncoming::from_listener(listener, true, None)
+                    .map_err(|err| BlockchainError::Io(std::io::Error::other(err)))?;
+                Server::builder()
+                    .add_service(service)
+                    .serve_with_incoming(incoming)
+                    .await
+                    .map_err(|err| BlockchainError::Io(std::io::Error::other(err)))
+            });
+            if let Err(err) = served {
+                error!(%err, "gRPC server stopped");
+            }
+        });
+        Ok(local)
+    }
+}
+
+fn failed(code: Code, err: impl std::fmt::Display) -> Status {
+    Status::new(code, err.to_string())
+}
+
+#[tonic::async_trait]
+impl Node for GrpcServer {
+    async fn get_chain_info(&self, _: Request<proto::GetChainInfoRequest>) -> Reply<proto::ChainInfo> {
+        let chain = read(&self.chain);
+        let pending = lock(&self.mempool).len();
+        Ok(Response::new(proto::ChainInfo {
+            chain_id: chain.params().chain_id.clone(),
+            height: chain.height(),
+            tip: chain.latest_block().hash().to_string(),
+            cumulative_work: chain.cumulative_work().to_string(),
+            next_bits: chain.next_bits(),
+            pending: pending as u64,
+        }))
+    }
+
+    async fn get_block(&self, request: Request<proto::GetBlockRequest>) -> Reply<proto::Block> {
+        let chain = read(&self.chain);
+        let block = match request.into_inner().block {
+            Some(BlockKey::Height(height)) => chain.block_by_index(height),
+            Some(BlockKey::Hash(hash)) => chain.block_by_hash(&hash),
+            None => return Err(failed(Code::InvalidArgument, "give a block height or hash")),
+        };
+        block
+            .map(|block| Response::new(block.into()))
+            .ok_or_else(|| failed(Code::NotFound, "no such block on the main chain"))
+    }
+
+    async fn get_transaction(&self, request: Request<proto::GetTransactionRequest>) -> Reply<proto::TransactionStatus> {
+        let txid = request.into_inner().txid;
+        if let Some((block, tx)) = read(&self.chain).get_transaction(&txid) {
+            return Ok(Response::new(proto::TransactionStatus {
+                transaction: Some(tx.into()),
+                block: Some(block.index()),
+            }));
+        }
+        match lock(&self.mempool).get(&txid) {
+            Some(tx) => Ok(Response::new(proto::TransactionStatus {
+                transaction: Some(tx.into()),
+                block: None,
+            })),
+            None => Err(failed(Code::NotFound, format!("no transaction {}", txid))),
+        }
+    }
+
+    async fn get_balance(&self, request: Request<proto::GetBalanceRequest>) -> Reply<proto::Balance> {
+        let address = request.into_inner().address;
+        let chain = read(&self.chain);
+        let next_sequence = chain.next_sequence(&lock(&self.mempool), &address);
+        let assets = chain.asset_balances(&address);
+        let (next_sequence, assets) =
+            next_sequence.and_then(|sequence| Ok((sequence, assets?))).map_err(|err| failed(Code::Internal, err))?;
+        Ok(Response::new(proto::Balance {
+            balance: chain.balance_of(&address).units(),
+            assets: assets.into_iter().map(|(asset, balance)| (asset, balance.units())).collect(),
+            next_sequence,
+            address,
+        }))
+    }
+
+    async fn submit_transaction(
+        &self,
+        request: Request<proto::Transaction>,
+    ) -> Reply<proto::SubmitTransactionResponse> {
+        let tx = Transaction::try_from(request.into_inner()).map_err(|err| failed(Code::InvalidArgument, err))?;
+        let txid = tx.hash();
+        let chain = read(&self.chain);
+        let mut mempool = lock(&self.mempool);
+        chain
+            .submit_transaction(&mut mempool, tx)
+            .map_err(|err| failed(Code::InvalidArgument, err))?;
+        Ok(Response::new(proto::SubmitTransactionResponse {
+            txid,
+            pending: mempool.len() as u64,
+        }))
+    }
+
+    async fn mine(&self, request: Request<proto::MineRequest>) -> Reply<proto::Block> {
+        let request = request.into_inner();
+        let count = if request.count == 0 { DEFAULT_BATCH_SIZE } else { request.count as usize };
+        let server = self.clone();
+        // Mining takes as long as it takes, so keep it off the threads
+        // answering other requests.
+        let mined = tokio::task::spawn_blocking(move || {
+            let block = Blockchain::mine_shared(&server.chain, &server.mempool, count, &request.miner)?;
+            (server.on_block)(&read(&server.chain));
+            debug!(index = block.index(), "mined block over gRPC");
+            Ok::<_, BlockchainError>(proto::Block::from(&block))
+        })
+        .await
+        .map_err(|err| failed(Code::Internal, err))?;
+        mined.map(Response::new).map_err(|err| failed(Code::Internal, err))
+    }
+}
+
+impl From<HashAlgorithm> for proto::HashAlgorithm {
+    fn from(algorithm: HashAlgorithm) -> Self {
+        match algorithm {
+            HashAlgorithm::Sha256 => proto::HashAlgorithm::Sha256,
+            HashAlgorithm::Sha3_256 => proto::HashAlgorithm::Sha3256,
+            HashAlgorithm::Blake3 => proto::HashAlgorithm::Blake3,
+        }
+    }
+}
+
+impl From<&Block> for proto::Block {
+    fn from(block: &Block) -> Self {
+        proto::Block {
+            header: Some(proto::BlockHeader {
+                index: block.index(),
+                timestamp: u64::try_from(block.timestamp()).unwrap_or(u64::MAX),
+                merkle_root: block.merkle_root().to_string(),
+                previous_hash: block.previous_hash().to_string(),
+                nonce: block.nonce(),
+                bits: block.bits(),
+                algorithm: proto::HashAlgorithm::from(block.header().algorithm()).into(),
+                version: block.version(),
+            }),
+            hash: block.hash().to_string(),
+            transactions: block.transactions().iter().map(proto::Transaction::from).collect(),
+        }
+    }
+}
+
+impl From<&Transaction> for proto::Transaction {
+    fn from(tx: &Transaction) -> Self {
+        proto::Transaction {
+            txid: tx.hash(),
+            sender: tx.sender().to_string(),
+            receiver: tx.receiver().to_string(),
+            amount: tx.amount().units(),
+            payments: tx
+                .payments()
+                .iter()
+                .map(|payment| proto::Payment {
+                    receiver: payment.receiver.clone(),
+                    amount: payment.amount.units(),
+                })
+                .collect(),
+            inputs: tx
+                .inputs()
+                .iter()
+                .map(|input| proto::OutPoint {
+                    txid: input.txid.clone(),
+                    vout: input.vout,
+                })
+                .collect(),
+            change: tx.change().units(),
+            fee: tx.fee().units(),
+            height: tx.height(),
+            sequence: tx.sequence(),
+            lock_time: tx.lock_time(),
+            asset: tx.asset().map(str::to_string),
+            issue: tx.is_issue(),
+            memo: tx.memo().map(<[u8]>::to_vec),
+            lock: tx.lock().map(ToString::to_string),
+            unlocks: tx.unlocks().iter().map(ToString::to_string).collect(),
+        }
+    }
+}
+
+/// Fails if one of the transaction's scripts doesn't parse.
+impl TryFrom<proto::Transaction> for Transaction {
+    type Error = BlockchainError;
+
+    fn try_from(tx: proto::Transaction) -> Result<Self> {
+        let inputs = tx
+            .inputs
+            .into_iter()
+            .map(|input| OutPoint {
+                txid: input.txid,
+                vout: input.vout,
+            })
+            .collect();
+        let payments = tx
+            .payments
+            .into_iter()
+            .map(|payment| Payment {
+                receiver: payment.receiver,
+                amount: Amount::from_units(payment.amount),
+            })
+            .collect();
+        Ok(Transaction::from_parts(
+            tx.sender,
+            tx.receiver,
+            Amount::from_units(tx.amount),
+            payments,
+            inputs,
+            Amount::from_units(tx.change),
+            Amount::from_units(tx.fee),
+            tx.height,
+            tx.sequence,
+            tx.lock_time,
+            tx.asset,
+            tx.issue,
+            tx.memo,
+            tx.lock.map(|lock| lock.parse()).transpose()?,
+            tx.unlocks.iter().map(|unlock| unlock.parse()).collect::<Result<_>>()?,
+        ))
+    }
+}
@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sha3::Sha3_256;
+use std::fmt;
+
+/// An incremental 256-bit hash, as used for block headers.
+pub trait Hasher: Send + Sync {
+    fn update(&mut self, bytes: &[u8]);
+
+    /// The digest of everything written so far followed by `suffix`,
+    /// leaving this state as it was.
+    fn finish_with(&self, suffix: &[u8]) -> [u8; 32];
+}
+
+impl Hasher for Sha256 {
+    fn update(&mut self, bytes: &[u8]) {
+        Digest::update(self, bytes);
+    }
+
+    fn finish_with(&self, suffix: &[u8]) -> [u8; 32] {
+        self.clone().chain_update(suffix).finalize().into()
+    }
+}
+
+impl Hasher for Sha3_256 {
+    fn update(&mut self, bytes: &[u8]) {
+        Digest::update(self, bytes);
+    }
+
+    fn finish_with(&self, suffix: &[u8]) -> [u8; 32] {
+        self.clone().chain_update(suffix).finalize().into()
+    }
+}
+
+impl Hasher for blake3::Hasher {
+    fn update(&mut self, bytes: &[u8]) {
+        blake3::Hasher::update(self, bytes);
+    }
+
+    fn finish_with(&self, suffix: &[u8]) -> [u8; 32] {
+        *self.clone().update(suffix).finalize().as_bytes()
+    }
+}
+
+/// The algorithm a chain hashes its block headers with. It is chosen in the
+/// genesis config and every header names it, so blocks hashed differently
+/// from the genesis block are rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Sha3_256,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    /// A fresh hash state for this algorithm.
+    pub fn hasher(self) -> Box<dyn Hasher> {
+        match self {
+            HashAlgorithm::Sha256 => Box::new(Sha256::new()),
+            HashAlgorithm::Sha3_256 => Box::new(Sha3_256::new()),
+            HashAlgorithm::Blake3 => Box::new(blake3::Hasher::new()),
+        }
+    }
+
+    pub fn digest(self, bytes: &[u8]) -> [u8; 32] {
+        self.hasher().finish_with(bytes)
+    }
+
+    pub fn is_default(&self) -> bool {
+        *self == HashAlgorithm::default()
+    }
+
+    /// Identifies the algorithm in the headers it hashes.
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            HashAlgorithm::Sha256 => 0,
+            HashAlgorithm::Sha3_256 => 1,
+            HashAlgorithm::Blake3 => 2,
+        }
+    }
+}
+
+impl fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HashAlgorithm::Sha256 => "SHA-256",
+            HashAlgorithm::Sha3_256 => "SHA3-256",
+            HashAlgorithm::Blake3 => "BLAKE3",
+        })
+    }
+}
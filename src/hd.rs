@@ -0,0 +1,74 @@
+use bip39::Mnemonic;
+use ed25519_dalek::SigningKey;
+use hmac::{Hmac, Mac};
+use rand_core::{OsRng, RngCore};
+use sha2::Sha512;
+
+use crate::error::{BlockchainError, Result};
+
+/// Hardened derivation path of account keys, `m/44'/1'/0'/0'/i'`, minus the
+/// final index.
+pub const ACCOUNT_PATH: [u32; 4] = [44, 1, 0, 0];
+
+const HARDENED: u32 = 1 << 31;
+
+/// Generates a BIP39 English mnemonic of 12 or 24 words.
+pub fn generate_mnemonic(words: usize) -> Result<String> {
+    let mut entropy = [0u8; 32];
+    let len = match words {
+        12 => 16,
+        24 => 32,
+        _ => return Err(BlockchainError::Wallet("mnemonics have 12 or 24 words".to_string())),
+    };
+    OsRng.fill_bytes(&mut entropy[..len]);
+    let mnemonic = Mnemonic::from_entropy(&entropy[..len])
+        .map_err(|err| BlockchainError::Wallet(format!("cannot build mnemonic: {}", err)))?;
+    Ok(mnemonic.to_string())
+}
+
+/// The 64-byte BIP39 seed of `phrase`, after checking its words and checksum.
+/// Wallets use no passphrase.
+pub fn seed_from_mnemonic(phrase: &str) -> Result<[u8; 64]> {
+    seed_with_passphrase(phrase, "")
+}
+
+/// The 64-byte BIP39 seed of `phrase` salted with `passphrase`.
+pub fn seed_with_passphrase(phrase: &str, passphrase: &str) -> Result<[u8; 64]> {
+    let mnemonic =
+        Mnemonic::parse(phrase).map_err(|err| BlockchainError::Wallet(format!("invalid mnemonic: {}", err)))?;
+    Ok(mnemonic.to_seed(passphrase))
+}
+
+/// Derives the ed25519 key at `path` from `seed` using SLIP-0010, which for
+/// ed25519 only allows hardened children; every index is hardened here.
+pub fn derive_key(seed: &[u8], path: &[u32]) -> SigningKey {
+    let (mut key, mut chain_code) = split(hmac(b"ed25519 seed", &[seed]));
+    for index in path {
+        let index = (index | HARDENED).to_be_bytes();
+        (key, chain_code) = split(hmac(&chain_code, &[&[0u8], &key, &index]));
+    }
+    SigningKey::from_bytes(&key)
+}
+
+/// The `index`th account key of `seed`.
+pub fn account_key(seed: &[u8], index: u32) -> SigningKey {
+    let mut path = ACCOUNT_PATH.to_vec();
+    path.push(index);
+    derive_key(seed, &path)
+}
+
+fn hmac(key: &[u8], parts: &[&[u8]]) -> [u8; 64] {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    for part in parts {
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+fn split(bytes: [u8; 64]) -> ([u8; 32], [u8; 32]) {
+    let mut left = [0u8; 32];
+    let mut right = [0u8; 32];
+    left.copy_from_slice(&bytes[..32]);
+    right.copy_from_slice(&bytes[32..]);
+    (left, right)
+}
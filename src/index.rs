@@ -0,0 +1,194 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::amount::Amount;
+use crate::block::Block;
+use crate::blockchain::Blockchain;
+
+/// Where a confirmed transaction sits: its block's height and its position
+/// in the block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxLocation {
+    pub height: u64,
+    pub position: usize,
+}
+
+/// Lookups over the main chain kept up to date as blocks are connected and
+/// rolled back, so they don't scan every block. Stores save it beside the
+/// blocks, so it is only rebuilt when it is lost or out of step with them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainIndex {
+    /// Hash of the last block connected.
+    tip: Option<String>,
+    /// Height of each block by hash.
+    heights: HashMap<String, u64>,
+    /// Where each transaction is, by ID.
+    transactions: HashMap<String, TxLocation>,
+    /// Transactions sending to or from each address, oldest first.
+    addresses: HashMap<String, Vec<TxLocation>>,
+}
+
+impl ChainIndex {
+    /// An index of `blocks`, a main chain from genesis.
+    pub fn build(blocks: &[Block]) -> Self {
+        let mut index = ChainIndex::default();
+        for block in blocks {
+            index.connect(block);
+        }
+        index
+    }
+
+    /// Brings an index saved earlier in line with `blocks` by connecting the
+    /// blocks added since, or rebuilds it if its tip is no longer among
+    /// them. Returns whether anything changed.
+    pub fn catch_up(&mut self, blocks: &[Block]) -> bool {
+        let saved = self.tip.clone();
+        let next = match self.tip() {
+            Some((hash, height)) if blocks.get(height as usize).is_some_and(|block| block.hash() == hash) => {
+                height as usize + 1
+            }
+            _ => {
+                *self = ChainIndex::default();
+                0
+            }
+        };
+        for block in &blocks[next.min(blocks.len())..] {
+            self.connect(block);
+        }
+        self.tip != saved
+    }
+
+    /// The hash and height of the last block connected.
+    pub fn tip(&self) -> Option<(&str, u64)> {
+        let hash = self.tip.as_deref()?;
+        Some((hash, *self.heights.get(hash)?))
+    }
+
+    /// Adds a block appended to the main chain.
+    pub fn connect(&mut self, block: &Block) {
+        self.heights.insert(block.hash().to_string(), block.index());
+        self.tip = Some(block.hash().to_string());
+        for (position, tx) in block.transactions().iter().enumerate() {
+            let location = TxLocation {
+                height: block.index(),
+                position,
+            };
+            self.transactions.insert(tx.hash(), location);
+            for address in tx.parties() {
+                self.addresses.entry(address.to_string()).or_default().push(location);
+            }
+        }
+    }
+
+    /// Removes the tip of the main chain, `block`, after it was rolled back.
+    pub fn disconnect(&mut self, block: &Block) {
+        self.heights.remove(block.hash());
+        self.tip = Some(block.previous_hash().to_string()).filter(|parent| self.heights.contains_key(parent));
+        for tx in block.transactions() {
+            let txid = tx.hash();
+            if self.transactions.get(&txid).is_some_and(|location| location.height == block.index()) {
+                self.transactions.remove(&txid);
+            }
+            for address in tx.parties() {
+                if let Some(locations) = self.addresses.get_mut(address) {
+                    locations.retain(|location| location.height < block.index());
+                    if locations.is_empty() {
+                        self.addresses.remove(address);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Height of the main-chain block with this hash.
+    pub fn height_of(&self, hash: &str) -> Option<u64> {
+        self.heights.get(hash).copied()
+    }
+
+    /// Where the confirmed transaction with this ID is.
+    pub fn transaction(&self, txid: &str) -> Option<TxLocation> {
+        self.transactions.get(txid).copied()
+    }
+
+    /// Transactions sending to or from `address`, oldest first.
+    pub fn address(&self, address: &str) -> &[TxLocation] {
+        self.addresses.get(address).map_or(&[], Vec::as_slice)
+    }
+
+    /// Number of transactions indexed.
+    pub fn transaction_count(&self) -> usize {
+        self.transactions.len()
+    }
+
+    /// Number of addresses with indexed transactions.
+    pub fn address_count(&self) -> usize {
+        self.addresses.len()
+    }
+}
+
+/// Which way a transaction moved coins for the address whose history lists it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    /// A block reward or genesis allocation.
+    Mined,
+    Received,
+    Sent,
+    /// Sent by the address to itself, costing only the fee.
+    ToSelf,
+}
+
+/// One line of [`history`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HistoryEntry {
+    pub height: u64,
+    pub txid: String,
+    pub direction: Direction,
+    /// The other side of the transaction, receivers separated by `, `; empty
+    /// for coinbases.
+    pub counterparty: String,
+    pub amount: Amount,
+    /// Set if `amount` is of this asset rather than the native coin.
+    pub asset: Option<String>,
+    /// The fee, if the address paid it.
+    pub fee: Amount,
+    /// The address's native balance after the transaction.
+    pub balance: Amount,
+}
+
+/// Every confirmed transaction involving `address`, oldest first, with its
+/// balance after each. On a pruned chain only transactions after the pruned
+/// blocks are listed, starting from the balance the chain kept for them.
+pub fn history(chain: &Blockchain, address: &str) -> Vec<HistoryEntry> {
+    let mut balance = chain.pruned_balance_of(address);
+    chain
+        .transactions_for_address(address)
+        .map(|(block, tx)| {
+            let sent = tx.sender() == address && !tx.is_coinbase();
+            if sent {
+                balance = balance.saturating_sub(tx.cost().unwrap_or(Amount::MAX));
+            }
+            if tx.asset().is_none() {
+                balance = balance.saturating_add(tx.amount_to(address));
+            }
+            let others: Vec<&str> = tx.parties().into_iter().filter(|party| *party != address).collect();
+            let total = tx.total_amount().unwrap_or(Amount::MAX);
+            let (direction, counterparty, amount) = match (tx.is_coinbase(), tx.sender() == address) {
+                (true, _) => (Direction::Mined, String::new(), tx.amount_to(address)),
+                (false, true) if others.is_empty() => (Direction::ToSelf, address.to_string(), total),
+                (false, true) => (Direction::Sent, others.join(", "), total),
+                (false, false) => (Direction::Received, tx.sender().to_string(), tx.amount_to(address)),
+            };
+            HistoryEntry {
+                height: block.index(),
+                txid: tx.hash(),
+                direction,
+                counterparty,
+                amount,
+                asset: tx.asset().map(str::to_string),
+                fee: if sent { tx.fee() } else { Amount::ZERO },
+                balance,
+            }
+        })
+        .collect()
+}
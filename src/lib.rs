@@ -0,0 +1,91 @@
+pub mod address;
+pub mod amount;
+pub mod anchor;
+pub mod asset;
+pub mod audit;
+pub mod automine;
+pub mod ban;
+pub mod batch;
+pub mod block;
+pub mod blockchain;
+pub mod client;
+pub mod clock;
+pub mod compact;
+pub mod config;
+pub mod consensus;
+#[cfg(unix)]
+pub mod control;
+mod download;
+pub mod error;
+pub mod events;
+pub mod export;
+pub mod fee;
+mod file;
+pub mod genesis;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod hash;
+pub mod hd;
+pub mod index;
+pub mod light;
+pub mod mempool;
+pub mod merkle;
+pub mod metrics;
+pub mod miner;
+pub mod network;
+pub mod noise;
+pub mod offline;
+pub mod orphan;
+pub mod params;
+pub mod profile;
+pub mod qr;
+pub mod rpc;
+pub mod script;
+pub mod sigcache;
+pub mod sim;
+pub mod state;
+pub mod stats;
+pub mod store;
+pub mod stratum;
+mod sync;
+pub mod target;
+pub mod transaction;
+pub mod utxo;
+pub mod validation;
+pub mod wallet;
+
+pub use amount::Amount;
+pub use asset::Asset;
+pub use audit::AuditReport;
+pub use ban::BanList;
+pub use block::{Block, BlockHeader, BlockTemplate, BlockTotals, HeaderHasher};
+pub use blockchain::Blockchain;
+pub use clock::{Clock, ManualClock, SystemClock};
+pub use config::{Config, StorageBackend};
+pub use consensus::{Consensus, ConsensusKind};
+pub use error::{BlockchainError, Result};
+pub use events::{ChainEvent, EventBus, NodeEvent, Subscription};
+pub use export::ExportFormat;
+pub use fee::FeeEstimate;
+pub use genesis::GenesisConfig;
+pub use hash::{HashAlgorithm, Hasher};
+pub use index::ChainIndex;
+pub use light::{HeaderChain, TxProof};
+pub use mempool::{Mempool, MempoolLimits};
+pub use merkle::MerkleProof;
+pub use metrics::Metrics;
+pub use miner::{CancelToken, Miner, MiningJob, MiningProgress};
+pub use noise::NodeKey;
+pub use orphan::OrphanPool;
+pub use params::{ChainParams, Upgrade};
+pub use profile::ChainProfile;
+pub use script::Script;
+pub use sigcache::SignatureCache;
+pub use state::ChainState;
+pub use stats::ChainStats;
+pub use store::{ChainStore, LogStore, SledStore};
+pub use target::Target;
+pub use transaction::{Payment, Transaction};
+pub use utxo::{OutPoint, TxOutput, UtxoSet};
+pub use validation::{TxCheck, TxError, ValidationReport, Violation};
+pub use wallet::{KeyBackup, UnlockedWallet, Wallet};
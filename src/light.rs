@@ -0,0 +1,218 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::block::{Block, BlockHeader};
+use crate::blockchain::{self, Blockchain};
+use crate::error::{BlockchainError, Result};
+use crate::file;
+use crate::merkle::MerkleProof;
+use crate::params::ChainParams;
+use crate::transaction::Transaction;
+
+/// A confirmed transaction and the Merkle proof that the block with
+/// `block_hash` commits to it, as a full node sends it to a light client.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TxProof {
+    pub block_hash: String,
+    pub transaction: Transaction,
+    pub proof: MerkleProof,
+}
+
+/// The chain as a light client keeps it: headers only, each checked to link
+/// to its parent, use the chain's hash algorithm, carry the expected target,
+/// meet it, and be later than the median of recent headers. Transactions
+/// are checked against the headers' Merkle roots with a [`TxProof`] rather
+/// than replayed.
+///
+/// Under proof of stake the producer of each block can't be checked without
+/// balances, so a light client trusts its peers' choice of producer.
+#[derive(Debug)]
+pub struct HeaderChain {
+    params: ChainParams,
+    /// The headers as pruned blocks, so the consensus engine can judge them.
+    blocks: Vec<Block>,
+}
+
+/// The form a header chain is saved in.
+#[derive(Serialize, Deserialize)]
+struct StoredHeaders {
+    params: ChainParams,
+    headers: Vec<BlockHeader>,
+}
+
+fn header_block(header: BlockHeader) -> Block {
+    let hash = header.compute_hash();
+    let mut block = Block::from_parts(header, hash, Vec::new());
+    block.prune();
+    block
+}
+
+impl HeaderChain {
+    /// A header chain holding only the genesis block of the chain with `params`.
+    pub fn new(params: ChainParams) -> Result<Self> {
+        let genesis = Blockchain::with_params(params.clone())?.latest_block().header().clone();
+        Ok(HeaderChain {
+            params,
+            blocks: vec![header_block(genesis)],
+        })
+    }
+
+    pub fn params(&self) -> &ChainParams {
+        &self.params
+    }
+
+    pub fn height(&self) -> u64 {
+        self.blocks.len() as u64 - 1
+    }
+
+    pub fn tip(&self) -> &BlockHeader {
+        self.blocks[self.blocks.len() - 1].header()
+    }
+
+    pub fn genesis_hash(&self) -> &str {
+        self.blocks[0].hash()
+    }
+
+    pub fn headers(&self) -> impl Iterator<Item = &BlockHeader> {
+        self.blocks.iter().map(Block::header)
+    }
+
+    /// The main-chain header with this hash, if we have it.
+    pub fn header_by_hash(&self, hash: &str) -> Option<&BlockHeader> {
+        self.blocks.iter().find(|block| block.hash() == hash).map(Block::header)
+    }
+
+    /// See [`Blockchain::locator`].
+    pub fn locator(&self) -> Vec<String> {
+        blockchain::locator_of(&self.blocks)
+    }
+
+    pub fn cumulative_work(&self) -> u128 {
+        blockchain::total_work(&self.blocks)
+    }
+
+    /// Checks `headers`, which must follow a header we have, and adopts them
+    /// if the chain they lead to has more work than ours. Returns how many
+    /// headers were connected (0 if ours was kept).
+    pub fn extend(&mut self, headers: Vec<BlockHeader>) -> Result<usize> {
+        let Some(first) = headers.first() else {
+            return Ok(0);
+        };
+        let fork = self
+            .blocks
+            .iter()
+            .rposition(|block| block.hash() == first.previous_hash())
+            .ok_or_else(|| {
+                BlockchainError::Validation(format!("header #{} does not build on our headers", first.index()))
+            })?;
+        let mut candidate = self.blocks[..=fork].to_vec();
+        let consensus = self.params.consensus.engine(&self.params);
+        for header in headers {
+            let block = header_block(header);
+            let index = block.index();
+            let parent = &candidate[candidate.len() - 1];
+            if block.previous_hash() != parent.hash() || index != parent.index() + 1 {
+                return Err(BlockchainError::Validation(format!(
+                    "header #{} does not follow the one before it",
+                    index
+                )));
+            }
+            let version = self.params.block_version(index);
+            if block.version() < version {
+                return Err(BlockchainError::Validation(format!(
+                    "header #{} is of version {}, below the {} an upgrade requires",
+                    index,
+                    block.version(),
+                    version
+                )));
+            }
+            if block.header().algorithm() != self.params.hash_algorithm {
+                return Err(BlockchainError::Validation(format!(
+                    "header #{} is hashed with {}, not {}",
+                    index,
+                    block.header().algorithm(),
+                    self.params.hash_algorithm
+                )));
+            }
+            if block.bits() != consensus.next_bits(&candidate) {
+                return Err(BlockchainError::Validation(format!("header #{} has the wrong target", index)));
+            }
+            if !block.meets_target() {
+                return Err(BlockchainError::Validation(format!(
+                    "header #{} does not meet its proof-of-work target",
+                    index
+                )));
+            }
+            if block.timestamp() <= blockchain::median_time_past(&candidate, self.params.median_time_span) {
+                return Err(BlockchainError::Validation(format!(
+                    "header #{} is not after the median of recent headers",
+                    index
+                )));
+            }
+            candidate.push(block);
+        }
+        if blockchain::total_work(&candidate) <= self.cumulative_work() {
+            return Ok(0);
+        }
+        let connected = candidate.len() - fork - 1;
+        self.blocks = candidate;
+        Ok(connected)
+    }
+
+    /// Checks that `proof` shows its transaction is in a block on our
+    /// header chain, returning the number of confirmations it has.
+    pub fn verify(&self, proof: &TxProof) -> Result<u64> {
+        let header = self.header_by_hash(&proof.block_hash).ok_or_else(|| {
+            BlockchainError::Validation(format!("block {} is not on our header chain", proof.block_hash))
+        })?;
+        if proof.transaction.hash() != proof.proof.tx_hash {
+            return Err(BlockchainError::Validation(
+                "proof is for a different transaction".to_string(),
+            ));
+        }
+        if !proof.proof.verify(header.merkle_root()) {
+            return Err(BlockchainError::Validation(format!(
+                "proof does not match the Merkle root of block #{}",
+                header.index()
+            )));
+        }
+        Ok(self.height() - header.index() + 1)
+    }
+
+    /// Writes the headers to `path` as JSON, replacing the file atomically.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let stored = StoredHeaders {
+            params: self.params.clone(),
+            headers: self.headers().cloned().collect(),
+        };
+        file::write_atomic(path.as_ref(), &serde_json::to_vec(&stored)?)
+    }
+
+    /// Reads headers saved by [`HeaderChain::save`], checking them again,
+    /// or starts from genesis if `path` does not exist. Headers saved for a
+    /// chain other than the one with `params` are refused.
+    pub fn load(path: impl AsRef<Path>, params: ChainParams) -> Result<Self> {
+        let path = path.as_ref();
+        let mut chain = HeaderChain::new(params)?;
+        if !path.exists() {
+            return Ok(chain);
+        }
+        let stored: StoredHeaders = serde_json::from_slice(&fs::read(path)?)?;
+        if stored.params != chain.params {
+            return Err(BlockchainError::Validation(format!(
+                "{} holds headers of a chain other than {}",
+                path.display(),
+                chain.params.chain_id
+            )));
+        }
+        if stored.headers.first().map(BlockHeader::compute_hash).as_deref() != Some(chain.genesis_hash()) {
+            return Err(BlockchainError::Validation(format!(
+                "{} starts from a different genesis block",
+                path.display()
+            )));
+        }
+        chain.extend(stored.headers.into_iter().skip(1).collect())?;
+        Ok(chain)
+    }
+}
@@ -1,202 +1,2604 @@
-use sha2::{Sha256, Digest};
-use std::io::Write;
-use std::time::{SystemTime, UNIX_EPOCH};
-use serde::{Serialize, Deserialize};
+use clap::{Args, Parser, Subcommand};
+use mini_block::anchor;
+use mini_block::audit;
+use mini_block::automine::{AutoMiner, AutoMining};
+use mini_block::batch;
+use mini_block::config::{self, CONFIG_FILE, Config, StorageBackend};
+#[cfg(unix)]
+use mini_block::control::{self, ControlRequest, ControlResponse, ControlSocket, SOCKET_FILE};
+use mini_block::export::{self, ExportFormat};
+use mini_block::fee;
+#[cfg(feature = "grpc")]
+use mini_block::grpc::GrpcServer;
+use mini_block::hd;
+use mini_block::index::{self, Direction};
+use mini_block::offline::TransactionFile;
+use mini_block::qr;
+use mini_block::sim::{self, Partition, SimConfig};
+use mini_block::stats;
+use mini_block::mempool::{DEFAULT_BATCH_SIZE, MempoolLimits};
+use mini_block::network::{LightClient, Node, SharedChain};
+use mini_block::rpc::{RpcServer, SharedMempool};
+use mini_block::stratum::{DEFAULT_SHARE_DIFFICULTY, StratumServer, StratumWorker};
+use mini_block::transaction::{describe_lock_time, describe_memo};
+use mini_block::{
+    Amount, Blockchain, BlockchainError, CancelToken, ChainProfile, ChainStore, GenesisConfig, HeaderChain, LogStore, Mempool,
+    Miner, NodeKey, Payment, SledStore, ChainParams, ConsensusKind, Target, Transaction, UnlockedWallet, Wallet,
+};
+use serde_json::{Value, json};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
 use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::EnvFilter;
 
-const DIFFICULTY: usize = 4; // Number of leading zeros for mining
+// Files kept in the data directory.
+const LOG_PATH: &str = "blockchain.log";
+const SLED_PATH: &str = "blockchain.db";
+/// Chain kept by older versions, migrated into the store when it is first created.
+const LEGACY_JSON: &str = "blockchain.json";
+const WALLET_PATH: &str = "wallet.json";
+const MEMPOOL_PATH: &str = "mempool.json";
+/// Headers kept in light mode.
+const HEADERS_PATH: &str = "headers.json";
+/// The node's private key, by whose public half peers know it.
+const NODE_KEY_PATH: &str = "node.key";
+/// Read instead of prompting for the wallet password, for scripts.
+const PASSWORD_ENV: &str = "MINI_BLOCK_PASSWORD";
+/// Restoring stops after this many unused addresses in a row.
+const RESTORE_GAP_LIMIT: usize = 20;
+/// How long `difficulty` measures the hash rate for.
+const CALIBRATION_TIME: Duration = Duration::from_secs(1);
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Transaction {
-    sender: String,
-    receiver: String,
-    amount: u32,
+thread_local! {
+    /// Output of the control command running on this thread, and the
+    /// password sent with it; see [`capture`].
+    static CONTROL: RefCell<Option<(String, Option<String>)>> = const { RefCell::new(None) };
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Block {
-    pub index: u64,
-    pub timestamp: u128,
-    pub transactions: Vec<Transaction>,
-    pub previous_hash: String,
-    pub hash: String,
-    pub nonce: u64,
+/// Like `println!`, except that while a control command runs the line is
+/// kept for the `ctl` client that sent it.
+macro_rules! outln {
+    () => {
+        outln!("")
+    };
+    ($($arg:tt)*) => {
+        write_line(format!($($arg)*))
+    };
 }
 
-impl Block {
-    pub fn new(index: u64, transactions: Vec<Transaction>, previous_hash: String) -> Self {
-        let mut nonce = 0;
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_millis();
+fn write_line(line: String) {
+    let captured = CONTROL.with_borrow_mut(|control| match control {
+        Some((output, _)) => {
+            output.push_str(&line);
+            output.push('\n');
+            true
+        }
+        None => false,
+    });
+    if !captured {
+        println!("{}", line);
+    }
+}
+
+/// Runs `command`, collecting what it prints and giving it `password` for
+/// the wallet.
+#[cfg(unix)]
+fn capture<T>(password: Option<String>, command: impl FnOnce() -> T) -> (String, T) {
+    CONTROL.set(Some((String::new(), password)));
+    let result = command();
+    let (output, _) = CONTROL.take().unwrap_or_default();
+    (output, result)
+}
+
+/// Mini blockchain with mining, transactions and peer-to-peer sync.
+///
+/// Run a command once (`mini-block add alice bob 10 --mine carol`) or start
+/// the interactive REPL by giving no command.
+#[derive(Parser)]
+#[command(name = "mini-block", version)]
+struct Cli {
+    /// Directory holding the chain, wallet and config.toml [default: ~/.mini-block]
+    #[arg(long, value_name = "DIR", global = true, env = "MINI_BLOCK_DATA_DIR")]
+    data_dir: Option<PathBuf>,
+    /// Configuration file [default: config.toml in the data directory]
+    #[arg(long, value_name = "FILE", global = true, env = "MINI_BLOCK_CONFIG")]
+    config: Option<PathBuf>,
+    /// Accept peer connections on this port
+    #[arg(long, value_name = "PORT", global = true, env = "MINI_BLOCK_LISTEN")]
+    listen: Option<u16>,
+    /// Connect to a peer at startup (repeatable)
+    #[arg(long = "peer", value_name = "ADDR", global = true, env = "MINI_BLOCK_PEERS", value_delimiter = ',')]
+    peers: Vec<String>,
+    /// Only talk to the peer with this public key (repeatable); see `node-key`
+    #[arg(long = "allow-peer", value_name = "KEY", global = true, env = "MINI_BLOCK_ALLOW_PEERS", value_delimiter = ',')]
+    allow_peers: Vec<String>,
+    /// Number of mining threads (defaults to one per CPU)
+    #[arg(long, value_name = "COUNT", global = true, env = "MINI_BLOCK_THREADS")]
+    threads: Option<usize>,
+    /// Print command results as JSON instead of text
+    #[arg(long, global = true)]
+    json: bool,
+    /// Log more detail to stderr (-v for debug, -vv for trace); overrides RUST_LOG
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+    /// Log only errors; overrides RUST_LOG
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Encrypted wallet file [default: wallet.json in the data directory]
+    #[arg(long, value_name = "FILE", global = true, env = "MINI_BLOCK_WALLET")]
+    wallet: Option<PathBuf>,
+    /// Genesis file (TOML or JSON) describing the network to join
+    #[arg(long, value_name = "FILE", global = true, env = "MINI_BLOCK_GENESIS")]
+    genesis: Option<PathBuf>,
+    /// Network to join: mainnet, testnet or regtest; other networks keep their files in a subdirectory of the
+    /// data directory [default: mainnet]
+    #[arg(long, value_name = "NAME", global = true, env = "MINI_BLOCK_CHAIN", conflicts_with = "genesis")]
+    chain: Option<ChainProfile>,
+    /// How the chain is stored: log or sled [default: log]
+    #[arg(long, value_name = "BACKEND", global = true, env = "MINI_BLOCK_STORAGE")]
+    storage: Option<StorageBackend>,
+    /// Leading zero hex digits required of block hashes, overriding the genesis
+    #[arg(long, value_name = "DIGITS", global = true, env = "MINI_BLOCK_DIFFICULTY")]
+    difficulty: Option<usize>,
+    /// Block reward, overriding the genesis
+    #[arg(long, value_name = "AMOUNT", global = true, env = "MINI_BLOCK_REWARD")]
+    reward: Option<Amount>,
+    /// Discard the transactions of blocks more than DEPTH below the tip, keeping their headers
+    #[arg(long, value_name = "DEPTH", global = true, env = "MINI_BLOCK_PRUNE")]
+    prune: Option<u64>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    #[command(flatten)]
+    Chain(ChainCommand),
+    /// Serve the HTTP API instead of running a command
+    Serve(ServeArgs),
+    /// Run headless, serving the HTTP API and taking commands from `ctl` over a control socket in the data
+    /// directory
+    #[cfg(unix)]
+    Daemon(ServeArgs),
+    /// Run a command, as typed into the REPL, on the daemon for this data directory
+    #[cfg(unix)]
+    Ctl {
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true, value_name = "COMMAND")]
+        args: Vec<String>,
+    },
+    /// Mine for the node serving mining jobs at POOL (see `serve --stratum`) until stopped
+    Worker {
+        /// Address of the node, as HOST:PORT
+        pool: String,
+        /// Name the node counts this worker's shares under
+        #[arg(long, default_value = "worker")]
+        name: String,
+    },
+    /// Start the interactive REPL (the default when no command is given)
+    Repl,
+    /// Show the public key peers know this node by, for their --allow-peer lists
+    NodeKey,
+    /// Follow the chain as a light client, keeping only block headers and checking transactions with Merkle
+    /// proofs from a --peer
+    Light {
+        #[command(subcommand)]
+        command: LightCommand,
+    },
+    /// Run a network of nodes in this process over delayed links, with random payments, mining and
+    /// partitions, and check that they agree on one chain in the end
+    Simulate(SimulateArgs),
+}
+
+#[derive(Args)]
+struct SimulateArgs {
+    #[arg(long, default_value_t = 4)]
+    nodes: usize,
+    #[arg(long, default_value_t = 10)]
+    rounds: usize,
+    /// Delay of every message between two nodes
+    #[arg(long, value_name = "MS", default_value_t = 20)]
+    latency_ms: u64,
+    /// Payments submitted each round
+    #[arg(long, default_value_t = 3)]
+    transactions: usize,
+    /// Cut nodes off from the rest for some rounds, as FROM..TO:NODE,NODE (e.g. 2..5:0,1); may be repeated
+    #[arg(long, value_name = "PARTITION")]
+    partition: Vec<Partition>,
+    /// Seed for the random payments and choice of miners
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+}
+
+#[derive(Args)]
+struct ServeArgs {
+    /// [default: rpc_port from the config file, or the chain's RPC port (8080 on mainnet)]
+    #[arg(env = "MINI_BLOCK_RPC_PORT")]
+    port: Option<u16>,
+    /// Also hand out mining jobs to `worker` processes on this port
+    #[arg(long, value_name = "PORT", requires = "payout")]
+    stratum: Option<u16>,
+    /// Address paid by the blocks workers find
+    #[arg(long, value_name = "ADDRESS", requires = "stratum")]
+    payout: Option<String>,
+    /// Leading zero hex digits a worker's share needs (block hashes also count)
+    #[arg(long, value_name = "DIGITS", default_value_t = DEFAULT_SHARE_DIFFICULTY)]
+    share_difficulty: usize,
+    /// Mine a block of pending transactions every SECS seconds
+    #[arg(long, value_name = "SECS", requires = "mine_to", value_parser = clap::value_parser!(u64).range(1..))]
+    auto_mine: Option<u64>,
+    /// Address paid by auto-mined blocks
+    #[arg(long, value_name = "ADDRESS", requires = "auto_mine")]
+    mine_to: Option<String>,
+    /// Auto-mine blocks even when nothing is pending (always on for regtest)
+    #[arg(long, requires = "auto_mine")]
+    mine_empty: bool,
+    /// Also serve the gRPC API of proto/mini_block.proto on this port
+    #[cfg(feature = "grpc")]
+    #[arg(long, value_name = "PORT")]
+    grpc: Option<u16>,
+}
+
+#[derive(Subcommand)]
+enum LightCommand {
+    /// Download and check the headers the peer has past ours
+    Sync,
+    /// Sync, then check that a transaction is in a block on the header chain
+    Verify { txid: String },
+}
 
-        // Mining: find hash with DIFFICULTY leading zeros
-        let mut hash = Block::calculate_hash(index, timestamp, &transactions, &previous_hash, nonce);
-        while !hash.starts_with(&"0".repeat(DIFFICULTY)) {
-            nonce += 1;
-            hash = Block::calculate_hash(index, timestamp, &transactions, &previous_hash, nonce);
+/// Commands available both one-shot and inside the REPL.
+#[derive(Subcommand)]
+enum ChainCommand {
+    /// Queue a new transaction
+    Add {
+        sender: String,
+        receiver: String,
+        amount: Amount,
+        /// Send this asset instead of the native coin
+        #[arg(long)]
+        asset: Option<String>,
+        /// Fee offered to the miner; higher fee rates are mined first
+        #[arg(long, default_value_t = Amount::ZERO)]
+        fee: Amount,
+        /// Keep it out of blocks until this height, or this time in milliseconds if at least 500000000
+        #[arg(long, value_name = "HEIGHT|MS", default_value_t = 0)]
+        lock_time: u64,
+        /// Data to record on the chain with it, such as a document hash to timestamp
+        #[arg(long, value_name = "TEXT", conflicts_with = "memo_hex")]
+        memo: Option<String>,
+        /// Like --memo, but given as hex-encoded bytes
+        #[arg(long, value_name = "HEX", value_parser = parse_memo_hex)]
+        memo_hex: Option<MemoBytes>,
+        /// Immediately mine a block containing it, rewarding this address
+        #[arg(long, value_name = "MINER")]
+        mine: Option<String>,
+    },
+    /// Queue an issuance of an asset, creating it if it does not exist yet
+    Issue {
+        asset: String,
+        amount: Amount,
+        /// Address credited with the new units; only the asset's first issuer may issue more
+        #[arg(long)]
+        issuer: String,
+        /// Fee offered to the miner; higher fee rates are mined first
+        #[arg(long, default_value_t = Amount::ZERO)]
+        fee: Amount,
+        /// Immediately mine a block containing it, rewarding this address
+        #[arg(long, value_name = "MINER")]
+        mine: Option<String>,
+    },
+    /// Pay from the wallet: sign a transaction from one of its addresses, queue it and print its ID
+    Send {
+        receiver: String,
+        amount: Amount,
+        /// Wallet address to pay from [default: the first that can afford it]
+        #[arg(long)]
+        from: Option<String>,
+        /// Fee offered to the miner; higher fee rates are mined first
+        #[arg(long, default_value_t = Amount::ZERO)]
+        fee: Amount,
+        /// Data to record on the chain with it, such as a document hash to timestamp
+        #[arg(long, value_name = "TEXT")]
+        memo: Option<String>,
+        /// Immediately mine a block containing it, rewarding this address
+        #[arg(long, value_name = "MINER")]
+        mine: Option<String>,
+    },
+    /// Build an unsigned transaction and write it to a file, to be signed offline with sign-tx
+    CreateTx {
+        receiver: String,
+        amount: Amount,
+        /// Address paying it, whose key signs it later
+        #[arg(long)]
+        from: String,
+        /// Fee offered to the miner; higher fee rates are mined first
+        #[arg(long, default_value_t = Amount::ZERO)]
+        fee: Amount,
+        /// Data to record on the chain with it, such as a document hash to timestamp
+        #[arg(long, value_name = "TEXT")]
+        memo: Option<String>,
+        /// Where to write the transaction file
+        #[arg(long, short)]
+        out: PathBuf,
+    },
+    /// Sign a transaction file from create-tx with the wallet's keys; needs no network
+    SignTx {
+        file: PathBuf,
+        /// Write the signed transaction here instead of over the file
+        #[arg(long, short)]
+        out: Option<PathBuf>,
+    },
+    /// Queue a transaction file signed with sign-tx
+    BroadcastTx {
+        file: PathBuf,
+        /// Immediately mine a block containing it, rewarding this address
+        #[arg(long, value_name = "MINER")]
+        mine: Option<String>,
+    },
+    /// Queue one transaction paying several receivers, e.g. `pay --from alice bob 5 carol 2`
+    Pay {
+        /// Receivers, each followed by the amount it is paid
+        #[arg(value_name = "RECEIVER AMOUNT", required = true, num_args = 2..)]
+        payments: Vec<String>,
+        /// Address paying them all
+        #[arg(long)]
+        from: String,
+        /// Fee offered to the miner; higher fee rates are mined first
+        #[arg(long, default_value_t = Amount::ZERO)]
+        fee: Amount,
+        /// Data to record on the chain with it, such as a document hash to timestamp
+        #[arg(long, value_name = "TEXT", conflicts_with = "memo_hex")]
+        memo: Option<String>,
+        /// Like --memo, but given as hex-encoded bytes
+        #[arg(long, value_name = "HEX", value_parser = parse_memo_hex)]
+        memo_hex: Option<MemoBytes>,
+        /// Immediately mine a block containing it, rewarding this address
+        #[arg(long, value_name = "MINER")]
+        mine: Option<String>,
+    },
+    /// Queue every transaction listed in a file, reporting the ones rejected
+    AddBatch {
+        /// CSV with a sender,receiver,amount[,fee] header, or a .json array of objects with those fields
+        file: PathBuf,
+        /// Immediately mine a block containing them, rewarding this address
+        #[arg(long, value_name = "MINER")]
+        mine: Option<String>,
+    },
+    /// Queue a transaction spending the sender's unspent outputs
+    Spend {
+        sender: String,
+        receiver: String,
+        amount: Amount,
+        /// Fee offered to the miner; higher fee rates are mined first
+        #[arg(long, default_value_t = Amount::ZERO)]
+        fee: Amount,
+        /// Keep it out of blocks until this height, or this time in milliseconds if at least 500000000
+        #[arg(long, value_name = "HEIGHT|MS", default_value_t = 0)]
+        lock_time: u64,
+        /// Data to record on the chain with it, such as a document hash to timestamp
+        #[arg(long, value_name = "TEXT", conflicts_with = "memo_hex")]
+        memo: Option<String>,
+        /// Like --memo, but given as hex-encoded bytes
+        #[arg(long, value_name = "HEX", value_parser = parse_memo_hex)]
+        memo_hex: Option<MemoBytes>,
+        /// Immediately mine a block containing it, rewarding this address
+        #[arg(long, value_name = "MINER")]
+        mine: Option<String>,
+    },
+    /// Replace a pending account-model transaction with a copy paying a higher fee
+    BumpFee {
+        txid: String,
+        /// The new fee, above the old one at no lower a fee rate
+        fee: Amount,
+        /// Immediately mine a block containing it, rewarding this address
+        #[arg(long, value_name = "MINER")]
+        mine: Option<String>,
+    },
+    /// Queue a transaction recording the SHA-256 digest of a file, proving it existed once mined
+    Anchor {
+        file: PathBuf,
+        /// Address the anchoring transaction is sent from and to
+        #[arg(long)]
+        from: String,
+        /// Fee offered to the miner; higher fee rates are mined first
+        #[arg(long, default_value_t = Amount::ZERO)]
+        fee: Amount,
+        /// Immediately mine a block containing it, rewarding this address
+        #[arg(long, value_name = "MINER")]
+        mine: Option<String>,
+    },
+    /// Mine pending transactions into a new block
+    Mine {
+        /// Address credited with the block reward and fees
+        miner: String,
+        /// Maximum number of pending transactions to include
+        #[arg(default_value_t = DEFAULT_BATCH_SIZE)]
+        count: usize,
+    },
+    /// Show the confirmed balance of an address, and of any assets it holds
+    Balance { address: String },
+    /// List the unspent outputs owned by an address
+    Utxos { address: String },
+    /// List every confirmed transaction involving an address, with its balance after each
+    History { address: String },
+    /// Show a confirmed or pending transaction by its ID
+    Tx { txid: String },
+    /// List the pending transactions, best fee rate first, with the mempool's size and limits
+    Mempool,
+    /// Show the next block's target and estimate how long this machine takes to mine it
+    Difficulty {
+        /// Estimate for hashes with this many leading zero hex digits instead
+        #[arg(long, value_name = "DIGITS")]
+        digits: Option<usize>,
+    },
+    /// Find the block that first anchored a file's digest, and when it was mined
+    VerifyAnchor { file: PathBuf },
+    /// View the entire blockchain
+    View,
+    /// Check the blockchain and report every rule it breaks
+    Validate {
+        /// Check every block again, not just those added since the last validation
+        #[arg(long)]
+        full: bool,
+    },
+    /// Rebuild the block, transaction and address indexes from the stored blocks
+    Reindex,
+    /// Summarize the chain: transactions, block intervals, coins in circulation and the richest addresses
+    Stats {
+        /// How many of the richest addresses to list
+        #[arg(long, default_value_t = 5)]
+        top: usize,
+    },
+    /// Suggest a fee for confirming within a number of blocks, from recent blocks and the pending transactions
+    FeeEstimate {
+        /// Blocks within which the transaction should confirm
+        #[arg(default_value_t = fee::DEFAULT_TARGET_BLOCKS)]
+        blocks: u64,
+    },
+    /// Replay the chain's books: coins issued, fees, each address's flows, and whether value was conserved
+    Audit {
+        /// Only list the wallet's addresses, watched ones included
+        #[arg(long)]
+        wallet_only: bool,
+    },
+    /// Export the chain to a file
+    Export {
+        file: PathBuf,
+        /// json, cbor, bincode or csv; guessed from the file extension by default
+        #[arg(long)]
+        format: Option<ExportFormat>,
+    },
+    /// Replace the chain with one exported from this network
+    Import {
+        file: PathBuf,
+        /// json, cbor, bincode or csv; guessed from the file extension by default
+        #[arg(long)]
+        format: Option<ExportFormat>,
+    },
+    /// Manage the encrypted wallet
+    #[command(subcommand)]
+    Wallet(WalletCommand),
+}
+
+#[derive(Subcommand)]
+enum WalletCommand {
+    /// Create a new wallet holding one fresh keypair
+    #[command(alias = "new")]
+    Create {
+        /// Derive keys from a new mnemonic phrase that can restore the wallet
+        #[arg(long)]
+        mnemonic: bool,
+        /// Number of words in the mnemonic (12 or 24)
+        #[arg(long, default_value_t = 12, requires = "mnemonic")]
+        words: usize,
+    },
+    /// Recreate a wallet and its used addresses from a mnemonic phrase
+    Restore {
+        #[arg(required = true, num_args = 1..)]
+        phrase: Vec<String>,
+    },
+    /// List the wallet's addresses and their balances
+    List,
+    /// Check the password and keep the keys unlocked for this session
+    Unlock,
+    /// Add a new keypair to the wallet
+    Generate,
+    /// Follow an address without its key, listing its balance and history with the wallet's own
+    Watch { address: String },
+    /// List the confirmed transactions of every wallet address, watched ones included
+    History,
+    /// Show a wallet address as a QR code, for a phone wallet or a paper backup
+    ExportQr {
+        address: String,
+        /// Encode the address's key, encrypted under the wallet password, instead
+        #[arg(long)]
+        key: bool,
+        /// Write a PNG image instead of drawing the code in the terminal
+        #[arg(long, value_name = "FILE")]
+        png: Option<PathBuf>,
+        /// Pixels per module in the PNG
+        #[arg(long, default_value_t = qr::DEFAULT_SCALE, requires = "png")]
+        scale: u32,
+    },
+}
+
+// A line typed into the REPL, parsed with the same command definitions.
+#[derive(Parser)]
+#[command(
+    no_binary_name = true,
+    disable_version_flag = true,
+    about = "Interactive mini-block session",
+    override_usage = "<COMMAND> [ARGS]"
+)]
+struct ReplLine {
+    #[command(subcommand)]
+    command: ReplCommand,
+}
+
+#[derive(Subcommand)]
+enum ReplCommand {
+    #[command(flatten)]
+    Chain(ChainCommand),
+    /// Set the number of mining threads
+    Threads { count: usize },
+    /// List the connected peers with their keys and misbehavior scores, and the banned addresses
+    Peers,
+    /// Exit the program
+    #[command(alias = "quit")]
+    Exit,
+}
+
+struct App {
+    json: bool,
+    chain: SharedChain,
+    cancel: CancelToken,
+    /// Whether the miner prints a live progress line.
+    progress: bool,
+    mempool: SharedMempool,
+    /// Where the mempool is saved on exit and restored from at startup.
+    mempool_path: PathBuf,
+    store: Store,
+    node: Option<Node>,
+    wallet_path: PathBuf,
+    wallet: Option<UnlockedWallet>,
+}
+
+/// Command-line flags and environment variables merged over the config file.
+struct Settings {
+    data_dir: PathBuf,
+    profile: ChainProfile,
+    listen: Option<u16>,
+    peers: Vec<String>,
+    allow_peers: Vec<String>,
+    rpc_port: u16,
+    threads: Option<usize>,
+    wallet: PathBuf,
+    genesis: Option<PathBuf>,
+    storage: StorageBackend,
+    difficulty: Option<usize>,
+    reward: Option<Amount>,
+    checkpoints: BTreeMap<u64, String>,
+    prune: Option<u64>,
+    mempool: MempoolLimits,
+}
+
+impl Settings {
+    fn resolve(cli: &Cli) -> Result<Self, String> {
+        let data_dir = cli.data_dir.clone().unwrap_or_else(config::default_data_dir);
+        fs::create_dir_all(&data_dir)
+            .map_err(|err| format!("Failed to create data directory {}: {}", data_dir.display(), err))?;
+        let config_path = cli.config.clone().unwrap_or_else(|| data_dir.join(CONFIG_FILE));
+        let config =
+            Config::load(&config_path).map_err(|err| format!("Failed to read {}: {}", config_path.display(), err))?;
+        let serve_port = match &cli.command {
+            Some(Command::Serve(serve)) => serve.port,
+            #[cfg(unix)]
+            Some(Command::Daemon(serve)) => serve.port,
+            _ => None,
+        };
+        let genesis = cli.genesis.clone().or_else(|| config.genesis.map(|path| data_dir.join(path)));
+        let profile = match (cli.chain, &genesis) {
+            (Some(profile), _) => profile,
+            (None, Some(_)) => ChainProfile::Mainnet,
+            (None, None) => config.chain.unwrap_or_default(),
+        };
+        let data_dir = match profile {
+            ChainProfile::Mainnet => data_dir,
+            profile => {
+                let dir = data_dir.join(profile.to_string());
+                fs::create_dir_all(&dir)
+                    .map_err(|err| format!("Failed to create data directory {}: {}", dir.display(), err))?;
+                dir
+            }
+        };
+        let peers = if cli.peers.is_empty() { config.peers } else { cli.peers.clone() };
+        let allow_peers = if cli.allow_peers.is_empty() { config.allow_peers } else { cli.allow_peers.clone() };
+        Ok(Settings {
+            profile,
+            listen: cli.listen.or(config.listen),
+            peers: peers.iter().map(|peer| with_default_port(peer, profile.peer_port())).collect(),
+            allow_peers,
+            rpc_port: serve_port.or(config.rpc_port).unwrap_or(profile.rpc_port()),
+            threads: cli.threads.or(config.threads),
+            wallet: cli
+                .wallet
+                .clone()
+                .unwrap_or_else(|| data_dir.join(config.wallet.as_deref().unwrap_or(Path::new(WALLET_PATH)))),
+            genesis,
+            storage: cli.storage.or(config.storage).unwrap_or_default(),
+            difficulty: cli.difficulty.or(config.difficulty),
+            reward: cli.reward.or(config.reward),
+            checkpoints: config.checkpoints,
+            prune: cli.prune.or(config.prune),
+            mempool: config.mempool,
+            data_dir,
+        })
+    }
+
+    /// The node's key, generated the first time it is needed.
+    fn node_key(&self) -> Result<NodeKey, String> {
+        let path = self.data_dir.join(NODE_KEY_PATH);
+        NodeKey::load_or_generate(&path).map_err(|err| format!("Failed to read node key {}: {}", path.display(), err))
+    }
+
+    /// Parameters a new chain must start from, if anything overrides the defaults.
+    fn params(&self) -> Result<Option<ChainParams>, String> {
+        let mut params = match &self.genesis {
+            Some(path) => Some(
+                GenesisConfig::load(path)
+                    .map_err(|err| format!("Failed to read {}: {}", path.display(), err))?
+                    .params(),
+            ),
+            None if self.profile != ChainProfile::Mainnet => Some(self.profile.params()),
+            None => None,
+        };
+        if self.difficulty.is_some() || self.reward.is_some() {
+            let params = params.get_or_insert_with(ChainParams::default);
+            if let Some(difficulty) = self.difficulty {
+                params.initial_difficulty = difficulty;
+            }
+            if let Some(reward) = self.reward {
+                params.block_reward = reward;
+            }
         }
+        Ok(params)
+    }
+}
 
-        Block {
-            index,
-            timestamp,
-            transactions,
-            previous_hash,
-            hash,
-            nonce,
+/// A rough duration, such as `about 3.5 minutes`.
+fn describe_seconds(seconds: f64) -> String {
+    const UNITS: [(f64, &str); 5] =
+        [(365.25 * 86_400.0, "years"), (86_400.0, "days"), (3600.0, "hours"), (60.0, "minutes"), (1.0, "seconds")];
+    if !seconds.is_finite() {
+        return "forever".to_string();
+    }
+    if seconds < 1.0 {
+        return "under a second".to_string();
+    }
+    let (unit, name) = UNITS.into_iter().find(|(unit, _)| seconds >= *unit).unwrap_or(UNITS[4]);
+    format!("about {:.1} {}", seconds / unit, name)
+}
+
+/// `peer` with `port` appended unless it already ends in one.
+fn with_default_port(peer: &str, port: u16) -> String {
+    match peer.rsplit_once(':') {
+        Some((_, given)) if given.parse::<u16>().is_ok() => peer.to_string(),
+        _ => format!("{}:{}", peer, port),
+    }
+}
+
+/// The chain store selected by the settings. Clones share the same storage.
+#[derive(Clone)]
+enum Store {
+    Log(LogStore),
+    Sled(SledStore),
+}
+
+impl Store {
+    fn get(&mut self) -> &mut dyn ChainStore {
+        match self {
+            Store::Log(store) => store,
+            Store::Sled(store) => store,
         }
     }
+}
 
-    fn calculate_hash(index: u64, timestamp: u128, transactions: &Vec<Transaction>, previous_hash: &str, nonce: u64) -> String {
-        let mut hasher = Sha256::new();
-        hasher.update(index.to_string());
-        hasher.update(timestamp.to_string());
-        hasher.update(nonce.to_string());
-        for tx in transactions {
-            hasher.update(&tx.sender);
-            hasher.update(&tx.receiver);
-            hasher.update(tx.amount.to_string());
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn read<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn write<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Writes the chain to `store` and the mempool to `mempool_path`, returning
+/// the chain height and how many transactions are pending.
+fn save_state(
+    chain: &SharedChain,
+    mempool: &SharedMempool,
+    store: &mut Store,
+    mempool_path: &Path,
+) -> mini_block::Result<(u64, usize)> {
+    let blockchain = read(chain);
+    blockchain.persist(store.get())?;
+    let mempool = lock(mempool);
+    mempool.save(mempool_path)?;
+    Ok((blockchain.height(), mempool.len()))
+}
+
+/// Queues the transactions saved by the last run again, dropping any that
+/// were mined since or are no longer valid, and keeps `path` up to date with
+/// the mempool from then on.
+fn restore_mempool(blockchain: &Blockchain, path: &Path, limits: MempoolLimits) -> Mempool {
+    let mut mempool = Mempool::with_limits(limits);
+    let saved = match Mempool::load_entries(path) {
+        Ok(saved) => saved,
+        Err(err) => {
+            warn!(path = %path.display(), %err, "failed to read saved mempool");
+            Vec::new()
         }
-        hasher.update(previous_hash);
-        let result = hasher.finalize();
-        format!("{:x}", result)
+    };
+    let total = saved.len();
+    for (tx, queued_at) in saved {
+        let queued_at = queued_at.unwrap_or_else(|| blockchain.height());
+        if let Err(err) = blockchain.restore_transaction(&mut mempool, tx, queued_at) {
+            debug!(%err, "dropped saved transaction");
+        }
+    }
+    if total > 0 {
+        info!(restored = mempool.len(), dropped = total - mempool.len(), "restored pending transactions");
     }
+    if mempool.len() < total
+        && let Err(err) = mempool.save(path)
+    {
+        warn!(path = %path.display(), %err, "failed to save the mempool");
+    }
+    mempool.persist_to(path);
+    mempool
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Blockchain {
-    pub blocks: Vec<Block>,
+/// Memo bytes as clap sees them: one value, not a list of `u8`s.
+type MemoBytes = Vec<u8>;
+
+fn parse_memo_hex(text: &str) -> std::result::Result<MemoBytes, String> {
+    hex::decode(text.trim_start_matches("0x")).map_err(|err| err.to_string())
 }
 
-impl Blockchain {
-    pub fn new() -> Self {
-        let mut blockchain = Blockchain { blocks: Vec::new() };
-        blockchain.create_genesis_block();
-        blockchain
+/// `tx` carrying whichever of the `--memo` and `--memo-hex` options was given.
+fn with_memo(tx: Transaction, memo: Option<String>, memo_hex: Option<Vec<u8>>) -> Transaction {
+    match memo.map(String::into_bytes).or(memo_hex) {
+        Some(memo) => tx.with_memo(memo),
+        None => tx,
     }
+}
+
+/// `payments` given as receiver and amount pairs, as `pay` takes them.
+fn parse_payments(payments: &[String]) -> mini_block::Result<Vec<Payment>> {
+    payments
+        .chunks(2)
+        .map(|pair| match pair {
+            [receiver, amount] => Ok(Payment {
+                receiver: receiver.clone(),
+                amount: amount.parse()?,
+            }),
+            _ => Err(BlockchainError::Validation(format!("no amount given for {}", pair[0]))),
+        })
+        .collect()
+}
+
+/// Each receiver of `tx` and its amount: `bob : 5, carol : 2`.
+fn describe_payments(tx: &Transaction) -> String {
+    let payments: Vec<String> =
+        tx.recipients().map(|(receiver, amount)| format!("{} : {}", receiver, amount)).collect();
+    payments.join(", ")
+}
 
-    fn create_genesis_block(&mut self) {
-        let genesis_block = Block::new(0, vec![], "0".to_string());
-        self.blocks.push(genesis_block);
+fn print_history(address: &str, entries: &[index::HistoryEntry]) {
+    if entries.is_empty() {
+        outln!("No confirmed transactions involve {}", address);
     }
+    for entry in entries {
+        let (action, sign) = match entry.direction {
+            Direction::Mined => ("mined".to_string(), "+"),
+            Direction::Received => (format!("received from {}", entry.counterparty), "+"),
+            Direction::Sent => (format!("sent to {}", entry.counterparty), "-"),
+            Direction::ToSelf => ("sent to itself".to_string(), ""),
+        };
+        let amount = match &entry.asset {
+            Some(asset) => format!("{} {}", entry.amount, asset),
+            None => entry.amount.to_string(),
+        };
+        let fee = if !entry.fee.is_zero() { format!(" (fee {})", entry.fee) } else { String::new() };
+        outln!(
+            "  #{} {} {}{}{} -> balance {}  [{}]",
+            entry.height, action, sign, amount, fee, entry.balance, entry.txid
+        );
+    }
+}
 
-    pub fn add_block(&mut self, transactions: Vec<Transaction>) {
-        let previous_block = self.blocks.last().unwrap();
-        let new_index = previous_block.index + 1;
-        let new_block = Block::new(new_index, transactions, previous_block.hash.clone());
-        self.blocks.push(new_block);
+fn view_chain(blockchain: &Blockchain) {
+    outln!("Blockchain:");
+    outln!("==========");
+    let mut work = 0u128;
+    for block in blockchain.blocks() {
+        work = work.saturating_add(block.work());
+        outln!("Block #{}", block.index());
+        outln!("Version: {}", block.version());
+        outln!("Timestamp: {}", block.timestamp());
+        outln!("Nonce: {}", block.nonce());
+        outln!("Target: {:08x}", block.bits());
+        outln!("Cumulative Work: {}", work);
+        outln!("Previous Hash: {}", block.previous_hash());
+        outln!("Merkle Root: {}", block.merkle_root());
+        outln!("Hash: {}", block.hash());
+        let totals = block.totals();
+        outln!("Size: {} bytes", totals.size);
+        outln!("Coinbase: {} ({} subsidy + {} fees)", totals.coinbase, totals.subsidy(), totals.fees);
+        outln!("Transferred: {}", totals.transferred);
+        if block.is_pruned() {
+            outln!("Transactions: pruned");
+        } else if block.transactions().is_empty() {
+            outln!("Transactions: None");
+        } else {
+            outln!("Transactions:");
+            for tx in block.transactions() {
+                let fee = if !tx.fee().is_zero() {
+                    format!(", fee {}", tx.fee())
+                } else {
+                    String::new()
+                };
+                if tx.inputs().is_empty() {
+                    outln!("  {} -> {}{}", tx.sender(), describe_payments(tx), fee);
+                } else {
+                    outln!(
+                        "  {} -> {} (spends {} output(s), change {}{})",
+                        tx.sender(),
+                        describe_payments(tx),
+                        tx.inputs().len(),
+                        tx.change(),
+                        fee
+                    );
+                }
+                if let Some(memo) = tx.memo() {
+                    outln!("    memo: {}", describe_memo(memo));
+                }
+            }
+        }
+        outln!("-------------------");
     }
+    outln!("Total work: {}", blockchain.cumulative_work());
+}
 
-    pub fn view_chain(&self) {
-        println!("Blockchain:");
-        println!("==========");
-        for block in &self.blocks {
-            println!("Block #{}", block.index);
-            println!("Timestamp: {}", block.timestamp);
-            println!("Nonce: {}", block.nonce);
-            println!("Previous Hash: {}", block.previous_hash);
-            println!("Hash: {}", block.hash);
-            if block.transactions.is_empty() {
-                println!("Transactions: None");
-            } else {
-                println!("Transactions:");
-                for tx in &block.transactions {
-                    println!("  {} -> {} : {}", tx.sender, tx.receiver, tx.amount);
+/// Callback that writes chain changes made outside the REPL to the store.
+fn persist_hook(store: Store) -> impl Fn(&Blockchain) + Send + Sync + 'static {
+    let store = Mutex::new(store);
+    move |blockchain| {
+        let mut store = store.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Err(err) = blockchain.persist(store.get()) {
+            error!(%err, "failed to save blockchain");
+        }
+    }
+}
+
+fn open_store(settings: &Settings) -> Result<Store, String> {
+    let path = settings.data_dir.join(match settings.storage {
+        StorageBackend::Log => LOG_PATH,
+        StorageBackend::Sled => SLED_PATH,
+    });
+    let failed = |err: BlockchainError| format!("Failed to open {}: {}", path.display(), err);
+    match settings.storage {
+        StorageBackend::Log => {
+            LogStore::open(&path).map(Store::Log).map_err(failed)
+        }
+        StorageBackend::Sled => SledStore::open(&path).map(Store::Sled).map_err(failed),
+    }
+}
+
+fn open_chain(settings: &Settings) -> Result<(Store, Blockchain), String> {
+    let mut store = open_store(settings)?;
+    let data_dir = &settings.data_dir;
+    let empty = store.get().is_empty().unwrap_or(false);
+    if empty && fs::canonicalize(data_dir).ok() != fs::canonicalize(".").ok() {
+        for file in [LOG_PATH, SLED_PATH, LEGACY_JSON] {
+            if Path::new(file).exists() {
+                warn!("found {} in the current directory; pass --data-dir . to keep using it", file);
+            }
+        }
+    }
+    if let Some(params) = settings.params()? {
+        let blockchain = Blockchain::open_store_with(store.get(), params)
+            .map_err(|err| format!("Failed to load blockchain: {}", err))?;
+        return configure_chain(settings, store, blockchain);
+    }
+    let legacy_db = data_dir.join(SLED_PATH);
+    let legacy_json = data_dir.join(LEGACY_JSON);
+    let loaded = match (&mut store, empty) {
+        // Migrate chains saved by older versions to a sled database or a
+        // single JSON file.
+        (Store::Log(log), true) if legacy_db.exists() => SledStore::open(&legacy_db)
+            .and_then(|mut legacy| Blockchain::open_store(&mut legacy))
+            .and_then(|blockchain| blockchain.persist(log).map(|()| blockchain)),
+        (store, true) if legacy_json.exists() => Blockchain::load_from_file(&legacy_json)
+            .and_then(|blockchain| blockchain.persist(store.get()).map(|()| blockchain)),
+        (store, _) => Blockchain::open_store(store.get()),
+    };
+    let blockchain = loaded.map_err(|err| format!("Failed to load blockchain: {}", err))?;
+    configure_chain(settings, store, blockchain)
+}
+
+/// Applies the checkpoints and prune depth, saving any blocks pruned.
+fn configure_chain(
+    settings: &Settings,
+    mut store: Store,
+    mut blockchain: Blockchain,
+) -> Result<(Store, Blockchain), String> {
+    blockchain.set_checkpoints(settings.checkpoints.clone());
+    blockchain
+        .set_prune_depth(settings.prune)
+        .and_then(|()| blockchain.persist(store.get()))
+        .map_err(|err| format!("Failed to prune blockchain: {}", err))?;
+    Ok((store, blockchain))
+}
+
+/// The password a control command was sent with, or failing that the one
+/// in the environment.
+fn given_password() -> Option<String> {
+    let sent = CONTROL.with_borrow(|control| control.as_ref().map(|(_, password)| password.clone()));
+    match sent {
+        Some(password) => password,
+        None => env::var(PASSWORD_ENV).ok(),
+    }
+}
+
+fn read_password(prompt: &str) -> io::Result<String> {
+    if let Some(password) = given_password() {
+        return Ok(password);
+    }
+    if CONTROL.with_borrow(Option::is_some) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("the daemon can't prompt for a password; set {} for ctl", PASSWORD_ENV),
+        ));
+    }
+    rpassword::prompt_password(prompt)
+}
+
+fn is_daemon(cli: &Cli) -> bool {
+    #[cfg(unix)]
+    if let Some(Command::Daemon(_)) = cli.command {
+        return true;
+    }
+    false
+}
+
+fn start_node(
+    settings: &Settings,
+    chain: &SharedChain,
+    mempool: &SharedMempool,
+    store: &Store,
+) -> Result<Option<Node>, String> {
+    if settings.listen.is_none() && settings.peers.is_empty() {
+        return Ok(None);
+    }
+    let key = settings.node_key()?;
+    info!(key = %key.public_key(), "node key");
+    let mut node = Node::new(Arc::clone(chain))
+        .with_update_hook(persist_hook(store.clone()))
+        .with_key(key)
+        .with_mempool(Arc::clone(mempool));
+    if !settings.allow_peers.is_empty() {
+        node = node.with_allowlist(settings.allow_peers.iter().cloned());
+    }
+    if let Some(port) = settings.listen {
+        let addr = node
+            .listen(("0.0.0.0", port))
+            .map_err(|err| format!("Failed to listen on port {}: {}", port, err))?;
+        info!(%addr, "listening for peers");
+    }
+    for peer in &settings.peers {
+        match node.connect(peer.as_str()) {
+            Ok(addr) => info!(%addr, "connected to peer"),
+            Err(err) => warn!(peer, %err, "failed to connect to peer"),
+        }
+    }
+    Ok(Some(node))
+}
+
+impl App {
+    /// Prints a command's result: `json` with `--json`, otherwise `text`.
+    fn emit(&self, json: impl FnOnce() -> Value, text: impl FnOnce()) {
+        if self.json {
+            outln!("{:#}", json());
+        } else {
+            text();
+        }
+    }
+
+    /// Saves the chain and mempool, returning whether that succeeded.
+    fn save(&mut self) -> bool {
+        match save_state(&self.chain, &self.mempool, &mut self.store, &self.mempool_path) {
+            Ok((_, pending)) => {
+                if pending > 0 && !self.json {
+                    outln!("Saved {} pending transaction(s)", pending);
                 }
+                true
             }
-            println!("-------------------");
+            Err(err) => self.fail("Failed to save", err),
         }
     }
 
-    pub fn is_chain_valid(&self) -> bool {
-        for i in 1..self.blocks.len() {
-            let current = &self.blocks[i];
-            let previous = &self.blocks[i - 1];
+    /// Reports a failed command and returns `false` for the caller to pass on.
+    fn fail(&self, context: &str, err: impl fmt::Display) -> bool {
+        if self.json {
+            outln!("{:#}", json!({ "error": format!("{}: {}", context, err) }));
+        } else {
+            outln!("{}: {}", context, err);
+        }
+        false
+    }
 
-            let recalculated_hash = Block::calculate_hash(
-                current.index,
-                current.timestamp,
-                &current.transactions,
-                &current.previous_hash,
-                current.nonce,
-            );
+    fn mine(&mut self, miner: &str, count: usize) -> bool {
+        self.cancel.reset();
+        let result = Blockchain::mine_shared(&self.chain, &self.mempool, count, miner);
+        if self.progress {
+            // Clear the progress line.
+            eprint!("\r\x1b[K");
+        }
+        let block = match result {
+            Ok(block) => block,
+            Err(err) => return self.fail("Failed to mine block", err),
+        };
+        if let Some(node) = &self.node {
+            node.broadcast_block(&block);
+        }
+        let mined = block.transactions().len() - 1;
+        // The coinbase pays the block reward plus the fees collected.
+        let reward = block.transactions()[0].amount();
+        self.emit(
+            || {
+                json!({
+                    "block": block,
+                    "transactions": mined,
+                    "miner": miner,
+                    "reward": reward,
+                    "pending": lock(&self.mempool).len(),
+                })
+            },
+            || {
+                outln!(
+                    "Block mined with {} transaction(s)! {} rewarded {}, {} still pending",
+                    mined,
+                    miner,
+                    reward,
+                    lock(&self.mempool).len()
+                )
+            },
+        );
+        if let Err(err) = read(&self.chain).persist(self.store.get()) {
+            return self.fail("Failed to save blockchain", err);
+        }
+        true
+    }
 
-            if current.hash != recalculated_hash {
-                return false;
+    fn submit(&mut self, tx: mini_block::Result<Transaction>, mine: Option<String>) -> bool {
+        let submitted = {
+            let blockchain = read(&self.chain);
+            tx.and_then(|tx| {
+                let txid = tx.hash();
+                blockchain.submit_transaction(&mut lock(&self.mempool), tx).map(|()| txid)
+            })
+        };
+        let txid = match submitted {
+            Ok(txid) => txid,
+            Err(err) => return self.fail("Transaction rejected", err),
+        };
+        match mine {
+            // The mining report already covers the transaction.
+            Some(miner) => self.mine(&miner, DEFAULT_BATCH_SIZE),
+            None => {
+                self.emit(
+                    || json!({ "queued": true, "txid": txid, "pending": lock(&self.mempool).len() }),
+                    || outln!("Transaction {} queued ({} pending)", txid, lock(&self.mempool).len()),
+                );
+                true
             }
+        }
+    }
 
-            if current.previous_hash != previous.hash {
-                return false;
+    /// Queues the transfers listed in `file` in order, as `add` would, and
+    /// reports each one that can't be read or is rejected by its line.
+    fn add_batch(&mut self, file: &Path, mine: Option<String>) -> bool {
+        let entries = match batch::read_batch(file) {
+            Ok(entries) => entries,
+            Err(err) => return self.fail("Failed to read batch", err),
+        };
+        let total = entries.len();
+        let mut queued = Vec::new();
+        let mut rejected = Vec::new();
+        {
+            let blockchain = read(&self.chain);
+            for (line, entry) in entries {
+                let submitted = entry.and_then(|entry| {
+                    let sequence = blockchain.next_sequence(&lock(&self.mempool), &entry.sender)?;
+                    let tx = Transaction::new(entry.sender, entry.receiver, entry.amount)
+                        .with_fee(entry.fee)
+                        .with_sequence(sequence);
+                    let txid = tx.hash();
+                    blockchain.submit_transaction(&mut lock(&self.mempool), tx).map(|()| txid)
+                });
+                match submitted {
+                    Ok(txid) => queued.push(txid),
+                    Err(err) => rejected.push((line, err.to_string())),
+                }
             }
         }
+        self.emit(
+            || {
+                let rejected: Vec<Value> =
+                    rejected.iter().map(|(line, err)| json!({ "line": line, "error": err })).collect();
+                json!({ "queued": queued, "rejected": rejected, "pending": lock(&self.mempool).len() })
+            },
+            || {
+                for (line, err) in &rejected {
+                    outln!("Line {}: {}", line, err);
+                }
+                outln!(
+                    "Queued {} of {} transaction(s) ({} pending)",
+                    queued.len(),
+                    total,
+                    lock(&self.mempool).len()
+                );
+            },
+        );
+        if let Some(miner) = mine
+            && !queued.is_empty()
+            && !self.mine(&miner, DEFAULT_BATCH_SIZE)
+        {
+            return false;
+        }
+        rejected.is_empty()
+    }
+
+    fn show_mempool(&self) -> bool {
+        let height = read(&self.chain).height();
+        let mut mempool = lock(&self.mempool);
+        mempool.expire(height);
+        let (stats, limits) = (mempool.stats(), *mempool.limits());
+        let mut entries: Vec<_> = mempool.entries().map(|(tx, queued)| (tx.hash(), tx, queued)).collect();
+        // Best fee rate first, compared without dividing.
+        entries.sort_by(|(_, a, _), (_, b, _)| {
+            let rate = |x: &Transaction, y: &Transaction| u128::from(x.fee().units()) * y.size() as u128;
+            rate(b, a).cmp(&rate(a, b))
+        });
+        self.emit(
+            || {
+                let transactions: Vec<Value> = entries
+                    .iter()
+                    .map(|(txid, tx, queued)| {
+                        json!({ "txid": txid, "transaction": tx, "size": tx.size(), "queued_at": queued })
+                    })
+                    .collect();
+                json!({ "stats": stats, "limits": limits, "transactions": transactions })
+            },
+            || {
+                outln!(
+                    "Mempool: {}/{} transaction(s), {}/{} bytes, {} in fees",
+                    stats.transactions, limits.max_transactions, stats.bytes, limits.max_bytes, stats.fees
+                );
+                match limits.expiry_blocks {
+                    0 => outln!("Transactions never expire"),
+                    blocks => outln!("Transactions expire {} blocks after they were queued", blocks),
+                }
+                if limits.min_fee_rate > 0 {
+                    outln!("Minimum relay fee: {} units per byte", limits.min_fee_rate);
+                }
+                if !limits.dust_limit.is_zero() {
+                    outln!("Dust limit: {}", limits.dust_limit);
+                }
+                for (txid, tx, queued) in &entries {
+                    outln!(
+                        "  {} {} -> {} (fee {}, {} bytes, queued at block #{})",
+                        txid,
+                        tx.sender(),
+                        describe_payments(tx),
+                        tx.fee(),
+                        tx.size(),
+                        queued
+                    );
+                }
+            },
+        );
         true
     }
 
-    pub fn save_to_file(&self, filename: &str) {
-        let json = serde_json::to_string_pretty(&self).unwrap();
-        fs::write(filename, json).expect("Unable to save blockchain");
+    fn difficulty(&self, digits: Option<usize>) -> bool {
+        let (target, proof_of_work, miner) = {
+            let blockchain = read(&self.chain);
+            let target = digits.map_or_else(|| Target::from_bits(blockchain.next_bits()), Target::from_leading_zeros);
+            let proof_of_work = blockchain.params().consensus == ConsensusKind::ProofOfWork;
+            (target, proof_of_work, blockchain.miner().clone())
+        };
+        if !proof_of_work && digits.is_none() {
+            return self.fail("No difficulty", "blocks on this chain are produced by stake, not mined");
+        }
+        let expected = target.work();
+        let rate = miner.calibrate(CALIBRATION_TIME).hash_rate();
+        let seconds = if rate > 0.0 { expected as f64 / rate } else { f64::INFINITY };
+        self.emit(
+            || {
+                json!({
+                    "bits": format!("{:08x}", target.to_bits()),
+                    "target": target.to_string(),
+                    // Past u64, as a float rather than a number JSON readers can't hold.
+                    "expected_hashes": u64::try_from(expected).map_or_else(|_| json!(expected as f64), |n| json!(n)),
+                    "hash_rate": rate,
+                    "threads": miner.threads(),
+                    "estimated_seconds": seconds.is_finite().then_some(seconds),
+                })
+            },
+            || {
+                outln!("Target: {} (bits {:08x})", target, target.to_bits());
+                outln!("Expected hashes per block: {}", expected);
+                outln!("Hash rate: {:.0} H/s on {} thread(s)", rate, miner.threads());
+                outln!("Estimated time to mine a block: {}", describe_seconds(seconds));
+            },
+        );
+        true
     }
 
-    pub fn load_from_file(filename: &str) -> Option<Self> {
-        if let Ok(data) = fs::read_to_string(filename) {
-            let bc: Blockchain = serde_json::from_str(&data).unwrap();
-            Some(bc)
-        } else {
-            None
+    fn verify_anchor(&self, file: &Path) -> bool {
+        let digest = match anchor::hash_file(file) {
+            Ok(digest) => digest,
+            Err(err) => return self.fail("Failed to read file", err),
+        };
+        let blockchain = read(&self.chain);
+        let Some((block, tx)) = anchor::find(&blockchain, &digest) else {
+            return self.fail("Not anchored", hex::encode(digest));
+        };
+        let confirmations = blockchain.latest_block().index() - block.index() + 1;
+        self.emit(
+            || {
+                json!({
+                    "digest": hex::encode(digest),
+                    "txid": tx.hash(),
+                    "sender": tx.sender(),
+                    "block": block.index(),
+                    "block_hash": block.hash(),
+                    "timestamp": block.timestamp(),
+                    "confirmations": confirmations,
+                })
+            },
+            || {
+                outln!("{} (SHA-256 {})", file.display(), hex::encode(digest));
+                outln!("  Anchored by {} in transaction {}", tx.sender(), tx.hash());
+                outln!("  Block #{} ({}), {} confirmation(s)", block.index(), block.hash(), confirmations);
+                outln!("  Timestamp: {}", block.timestamp());
+            },
+        );
+        true
+    }
+
+    fn audit(&self, wallet_only: bool) -> bool {
+        let mut report = match audit::audit(&read(&self.chain)) {
+            Ok(report) => report,
+            Err(err) => return self.fail("Failed to audit blockchain", err),
+        };
+        let mut watched = Vec::new();
+        if wallet_only {
+            let addresses = match self.wallet_addresses() {
+                Ok(addresses) => addresses,
+                Err(err) => return self.fail("Failed to read wallet", err),
+            };
+            report.addresses = addresses
+                .into_iter()
+                .map(|(address, watch_only)| {
+                    let flows = report.addresses.get(&address).copied().unwrap_or_default();
+                    if watch_only {
+                        watched.push(address.clone());
+                    }
+                    (address, flows)
+                })
+                .collect();
         }
+        self.emit(
+            || {
+                let mut value = json!(report);
+                if wallet_only {
+                    value["watch_only"] = json!(watched);
+                }
+                value
+            },
+            || {
+                outln!("Blocks audited: {}", report.blocks);
+                outln!("Allocated at genesis: {}", report.allocated);
+                outln!("Issued as block rewards: {}", report.issued);
+                outln!("Fees paid: {}", report.fees);
+                outln!("Supply: {}", report.supply);
+                outln!("Addresses:");
+                for (address, flows) in &report.addresses {
+                    outln!(
+                        "  {}{}: received {}, sent {}, fees {}, balance {}",
+                        address,
+                        if watched.contains(address) { " (watch-only)" } else { "" },
+                        flows.received,
+                        flows.sent,
+                        flows.fees,
+                        flows.balance()
+                    );
+                }
+                if !report.assets.is_empty() {
+                    outln!("Assets:");
+                }
+                for (name, asset) in &report.assets {
+                    outln!("  {}: issued {} by {}, supply {}", name, asset.issued, asset.issuer, asset.supply());
+                }
+                outln!("Value conserved? {}", report.is_balanced());
+                for violation in &report.violations {
+                    outln!("  {}", violation);
+                }
+            },
+        );
+        report.is_balanced()
     }
-}
 
-fn main() {
-    let filename = "blockchain.json";
-    let mut blockchain = Blockchain::load_from_file(filename).unwrap_or_else(Blockchain::new);
-
-    println!("Mini Blockchain CLI with Mining & Transactions");
-    println!("Commands:");
-    println!("  add <sender> <receiver> <amount>  - Add a new transaction as a block");
-    println!("  view                              - View the entire blockchain");
-    println!("  validate                          - Check if blockchain is valid");
-    println!("  exit                              - Exit the program");
-    println!();
-
-    loop {
-        print!("> ");
-        std::io::stdout().flush().unwrap();
-
-        let mut input = String::new();
-        std::io::stdin().read_line(&mut input).expect("Failed to read line");
-
-        let input = input.trim();
-        let parts: Vec<&str> = input.split_whitespace().collect();
-
-        match parts.as_slice() {
-            ["add", sender, receiver, amount] => {
-                if let Ok(amount) = amount.parse::<u32>() {
-                    let tx = Transaction {
-                        sender: sender.to_string(),
-                        receiver: receiver.to_string(),
-                        amount,
+    /// Replaces the chain with a valid one read from `file`, which must use
+    /// this node's chain parameters.
+    fn import(&mut self, file: &Path, format: Option<ExportFormat>) -> bool {
+        let format = format.unwrap_or_else(|| ExportFormat::from_path(file));
+        let mut imported = match export::import(file, format) {
+            Ok(imported) => imported,
+            Err(err) => return self.fail("Failed to import blockchain", err),
+        };
+        let mut blockchain = write(&self.chain);
+        if format == ExportFormat::Csv {
+            // CSV exports carry no parameters; assume they are ours.
+            match Blockchain::from_blocks(imported.blocks().to_vec(), blockchain.params().clone()) {
+                Ok(chain) => imported = chain,
+                Err(err) => return self.fail("Failed to import blockchain", err),
+            }
+        }
+        if imported.params() != blockchain.params() {
+            let err = BlockchainError::Validation("the file was exported from a different network".to_string());
+            return self.fail("Failed to import blockchain", err);
+        }
+        imported.set_checkpoints(blockchain.checkpoints().clone());
+        if let Err(err) = imported.validate() {
+            return self.fail("Refusing to import an invalid chain", err);
+        }
+        if let Err(err) = imported.set_prune_depth(blockchain.prune_depth()) {
+            return self.fail("Failed to prune blockchain", err);
+        }
+        if let Err(err) = imported.persist(self.store.get()) {
+            return self.fail("Failed to save blockchain", err);
+        }
+        imported.set_miner(blockchain.miner().clone());
+        imported.set_event_bus(blockchain.event_bus().clone());
+        imported.set_metrics(blockchain.metrics().clone());
+        *blockchain = imported;
+        let blocks = blockchain.blocks().len();
+        self.emit(
+            || json!({ "file": file, "format": format.to_string(), "blocks": blocks }),
+            || outln!("Imported {} blocks from {}", blocks, file.display()),
+        );
+        true
+    }
+
+    /// Runs a command, returning whether it succeeded.
+    fn run(&mut self, command: ChainCommand) -> bool {
+        match command {
+            ChainCommand::Add {
+                sender,
+                receiver,
+                amount,
+                asset,
+                fee,
+                lock_time,
+                memo,
+                memo_hex,
+                mine,
+            } => {
+                let tx = read(&self.chain).next_sequence(&lock(&self.mempool), &sender).map(|sequence| {
+                    let tx = Transaction::new(sender, receiver, amount)
+                        .with_fee(fee)
+                        .with_sequence(sequence)
+                        .with_lock_time(lock_time);
+                    let tx = match asset {
+                        Some(asset) => tx.with_asset(asset),
+                        None => tx,
                     };
-                    blockchain.add_block(vec![tx]);
-                    println!("Block mined and added successfully!");
-                    blockchain.save_to_file(filename);
+                    with_memo(tx, memo, memo_hex)
+                });
+                self.submit(tx, mine)
+            }
+            ChainCommand::Issue {
+                asset,
+                amount,
+                issuer,
+                fee,
+                mine,
+            } => {
+                let tx = read(&self.chain).next_sequence(&lock(&self.mempool), &issuer).map(|sequence| {
+                    Transaction::issue(issuer, asset, amount)
+                        .with_fee(fee)
+                        .with_sequence(sequence)
+                });
+                self.submit(tx, mine)
+            }
+            ChainCommand::AddBatch { file, mine } => self.add_batch(&file, mine),
+            ChainCommand::Send {
+                receiver,
+                amount,
+                from,
+                fee,
+                memo,
+                mine,
+            } => {
+                if let Err(err) = self.unlock_wallet() {
+                    return self.fail("Failed to unlock wallet", err);
+                }
+                let tx = self.build_send(from, &receiver, amount, fee, memo);
+                self.submit(tx, mine)
+            }
+            ChainCommand::CreateTx {
+                receiver,
+                amount,
+                from,
+                fee,
+                memo,
+                out,
+            } => {
+                let blockchain = read(&self.chain);
+                let created = blockchain
+                    .build_unsigned_transaction(&lock(&self.mempool), &from, &receiver, amount, fee)
+                    .and_then(|tx| TransactionFile::new(&blockchain, with_memo(tx, memo, None)))
+                    .and_then(|file| file.save(&out).map(|()| file));
+                let file = match created {
+                    Ok(file) => file,
+                    Err(err) => return self.fail("Failed to create transaction", err),
+                };
+                let unsigned = file.unsigned_inputs();
+                self.emit(
+                    || json!({ "file": out, "transaction": file.transaction, "unsigned_inputs": unsigned }),
+                    || {
+                        outln!("Wrote {} to {}", describe_payments(&file.transaction), out.display());
+                        outln!("{} of its {} input(s) need signing with sign-tx", unsigned, file.spent.len());
+                    },
+                );
+                true
+            }
+            ChainCommand::SignTx { file, out } => {
+                let mut transaction = match TransactionFile::load(&file) {
+                    Ok(transaction) => transaction,
+                    Err(err) => return self.fail("Failed to read transaction file", err),
+                };
+                let wallet = match self.unlock_wallet() {
+                    Ok(wallet) => wallet,
+                    Err(err) => return self.fail("Failed to unlock wallet", err),
+                };
+                let sender = transaction.transaction.sender().to_string();
+                if wallet.is_watch_only(&sender) {
+                    let err = format!("{} is watch-only; the wallet has no key to sign for it", sender);
+                    return self.fail("Failed to sign", err);
+                }
+                let signed = transaction.sign(wallet);
+                if signed == 0 && !transaction.is_signed() {
+                    return self.fail("Failed to sign", "the wallet holds none of the keys the transaction needs");
+                }
+                let out = out.unwrap_or(file);
+                if let Err(err) = transaction.save(&out) {
+                    return self.fail("Failed to write transaction file", err);
+                }
+                let unsigned = transaction.unsigned_inputs();
+                self.emit(
+                    || json!({ "file": out, "signed": signed, "unsigned_inputs": unsigned }),
+                    || {
+                        let tx = &transaction.transaction;
+                        outln!(
+                            "Signed {} input(s) of {} -> {} on {}",
+                            signed,
+                            tx.sender(),
+                            describe_payments(tx),
+                            transaction.chain_id
+                        );
+                        match unsigned {
+                            0 => outln!("Wrote {}, ready for broadcast-tx", out.display()),
+                            unsigned => outln!("Wrote {}; {} input(s) still need another key", out.display(), unsigned),
+                        }
+                    },
+                );
+                true
+            }
+            ChainCommand::BroadcastTx { file, mine } => {
+                let transaction = match TransactionFile::load(&file) {
+                    Ok(transaction) => transaction,
+                    Err(err) => return self.fail("Failed to read transaction file", err),
+                };
+                let chain_id = read(&self.chain).params().chain_id.clone();
+                let tx = if transaction.chain_id != chain_id {
+                    Err(BlockchainError::Validation(format!(
+                        "transaction was signed for chain {}, not {}",
+                        transaction.chain_id, chain_id
+                    )))
+                } else if !transaction.is_signed() {
+                    Err(BlockchainError::Validation(format!(
+                        "{} input(s) are unsigned; sign the file with sign-tx first",
+                        transaction.unsigned_inputs()
+                    )))
+                } else {
+                    Ok(transaction.transaction)
+                };
+                self.submit(tx, mine)
+            }
+            ChainCommand::Pay {
+                payments,
+                from,
+                fee,
+                memo,
+                memo_hex,
+                mine,
+            } => {
+                let tx = parse_payments(&payments).and_then(|payments| {
+                    let sequence = read(&self.chain).next_sequence(&lock(&self.mempool), &from)?;
+                    let (first, rest) = payments.split_first().expect("clap requires a payment");
+                    let tx = rest.iter().fold(
+                        Transaction::new(from, first.receiver.clone(), first.amount),
+                        |tx, payment| tx.with_payment(payment.receiver.clone(), payment.amount),
+                    );
+                    Ok(with_memo(tx.with_fee(fee).with_sequence(sequence), memo, memo_hex))
+                });
+                self.submit(tx, mine)
+            }
+            ChainCommand::Spend {
+                sender,
+                receiver,
+                amount,
+                fee,
+                lock_time,
+                memo,
+                memo_hex,
+                mine,
+            } => {
+                let tx = read(&self.chain)
+                    .build_utxo_transaction(&lock(&self.mempool), &sender, &receiver, amount, fee)
+                    .map(|tx| with_memo(tx.with_lock_time(lock_time), memo, memo_hex));
+                self.submit(tx, mine)
+            }
+            ChainCommand::BumpFee { txid, fee, mine } => {
+                let tx = match lock(&self.mempool).get(&txid) {
+                    Some(tx) if tx.is_sequenced() => Ok(tx.clone().with_fee(fee)),
+                    Some(_) => Err(BlockchainError::Validation(
+                        "only account-model transactions can be replaced".to_string(),
+                    )),
+                    None => Err(BlockchainError::Validation(format!("transaction {} is not pending", txid))),
+                };
+                self.submit(tx, mine)
+            }
+            ChainCommand::Anchor { file, from, fee, mine } => {
+                let tx = anchor::hash_file(&file).and_then(|digest| {
+                    let sequence = read(&self.chain).next_sequence(&lock(&self.mempool), &from)?;
+                    Ok(anchor::transaction(from, digest).with_fee(fee).with_sequence(sequence))
+                });
+                self.submit(tx, mine)
+            }
+            ChainCommand::Mine { miner, count } => self.mine(&miner, count),
+            ChainCommand::Balance { address } => {
+                let blockchain = read(&self.chain);
+                let (balance, immature) = (blockchain.balance_of(&address), blockchain.immature_balance_of(&address));
+                let assets = match blockchain.asset_balances(&address) {
+                    Ok(assets) => assets,
+                    Err(err) => return self.fail("Failed to compute asset balances", err),
+                };
+                self.emit(
+                    || json!({ "address": address, "balance": balance, "immature": immature, "assets": assets }),
+                    || {
+                        outln!("Balance of {}: {}", address, balance);
+                        if !immature.is_zero() {
+                            outln!("  of which {} in rewards not yet spendable", immature);
+                        }
+                        for (asset, balance) in &assets {
+                            outln!("  {}: {}", asset, balance);
+                        }
+                    },
+                );
+                true
+            }
+            ChainCommand::Utxos { address } => {
+                let utxos = match read(&self.chain).utxo_set() {
+                    Ok(utxos) => utxos,
+                    Err(err) => return self.fail("Failed to compute unspent outputs", err),
+                };
+                let mut outputs: Vec<_> = utxos.outputs_for(&address).collect();
+                outputs.sort_by(|(a, _), (b, _)| (&a.txid, a.vout).cmp(&(&b.txid, b.vout)));
+                self.emit(
+                    || {
+                        let outputs: Vec<Value> = outputs
+                            .iter()
+                            .map(|(outpoint, output)| {
+                                json!({ "txid": outpoint.txid, "vout": outpoint.vout, "amount": output.amount })
+                            })
+                            .collect();
+                        json!({ "address": address, "outputs": outputs })
+                    },
+                    || {
+                        outln!("Unspent outputs of {}:", address);
+                        for (outpoint, output) in &outputs {
+                            outln!("  {}:{} : {}", outpoint.txid, outpoint.vout, output.amount);
+                        }
+                    },
+                );
+                true
+            }
+            ChainCommand::Tx { txid } => {
+                let blockchain = read(&self.chain);
+                let mempool = lock(&self.mempool);
+                let (tx, block) = match blockchain.get_transaction(&txid) {
+                    Some((block, tx)) => (tx, Some(block.index())),
+                    None => match mempool.get(&txid) {
+                        Some(tx) => (tx, None),
+                        None => return self.fail("Unknown transaction", &txid),
+                    },
+                };
+                self.emit(
+                    || json!({ "txid": txid, "transaction": tx, "block": block, "confirmed": block.is_some() }),
+                    || {
+                        outln!("Transaction {}", txid);
+                        let units = tx.asset().map(|asset| format!(" {}", asset)).unwrap_or_default();
+                        let kind = if tx.is_issue() { "issues" } else { "->" };
+                        outln!(
+                            "  {} {} {}{} (fee {})",
+                            tx.sender(),
+                            kind,
+                            describe_payments(tx),
+                            units,
+                            tx.fee()
+                        );
+                        if tx.is_sequenced() {
+                            outln!("  Sequence {}", tx.sequence());
+                        }
+                        if tx.lock_time() > 0 {
+                            outln!("  Locked until {}", describe_lock_time(tx.lock_time()));
+                        }
+                        if let Some(memo) = tx.memo() {
+                            outln!("  Memo: {}", describe_memo(memo));
+                        }
+                        match block {
+                            Some(index) => outln!("  Confirmed in block #{}", index),
+                            None => outln!("  Pending"),
+                        }
+                    },
+                );
+                true
+            }
+            ChainCommand::View => {
+                let blockchain = read(&self.chain);
+                // Work can exceed what JSON numbers hold exactly.
+                let work = blockchain.cumulative_work().to_string();
+                self.emit(
+                    || {
+                        let blocks: Vec<Value> = blockchain
+                            .blocks()
+                            .iter()
+                            .map(|block| {
+                                let mut value = json!(block);
+                                value["totals"] = json!(block.totals());
+                                value["totals"]["subsidy"] = json!(block.totals().subsidy());
+                                value
+                            })
+                            .collect();
+                        json!({ "blocks": blocks, "cumulative_work": work })
+                    },
+                    || view_chain(&blockchain),
+                );
+                true
+            }
+            ChainCommand::Validate { full } => {
+                let blockchain = read(&self.chain);
+                if full {
+                    blockchain.clear_validation_cache();
+                }
+                let report = blockchain.validate_detailed();
+                drop(blockchain);
+                self.emit(
+                    || json!(report),
+                    || {
+                        outln!("Blockchain valid? {}", report.is_valid());
+                        for violation in &report.violations {
+                            outln!("  {}", violation);
+                        }
+                    },
+                );
+                report.is_valid()
+            }
+            ChainCommand::Mempool => self.show_mempool(),
+            ChainCommand::Difficulty { digits } => self.difficulty(digits),
+            ChainCommand::VerifyAnchor { file } => self.verify_anchor(&file),
+            ChainCommand::History { address } => {
+                let entries = index::history(&read(&self.chain), &address);
+                self.emit(
+                    || json!({ "address": address, "transactions": entries }),
+                    || print_history(&address, &entries),
+                );
+                true
+            }
+            ChainCommand::Reindex => {
+                let mut blockchain = write(&self.chain);
+                if let Err(err) = blockchain.reindex(self.store.get()) {
+                    return self.fail("Failed to reindex blockchain", err);
+                }
+                let index = blockchain.index();
+                let (blocks, transactions, addresses) =
+                    (blockchain.blocks().len(), index.transaction_count(), index.address_count());
+                drop(blockchain);
+                self.emit(
+                    || json!({ "blocks": blocks, "transactions": transactions, "addresses": addresses }),
+                    || {
+                        outln!(
+                            "Indexed {} blocks, {} transactions and {} addresses",
+                            blocks, transactions, addresses
+                        )
+                    },
+                );
+                true
+            }
+            ChainCommand::Stats { top } => {
+                let stats = stats::stats(&read(&self.chain), top);
+                self.emit(
+                    || json!(stats),
+                    || {
+                        outln!("Height: {}", stats.height);
+                        outln!("Transactions: {}", stats.transactions);
+                        if stats.pruned_blocks > 0 {
+                            outln!("  (not counting {} pruned block(s))", stats.pruned_blocks);
+                        }
+                        match stats.average_block_interval_ms {
+                            Some(interval) => outln!("Average block interval: {:.1}s", interval / 1000.0),
+                            None => outln!("Average block interval: n/a"),
+                        }
+                        outln!("Average transactions per block: {:.2}", stats.average_transactions_per_block);
+                        outln!("Coins in circulation: {}", stats.circulation);
+                        match stats.total_supply {
+                            Some(supply) => outln!("Total eventual supply: {}", supply),
+                            None => outln!("Total eventual supply: unlimited"),
+                        }
+                        outln!("Block reward: {}", stats.block_reward);
+                        if let Some(height) = stats.next_halving {
+                            outln!("Next halving: block #{}", height);
+                        }
+                        outln!("Average nonce: {:.0}", stats.average_nonce);
+                        outln!("Average work per block: {:.0} hashes", stats.average_work);
+                        outln!("Top addresses:");
+                        for (address, balance) in &stats.top_addresses {
+                            outln!("  {}: {}", address, balance);
+                        }
+                    },
+                );
+                true
+            }
+            ChainCommand::FeeEstimate { blocks } => {
+                let estimate = fee::estimate_fee(&read(&self.chain), &lock(&self.mempool), blocks);
+                self.emit(
+                    || json!(estimate),
+                    || {
+                        outln!(
+                            "Suggested fee: {} ({} units/byte for a {}-byte transaction) to confirm within {} block(s)",
+                            estimate.fee,
+                            estimate.fee_rate,
+                            estimate.typical_size,
+                            estimate.target_blocks
+                        );
+                        outln!(
+                            "  recent blocks: {} units/byte over {} block(s)",
+                            estimate.history_rate,
+                            estimate.blocks_sampled
+                        );
+                        outln!("  mempool: {} units/byte with {} pending", estimate.mempool_rate, estimate.pending);
+                    },
+                );
+                true
+            }
+            ChainCommand::Audit { wallet_only } => self.audit(wallet_only),
+            ChainCommand::Wallet(command) => self.run_wallet(command),
+            ChainCommand::Export { file, format } => {
+                let format = format.unwrap_or_else(|| ExportFormat::from_path(&file));
+                let blockchain = read(&self.chain);
+                if let Err(err) = export::export(&blockchain, &file, format) {
+                    return self.fail("Failed to export blockchain", err);
+                }
+                let blocks = blockchain.blocks().len();
+                self.emit(
+                    || json!({ "file": file, "format": format.to_string(), "blocks": blocks }),
+                    || outln!("Exported {} blocks to {} as {}", blocks, file.display(), format),
+                );
+                true
+            }
+            ChainCommand::Import { file, format } => self.import(&file, format),
+        }
+    }
+
+    /// Asks for a new password and writes a new wallet (deterministic if a
+    /// mnemonic is given) after `fill` adds its keys. Returns the last address.
+    fn create_wallet(&mut self, mnemonic: Option<&str>, fill: impl FnOnce(&mut UnlockedWallet)) -> mini_block::Result<String> {
+        if self.wallet_path.exists() {
+            return Err(BlockchainError::Wallet(format!("{} already exists", self.wallet_path.display())));
+        }
+        let password = read_password("New wallet password: ")?;
+        if given_password().is_none() && read_password("Repeat password: ")? != password {
+            return Err(BlockchainError::Wallet("passwords do not match".to_string()));
+        }
+        let version = read(&self.chain).params().address_version;
+        let mut wallet = match mnemonic {
+            Some(phrase) => UnlockedWallet::from_mnemonic(&password, phrase, version)?,
+            None => UnlockedWallet::create(&password, version)?,
+        };
+        fill(&mut wallet);
+        wallet.save(&self.wallet_path)?;
+        let address = wallet.addresses().pop().unwrap_or_default();
+        self.wallet = Some(wallet);
+        Ok(address)
+    }
+
+    /// The wallet's own addresses and then its watched ones, each with
+    /// whether it is watch-only, read from the file if the wallet is locked.
+    fn wallet_addresses(&self) -> mini_block::Result<Vec<(String, bool)>> {
+        let (own, watched) = match &self.wallet {
+            Some(wallet) => (wallet.addresses(), wallet.watched().to_vec()),
+            None => {
+                let wallet = Wallet::load(&self.wallet_path)?;
+                (wallet.addresses().to_vec(), wallet.watched().to_vec())
+            }
+        };
+        let own = own.into_iter().map(|address| (address, false));
+        Ok(own.chain(watched.into_iter().map(|address| (address, true))).collect())
+    }
+
+    /// A signed transaction paying `amount` to `receiver` from `from`, or
+    /// else the unlocked wallet's first address with enough spendable coins
+    /// not already pending.
+    fn build_send(
+        &self,
+        from: Option<String>,
+        receiver: &str,
+        amount: Amount,
+        fee: Amount,
+        memo: Option<String>,
+    ) -> mini_block::Result<Transaction> {
+        let Some(wallet) = &self.wallet else {
+            return Err(BlockchainError::Validation("the wallet is locked".to_string()));
+        };
+        let blockchain = read(&self.chain);
+        let mempool = lock(&self.mempool);
+        let available =
+            |address: &str| blockchain.spendable_balance_of(address).saturating_sub(mempool.pending_outgoing(address));
+        let cost = amount.try_add(fee, "amount plus fee")?;
+        let sender = match from {
+            Some(from) => from,
+            None => wallet.addresses().into_iter().find(|address| available(address) >= cost).ok_or_else(|| {
+                BlockchainError::Validation(format!("no address in the wallet has {} available", cost))
+            })?,
+        };
+        if wallet.is_watch_only(&sender) {
+            let message = format!("{} is watch-only; the wallet has no key to sign for it", sender);
+            return Err(BlockchainError::Wallet(message));
+        }
+        let Some(key) = wallet.signing_key(&sender) else {
+            return Err(BlockchainError::Validation(format!("{} is not an address of this wallet", sender)));
+        };
+        if available(&sender) < cost {
+            return Err(BlockchainError::Validation(format!(
+                "insufficient balance: {} has {} available but tried to spend {}",
+                sender,
+                available(&sender),
+                cost
+            )));
+        }
+        let tx = blockchain.build_transaction_for_key(&mempool, &sender, &key.verifying_key(), receiver, amount, fee)?;
+        let tx = with_memo(tx, memo, None);
+        Ok(wallet.sign(tx, &blockchain.utxo_set()?, &blockchain.params().chain_id))
+    }
+
+    /// Draws `address`, or with `key` its encrypted key, as a QR code in the
+    /// terminal or into the PNG file `png`.
+    fn export_qr(&mut self, address: &str, key: bool, png: Option<PathBuf>, scale: u32) -> bool {
+        match self.wallet_addresses() {
+            Ok(addresses) if addresses.iter().any(|(own, _)| own == address) => {}
+            Ok(_) => {
+                let err = BlockchainError::Wallet(format!("{} is not in the wallet", address));
+                return self.fail("Failed to export QR code", err);
+            }
+            Err(err) => return self.fail("Failed to read wallet", err),
+        }
+        let data = match key {
+            true => match self.unlock_wallet().and_then(|wallet| wallet.backup_key(address)) {
+                Ok(backup) => json!(backup).to_string(),
+                Err(err) => return self.fail("Failed to back up key", err),
+            },
+            false => address.to_string(),
+        };
+        let drawn = match &png {
+            Some(file) => qr::write_png(&data, file, scale).map(|()| None),
+            None => qr::to_terminal(&data).map(Some),
+        };
+        match drawn {
+            Ok(drawn) => {
+                self.emit(
+                    || json!({ "address": address, "data": data, "png": png, "qr": drawn }),
+                    || {
+                        match (&drawn, &png) {
+                            (Some(drawn), _) => outln!("{}", drawn),
+                            (None, Some(file)) => outln!("Wrote QR code to {}", file.display()),
+                            (None, None) => {}
+                        }
+                        outln!("{}", data);
+                    },
+                );
+                true
+            }
+            Err(err) => self.fail("Failed to export QR code", err),
+        }
+    }
+
+    /// Returns the unlocked wallet, asking for the password if needed.
+    fn unlock_wallet(&mut self) -> mini_block::Result<&mut UnlockedWallet> {
+        if self.wallet.is_none() {
+            let wallet = Wallet::load(&self.wallet_path)?;
+            let password = read_password("Wallet password: ")?;
+            self.wallet = Some(wallet.unlock(&password)?);
+        }
+        Ok(self.wallet.as_mut().expect("wallet was just unlocked"))
+    }
+
+    fn run_wallet(&mut self, command: WalletCommand) -> bool {
+        match command {
+            WalletCommand::Create { mnemonic, words } => {
+                let phrase = if mnemonic {
+                    match hd::generate_mnemonic(words) {
+                        Ok(phrase) => Some(phrase),
+                        Err(err) => return self.fail("Failed to create wallet", err),
+                    }
                 } else {
-                    println!("Invalid amount");
+                    None
+                };
+                let created = self.create_wallet(phrase.as_deref(), |wallet| {
+                    wallet.generate_key();
+                });
+                match created {
+                    Ok(address) => {
+                        self.emit(
+                            || json!({ "wallet": self.wallet_path, "address": address, "mnemonic": phrase }),
+                            || {
+                                outln!("Created {} with address {}", self.wallet_path.display(), address);
+                                if let Some(phrase) = &phrase {
+                                    outln!("Write down this recovery phrase; it is the only way to restore the wallet:");
+                                    outln!("  {}", phrase);
+                                }
+                            },
+                        );
+                        true
+                    }
+                    Err(err) => self.fail("Failed to create wallet", err),
+                }
+            }
+            WalletCommand::Restore { phrase } => {
+                let chain = Arc::clone(&self.chain);
+                let restored = self.create_wallet(Some(&phrase.join(" ")), |wallet| {
+                    let balances = read(&chain).balances();
+                    wallet.restore_keys(|address| balances.contains_key(address), RESTORE_GAP_LIMIT);
+                });
+                match restored {
+                    Ok(_) => {
+                        let addresses = self.wallet.as_ref().map(UnlockedWallet::addresses).unwrap_or_default();
+                        self.emit(
+                            || json!({ "wallet": self.wallet_path, "addresses": addresses }),
+                            || {
+                                outln!("Restored {} with {} address(es):", self.wallet_path.display(), addresses.len());
+                                for address in &addresses {
+                                    outln!("  {}", address);
+                                }
+                            },
+                        );
+                        true
+                    }
+                    Err(err) => self.fail("Failed to restore wallet", err),
+                }
+            }
+            WalletCommand::List => {
+                let addresses = match self.wallet_addresses() {
+                    Ok(addresses) => addresses,
+                    Err(err) => return self.fail("Failed to read wallet", err),
+                };
+                let blockchain = read(&self.chain);
+                let balances: Vec<(String, bool, Amount)> = addresses
+                    .into_iter()
+                    .map(|(address, watch_only)| {
+                        let balance = blockchain.balance_of(&address);
+                        (address, watch_only, balance)
+                    })
+                    .collect();
+                self.emit(
+                    || {
+                        let entries: Vec<Value> = balances
+                            .iter()
+                            .map(|(address, watch_only, balance)| {
+                                json!({ "address": address, "watch_only": watch_only, "balance": balance })
+                            })
+                            .collect();
+                        json!({ "unlocked": self.wallet.is_some(), "addresses": entries })
+                    },
+                    || {
+                        for (address, watch_only, balance) in &balances {
+                            let tag = if *watch_only { " (watch-only)" } else { "" };
+                            outln!("{} : {}{}", address, balance, tag);
+                        }
+                    },
+                );
+                true
+            }
+            WalletCommand::Unlock => match self.unlock_wallet() {
+                Ok(wallet) => {
+                    let keys = wallet.addresses().len();
+                    self.emit(
+                        || json!({ "unlocked": true, "keys": keys }),
+                        || outln!("Wallet unlocked ({} key(s))", keys),
+                    );
+                    true
+                }
+                Err(err) => self.fail("Failed to unlock wallet", err),
+            },
+            WalletCommand::Generate => {
+                let path = self.wallet_path.clone();
+                let generated = self.unlock_wallet().and_then(|wallet| {
+                    let address = wallet.generate_key();
+                    wallet.save(&path)?;
+                    Ok(address)
+                });
+                match generated {
+                    Ok(address) => {
+                        self.emit(
+                            || json!({ "address": address }),
+                            || outln!("New address {}", address),
+                        );
+                        true
+                    }
+                    Err(err) => self.fail("Failed to generate key", err),
+                }
+            }
+            WalletCommand::Watch { address } => {
+                let path = self.wallet_path.clone();
+                let added = match &mut self.wallet {
+                    Some(wallet) => wallet.watch(&address).and_then(|added| wallet.save(&path).map(|()| added)),
+                    None => Wallet::load(&path).and_then(|mut wallet| {
+                        let added = wallet.watch(&address)?;
+                        wallet.save(&path)?;
+                        Ok(added)
+                    }),
+                };
+                match added {
+                    Ok(added) => {
+                        self.emit(
+                            || json!({ "address": address, "added": added }),
+                            || match added {
+                                true => outln!("Watching {}", address),
+                                false => outln!("Already watching {}", address),
+                            },
+                        );
+                        true
+                    }
+                    Err(err) => self.fail("Failed to watch address", err),
+                }
+            }
+            WalletCommand::ExportQr { address, key, png, scale } => self.export_qr(&address, key, png, scale),
+            WalletCommand::History => {
+                let addresses = match self.wallet_addresses() {
+                    Ok(addresses) => addresses,
+                    Err(err) => return self.fail("Failed to read wallet", err),
+                };
+                let blockchain = read(&self.chain);
+                let histories: Vec<(String, bool, Vec<index::HistoryEntry>)> = addresses
+                    .into_iter()
+                    .map(|(address, watch_only)| {
+                        let entries = index::history(&blockchain, &address);
+                        (address, watch_only, entries)
+                    })
+                    .collect();
+                self.emit(
+                    || {
+                        let entries: Vec<Value> = histories
+                            .iter()
+                            .map(|(address, watch_only, entries)| {
+                                json!({ "address": address, "watch_only": watch_only, "transactions": entries })
+                            })
+                            .collect();
+                        json!({ "addresses": entries })
+                    },
+                    || {
+                        for (address, watch_only, entries) in &histories {
+                            outln!("{}{}:", address, if *watch_only { " (watch-only)" } else { "" });
+                            print_history(address, entries);
+                        }
+                    },
+                );
+                true
+            }
+        }
+    }
+
+    /// Runs one REPL command; returns false once the user asks to exit.
+    fn run_repl_command(&mut self, command: ReplCommand) -> bool {
+        match command {
+            ReplCommand::Chain(command) => {
+                self.run(command);
+            }
+            ReplCommand::Threads { count } if count > 0 => {
+                write(&self.chain).set_miner(configure_miner(Miner::new(count), &self.cancel, self.progress));
+                self.emit(
+                    || json!({ "threads": count }),
+                    || outln!("Mining with {} thread(s)", count),
+                );
+            }
+            ReplCommand::Threads { .. } => {
+                self.fail("Invalid thread count", "must be at least 1");
+            }
+            ReplCommand::Peers => {
+                let peers = self.node.as_ref().map(Node::peer_scores).unwrap_or_default();
+                let keys: BTreeMap<_, _> = self.node.as_ref().map(Node::peer_keys).unwrap_or_default().into_iter().collect();
+                let bans = self.node.as_ref().map(Node::bans).unwrap_or_default();
+                self.emit(
+                    || {
+                        let peers: Vec<_> = peers
+                            .iter()
+                            .map(|(addr, score)| json!({ "addr": addr, "key": keys.get(addr), "score": score }))
+                            .collect();
+                        let bans: Vec<_> = bans
+                            .iter()
+                            .map(|(ip, left)| json!({ "ip": ip, "seconds_left": left.as_secs() }))
+                            .collect();
+                        json!({ "peers": peers, "banned": bans })
+                    },
+                    || {
+                        outln!("Connected peers: {}", peers.len());
+                        for (addr, score) in &peers {
+                            let key = keys.get(addr).map_or("", String::as_str);
+                            outln!("  {} key {} (misbehavior score {})", addr, key, score);
+                        }
+                        if !bans.is_empty() {
+                            outln!("Banned:");
+                        }
+                        for (ip, left) in &bans {
+                            outln!("  {} for {} more", ip, describe_seconds(left.as_secs_f64()));
+                        }
+                    },
+                );
+            }
+            ReplCommand::Exit => return false,
+        }
+        true
+    }
+
+    fn repl(&mut self) {
+        outln!("Mini Blockchain CLI with Mining & Transactions");
+        outln!(
+            "Mining with {} thread(s). Type 'help' for commands or '<command> --help' for details.",
+            read(&self.chain).miner().threads()
+        );
+        outln!();
+
+        loop {
+            print!("> ");
+            let _ = io::stdout().flush();
+
+            let mut input = String::new();
+            match io::stdin().read_line(&mut input) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(err) => {
+                    self.fail("Failed to read command", err);
+                    break;
+                }
+            }
+            let words: Vec<&str> = input.split_whitespace().collect();
+            if words.is_empty() {
+                continue;
+            }
+
+            match ReplLine::try_parse_from(words) {
+                Ok(line) => {
+                    if !self.run_repl_command(line.command) {
+                        break;
+                    }
+                }
+                // Covers both `help`/`--help` output and usage errors.
+                Err(err) => {
+                    let _ = err.print();
                 }
             }
-            ["view"] => blockchain.view_chain(),
-            ["validate"] => {
-                println!("Blockchain valid? {}", blockchain.is_chain_valid());
+            outln!();
+        }
+        self.save();
+        outln!("Goodbye!");
+    }
+
+    /// Runs the commands `ctl` sends until one of them is `exit`, answering
+    /// each with what it printed.
+    #[cfg(unix)]
+    fn control(&mut self, socket: &ControlSocket) {
+        loop {
+            let (request, reply) = match socket.accept() {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    warn!(%err, "bad control connection");
+                    continue;
+                }
+            };
+            debug!(args = ?request.args, "control command");
+            let json = std::mem::replace(&mut self.json, request.json);
+            let (output, (ok, running)) = capture(request.password, || match ReplLine::try_parse_from(&request.args) {
+                Ok(ReplLine {
+                    command: ReplCommand::Chain(command),
+                }) => (self.run(command), true),
+                Ok(line) => (true, self.run_repl_command(line.command)),
+                Err(err) => {
+                    let ok = !err.use_stderr();
+                    outln!("{}", err.render().to_string().trim_end());
+                    (ok, true)
+                }
+            });
+            self.json = json;
+            if let Err(err) = reply.send(&ControlResponse { output, ok }) {
+                warn!(%err, "failed to answer control command");
             }
-            ["exit"] => {
-                println!("Goodbye!");
+            if !running {
                 break;
             }
-            _ => {
-                println!("Invalid command. Use 'add <sender> <receiver> <amount>', 'view', 'validate', or 'exit'");
+        }
+        self.save();
+        info!("daemon stopped");
+    }
+}
+
+/// Runs the simulation `args` describe and reports on it. Returns whether
+/// the nodes agreed in the end.
+fn simulate(args: &SimulateArgs, json: bool) -> bool {
+    let config = SimConfig {
+        nodes: args.nodes,
+        rounds: args.rounds,
+        latency: Duration::from_millis(args.latency_ms),
+        transactions_per_round: args.transactions,
+        partitions: args.partition.clone(),
+        seed: args.seed,
+        ..SimConfig::default()
+    };
+    let report = match sim::run(&config) {
+        Ok(report) => report,
+        Err(err) => {
+            eprintln!("Failed to run the simulation: {}", err);
+            return false;
+        }
+    };
+    if json {
+        outln!("{:#}", json!(report));
+    } else {
+        outln!(
+            "{} node(s), {} round(s): {} block(s) mined, {} transaction(s) submitted",
+            report.nodes,
+            report.rounds,
+            report.blocks_mined,
+            report.transactions_submitted
+        );
+        for (i, (height, tip)) in report.heights.iter().zip(&report.tips).enumerate() {
+            outln!("  node {}: height {}, tip {}", i, height, tip);
+        }
+        if report.consistent {
+            outln!("All nodes agree on the chain.");
+        } else {
+            outln!("The nodes did not agree on one chain.");
+        }
+    }
+    report.consistent
+}
+
+/// Runs a light-mode command: syncs the saved headers from the first peer that
+/// accepts us and saves them again, then does what `command` asks.
+fn run_light(settings: &Settings, command: &LightCommand, json: bool) -> Result<(), String> {
+    let params = settings.params()?.unwrap_or_default();
+    let path = settings.data_dir.join(HEADERS_PATH);
+    let mut headers =
+        HeaderChain::load(&path, params).map_err(|err| format!("Failed to read {}: {}", path.display(), err))?;
+    if settings.peers.is_empty() {
+        return Err("Light mode needs a --peer to sync from".to_string());
+    }
+    let key = settings.node_key()?;
+    let mut client = settings
+        .peers
+        .iter()
+        .find_map(|peer| {
+            LightClient::connect_as(peer.as_str(), &headers, &key)
+                .inspect_err(|err| warn!(peer, %err, "failed to connect to peer"))
+                .ok()
+        })
+        .ok_or("Could not connect to any peer")?;
+    let connected = client.sync(&mut headers).map_err(|err| format!("Failed to sync headers: {}", err))?;
+    headers.save(&path).map_err(|err| format!("Failed to save headers: {}", err))?;
+    let (height, peer) = (headers.height(), client.peer());
+    match command {
+        LightCommand::Sync if json => outln!("{:#}", json!({ "peer": peer, "height": height, "connected": connected })),
+        LightCommand::Sync => outln!("Synced {} header(s) from {}; at block #{}", connected, peer, height),
+        LightCommand::Verify { txid } => {
+            let proof = client
+                .fetch_proof(txid)
+                .map_err(|err| format!("Failed to fetch proof: {}", err))?
+                .ok_or_else(|| format!("{} has no confirmed transaction {}", peer, txid))?;
+            let confirmations = headers.verify(&proof).map_err(|err| format!("Invalid proof: {}", err))?;
+            let block = headers.header_by_hash(&proof.block_hash).map_or(0, |header| header.index());
+            if json {
+                outln!("{:#}", json!({ "txid": txid, "block": block, "confirmations": confirmations }));
+            } else {
+                outln!("Transaction {} is in block #{} ({} confirmation(s))", txid, block, confirmations);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sends logs to stderr, keeping stdout for command output. Without
+/// `--verbose` or `--quiet`, `RUST_LOG` picks what is logged (info by default).
+fn init_logging(verbose: u8, quiet: bool) {
+    let filter = match (quiet, verbose) {
+        (true, _) => EnvFilter::new("error"),
+        (false, 0) => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        (false, 1) => EnvFilter::new("debug"),
+        (false, _) => EnvFilter::new("trace"),
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .with_ansi(io::stderr().is_terminal())
+        .init();
+}
+
+/// Attaches the Ctrl-C cancel token and, if wanted, a live hash rate line.
+fn configure_miner(miner: Miner, cancel: &CancelToken, progress: bool) -> Miner {
+    let miner = miner.with_cancel_token(cancel.clone());
+    if !progress {
+        return miner;
+    }
+    miner.with_progress(|progress| {
+        eprint!("\r\x1b[KMining... {}", progress);
+        let _ = io::stderr().flush();
+    })
+}
+
+/// Ctrl-C or a termination signal cancels the block being mined, if any,
+/// then saves the chain and mempool and exits.
+/// Starts the mining job and gRPC servers `serve` asks for, and returns
+/// the HTTP API server for the caller to run.
+/// Starts the servers `serve` asks for besides the HTTP API, which is
+/// returned unstarted, along with the auto-miner if there is one.
+fn start_servers(app: &App, serve: &ServeArgs, profile: ChainProfile) -> (RpcServer, Option<AutoMining>) {
+    let block_hook = || {
+        let persist = persist_hook(app.store.clone());
+        let node = app.node.clone();
+        move |blockchain: &Blockchain| {
+            persist(blockchain);
+            if let Some(node) = &node {
+                node.broadcast_block(blockchain.latest_block());
+            }
+        }
+    };
+    if let (Some(stratum), Some(payout)) = (serve.stratum, &serve.payout) {
+        let pool = StratumServer::new(Arc::clone(&app.chain), Arc::clone(&app.mempool), payout.as_str())
+            .with_share_difficulty(serve.share_difficulty)
+            .with_block_hook(block_hook());
+        match pool.listen(("0.0.0.0", stratum)) {
+            Ok(addr) => info!(port = addr.port(), "serving mining jobs"),
+            Err(err) => {
+                eprintln!("Failed to serve mining jobs: {}", err);
+                process::exit(1);
+            }
+        }
+    }
+    #[cfg(feature = "grpc")]
+    if let Some(grpc) = serve.grpc {
+        let server = GrpcServer::new(Arc::clone(&app.chain), Arc::clone(&app.mempool)).with_block_hook(block_hook());
+        match server.listen(("0.0.0.0", grpc)) {
+            Ok(addr) => info!(port = addr.port(), "serving gRPC API"),
+            Err(err) => {
+                eprintln!("Failed to serve the gRPC API: {}", err);
+                process::exit(1);
+            }
+        }
+    }
+    let mining = serve.auto_mine.zip(serve.mine_to.as_ref()).map(|(seconds, miner)| {
+        info!(seconds, miner = miner.as_str(), "auto-mining");
+        AutoMiner::new(Arc::clone(&app.chain), Arc::clone(&app.mempool), miner.as_str(), Duration::from_secs(seconds))
+            .with_empty_blocks(serve.mine_empty || profile == ChainProfile::Regtest)
+            .with_block_hook(block_hook())
+            .start()
+    });
+    let server = RpcServer::new(Arc::clone(&app.chain), Arc::clone(&app.mempool)).with_block_hook(block_hook());
+    (server, mining)
+}
+
+fn handle_shutdown(app: &App) {
+    let (cancel, chain, mempool) = (app.cancel.clone(), Arc::clone(&app.chain), Arc::clone(&app.mempool));
+    let (mut store, mempool_path) = (app.store.clone(), app.mempool_path.clone());
+    let installed = ctrlc::set_handler(move || {
+        cancel.cancel();
+        match save_state(&chain, &mempool, &mut store, &mempool_path) {
+            Ok((height, pending)) => {
+                eprintln!(
+                    "\nShutting down: saved the chain at block #{} and {} pending transaction(s)",
+                    height, pending
+                );
+                process::exit(130);
+            }
+            Err(err) => {
+                eprintln!("\nShutting down without saving: {}", err);
+                process::exit(1);
+            }
+        }
+    });
+    if let Err(err) = installed {
+        warn!(%err, "failed to install shutdown handler");
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    init_logging(cli.verbose, cli.quiet);
+    let cancel = CancelToken::new();
+
+    let settings = Settings::resolve(&cli).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+    if let Some(Command::Worker { pool, name }) = &cli.command {
+        let miner = settings.threads.map_or_else(Miner::default, Miner::new).with_cancel_token(cancel.clone());
+        if let Err(err) = ctrlc::set_handler(move || cancel.cancel()) {
+            warn!(%err, "failed to install shutdown handler");
+        }
+        match StratumWorker::new(name.as_str(), miner).run(pool.as_str()) {
+            Ok(stats) if cli.json => outln!("{:#}", json!(stats)),
+            Ok(stats) => outln!("{} share(s) accepted, {} of them block(s)", stats.shares, stats.blocks),
+            Err(err) => {
+                eprintln!("Failed to mine for {}: {}", pool, err);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+    #[cfg(unix)]
+    if let Some(Command::Ctl { args }) = &cli.command {
+        let request = ControlRequest {
+            args: args.clone(),
+            json: cli.json,
+            password: env::var(PASSWORD_ENV).ok(),
+        };
+        match control::send(&settings.data_dir.join(SOCKET_FILE), &request) {
+            Ok(response) => {
+                print!("{}", response.output);
+                if !response.ok {
+                    process::exit(1);
+                }
+            }
+            Err(err) => {
+                eprintln!("Failed to reach the daemon: {}", err);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+    if let Some(Command::NodeKey) = &cli.command {
+        match settings.node_key() {
+            Ok(key) if cli.json => outln!("{:#}", json!({ "public_key": key.public_key() })),
+            Ok(key) => outln!("Node key: {}", key.public_key()),
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
+            }
+        }
+        return;
+    }
+    if let Some(Command::Simulate(args)) = &cli.command {
+        if !simulate(args, cli.json) {
+            process::exit(1);
+        }
+        return;
+    }
+    if let Some(Command::Light { command }) = &cli.command {
+        if let Err(err) = run_light(&settings, command, cli.json) {
+            if cli.json {
+                outln!("{:#}", json!({ "error": err }));
+            } else {
+                outln!("{}", err);
+            }
+            process::exit(1);
+        }
+        return;
+    }
+    let (store, mut blockchain) = open_chain(&settings).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+    // Live hash rate output would garble JSON or redirected output, and a
+    // daemon has no one watching.
+    let progress = !cli.json && io::stderr().is_terminal() && !is_daemon(&cli);
+    let miner = settings.threads.map_or_else(Miner::default, Miner::new);
+    blockchain.set_miner(configure_miner(miner, &cancel, progress));
+    let mempool_path = settings.data_dir.join(MEMPOOL_PATH);
+    let mempool = Arc::new(Mutex::new(restore_mempool(&blockchain, &mempool_path, settings.mempool)));
+    let chain = Arc::new(RwLock::new(blockchain));
+    let node = start_node(&settings, &chain, &mempool, &store).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+
+    let mut app = App {
+        json: cli.json,
+        chain,
+        cancel,
+        progress,
+        mempool,
+        mempool_path,
+        store,
+        node,
+        wallet_path: settings.wallet.clone(),
+        wallet: None,
+    };
+
+    handle_shutdown(&app);
+
+    match cli.command {
+        None | Some(Command::Repl) => app.repl(),
+        Some(Command::Light { .. } | Command::Worker { .. } | Command::NodeKey | Command::Simulate(_)) => {
+            unreachable!("light mode, workers, node-key and simulations never open the chain")
+        }
+        #[cfg(unix)]
+        Some(Command::Ctl { .. }) => unreachable!("ctl never opens the chain"),
+        Some(Command::Chain(command)) => {
+            let succeeded = app.run(command);
+            if !app.save() || !succeeded {
+                process::exit(1);
+            }
+        }
+        Some(Command::Serve(serve)) => {
+            let port = settings.rpc_port;
+            let (server, _mining) = start_servers(&app, &serve, settings.profile);
+            info!(port, "serving HTTP API");
+            if let Err(err) = server.serve(("0.0.0.0", port)) {
+                error!(%err, "HTTP server stopped");
+                process::exit(1);
+            }
+        }
+        #[cfg(unix)]
+        Some(Command::Daemon(serve)) => {
+            let path = settings.data_dir.join(SOCKET_FILE);
+            let socket = ControlSocket::bind(&path).unwrap_or_else(|err| {
+                eprintln!("Failed to open the control socket {}: {}", path.display(), err);
+                process::exit(1);
+            });
+            let port = settings.rpc_port;
+            let (server, _mining) = start_servers(&app, &serve, settings.profile);
+            match server.listen(("0.0.0.0", port)) {
+                Ok(addr) => info!(port = addr.port(), "serving HTTP API"),
+                Err(err) => {
+                    eprintln!("Failed to serve the HTTP API: {}", err);
+                    process::exit(1);
+                }
             }
+            info!(socket = %path.display(), "daemon started");
+            app.control(&socket);
         }
-        println!();
     }
 }
@@ -0,0 +1,531 @@
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+use crate::amount::Amount;
+use crate::error::{BlockchainError, Result};
+use crate::file;
+use crate::transaction::Transaction;
+use crate::utxo::OutPoint;
+
+pub const DEFAULT_BATCH_SIZE: usize = 10; // Max transactions packaged per mined block
+pub const DEFAULT_MAX_TRANSACTIONS: usize = 10_000;
+pub const DEFAULT_MAX_BYTES: usize = 10_000_000; // Serialized transactions
+pub const DEFAULT_EXPIRY_BLOCKS: u64 = 1_000;
+
+/// How much a mempool holds, for how long, and what it takes, e.g. from the
+/// `[mempool]` table of the config file.
+///
+/// The minimum fee rate and dust limit are this node's relay policy, not
+/// consensus: transactions they refuse are still valid in blocks, whoever
+/// mined them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MempoolLimits {
+    /// Most transactions held. When full, ones paying lower fee rates than a
+    /// newcomer are evicted to make room for it.
+    pub max_transactions: usize,
+    /// Most bytes of serialized transactions held, enforced the same way.
+    pub max_bytes: usize,
+    /// Transactions still pending this many blocks after they were queued
+    /// are dropped; 0 keeps them until mined.
+    pub expiry_blocks: u64,
+    /// Lowest fee accepted, in base units per serialized byte; 0 takes
+    /// transactions paying no fee.
+    pub min_fee_rate: u64,
+    /// Smallest native-coin output accepted, including change; 0 allows any.
+    /// Outputs of nothing, as data-only transactions make, always pass.
+    pub dust_limit: Amount,
+}
+
+impl MempoolLimits {
+    /// The lowest fee accepted for a transaction of `size` bytes.
+    pub fn min_fee_for(&self, size: usize) -> Amount {
+        Amount::from_units(self.min_fee_rate.saturating_mul(size as u64))
+    }
+
+    /// Fails if `tx` pays less than the minimum fee rate.
+    pub fn check_fee_rate(&self, tx: &Transaction) -> Result<()> {
+        let minimum = self.min_fee_for(tx.size());
+        if tx.fee() < minimum {
+            return Err(BlockchainError::Validation(format!(
+                "fee {} is below the minimum relay fee of {} for {} bytes ({} units per byte)",
+                tx.fee(),
+                minimum,
+                tx.size(),
+                self.min_fee_rate
+            )));
+        }
+        Ok(())
+    }
+
+    /// Fails if `tx` creates an output worth less than the dust limit.
+    pub fn check_dust(&self, tx: &Transaction) -> Result<()> {
+        let dust = tx.outputs().into_iter().find(|output| !output.amount.is_zero() && output.amount < self.dust_limit);
+        if let Some(output) = dust {
+            return Err(BlockchainError::Validation(format!(
+                "output of {} to {} is below the dust limit of {}",
+                output.amount, output.owner, self.dust_limit
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Default for MempoolLimits {
+    fn default() -> Self {
+        MempoolLimits {
+            max_transactions: DEFAULT_MAX_TRANSACTIONS,
+            max_bytes: DEFAULT_MAX_BYTES,
+            expiry_blocks: DEFAULT_EXPIRY_BLOCKS,
+            min_fee_rate: 0,
+            dust_limit: Amount::ZERO,
+        }
+    }
+}
+
+/// A pending transaction and the chain height it was queued at.
+#[derive(Debug, Clone)]
+struct Entry {
+    txid: String,
+    tx: Transaction,
+    height: u64,
+    size: usize,
+}
+
+impl Entry {
+    /// Orders by fee per byte, without dividing.
+    fn cmp_rate(&self, other: &Entry) -> Ordering {
+        let ours = u128::from(self.tx.fee().units()) * other.size.max(1) as u128;
+        let theirs = u128::from(other.tx.fee().units()) * self.size.max(1) as u128;
+        ours.cmp(&theirs)
+    }
+}
+
+/// Fails unless `replacement` may take the place of the pending `original`,
+/// which it must pay a higher fee than, at no lower a fee rate, so miners
+/// never lose by the swap.
+pub fn check_replacement(original: &Transaction, replacement: &Transaction) -> Result<()> {
+    let rate = |tx: &Transaction, other: &Transaction| u128::from(tx.fee().units()) * other.size().max(1) as u128;
+    if replacement.fee() <= original.fee() || rate(replacement, original) < rate(original, replacement) {
+        return Err(BlockchainError::Validation(format!(
+            "a replacement for pending transaction {} must pay a fee above {} at no lower a fee rate, but offers {}",
+            original.hash(),
+            original.fee(),
+            replacement.fee()
+        )));
+    }
+    Ok(())
+}
+
+/// A pending transaction as [`Mempool::save`] writes it.
+#[derive(Serialize, Deserialize)]
+struct SavedEntry<T> {
+    transaction: T,
+    queued_at: u64,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Saved {
+    Entry(SavedEntry<Transaction>),
+    /// Saved before queue heights were kept.
+    Bare(Transaction),
+}
+
+/// Counts and sizes of a mempool's contents, for display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MempoolStats {
+    pub transactions: usize,
+    pub bytes: usize,
+    pub fees: Amount,
+    /// Height the oldest pending transaction was queued at.
+    pub oldest_height: Option<u64>,
+}
+
+/// Transactions waiting to be mined, in arrival order, within the
+/// mempool's [`MempoolLimits`].
+///
+/// A sender may replace a pending account-model transaction by sending
+/// another with the same sequence number and a higher fee; see
+/// [`check_replacement`].
+#[derive(Debug, Default, Clone)]
+pub struct Mempool {
+    pending: VecDeque<Entry>,
+    limits: MempoolLimits,
+    bytes: usize,
+    /// Where the pending transactions are saved each time they change.
+    file: Option<PathBuf>,
+}
+
+impl Mempool {
+    pub fn new() -> Self {
+        Mempool::default()
+    }
+
+    pub fn with_limits(limits: MempoolLimits) -> Self {
+        Mempool {
+            limits,
+            ..Mempool::default()
+        }
+    }
+
+    pub fn limits(&self) -> &MempoolLimits {
+        &self.limits
+    }
+
+    /// Queues `tx`, received at chain height `height`, without checking it
+    /// against the chain; [`Blockchain::submit_transaction`](crate::Blockchain::submit_transaction)
+    /// does that first. If the mempool is full, the transactions paying the
+    /// lowest fee rates are evicted to make room, and returned; if that
+    /// would take evicting ones paying as much as `tx`, it is refused.
+    pub fn insert(&mut self, tx: Transaction, height: u64) -> Result<Vec<Transaction>> {
+        let entry = Entry {
+            txid: tx.hash(),
+            size: tx.size(),
+            tx,
+            height,
+        };
+        let evict = self.eviction_for(&entry)?;
+        let evicted = self.remove_where(|pending| evict.contains(&pending.txid));
+        if !evicted.is_empty() {
+            debug!(count = evicted.len(), "evicted low-fee transactions to make room");
+        }
+        self.bytes += entry.size;
+        self.pending.push_back(entry);
+        self.changed();
+        Ok(evicted)
+    }
+
+    /// Swaps the pending transaction with ID `txid` for `replacement`,
+    /// received at `height`, and returns it. Like [`Mempool::insert`], this
+    /// does not check `replacement` against the chain, and may evict others
+    /// to make room; if it doesn't fit, the original stays.
+    pub fn replace(&mut self, txid: &str, replacement: Transaction, height: u64) -> Result<Transaction> {
+        let original = self
+            .pending
+            .iter()
+            .position(|entry| entry.txid == txid)
+            .and_then(|position| Some((position, self.pending.remove(position)?)));
+        let Some((position, original)) = original else {
+            return Err(BlockchainError::Validation(format!("transaction {} is not pending", txid)));
+        };
+        self.bytes -= original.size;
+        match self.insert(replacement, height) {
+            Ok(_) => Ok(original.tx),
+            Err(err) => {
+                self.bytes += original.size;
+                self.pending.insert(position, original);
+                Err(err)
+            }
+        }
+    }
+
+    /// The pending transaction `tx` would replace: the account-model one
+    /// from the same sender with the same sequence number.
+    pub fn replaceable_by(&self, tx: &Transaction) -> Option<&Transaction> {
+        if !tx.is_sequenced() {
+            return None;
+        }
+        self.iter().find(|pending| {
+            pending.is_sequenced() && pending.sender() == tx.sender() && pending.sequence() == tx.sequence()
+        })
+    }
+
+    /// Checks that `tx` would fit, by evicting transactions paying lower fee
+    /// rates if the mempool is full, without inserting it.
+    pub fn check_room(&self, tx: &Transaction) -> Result<()> {
+        let entry = Entry {
+            txid: tx.hash(),
+            size: tx.size(),
+            tx: tx.clone(),
+            height: 0,
+        };
+        self.eviction_for(&entry).map(drop)
+    }
+
+    /// IDs of the transactions to evict so `entry` fits: the lowest fee
+    /// rates first, each with its sender's later account-model transactions,
+    /// which could no longer apply without it. The newcomer's own sender's
+    /// are kept, since it may follow them.
+    fn eviction_for(&self, entry: &Entry) -> Result<Vec<String>> {
+        let full = |count: usize, bytes: usize| {
+            count + 1 > self.limits.max_transactions || bytes + entry.size > self.limits.max_bytes
+        };
+        let (mut count, mut bytes) = (self.pending.len(), self.bytes);
+        if !full(count, bytes) {
+            return Ok(Vec::new());
+        }
+        let mut candidates: Vec<&Entry> = self
+            .pending
+            .iter()
+            .filter(|pending| pending.tx.sender() != entry.tx.sender())
+            .collect();
+        candidates.sort_by(|a, b| a.cmp_rate(b));
+        let mut evict: Vec<String> = Vec::new();
+        for candidate in candidates {
+            if !full(count, bytes) {
+                break;
+            }
+            if evict.contains(&candidate.txid) {
+                continue;
+            }
+            if candidate.cmp_rate(entry) != Ordering::Less {
+                break;
+            }
+            for pending in self.pending.iter().filter(|pending| {
+                pending.txid == candidate.txid
+                    || (candidate.tx.is_sequenced()
+                        && pending.tx.is_sequenced()
+                        && pending.tx.sender() == candidate.tx.sender()
+                        && pending.tx.sequence() > candidate.tx.sequence())
+            }) {
+                if !evict.contains(&pending.txid) {
+                    count -= 1;
+                    bytes -= pending.size;
+                    evict.push(pending.txid.clone());
+                }
+            }
+        }
+        if full(count, bytes) {
+            return Err(BlockchainError::Validation(format!(
+                "mempool is full ({} transactions, {} bytes); a higher fee rate is needed to replace what it holds",
+                self.pending.len(),
+                self.bytes
+            )));
+        }
+        Ok(evict)
+    }
+
+    /// Drops the transactions queued `expiry_blocks` or more blocks before
+    /// `height`, with their senders' later account-model transactions, and
+    /// returns them.
+    pub fn expire(&mut self, height: u64) -> Vec<Transaction> {
+        let expiry = self.limits.expiry_blocks;
+        if expiry == 0 {
+            return Vec::new();
+        }
+        let expired: Vec<(String, u64)> = self
+            .pending
+            .iter()
+            .filter(|entry| height.saturating_sub(entry.height) >= expiry && entry.tx.is_sequenced())
+            .map(|entry| (entry.tx.sender().to_string(), entry.tx.sequence()))
+            .collect();
+        let removed = self.remove_where(|entry| {
+            height.saturating_sub(entry.height) >= expiry
+                || (entry.tx.is_sequenced()
+                    && expired
+                        .iter()
+                        .any(|(sender, sequence)| entry.tx.sender() == sender && entry.tx.sequence() > *sequence))
+        });
+        if !removed.is_empty() {
+            debug!(count = removed.len(), height, "expired pending transactions");
+        }
+        removed
+    }
+
+    /// Removes and returns the pending transactions `remove` picks, saving
+    /// the change if there was one.
+    fn remove_where(&mut self, remove: impl Fn(&Entry) -> bool) -> Vec<Transaction> {
+        let (removed, kept): (VecDeque<Entry>, VecDeque<Entry>) = self.pending.drain(..).partition(|entry| remove(entry));
+        self.pending = kept;
+        if removed.is_empty() {
+            return Vec::new();
+        }
+        self.bytes -= removed.iter().map(|entry| entry.size).sum::<usize>();
+        self.changed();
+        removed.into_iter().map(|entry| entry.tx).collect()
+    }
+
+    pub fn stats(&self) -> MempoolStats {
+        MempoolStats {
+            transactions: self.pending.len(),
+            bytes: self.bytes,
+            fees: self.iter().fold(Amount::ZERO, |total, tx| total.saturating_add(tx.fee())),
+            oldest_height: self.pending.iter().map(|entry| entry.height).min(),
+        }
+    }
+
+    /// The height each pending transaction was queued at, in arrival order.
+    pub fn entries(&self) -> impl Iterator<Item = (&Transaction, u64)> {
+        self.pending.iter().map(|entry| (&entry.tx, entry.height))
+    }
+
+    /// From now on, saves the pending transactions to `path` as
+    /// [`Mempool::save`] does whenever they change, so they survive a crash.
+    /// Failures to save are logged rather than returned.
+    pub fn persist_to(&mut self, path: impl Into<PathBuf>) {
+        self.file = Some(path.into());
+    }
+
+    fn changed(&self) {
+        if let Some(path) = &self.file
+            && let Err(err) = self.save(path)
+        {
+            warn!(path = %path.display(), %err, "failed to save the mempool");
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Transaction> {
+        self.pending.iter().map(|entry| &entry.tx)
+    }
+
+    /// The pending transaction with this ID.
+    pub fn get(&self, txid: &str) -> Option<&Transaction> {
+        self.pending.iter().find(|entry| entry.txid == txid).map(|entry| &entry.tx)
+    }
+
+    pub fn contains(&self, txid: &str) -> bool {
+        self.get(txid).is_some()
+    }
+
+    /// Total amount (fees included) the given address is already spending in
+    /// pending transactions.
+    pub fn pending_outgoing(&self, address: &str) -> Amount {
+        self.iter()
+            .filter(|tx| tx.sender() == address)
+            .fold(Amount::ZERO, |total, tx| total.saturating_add(tx.cost().unwrap_or(Amount::MAX)))
+    }
+
+    /// Total amount of `asset` the given address is already sending in
+    /// pending transfers.
+    pub fn pending_asset_outgoing(&self, address: &str, asset: &str) -> Amount {
+        self.iter()
+            .filter(|tx| tx.sender() == address && tx.asset() == Some(asset) && !tx.is_issue())
+            .fold(Amount::ZERO, |total, tx| total.saturating_add(tx.amount()))
+    }
+
+    /// How many pending account-model transactions `address` has sent, each
+    /// holding one of its sequence numbers.
+    pub fn pending_sequenced(&self, address: &str) -> u64 {
+        self.iter()
+            .filter(|tx| tx.is_sequenced() && tx.sender() == address)
+            .count() as u64
+    }
+
+    /// Whether a pending transaction already spends `outpoint`.
+    pub fn is_spent(&self, outpoint: &OutPoint) -> bool {
+        self.iter().any(|tx| tx.inputs().contains(outpoint))
+    }
+
+    /// Returns copies of the `max` transactions paying the highest fee per
+    /// byte, without removing them.
+    pub fn peek_batch(&self, max: usize) -> Vec<Transaction> {
+        self.peek_batch_within(max, usize::MAX)
+    }
+
+    /// Like [`Mempool::peek_batch`], but skips any transaction that would
+    /// take the batch's total size past `max_bytes`. A sender's account-model
+    /// transactions are only picked after the ones with lower sequence
+    /// numbers, so the batch always applies in order.
+    pub fn peek_batch_within(&self, max: usize, max_bytes: usize) -> Vec<Transaction> {
+        self.peek_batch_where(max, max_bytes, |_| true)
+    }
+
+    /// Like [`Mempool::peek_batch_within`], but only picks transactions
+    /// `eligible` accepts, e.g. ones whose lock time has passed. The rest
+    /// still hold back their senders' later account-model transactions.
+    pub fn peek_batch_where(
+        &self,
+        max: usize,
+        max_bytes: usize,
+        eligible: impl Fn(&Transaction) -> bool,
+    ) -> Vec<Transaction> {
+        let mut by_rate: Vec<(u64, u64, &Transaction)> = self
+            .pending
+            .iter()
+            .map(|entry| (entry.tx.fee().units(), entry.size.max(1) as u64, &entry.tx))
+            .collect();
+        // Compare fee_a / size_a with fee_b / size_b without dividing; the
+        // sort is stable, so ties stay in arrival order.
+        by_rate.sort_by(|(fee_a, size_a, _), (fee_b, size_b, _)| {
+            let a = u128::from(*fee_a) * u128::from(*size_b);
+            let b = u128::from(*fee_b) * u128::from(*size_a);
+            b.cmp(&a)
+        });
+        let waits_on = |tx: &Transaction, earlier: &Transaction| {
+            tx.is_sequenced()
+                && earlier.is_sequenced()
+                && earlier.sender() == tx.sender()
+                && earlier.sequence() < tx.sequence()
+        };
+        let mut budget = max_bytes;
+        let mut batch = Vec::new();
+        while batch.len() < max {
+            let next = by_rate.iter().position(|&(_, size, tx)| {
+                size as usize <= budget && eligible(tx) && !by_rate.iter().any(|&(_, _, other)| waits_on(tx, other))
+            });
+            let Some(next) = next else {
+                break;
+            };
+            let (_, size, tx) = by_rate.remove(next);
+            budget -= size as usize;
+            batch.push(tx.clone());
+        }
+        batch
+    }
+
+    /// Writes the pending transactions and the heights they were queued at
+    /// to `path` as JSON, replacing the file atomically.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let pending: Vec<SavedEntry<&Transaction>> = self
+            .entries()
+            .map(|(transaction, queued_at)| SavedEntry { transaction, queued_at })
+            .collect();
+        file::write_atomic(path.as_ref(), &serde_json::to_vec(&pending)?)
+    }
+
+    /// Transactions written by [`Mempool::save`], in arrival order, or none
+    /// if `path` does not exist. They are not checked against any chain;
+    /// queue them with [`Blockchain::submit_transaction`](crate::Blockchain::submit_transaction).
+    pub fn load(path: impl AsRef<Path>) -> Result<Vec<Transaction>> {
+        Ok(Mempool::load_entries(path)?.into_iter().map(|(tx, _)| tx).collect())
+    }
+
+    /// Like [`Mempool::load`], with the height each transaction was queued
+    /// at if the file records it; queue them again with
+    /// [`Blockchain::restore_transaction`](crate::Blockchain::restore_transaction)
+    /// so they keep their age.
+    pub fn load_entries(path: impl AsRef<Path>) -> Result<Vec<(Transaction, Option<u64>)>> {
+        let saved: Vec<Saved> = match fs::read(path) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(saved
+            .into_iter()
+            .map(|saved| match saved {
+                Saved::Entry(entry) => (entry.transaction, Some(entry.queued_at)),
+                Saved::Bare(tx) => (tx, None),
+            })
+            .collect())
+    }
+
+    /// Drops one pending copy of each transaction in `batch`, e.g. once they
+    /// have been mined.
+    pub fn remove_batch(&mut self, batch: &[Transaction]) {
+        let before = self.pending.len();
+        for tx in batch {
+            let hash = tx.hash();
+            if let Some(position) = self.pending.iter().position(|pending| pending.txid == hash) {
+                let removed = self.pending.remove(position).map_or(0, |entry| entry.size);
+                self.bytes -= removed;
+            }
+        }
+        if self.pending.len() != before {
+            self.changed();
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::transaction::Transaction;
+
+/// Root used for a block with no transactions.
+pub const EMPTY_ROOT: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+fn hash_pair(left: &str, right: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    format!("{:x}", hasher.finalize())
+}
+
+/// Hashes one tree level into the next, pairing an odd last node with itself.
+fn next_level(level: &[String]) -> Vec<String> {
+    level
+        .chunks(2)
+        .map(|pair| hash_pair(&pair[0], pair.get(1).unwrap_or(&pair[0])))
+        .collect()
+}
+
+pub fn merkle_root(transactions: &[Transaction]) -> String {
+    let mut level: Vec<String> = transactions.iter().map(Transaction::hash).collect();
+    if level.is_empty() {
+        return EMPTY_ROOT.to_string();
+    }
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level.remove(0)
+}
+
+/// Proof that a transaction is included under a Merkle root: the sibling
+/// hashes from the leaf up to the root.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    pub tx_hash: String,
+    pub index: usize,
+    pub siblings: Vec<String>,
+}
+
+impl MerkleProof {
+    pub fn build(transactions: &[Transaction], index: usize) -> Option<Self> {
+        let mut level: Vec<String> = transactions.iter().map(Transaction::hash).collect();
+        let tx_hash = level.get(index)?.clone();
+        let mut siblings = Vec::new();
+        let mut position = index;
+        while level.len() > 1 {
+            let sibling = level.get(position ^ 1).unwrap_or(&level[position]);
+            siblings.push(sibling.clone());
+            level = next_level(&level);
+            position /= 2;
+        }
+        Some(MerkleProof {
+            tx_hash,
+            index,
+            siblings,
+        })
+    }
+
+    /// Recomputes the root from the leaf and siblings and compares it to `root`.
+    pub fn verify(&self, root: &str) -> bool {
+        let mut hash = self.tx_hash.clone();
+        let mut position = self.index;
+        for sibling in &self.siblings {
+            hash = if position.is_multiple_of(2) {
+                hash_pair(&hash, sibling)
+            } else {
+                hash_pair(sibling, &hash)
+            };
+            position /= 2;
+        }
+        hash == root
+    }
+}
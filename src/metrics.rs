@@ -0,0 +1,85 @@
+use std::fmt::Write;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+#[derive(Debug, Default)]
+struct Counters {
+    hashes: AtomicU64,
+    /// An `f64` stored as its bits.
+    hash_rate: AtomicU64,
+    validation_failures: AtomicU64,
+    peers: AtomicU64,
+}
+
+/// Counters and gauges a node's chain, miner and peer network update as they
+/// run, rendered for Prometheus by [`Metrics::render`]. Clones share the same
+/// values.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics(Arc<Counters>);
+
+impl Metrics {
+    pub fn new() -> Self {
+        Metrics::default()
+    }
+
+    /// Counts hashes as a search tries them.
+    pub fn add_hashes(&self, hashes: u64) {
+        self.0.hashes.fetch_add(hashes, Ordering::Relaxed);
+    }
+
+    /// Records the hash rate of a finished search.
+    pub fn set_hash_rate(&self, hashes: u64, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        let rate = if seconds > 0.0 { hashes as f64 / seconds } else { 0.0 };
+        self.0.hash_rate.store(rate.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Counts a block, headers or transaction rejected as invalid.
+    pub fn add_validation_failure(&self) {
+        self.0.validation_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn set_peers(&self, peers: usize) {
+        self.0.peers.store(peers as u64, Ordering::Relaxed);
+    }
+
+    pub fn hashes(&self) -> u64 {
+        self.0.hashes.load(Ordering::Relaxed)
+    }
+
+    pub fn hash_rate(&self) -> f64 {
+        f64::from_bits(self.0.hash_rate.load(Ordering::Relaxed))
+    }
+
+    pub fn validation_failures(&self) -> u64 {
+        self.0.validation_failures.load(Ordering::Relaxed)
+    }
+
+    pub fn peers(&self) -> u64 {
+        self.0.peers.load(Ordering::Relaxed)
+    }
+
+    /// The metrics in the Prometheus text exposition format, along with the
+    /// chain height and mempool size, which the caller reads at scrape time.
+    pub fn render(&self, height: u64, mempool: usize) -> String {
+        let metrics: [(&str, &str, &str, f64); 6] = [
+            ("mini_block_chain_height", "gauge", "Index of the chain tip.", height as f64),
+            ("mini_block_mempool_transactions", "gauge", "Transactions waiting to be mined.", mempool as f64),
+            ("mini_block_hashes_total", "counter", "Hashes tried while mining.", self.hashes() as f64),
+            ("mini_block_hash_rate", "gauge", "Hashes per second of the last block mined.", self.hash_rate()),
+            ("mini_block_peers", "gauge", "Connected peers.", self.peers() as f64),
+            (
+                "mini_block_validation_failures_total",
+                "counter",
+                "Blocks, headers and transactions rejected as invalid.",
+                self.validation_failures() as f64,
+            ),
+        ];
+        let mut out = String::new();
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(out, "# HELP {} {}\n# TYPE {} {}\n{} {}", name, help, name, kind, name, value);
+        }
+        out
+    }
+}
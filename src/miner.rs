@@ -0,0 +1,396 @@
+use std::fmt;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tracing::{debug, debug_span};
+
+use crate::block::BlockHeader;
+use crate::clock::{Clock, SystemClock};
+use crate::error::{BlockchainError, Result};
+use crate::metrics::Metrics;
+use crate::target::Target;
+
+/// How often a progress callback is invoked while mining.
+pub const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
+/// How often blocks mined for a chain get a fresh timestamp; see
+/// [`Miner::with_timestamp_refresh`].
+pub const TIMESTAMP_REFRESH: Duration = Duration::from_secs(1);
+/// Workers publish their hash counts in batches of this size.
+const COUNT_BATCH: u64 = 1024;
+
+/// Stops mining in progress from another thread (or a signal handler).
+/// Clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Clears a cancellation so the token can be used for the next search.
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+/// A snapshot of a search in progress.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MiningProgress {
+    pub hashes: u64,
+    pub elapsed: Duration,
+}
+
+impl MiningProgress {
+    /// Hashes per second so far.
+    pub fn hash_rate(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 { self.hashes as f64 / seconds } else { 0.0 }
+    }
+}
+
+impl fmt::Display for MiningProgress {
+    /// Formats as e.g. `1.20 MH/s, 3.6M hashes in 3.0s`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let rate = self.hash_rate();
+        let (scaled, unit) = match rate {
+            r if r >= 1e9 => (r / 1e9, "GH/s"),
+            r if r >= 1e6 => (r / 1e6, "MH/s"),
+            r if r >= 1e3 => (r / 1e3, "kH/s"),
+            r => (r, "H/s"),
+        };
+        write!(
+            f,
+            "{:.2} {}, {:.1}M hashes in {:.1}s",
+            scaled,
+            unit,
+            self.hashes as f64 / 1e6,
+            self.elapsed.as_secs_f64()
+        )
+    }
+}
+
+type ProgressHook = Arc<dyn Fn(&MiningProgress) + Send + Sync>;
+type NonceStart = Arc<dyn Fn(&BlockHeader) -> u64 + Send + Sync>;
+
+/// Proof-of-work search that splits the nonce space across worker threads.
+///
+/// Thread `i` of `n` tries nonces `s + i, s + i + n, s + i + 2n, ...`
+/// (wrapping), where the start `s` is 0 unless a [`Miner::with_nonce_start`]
+/// hook picks it; the first thread to find a valid hash tells the others to
+/// stop. The miner's [`Clock`] timestamps the blocks it builds, and, with
+/// [`Miner::with_timestamp_refresh`], restamps the ones it searches.
+#[derive(Clone)]
+pub struct Miner {
+    threads: usize,
+    cancel: CancelToken,
+    on_progress: Option<ProgressHook>,
+    metrics: Metrics,
+    clock: Arc<dyn Clock>,
+    nonce_start: Option<NonceStart>,
+    timestamp_refresh: Option<Duration>,
+}
+
+impl fmt::Debug for Miner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Miner")
+            .field("threads", &self.threads)
+            .field("cancel", &self.cancel)
+            .field("clock", &self.clock)
+            .field("timestamp_refresh", &self.timestamp_refresh)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A search running on a background thread, started by [`Miner::spawn`].
+pub struct MiningJob {
+    handle: JoinHandle<Result<(BlockHeader, String)>>,
+    cancel: CancelToken,
+}
+
+impl MiningJob {
+    /// Asks the search to stop; [`MiningJob::join`] then returns
+    /// [`BlockchainError::Cancelled`].
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Waits for the search to finish.
+    pub fn join(self) -> Result<(BlockHeader, String)> {
+        self.handle
+            .join()
+            .unwrap_or_else(|_| Err(BlockchainError::Mining("mining thread panicked".to_string())))
+    }
+}
+
+impl Miner {
+    pub fn new(threads: usize) -> Self {
+        Miner {
+            threads: threads.max(1),
+            cancel: CancelToken::new(),
+            on_progress: None,
+            metrics: Metrics::new(),
+            clock: Arc::new(SystemClock),
+            nonce_start: None,
+            timestamp_refresh: None,
+        }
+    }
+
+    /// Makes searches stop early once `cancel` is cancelled.
+    pub fn with_cancel_token(mut self, cancel: CancelToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Registers a callback run every [`PROGRESS_INTERVAL`] during a search.
+    pub fn with_progress(mut self, hook: impl Fn(&MiningProgress) + Send + Sync + 'static) -> Self {
+        self.on_progress = Some(Arc::new(hook));
+        self
+    }
+
+    /// Records hashes tried and the hash rate of each search in `metrics`.
+    pub fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Timestamps blocks with `clock` instead of the system clock.
+    pub fn with_clock(mut self, clock: impl Clock + 'static) -> Self {
+        self.clock = Arc::new(clock);
+        self
+    }
+
+    /// Has each search start from the nonce `hook` returns for its header,
+    /// e.g. a random one so that miners paying the same address don't repeat
+    /// each other's work.
+    pub fn with_nonce_start(mut self, hook: impl Fn(&BlockHeader) -> u64 + Send + Sync + 'static) -> Self {
+        self.nonce_start = Some(Arc::new(hook));
+        self
+    }
+
+    /// Restamps the header with the miner's clock every `interval` and once
+    /// every nonce has been tried, searching the nonces again under the new
+    /// timestamp, so a search keeps the time current and never runs out.
+    /// Without this the header's timestamp is kept, as genesis blocks need.
+    pub fn with_timestamp_refresh(mut self, interval: Duration) -> Self {
+        self.timestamp_refresh = Some(interval);
+        self
+    }
+
+    pub fn clock(&self) -> &dyn Clock {
+        self.clock.as_ref()
+    }
+
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    pub fn cancel_token(&self) -> &CancelToken {
+        &self.cancel
+    }
+
+    /// Measures this miner's hash rate by searching a throwaway header for
+    /// about `duration`.
+    pub fn calibrate(&self, duration: Duration) -> MiningProgress {
+        let cancel = CancelToken::new();
+        let miner = Miner {
+            cancel: cancel.clone(),
+            on_progress: None,
+            metrics: Metrics::new(),
+            nonce_start: None,
+            timestamp_refresh: None,
+            ..self.clone()
+        };
+        let placeholder = "0".repeat(64);
+        let impossible = Target::from_leading_zeros(64);
+        let header = BlockHeader::new(0, 0, placeholder.clone(), placeholder, impossible.to_bits());
+        let started = Instant::now();
+        let timer = thread::spawn(move || {
+            thread::sleep(duration);
+            cancel.cancel();
+        });
+        let _ = miner.mine_to(header, impossible);
+        let _ = timer.join();
+        MiningProgress {
+            hashes: miner.metrics.hashes(),
+            elapsed: started.elapsed(),
+        }
+    }
+
+    /// Runs [`Miner::mine`] on a background thread.
+    pub fn spawn(&self, header: BlockHeader) -> MiningJob {
+        let miner = self.clone();
+        MiningJob {
+            cancel: self.cancel.clone(),
+            handle: thread::spawn(move || miner.mine(header)),
+        }
+    }
+
+    /// Searches for a nonce whose hash meets the header's target,
+    /// returning the header with that nonce (and any refreshed timestamp)
+    /// set and its hash, or
+    /// [`BlockchainError::Cancelled`] if the miner's cancel token fires first.
+    pub fn mine(&self, header: BlockHeader) -> Result<(BlockHeader, String)> {
+        let target = header.target();
+        self.mine_to(header, target)
+    }
+
+    /// Like [`Miner::mine`], but for a hash meeting `target` rather than the
+    /// header's own, e.g. an easier one for pool shares.
+    pub fn mine_to(&self, mut header: BlockHeader, target: Target) -> Result<(BlockHeader, String)> {
+        let _span = debug_span!("mine", index = header.index(), bits = header.bits(), threads = self.threads).entered();
+        let done = AtomicBool::new(false);
+        let hashes = AtomicU64::new(0);
+        let started = Instant::now();
+        let base = self.nonce_start.as_ref().map_or(0, |hook| hook(&header));
+
+        let outcome = thread::scope(|scope| {
+            if let Some(hook) = &self.on_progress {
+                let (done, hashes, cancel) = (&done, &hashes, &self.cancel);
+                scope.spawn(move || {
+                    let mut next_report = started + PROGRESS_INTERVAL;
+                    while !done.load(Ordering::Relaxed) && !cancel.is_cancelled() {
+                        thread::sleep(Duration::from_millis(20));
+                        if Instant::now() >= next_report {
+                            hook(&MiningProgress {
+                                hashes: hashes.load(Ordering::Relaxed),
+                                elapsed: started.elapsed(),
+                            });
+                            next_report += PROGRESS_INTERVAL;
+                        }
+                    }
+                });
+            }
+            let outcome = loop {
+                let deadline = self.timestamp_refresh.map(|every| Instant::now() + every);
+                match self.search(&header, &target, base, deadline, &hashes) {
+                    Round::Solved(solution) => break Some(solution),
+                    Round::Cancelled => break None,
+                    Round::Exhausted if self.timestamp_refresh.is_none() => break None,
+                    Round::Exhausted | Round::Expired => {
+                        // A new timestamp makes every nonce worth trying again.
+                        let now = self.clock.now_millis().unwrap_or_default();
+                        header.set_timestamp(now.max(header.timestamp() + 1));
+                        debug!(timestamp = header.timestamp(), "refreshed the block timestamp");
+                    }
+                }
+            };
+            // Lets the progress reporter exit even if the nonce space ran out.
+            done.store(true, Ordering::Relaxed);
+            outcome
+        });
+
+        let hashes = hashes.load(Ordering::Relaxed);
+        let elapsed = started.elapsed();
+        match outcome {
+            Some(solution) => {
+                self.metrics.set_hash_rate(hashes, elapsed);
+                debug!(nonce = solution.0.nonce(), hashes, ?elapsed, "found nonce");
+                Ok(solution)
+            }
+            None if self.cancel.is_cancelled() => {
+                debug!(hashes, ?elapsed, "mining cancelled");
+                Err(BlockchainError::Cancelled)
+            }
+            None => Err(BlockchainError::Mining("nonce space exhausted".to_string())),
+        }
+    }
+
+    /// Searches nonces of `header` from `base` across the worker threads
+    /// until one meets `target`, the search is cancelled, every nonce has
+    /// been tried or `deadline` passes.
+    fn search(
+        &self,
+        header: &BlockHeader,
+        target: &Target,
+        base: u64,
+        deadline: Option<Instant>,
+        hashes: &AtomicU64,
+    ) -> Round {
+        let found = AtomicBool::new(false);
+        let expired = AtomicBool::new(false);
+        let stride = self.threads as u64;
+        let solution = thread::scope(|scope| {
+            let workers: Vec<_> = (0..stride)
+                .map(|start| {
+                    let (found, expired, cancel, metrics) = (&found, &expired, &self.cancel, &self.metrics);
+                    let (mut header, hasher) = (header.clone(), header.hasher());
+                    scope.spawn(move || {
+                        // Offset from `base`, so the search covers every nonce once.
+                        let mut offset = start;
+                        let mut tried = 0;
+                        let result = loop {
+                            if found.load(Ordering::Relaxed) || cancel.is_cancelled() {
+                                break None;
+                            }
+                            let nonce = base.wrapping_add(offset);
+                            let digest = hasher.hash(nonce);
+                            tried += 1;
+                            if tried == COUNT_BATCH {
+                                hashes.fetch_add(tried, Ordering::Relaxed);
+                                metrics.add_hashes(tried);
+                                tried = 0;
+                                if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                                    expired.store(true, Ordering::Relaxed);
+                                    found.store(true, Ordering::Relaxed);
+                                }
+                            }
+                            if target.is_met_by_digest(&digest) {
+                                found.store(true, Ordering::Relaxed);
+                                header.set_nonce(nonce);
+                                break Some((header, hex::encode(digest)));
+                            }
+                            match offset.checked_add(stride) {
+                                Some(next) => offset = next,
+                                None => break None,
+                            }
+                        };
+                        hashes.fetch_add(tried, Ordering::Relaxed);
+                        metrics.add_hashes(tried);
+                        result
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .filter_map(|worker| worker.join().ok().flatten())
+                .min_by_key(|(header, _)| header.nonce().wrapping_sub(base))
+        });
+        match solution {
+            Some(solution) => Round::Solved(solution),
+            None if self.cancel.is_cancelled() => Round::Cancelled,
+            None if expired.load(Ordering::Relaxed) => Round::Expired,
+            None => Round::Exhausted,
+        }
+    }
+}
+
+/// How one pass of [`Miner::search`] over a header ended.
+enum Round {
+    Solved((BlockHeader, String)),
+    Cancelled,
+    /// Every nonce was tried.
+    Exhausted,
+    /// The timestamp refresh interval passed.
+    Expired,
+}
+
+impl Default for Miner {
+    /// Uses one worker per available CPU.
+    fn default() -> Self {
+        Miner::new(thread::available_parallelism().map_or(1, |n| n.get()))
+    }
+}
@@ -0,0 +1,1158 @@
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{IpAddr, Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, info_span, warn};
+
+use crate::ban::{BanList, Offense};
+use crate::block::{Block, BlockHeader};
+use crate::blockchain::Blockchain;
+use crate::compact::{CompactBlock, PartialBlock};
+use crate::download::{Download, Request};
+use crate::error::{BlockchainError, Result};
+use crate::events::{ChainEvent, NodeEvent};
+use crate::light::{HeaderChain, TxProof};
+use crate::metrics::Metrics;
+use crate::noise::{NodeKey, SecureStream};
+use crate::orphan::OrphanPool;
+use crate::rpc::SharedMempool;
+use crate::sync::{lock, read, write};
+use crate::transaction::Transaction;
+
+pub use crate::download::{BLOCK_BATCH, MAX_HEADERS};
+
+/// Version of the peer protocol spoken by this node. Peers announcing a
+/// different version are disconnected.
+pub const PROTOCOL_VERSION: u32 = 6;
+
+/// Connections beyond this many peers are refused, and peer exchange stops
+/// dialing new ones.
+pub const MAX_PEERS: usize = 8;
+
+/// Most messages a peer may send in a second before it is punished for
+/// flooding.
+pub const MAX_MESSAGE_RATE: usize = 200;
+
+/// Longest message a peer may send by default, in bytes, room for a batch
+/// of [`BLOCK_BATCH`] full blocks of the default size. A peer sending a
+/// longer one is punished and disconnected; see
+/// [`Node::with_max_message_bytes`].
+pub const MAX_MESSAGE_BYTES: usize = 32 << 20;
+
+/// Most transaction IDs announced, or asked for, in one message.
+pub const MAX_INVENTORY: usize = 1000;
+
+/// Transaction IDs remembered per peer as ones it has; the record starts
+/// over once it grows past this.
+const MAX_KNOWN_TRANSACTIONS: usize = 10_000;
+
+/// How long an announced transaction asked of one peer isn't asked of
+/// another that announces it too.
+const TRANSACTION_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Compact blocks held while their missing transactions are asked for; the
+/// oldest requests are dropped once there are more.
+const MAX_PENDING_COMPACT_BLOCKS: usize = 16;
+
+/// Messages exchanged between peers, sent as one JSON object per line over
+/// a connection encrypted with Noise (see [`NodeKey`]).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data")]
+pub enum Message {
+    /// The first message each side sends. Nothing else is accepted from a
+    /// peer until its handshake has been checked.
+    Hello(Handshake),
+    /// Asks the peer for the addresses of the peers it knows.
+    GetPeers,
+    /// Reply to `GetPeers`: addresses other nodes accept connections on.
+    Peers(Vec<SocketAddr>),
+    /// A block that was just mined or accepted by the sender.
+    NewBlock(Block),
+    /// Like `NewBlock`, but sent as a [`CompactBlock`] for the receiver to
+    /// rebuild from its mempool. Nodes announce blocks this way.
+    CompactBlock(CompactBlock),
+    /// Asks for the transactions at these positions in the block with this
+    /// hash, to complete a compact block.
+    GetBlockTransactions { hash: String, positions: Vec<usize> },
+    /// Reply to `GetBlockTransactions`, in the order asked for; empty if the
+    /// sender doesn't have the block.
+    BlockTransactions { hash: String, transactions: Vec<Transaction> },
+    /// Asks for the headers following the first of these block hashes (a
+    /// [`Blockchain::locator`]) that the peer has on its main chain.
+    GetHeaders(Vec<String>),
+    /// Reply to `GetHeaders`: at most [`MAX_HEADERS`] consecutive headers.
+    Headers(Vec<BlockHeader>),
+    /// Asks for the main-chain blocks with these hashes.
+    GetBlocks(Vec<String>),
+    /// Reply to `GetBlocks`, in the order requested.
+    Blocks(Vec<Block>),
+    /// Asks for a confirmed transaction and the proof its block includes it.
+    GetProof(String),
+    /// Reply to `GetProof`, or `None` if the transaction is not on the
+    /// sender's main chain.
+    Proof(Option<TxProof>),
+    /// IDs of transactions the sender has pending, at most
+    /// [`MAX_INVENTORY`]. Sent as they are queued, and on connecting.
+    Inventory(Vec<String>),
+    /// Asks for the pending transactions with these IDs.
+    GetTransactions(Vec<String>),
+    /// Reply to `GetTransactions`, leaving out those no longer pending.
+    Transactions(Vec<Transaction>),
+}
+
+impl Message {
+    /// The message's type tag, for logging.
+    fn kind(&self) -> &'static str {
+        match self {
+            Message::Hello(_) => "Hello",
+            Message::GetPeers => "GetPeers",
+            Message::Peers(_) => "Peers",
+            Message::NewBlock(_) => "NewBlock",
+            Message::CompactBlock(_) => "CompactBlock",
+            Message::GetBlockTransactions { .. } => "GetBlockTransactions",
+            Message::BlockTransactions { .. } => "BlockTransactions",
+            Message::GetHeaders(_) => "GetHeaders",
+            Message::Headers(_) => "Headers",
+            Message::GetBlocks(_) => "GetBlocks",
+            Message::Blocks(_) => "Blocks",
+            Message::GetProof(_) => "GetProof",
+            Message::Proof(_) => "Proof",
+            Message::Inventory(_) => "Inventory",
+            Message::GetTransactions(_) => "GetTransactions",
+            Message::Transactions(_) => "Transactions",
+        }
+    }
+}
+
+/// What a node tells a new peer about itself.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Handshake {
+    pub version: u32,
+    /// Peers with a different genesis block are on another network.
+    pub genesis_hash: String,
+    pub height: u64,
+    /// Port the sender accepts peers on, if it listens at all.
+    pub listen_port: Option<u16>,
+    /// Random per-node value, so a node that dials itself can tell.
+    pub nonce: u64,
+    /// Whether the sender is a [`LightClient`], which has headers but no
+    /// blocks to serve.
+    #[serde(default)]
+    pub light: bool,
+}
+
+/// How long a new peer has to complete the encryption handshake, and then
+/// to send its own.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A chain several threads use: queries share the read lock, and only
+/// changes to the chain take the write lock.
+pub type SharedChain = Arc<RwLock<Blockchain>>;
+type UpdateHook = Arc<dyn Fn(&Blockchain) + Send + Sync>;
+
+struct Peer {
+    addr: SocketAddr,
+    stream: SecureStream,
+    handshake: Handshake,
+    /// IDs of transactions the peer announced or was announced.
+    known: HashSet<String>,
+}
+
+impl Peer {
+    fn remember(&mut self, txids: &[String]) {
+        if self.known.len() + txids.len() > MAX_KNOWN_TRANSACTIONS {
+            self.known.clear();
+        }
+        self.known.extend(txids.iter().cloned());
+    }
+
+    /// The address the peer accepts connections on, if it listens.
+    fn listen_addr(&self) -> Option<SocketAddr> {
+        let port = self.handshake.listen_port?;
+        Some(SocketAddr::new(self.addr.ip(), port))
+    }
+}
+
+/// A peer-to-peer node that gossips blocks over TCP and adopts the valid
+/// chain with the most work it hears about. Cloning a node yields another handle to the
+/// same peer set and chain.
+///
+/// Peers start by exchanging a [`Handshake`]; one on a different network or
+/// protocol version is dropped. Each accepted peer is asked for the peers
+/// it knows, which the node then connects to as well, up to [`MAX_PEERS`].
+///
+/// A node that finds itself behind a peer at the handshake downloads the
+/// peer's headers, checks them, and then fetches the missing blocks in
+/// batches of [`BLOCK_BATCH`]. One such download runs at a time.
+///
+/// A gossiped block whose parent we lack is held in an [`OrphanPool`] and
+/// its missing ancestor asked for, block by block, until it connects. If it
+/// is more than [`BLOCK_BATCH`] blocks ahead of us, we download from its
+/// sender instead.
+///
+/// Given a mempool, the node relays transactions: it announces those queued
+/// to peers not known to have them, and asks a peer for those it announces
+/// that we lack, submitting them to the mempool in turn.
+///
+/// Blocks are announced as [`CompactBlock`]s, and a peer rebuilding one
+/// asks only for the transactions missing from its mempool.
+///
+/// Light clients ([`LightClient`]) are sent headers and transaction proofs
+/// on request, but never synced from.
+///
+/// Peers that send invalid blocks or headers, malformed or unexpected
+/// messages, or more than [`MAX_MESSAGE_RATE`] messages a second are
+/// penalized in a [`BanList`], and banned once their score is too high:
+/// disconnected, and refused until the ban runs out.
+///
+/// Connections are encrypted, and each side authenticated by its static
+/// [`NodeKey`]. A node given an allowlist only talks to peers whose keys
+/// are on it.
+#[derive(Clone)]
+pub struct Node {
+    chain: SharedChain,
+    peers: Arc<Mutex<Vec<Peer>>>,
+    download: Arc<Mutex<Download>>,
+    orphans: Arc<Mutex<OrphanPool>>,
+    bans: Arc<Mutex<BanList>>,
+    mempool: Option<SharedMempool>,
+    /// Transactions asked of a peer, and when.
+    requested: Arc<Mutex<HashMap<String, Instant>>>,
+    /// Compact blocks waiting for their missing transactions, by hash, with
+    /// the peer asked for them.
+    compact: Arc<Mutex<HashMap<String, (SocketAddr, PartialBlock)>>>,
+    key: Arc<NodeKey>,
+    allowlist: Option<Arc<HashSet<String>>>,
+    on_update: UpdateHook,
+    listen_port: Arc<Mutex<Option<u16>>>,
+    /// Whether to ask peers for the peers they know, and dial those.
+    discover: bool,
+    /// See [`Node::with_max_message_bytes`].
+    max_message_bytes: usize,
+    nonce: u64,
+    metrics: Metrics,
+}
+
+/// Reads the next message line into `line`, returning its length, or 0 at
+/// the end of the stream. Fails without reading further once the line runs
+/// past `max` bytes.
+fn read_message(reader: &mut impl BufRead, line: &mut Vec<u8>, max: usize) -> Result<usize> {
+    line.clear();
+    let read = reader.by_ref().take(max as u64 + 1).read_until(b'\n', line)?;
+    if read > max && !line.ends_with(b"\n") {
+        return Err(BlockchainError::Validation(format!("message is longer than {} bytes", max)));
+    }
+    Ok(read)
+}
+
+fn send(stream: &mut SecureStream, message: &Message) -> Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    stream.write_all(&line)?;
+    Ok(())
+}
+
+impl Node {
+    /// A node for `chain` with a new random key, reporting its peer count
+    /// and invalid downloads to the chain's [`Metrics`].
+    pub fn new(chain: SharedChain) -> Self {
+        let metrics = read(&chain).metrics().clone();
+        Node {
+            chain,
+            peers: Arc::new(Mutex::new(Vec::new())),
+            download: Arc::new(Mutex::new(Download::Idle)),
+            orphans: Arc::new(Mutex::new(OrphanPool::default())),
+            bans: Arc::new(Mutex::new(BanList::default())),
+            mempool: None,
+            requested: Arc::new(Mutex::new(HashMap::new())),
+            compact: Arc::new(Mutex::new(HashMap::new())),
+            key: Arc::new(NodeKey::generate()),
+            allowlist: None,
+            on_update: Arc::new(|_| {}),
+            listen_port: Arc::new(Mutex::new(None)),
+            discover: true,
+            max_message_bytes: MAX_MESSAGE_BYTES,
+            nonce: OsRng.next_u64(),
+            metrics,
+        }
+    }
+
+    /// Registers a callback run (with the chain locked) whenever a block or
+    /// chain received from a peer changes our chain, e.g. to persist it.
+    pub fn with_update_hook(mut self, hook: impl Fn(&Blockchain) + Send + Sync + 'static) -> Self {
+        self.on_update = Arc::new(hook);
+        self
+    }
+
+    /// Punishes and disconnects peers sending messages longer than `bytes`,
+    /// rather than [`MAX_MESSAGE_BYTES`].
+    pub fn with_max_message_bytes(mut self, bytes: usize) -> Self {
+        self.max_message_bytes = bytes;
+        self
+    }
+
+    /// Judges peers by `bans` instead of the default thresholds.
+    pub fn with_ban_list(mut self, bans: BanList) -> Self {
+        self.bans = Arc::new(Mutex::new(bans));
+        self
+    }
+
+    /// Relays transactions through `mempool`: every transaction the chain
+    /// queues in it is announced to our peers, and those peers announce are
+    /// fetched and submitted to it.
+    pub fn with_mempool(mut self, mempool: SharedMempool) -> Self {
+        let events = read(&self.chain).subscribe();
+        self.mempool = Some(mempool);
+        let node = self.clone();
+        thread::spawn(move || {
+            for event in events {
+                if let NodeEvent::TransactionQueued { txid, .. } | NodeEvent::TransactionReplaced { txid, .. } = event {
+                    node.announce(&[txid], None);
+                }
+            }
+        });
+        self
+    }
+
+    /// Keeps to the peers we connect to ourselves or that connect to us,
+    /// never asking for or dialing others, for a fixed topology.
+    pub fn without_discovery(mut self) -> Self {
+        self.discover = false;
+        self
+    }
+
+    /// Identifies the node to its peers as `key` instead of a random key.
+    pub fn with_key(mut self, key: NodeKey) -> Self {
+        self.key = Arc::new(key);
+        self
+    }
+
+    /// Only talks to peers whose public keys, hex-encoded, are among `keys`.
+    pub fn with_allowlist(mut self, keys: impl IntoIterator<Item = String>) -> Self {
+        self.allowlist = Some(Arc::new(keys.into_iter().map(|key| key.to_lowercase()).collect()));
+        self
+    }
+
+    /// Our public key, hex-encoded.
+    pub fn public_key(&self) -> String {
+        self.key.public_key()
+    }
+
+    pub fn chain(&self) -> &SharedChain {
+        &self.chain
+    }
+
+    pub fn peer_count(&self) -> usize {
+        lock(&self.peers).len()
+    }
+
+    pub fn peers(&self) -> Vec<SocketAddr> {
+        lock(&self.peers).iter().map(|peer| peer.addr).collect()
+    }
+
+    /// Each connected peer with its public key.
+    pub fn peer_keys(&self) -> Vec<(SocketAddr, String)> {
+        lock(&self.peers).iter().map(|peer| (peer.addr, peer.stream.remote_key().to_string())).collect()
+    }
+
+    /// Each connected peer with the misbehavior score of its IP address.
+    pub fn peer_scores(&self) -> Vec<(SocketAddr, u32)> {
+        let addrs = self.peers();
+        let bans = lock(&self.bans);
+        addrs.into_iter().map(|addr| (addr, bans.score(addr.ip()))).collect()
+    }
+
+    /// Banned IP addresses and how long each ban has left.
+    pub fn bans(&self) -> Vec<(IpAddr, Duration)> {
+        lock(&self.bans).bans()
+    }
+
+    /// Blocks held until their parents arrive.
+    pub fn orphan_count(&self) -> usize {
+        lock(&self.orphans).len()
+    }
+
+    /// Accepts incoming peer connections on `addr` in a background thread.
+    pub fn listen(&self, addr: impl ToSocketAddrs) -> Result<SocketAddr> {
+        let listener = TcpListener::bind(addr)?;
+        let local = listener.local_addr()?;
+        *lock(&self.listen_port) = Some(local.port());
+        let node = self.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let node = node.clone();
+                // A peer that fails the handshake is simply dropped.
+                thread::spawn(move || {
+                    if let Err(err) = node.add_peer(stream, false) {
+                        info!(%err, "refused incoming peer");
+                    }
+                });
+            }
+        });
+        Ok(local)
+    }
+
+    /// Connects to a peer, failing if it rejects our handshake or we reject
+    /// its. If the peer's chain is longer we then fetch it to catch up.
+    pub fn connect(&self, addr: impl ToSocketAddrs) -> Result<SocketAddr> {
+        let stream = TcpStream::connect(addr)?;
+        let peer = stream.peer_addr()?;
+        self.add_peer(stream, true)?;
+        Ok(peer)
+    }
+
+    /// Announces a block we mined ourselves to every peer.
+    pub fn broadcast_block(&self, block: &Block) {
+        self.announce_block(block, None);
+    }
+
+    /// Secures a newly connected peer's connection, as the side that dialed
+    /// if `initiator`, and exchanges handshakes with it. If they are
+    /// compatible, starts reading its messages.
+    fn add_peer(&self, stream: TcpStream, initiator: bool) -> Result<()> {
+        let addr = stream.peer_addr()?;
+        if lock(&self.bans).is_banned(addr.ip()) {
+            return Err(self.reject(&stream, addr, "is banned for misbehaving".to_string()));
+        }
+        stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+        let magic = read(&self.chain).params().network_magic();
+        let mut stream = match SecureStream::handshake(stream.try_clone()?, &self.key, initiator, magic) {
+            Ok(secure) => secure,
+            Err(err) => return Err(self.reject(&stream, addr, format!("failed the encryption handshake: {}", err))),
+        };
+        if let Some(allowlist) = &self.allowlist
+            && !allowlist.contains(stream.remote_key())
+        {
+            let reason = format!("has key {}, which is not on the allowlist", stream.remote_key());
+            return Err(self.reject(stream.tcp(), addr, reason));
+        }
+        send(&mut stream, &Message::Hello(self.handshake()))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut line = Vec::new();
+        if let Err(err) = read_message(&mut reader, &mut line, self.max_message_bytes) {
+            if matches!(err, BlockchainError::Validation(_)) {
+                self.punish(addr, Offense::OversizedMessage);
+            }
+            return Err(self.reject(stream.tcp(), addr, format!("failed to send a handshake: {}", err)));
+        }
+        stream.tcp().set_read_timeout(None)?;
+        let handshake = match serde_json::from_slice(&line) {
+            Ok(Message::Hello(handshake)) => handshake,
+            _ => return Err(self.reject(stream.tcp(), addr, "did not start with a handshake".to_string())),
+        };
+        // Never wait for the peer list while holding the chain: hooks run
+        // with the chain locked may broadcast, which takes the peer list.
+        let (genesis, height) = {
+            let chain = read(&self.chain);
+            (chain.blocks()[0].hash().to_string(), chain.latest_block().index())
+        };
+        let behind = handshake.height > height && !handshake.light;
+        let their_height = handshake.height;
+        {
+            let mut peers = lock(&self.peers);
+            if let Some(reason) = self.check_handshake(&handshake, &genesis, &peers) {
+                drop(peers);
+                return Err(self.reject(stream.tcp(), addr, reason));
+            }
+            peers.push(Peer {
+                addr,
+                stream,
+                handshake,
+                known: HashSet::new(),
+            });
+            self.metrics.set_peers(peers.len());
+        }
+        info!(peer = %addr, height = their_height, "peer connected");
+        if self.discover {
+            self.send_to(addr, &Message::GetPeers);
+        }
+        if let Some(mempool) = &self.mempool {
+            let pending: Vec<_> = lock(mempool).iter().map(Transaction::hash).collect();
+            self.announce(&pending, Some(addr));
+        }
+        if behind {
+            self.start_download(addr);
+        }
+        let node = self.clone();
+        thread::spawn(move || node.read_loop(addr, reader));
+        Ok(())
+    }
+
+    fn handshake(&self) -> Handshake {
+        let chain = read(&self.chain);
+        Handshake {
+            version: PROTOCOL_VERSION,
+            genesis_hash: chain.blocks()[0].hash().to_string(),
+            height: chain.latest_block().index(),
+            listen_port: *lock(&self.listen_port),
+            nonce: self.nonce,
+            light: false,
+        }
+    }
+
+    /// Why a peer with `handshake` can't join `peers` on the network whose
+    /// genesis block is `genesis`, if it can't.
+    fn check_handshake(&self, handshake: &Handshake, genesis: &str, peers: &[Peer]) -> Option<String> {
+        if handshake.nonce == self.nonce {
+            return Some("connected to ourselves".to_string());
+        }
+        if handshake.version != PROTOCOL_VERSION {
+            return Some(format!(
+                "speaks protocol version {}, not {}",
+                handshake.version, PROTOCOL_VERSION
+            ));
+        }
+        if handshake.genesis_hash != genesis {
+            return Some(format!("is on a different network (genesis block {})", handshake.genesis_hash));
+        }
+        if peers.iter().any(|peer| peer.handshake.nonce == handshake.nonce) {
+            return Some("is already connected".to_string());
+        }
+        if peers.len() >= MAX_PEERS {
+            return Some(format!("would exceed the limit of {} peers", MAX_PEERS));
+        }
+        None
+    }
+
+    fn reject(&self, stream: &TcpStream, addr: SocketAddr, reason: String) -> BlockchainError {
+        let _ = stream.shutdown(Shutdown::Both);
+        BlockchainError::Validation(format!("peer {} {}", addr, reason))
+    }
+
+    fn read_loop(&self, addr: SocketAddr, mut reader: BufReader<SecureStream>) {
+        let _span = info_span!("peer", %addr).entered();
+        let (mut window, mut received) = (Instant::now(), 0);
+        let mut line = Vec::new();
+        loop {
+            match read_message(&mut reader, &mut line, self.max_message_bytes) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(BlockchainError::Validation(reason)) => {
+                    warn!(%reason, "oversized message");
+                    self.punish(addr, Offense::OversizedMessage);
+                    break;
+                }
+                Err(_) => break,
+            }
+            if window.elapsed() >= Duration::from_secs(1) {
+                (window, received) = (Instant::now(), 0);
+            }
+            received += 1;
+            if received > MAX_MESSAGE_RATE {
+                (window, received) = (Instant::now(), 0);
+                if self.punish(addr, Offense::Flooding) {
+                    break;
+                }
+            }
+            let message = match serde_json::from_slice::<Message>(&line) {
+                Ok(message) => message,
+                Err(err) => {
+                    warn!(%err, "malformed message");
+                    self.punish(addr, Offense::MalformedMessage);
+                    break;
+                }
+            };
+            if !self.handle(addr, message) {
+                break;
+            }
+        }
+        self.remove_peer(addr);
+        info!("peer disconnected");
+        let mut download = lock(&self.download);
+        if download.peer() == Some(addr) {
+            warn!("sync peer disconnected before the download finished");
+            *download = Download::Idle;
+        }
+    }
+
+    /// Handles one message, returning whether to stay connected.
+    fn handle(&self, from: SocketAddr, message: Message) -> bool {
+        debug!(message = message.kind(), "received message");
+        match message {
+            // Handshakes are only valid as the first message.
+            Message::Hello(_) => {
+                self.punish(from, Offense::UnexpectedMessage);
+                return false;
+            }
+            Message::GetPeers => {
+                let addrs = lock(&self.peers)
+                    .iter()
+                    .filter(|peer| peer.addr != from)
+                    .filter_map(Peer::listen_addr)
+                    .collect();
+                self.send_to(from, &Message::Peers(addrs));
+            }
+            Message::Peers(addrs) => self.handle_peers(addrs),
+            Message::NewBlock(block) => self.handle_block(from, block),
+            Message::CompactBlock(compact) => self.handle_compact_block(from, compact),
+            Message::GetBlockTransactions { hash, positions } => {
+                let transactions = read(&self.chain).find_block(&hash).map_or_else(Vec::new, |block| {
+                    positions.iter().filter_map(|position| block.transactions().get(*position).cloned()).collect()
+                });
+                self.send_to(from, &Message::BlockTransactions { hash, transactions });
+            }
+            Message::BlockTransactions { hash, transactions } => {
+                self.handle_block_transactions(from, hash, transactions)
+            }
+            Message::GetHeaders(locator) => {
+                let headers = read(&self.chain).headers_after(&locator, MAX_HEADERS);
+                self.send_to(from, &Message::Headers(headers));
+            }
+            Message::Headers(headers) => return self.handle_headers(from, headers),
+            Message::GetBlocks(hashes) => {
+                let chain = read(&self.chain);
+                let blocks = hashes
+                    .iter()
+                    .take(BLOCK_BATCH)
+                    .filter_map(|hash| chain.block_by_hash(hash).filter(|block| !block.is_pruned()).cloned())
+                    .collect();
+                drop(chain);
+                self.send_to(from, &Message::Blocks(blocks));
+            }
+            Message::Blocks(blocks) => return self.handle_blocks(from, blocks),
+            Message::GetProof(txid) => {
+                let proof = read(&self.chain).transaction_proof(&txid);
+                self.send_to(from, &Message::Proof(proof));
+            }
+            // We never ask for proofs.
+            Message::Proof(_) => {}
+            Message::Inventory(txids) => self.handle_inventory(from, txids),
+            Message::GetTransactions(txids) => {
+                let transactions = self.mempool.as_ref().map_or_else(Vec::new, |mempool| {
+                    let mempool = lock(mempool);
+                    txids.iter().take(MAX_INVENTORY).filter_map(|txid| mempool.get(txid).cloned()).collect()
+                });
+                self.send_to(from, &Message::Transactions(transactions));
+            }
+            Message::Transactions(transactions) => self.handle_transactions(from, transactions),
+        }
+        true
+    }
+
+    /// Begins catching up with `peer`, unless a download is already running.
+    fn start_download(&self, peer: SocketAddr) {
+        let request = {
+            let chain = read(&self.chain);
+            lock(&self.download).start(peer, &chain)
+        };
+        if let Some(request) = request {
+            info!(%peer, "syncing from peer");
+            self.request(peer, request);
+        }
+    }
+
+    fn request(&self, peer: SocketAddr, request: Request) {
+        let message = match request {
+            Request::Headers(locator) => Message::GetHeaders(locator),
+            Request::Blocks(hashes) => Message::GetBlocks(hashes),
+        };
+        self.send_to(peer, &message);
+    }
+
+    /// Returns false, dropping the peer, if it sent invalid headers.
+    fn handle_headers(&self, from: SocketAddr, headers: Vec<BlockHeader>) -> bool {
+        let result = {
+            let chain = read(&self.chain);
+            let mut download = lock(&self.download);
+            let result = download.on_headers(from, headers, &chain);
+            if result.is_err() {
+                *download = Download::Idle;
+            }
+            result
+        };
+        match result {
+            Ok(Some(request)) => self.request(from, request),
+            Ok(None) => debug!("peer has no headers we need"),
+            Err(err) => {
+                warn!(%err, "invalid headers");
+                self.metrics.add_validation_failure();
+                self.punish(from, Offense::InvalidHeaders);
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Connects a batch of downloaded blocks and asks for the next one.
+    /// Returns false, dropping the peer, if any block is invalid.
+    fn handle_blocks(&self, from: SocketAddr, blocks: Vec<Block>) -> bool {
+        if !lock(&self.download).awaits_blocks_from(from) {
+            // Ancestors of orphans we asked for, which connect like gossip.
+            for block in blocks {
+                self.handle_block(from, block);
+            }
+            return true;
+        }
+        let mut chain = write(&self.chain);
+        let mut download = lock(&self.download);
+        let blocks = match download.on_blocks(from, blocks) {
+            Ok(blocks) => blocks,
+            Err(err) => {
+                warn!(%err, "invalid blocks");
+                self.metrics.add_validation_failure();
+                *download = Download::Idle;
+                drop(download);
+                drop(chain);
+                self.punish(from, Offense::InvalidBlock);
+                return false;
+            }
+        };
+        if blocks.is_empty() {
+            return true;
+        }
+        let mut changed = false;
+        let mut valid = true;
+        for block in blocks {
+            match self.accept(&mut chain, block) {
+                Ok((connected, _)) => changed |= connected,
+                Err(_) => {
+                    valid = false;
+                    break;
+                }
+            }
+        }
+        if changed {
+            self.chain_changed(&chain);
+        }
+        if !valid {
+            *download = Download::Idle;
+            drop(download);
+            drop(chain);
+            self.punish(from, Offense::InvalidBlock);
+            return false;
+        }
+        let next = download.next_batch();
+        let tip = chain.latest_block().clone();
+        drop(download);
+        drop(chain);
+        if next.is_none() {
+            info!(height = tip.index(), "sync finished");
+        }
+        match next {
+            Some(request) => self.request(from, request),
+            None if changed => self.announce_block(&tip, Some(from)),
+            None => {}
+        }
+        true
+    }
+
+    /// Connects, in the background, to advertised peers we are not yet
+    /// connected to.
+    fn handle_peers(&self, addrs: Vec<SocketAddr>) {
+        if !self.discover {
+            return;
+        }
+        for addr in addrs {
+            let known = {
+                let peers = lock(&self.peers);
+                peers.len() >= MAX_PEERS
+                    || peers.iter().any(|peer| peer.addr == addr || peer.listen_addr() == Some(addr))
+            };
+            let known = known || lock(&self.bans).is_banned(addr.ip());
+            if !known {
+                let node = self.clone();
+                thread::spawn(move || {
+                    debug!(%addr, "dialing advertised peer");
+                    if let Err(err) = node.connect(addr) {
+                        debug!(%addr, %err, "could not connect to advertised peer");
+                    }
+                });
+            }
+        }
+    }
+
+    fn handle_block(&self, from: SocketAddr, block: Block) {
+        let mut chain = write(&self.chain);
+        if chain.knows_block(block.hash()) || lock(&self.orphans).contains(block.hash()) {
+            return;
+        }
+        if !chain.knows_block(block.previous_hash()) {
+            let height = chain.height();
+            drop(chain);
+            self.handle_orphan(from, block, height);
+            return;
+        }
+        match self.accept(&mut chain, block.clone()) {
+            Ok((changed, last_orphan)) => {
+                if changed {
+                    self.chain_changed(&chain);
+                }
+                drop(chain);
+                self.announce_block(&last_orphan.unwrap_or(block), Some(from));
+            }
+            Err(err) => {
+                drop(chain);
+                debug!(%err, "rejected gossiped block");
+                if self.punish(from, Offense::InvalidBlock) {
+                    self.disconnect(from);
+                }
+            }
+        }
+    }
+
+    /// Rebuilds a compact block from our mempool, asking `from` for the
+    /// transactions we don't have.
+    fn handle_compact_block(&self, from: SocketAddr, compact: CompactBlock) {
+        let hash = compact.hash();
+        let partial = {
+            let chain = read(&self.chain);
+            if chain.knows_block(&hash) || lock(&self.orphans).contains(&hash) {
+                return;
+            }
+            let mempool = self.mempool.as_ref().map(|mempool| lock(mempool));
+            compact.reconstruct(mempool.as_deref())
+        };
+        let missing = partial.missing();
+        if missing.is_empty() {
+            self.complete_block(from, partial);
+            return;
+        }
+        debug!(%hash, missing = missing.len(), "asking for the missing transactions of a compact block");
+        {
+            let mut pending = lock(&self.compact);
+            if pending.len() >= MAX_PENDING_COMPACT_BLOCKS {
+                pending.clear();
+            }
+            pending.insert(hash.clone(), (from, partial));
+        }
+        self.send_to(from, &Message::GetBlockTransactions { hash, positions: missing });
+    }
+
+    fn handle_block_transactions(&self, from: SocketAddr, hash: String, transactions: Vec<Transaction>) {
+        let partial = {
+            let mut pending = lock(&self.compact);
+            match pending.get(&hash) {
+                Some((peer, _)) if *peer == from => pending.remove(&hash).map(|(_, partial)| partial),
+                _ => None,
+            }
+        };
+        let Some(mut partial) = partial else { return };
+        match partial.fill(transactions) {
+            Ok(()) => self.complete_block(from, partial),
+            Err(err) => {
+                debug!(%hash, %err, "could not complete compact block");
+                self.request_block(from, hash);
+            }
+        }
+    }
+
+    /// Handles a rebuilt compact block like a gossiped one, unless its
+    /// transactions don't match its Merkle root, as when a short ID matched
+    /// the wrong one; then the whole block is asked for instead.
+    fn complete_block(&self, from: SocketAddr, partial: PartialBlock) {
+        let hash = partial.hash();
+        match partial.into_block() {
+            Some(block) if block.has_valid_merkle_root() => self.handle_block(from, block),
+            _ => self.request_block(from, hash),
+        }
+    }
+
+    fn request_block(&self, from: SocketAddr, hash: String) {
+        // A download from this peer would take the reply for its own.
+        if lock(&self.download).peer() != Some(from) {
+            self.send_to(from, &Message::GetBlocks(vec![hash]));
+        }
+    }
+
+    /// Holds a block whose parent we lack and asks `from` for its missing
+    /// ancestor, or, if it is too far past our `height` to fetch one block
+    /// at a time, downloads from `from` as when we connect to a peer ahead
+    /// of us.
+    fn handle_orphan(&self, from: SocketAddr, block: Block, height: u64) {
+        let (hash, index) = (block.hash().to_string(), block.index());
+        let missing = {
+            let mut orphans = lock(&self.orphans);
+            if !orphans.insert(block) {
+                return;
+            }
+            orphans.missing_ancestor(&hash)
+        };
+        debug!(%hash, index, "holding orphan block");
+        if index > height + BLOCK_BATCH as u64 {
+            self.start_download(from);
+        } else if let Some(missing) = missing
+            && lock(&self.download).peer() != Some(from)
+        {
+            // A download from this peer fetches the ancestor anyway, and
+            // would take our reply for its own.
+            self.send_to(from, &Message::GetBlocks(vec![missing]));
+        }
+    }
+
+    /// Runs the update hook once blocks from a peer changed the main chain,
+    /// and drops the pending transactions they made invalid.
+    fn chain_changed(&self, chain: &Blockchain) {
+        (self.on_update)(chain);
+        if let Some(mempool) = &self.mempool {
+            let dropped = chain.drop_invalid_pending(&mut lock(mempool));
+            if !dropped.is_empty() {
+                debug!(count = dropped.len(), "dropped pending transactions the new blocks made invalid");
+            }
+        }
+    }
+
+    /// Accepts `block`, then the orphans that were waiting on it. Returns
+    /// whether the main chain changed, and the last orphan connected.
+    fn accept(&self, chain: &mut Blockchain, block: Block) -> Result<(bool, Option<Block>)> {
+        let connects = |events: &[ChainEvent]| events.iter().any(|event| matches!(event, ChainEvent::BlockConnected(_)));
+        let hash = block.hash().to_string();
+        let mut changed = connects(&chain.accept_block(block)?);
+        let orphans = lock(&self.orphans).take_descendants(&hash);
+        let mut last = None;
+        for orphan in orphans {
+            match chain.accept_block(orphan.clone()) {
+                Ok(events) => {
+                    changed |= connects(&events);
+                    last = Some(orphan);
+                }
+                Err(err) => debug!(%err, "orphan block did not connect"),
+            }
+        }
+        Ok((changed, last))
+    }
+
+    /// Asks `from` for the transactions it announced that we neither have
+    /// nor have asked another peer for.
+    fn handle_inventory(&self, from: SocketAddr, mut txids: Vec<String>) {
+        let Some(mempool) = &self.mempool else { return };
+        txids.truncate(MAX_INVENTORY);
+        if let Some(peer) = lock(&self.peers).iter_mut().find(|peer| peer.addr == from) {
+            peer.remember(&txids);
+        }
+        let wanted: Vec<_> = {
+            let chain = read(&self.chain);
+            let mempool = lock(mempool);
+            txids
+                .into_iter()
+                .filter(|txid| !mempool.contains(txid) && chain.get_transaction(txid).is_none())
+                .collect()
+        };
+        let wanted: Vec<_> = {
+            let mut requested = lock(&self.requested);
+            requested.retain(|_, at| at.elapsed() < TRANSACTION_REQUEST_TIMEOUT);
+            wanted
+                .into_iter()
+                .filter(|txid| requested.insert(txid.clone(), Instant::now()).is_none())
+                .collect()
+        };
+        if !wanted.is_empty() {
+            self.send_to(from, &Message::GetTransactions(wanted));
+        }
+    }
+
+    /// Submits transactions a peer sent us to our mempool. The chain's event
+    /// for each one accepted then announces it to our other peers.
+    fn handle_transactions(&self, from: SocketAddr, transactions: Vec<Transaction>) {
+        let Some(mempool) = &self.mempool else { return };
+        let txids: Vec<_> = transactions.iter().map(Transaction::hash).collect();
+        // The sender has them, so must not be announced them back.
+        if let Some(peer) = lock(&self.peers).iter_mut().find(|peer| peer.addr == from) {
+            peer.remember(&txids);
+        }
+        {
+            let mut requested = lock(&self.requested);
+            for txid in &txids {
+                requested.remove(txid);
+            }
+        }
+        let chain = read(&self.chain);
+        let mut mempool = lock(mempool);
+        for (tx, txid) in transactions.into_iter().zip(txids) {
+            if mempool.contains(&txid) {
+                continue;
+            }
+            match chain.submit_transaction(&mut mempool, tx) {
+                Ok(()) => debug!(%txid, "queued relayed transaction"),
+                Err(err) => debug!(%txid, %err, "ignored relayed transaction"),
+            }
+        }
+    }
+
+    /// Announces `block` to every peer but `except`, compactly.
+    fn announce_block(&self, block: &Block, except: Option<SocketAddr>) {
+        self.broadcast(&Message::CompactBlock(CompactBlock::new(block)), except);
+    }
+
+    /// Announces transactions to `to`, or to every full peer, leaving out
+    /// those the peer is known to have.
+    fn announce(&self, txids: &[String], to: Option<SocketAddr>) {
+        let mut peers = lock(&self.peers);
+        peers.retain_mut(|peer| {
+            if to.is_some_and(|to| to != peer.addr) || peer.handshake.light {
+                return true;
+            }
+            let fresh: Vec<_> = txids.iter().filter(|txid| !peer.known.contains(*txid)).cloned().collect();
+            peer.remember(&fresh);
+            fresh
+                .chunks(MAX_INVENTORY)
+                .all(|chunk| send(&mut peer.stream, &Message::Inventory(chunk.to_vec())).is_ok())
+        });
+        self.metrics.set_peers(peers.len());
+    }
+
+    /// Penalizes `peer` for `offense`, returning whether that got it banned,
+    /// in which case the caller must disconnect it.
+    fn punish(&self, peer: SocketAddr, offense: Offense) -> bool {
+        let mut bans = lock(&self.bans);
+        let banned = bans.punish(peer.ip(), offense);
+        if banned {
+            warn!(%peer, %offense, "banned misbehaving peer");
+        } else {
+            info!(%peer, %offense, score = bans.score(peer.ip()), "peer misbehaved");
+        }
+        banned
+    }
+
+    /// Closes the connection to `addr`, which ends its read loop.
+    fn disconnect(&self, addr: SocketAddr) {
+        if let Some(peer) = lock(&self.peers).iter().find(|peer| peer.addr == addr) {
+            let _ = peer.stream.tcp().shutdown(Shutdown::Both);
+        }
+    }
+
+    fn send_to(&self, addr: SocketAddr, message: &Message) {
+        let mut peers = lock(&self.peers);
+        if let Some(peer) = peers.iter_mut().find(|peer| peer.addr == addr)
+            && send(&mut peer.stream, message).is_err()
+        {
+            drop(peers);
+            self.remove_peer(addr);
+        }
+    }
+
+    fn remove_peer(&self, addr: SocketAddr) {
+        let mut peers = lock(&self.peers);
+        peers.retain(|peer| peer.addr != addr);
+        self.metrics.set_peers(peers.len());
+    }
+
+    fn broadcast(&self, message: &Message, except: Option<SocketAddr>) {
+        let mut peers = lock(&self.peers);
+        peers.retain_mut(|peer| Some(peer.addr) == except || send(&mut peer.stream, message).is_ok());
+        self.metrics.set_peers(peers.len());
+    }
+}
+
+/// How long a [`LightClient`] waits for a reply.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A light client's connection to a full node, which it asks for headers
+/// and for proofs of the transactions it cares about.
+pub struct LightClient {
+    addr: SocketAddr,
+    stream: SecureStream,
+    reader: BufReader<SecureStream>,
+}
+
+impl LightClient {
+    /// Connects to a full node with a new random key and exchanges
+    /// handshakes, failing if it is on a network other than that of
+    /// `headers`.
+    pub fn connect(addr: impl ToSocketAddrs, headers: &HeaderChain) -> Result<Self> {
+        LightClient::connect_as(addr, headers, &NodeKey::generate())
+    }
+
+    /// Like [`connect`](Self::connect), identifying ourselves as `key`, as
+    /// a node with an allowlist requires.
+    pub fn connect_as(addr: impl ToSocketAddrs, headers: &HeaderChain, key: &NodeKey) -> Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        let addr = stream.peer_addr()?;
+        stream.set_read_timeout(Some(REPLY_TIMEOUT))?;
+        let stream = SecureStream::handshake(stream, key, true, headers.params().network_magic())?;
+        let mut client = LightClient {
+            addr,
+            reader: BufReader::new(stream.try_clone()?),
+            stream,
+        };
+        let hello = Handshake {
+            version: PROTOCOL_VERSION,
+            genesis_hash: headers.genesis_hash().to_string(),
+            height: headers.height(),
+            listen_port: None,
+            nonce: OsRng.next_u64(),
+            light: true,
+        };
+        send(&mut client.stream, &Message::Hello(hello))?;
+        let handshake = client.receive(|message| match message {
+            Message::Hello(handshake) => Some(handshake),
+            _ => None,
+        })?;
+        if handshake.version != PROTOCOL_VERSION {
+            return Err(client.refuse(format!("speaks protocol version {}", handshake.version)));
+        }
+        if handshake.genesis_hash != headers.genesis_hash() {
+            return Err(client.refuse("is on a different network".to_string()));
+        }
+        Ok(client)
+    }
+
+    pub fn peer(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Downloads and checks the headers the peer has past ours, returning
+    /// how many were connected.
+    pub fn sync(&mut self, headers: &mut HeaderChain) -> Result<usize> {
+        let mut connected = 0;
+        loop {
+            send(&mut self.stream, &Message::GetHeaders(headers.locator()))?;
+            let received = self.receive(|message| match message {
+                Message::Headers(headers) => Some(headers),
+                _ => None,
+            })?;
+            let full = received.len() >= MAX_HEADERS;
+            let added = headers.extend(received)?;
+            connected += added;
+            if !full || added == 0 {
+                return Ok(connected);
+            }
+        }
+    }
+
+    /// Asks the peer for the transaction with `txid` and the proof of its
+    /// inclusion, without checking it; see [`HeaderChain::verify`].
+    pub fn fetch_proof(&mut self, txid: &str) -> Result<Option<TxProof>> {
+        send(&mut self.stream, &Message::GetProof(txid.to_string()))?;
+        self.receive(|message| match message {
+            Message::Proof(proof) => Some(proof),
+            _ => None,
+        })
+    }
+
+    /// Reads messages until `pick` accepts one, answering requests the peer
+    /// sends meanwhile with empty replies and skipping its announcements.
+    fn receive<T>(&mut self, pick: impl Fn(Message) -> Option<T>) -> Result<T> {
+        let mut line = Vec::new();
+        loop {
+            if read_message(&mut self.reader, &mut line, MAX_MESSAGE_BYTES)? == 0 {
+                return Err(BlockchainError::Validation(format!("peer {} disconnected", self.addr)));
+            }
+            let message: Message = serde_json::from_slice(&line)?;
+            let reply = match &message {
+                Message::GetPeers => Some(Message::Peers(Vec::new())),
+                Message::GetHeaders(_) => Some(Message::Headers(Vec::new())),
+                Message::GetBlocks(_) => Some(Message::Blocks(Vec::new())),
+                Message::GetProof(_) => Some(Message::Proof(None)),
+                _ => None,
+            };
+            if let Some(reply) = reply {
+                send(&mut self.stream, &reply)?;
+                continue;
+            }
+            let kind = message.kind();
+            match pick(message) {
+                Some(value) => return Ok(value),
+                None => debug!(message = kind, "skipped message"),
+            }
+        }
+    }
+
+    fn refuse(&self, reason: String) -> BlockchainError {
+        let _ = self.stream.tcp().shutdown(Shutdown::Both);
+        BlockchainError::Validation(format!("peer {} {}", self.addr, reason))
+    }
+}
@@ -0,0 +1,246 @@
+use snow::params::{DHChoice, NoiseParams};
+use snow::resolvers::{CryptoResolver, DefaultResolver};
+use snow::{Builder, StatelessTransportState};
+use std::fmt;
+use std::fs;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::error::{BlockchainError, Result};
+use crate::file;
+use crate::sync::lock;
+
+/// The Noise protocol peer connections speak. With the XX pattern each side
+/// learns and authenticates the other's static key during the handshake.
+pub const NOISE_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_BLAKE2s";
+
+/// Longest Noise message, which is also the most a frame's length prefix
+/// can describe.
+const MAX_FRAME: usize = 65535;
+/// Bytes of authentication tag the cipher adds to each message.
+const TAG_LEN: usize = 16;
+const MAX_PLAINTEXT: usize = MAX_FRAME - TAG_LEN;
+
+fn params() -> NoiseParams {
+    NOISE_PARAMS.parse().expect("NOISE_PARAMS is a valid protocol name")
+}
+
+fn noise_error(err: snow::Error) -> BlockchainError {
+    BlockchainError::Validation(format!("Noise handshake failed: {}", err))
+}
+
+/// A node's static Curve25519 key, by which its peers know it.
+#[derive(Clone)]
+pub struct NodeKey {
+    private: Vec<u8>,
+    public: Vec<u8>,
+}
+
+impl NodeKey {
+    /// A new random key.
+    pub fn generate() -> Self {
+        let keypair = Builder::new(params()).generate_keypair().expect("the default resolver supports Curve25519");
+        NodeKey {
+            private: keypair.private,
+            public: keypair.public,
+        }
+    }
+
+    /// The key whose private half is `private`, hex-encoded.
+    pub fn from_hex(private: &str) -> Result<Self> {
+        let private = hex::decode(private.trim())
+            .map_err(|err| BlockchainError::Encoding(format!("invalid node key: {}", err)))?;
+        if private.len() != 32 {
+            return Err(BlockchainError::Encoding(format!(
+                "invalid node key: expected 32 bytes, got {}",
+                private.len()
+            )));
+        }
+        let mut dh = DefaultResolver.resolve_dh(&DHChoice::Curve25519).expect("the default resolver supports Curve25519");
+        dh.set(&private);
+        Ok(NodeKey {
+            public: dh.pubkey().to_vec(),
+            private,
+        })
+    }
+
+    /// Reads the key kept at `path`, generating and saving one there first
+    /// if the file doesn't exist, so a node keeps its identity across runs.
+    pub fn load_or_generate(path: &Path) -> Result<Self> {
+        if path.exists() {
+            return NodeKey::from_hex(&fs::read_to_string(path)?);
+        }
+        let key = NodeKey::generate();
+        file::write_atomic(path, format!("{}\n", hex::encode(&key.private)).as_bytes())?;
+        Ok(key)
+    }
+
+    /// The public half, hex-encoded: what peers put on their allowlists.
+    pub fn public_key(&self) -> String {
+        hex::encode(&self.public)
+    }
+}
+
+impl fmt::Debug for NodeKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("NodeKey").field("public", &self.public_key()).finish_non_exhaustive()
+    }
+}
+
+/// A TCP connection encrypted with Noise, carrying each message in a frame
+/// behind the network's magic and its length as a big-endian `u16`. Clones
+/// share the connection and its nonces, so one can read while others write;
+/// writes of a whole buffer with `write_all` are never interleaved with
+/// another clone's.
+pub struct SecureStream {
+    stream: TcpStream,
+    transport: Arc<StatelessTransportState>,
+    sent: Arc<Mutex<u64>>,
+    received: Arc<Mutex<u64>>,
+    remote_key: String,
+    magic: [u8; 4],
+    /// Decrypted bytes not read yet.
+    buffer: Vec<u8>,
+    offset: usize,
+}
+
+impl SecureStream {
+    /// Runs the handshake over a new connection, as the side that dialed if
+    /// `initiator`, proving we hold `key` and learning the peer's key. Fails
+    /// if the peer's frames carry a [`magic`](crate::ChainParams::network_magic)
+    /// other than ours.
+    pub fn handshake(mut stream: TcpStream, key: &NodeKey, initiator: bool, magic: [u8; 4]) -> Result<Self> {
+        let builder = Builder::new(params()).local_private_key(&key.private);
+        let mut state = if initiator { builder.build_initiator() } else { builder.build_responder() }
+            .map_err(noise_error)?;
+        let mut message = vec![0; MAX_FRAME];
+        while !state.is_handshake_finished() {
+            if state.is_my_turn() {
+                let len = state.write_message(&[], &mut message).map_err(noise_error)?;
+                write_frame(&mut stream, magic, &message[..len])?;
+            } else {
+                let frame = read_frame(&mut stream, magic)?;
+                state.read_message(&frame, &mut message).map_err(noise_error)?;
+            }
+        }
+        let remote_key = hex::encode(state.get_remote_static().unwrap_or_default());
+        Ok(SecureStream {
+            stream,
+            transport: Arc::new(state.into_stateless_transport_mode().map_err(noise_error)?),
+            sent: Arc::new(Mutex::new(0)),
+            received: Arc::new(Mutex::new(0)),
+            remote_key,
+            magic,
+            buffer: Vec::new(),
+            offset: 0,
+        })
+    }
+
+    /// The peer's static public key, hex-encoded.
+    pub fn remote_key(&self) -> &str {
+        &self.remote_key
+    }
+
+    pub fn tcp(&self) -> &TcpStream {
+        &self.stream
+    }
+
+    pub fn try_clone(&self) -> io::Result<Self> {
+        Ok(SecureStream {
+            stream: self.stream.try_clone()?,
+            transport: self.transport.clone(),
+            sent: self.sent.clone(),
+            received: self.received.clone(),
+            remote_key: self.remote_key.clone(),
+            magic: self.magic,
+            buffer: Vec::new(),
+            offset: 0,
+        })
+    }
+
+    /// Encrypts `chunk`, at most [`MAX_PLAINTEXT`] bytes, into one frame.
+    fn write_chunk(&mut self, nonce: &mut u64, chunk: &[u8]) -> io::Result<()> {
+        let mut message = vec![0; chunk.len() + TAG_LEN];
+        let len = self.transport.write_message(*nonce, chunk, &mut message).map_err(io::Error::other)?;
+        *nonce += 1;
+        write_frame(&mut self.stream, self.magic, &message[..len])
+    }
+}
+
+impl Read for SecureStream {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        // Loops past empty messages, which must not read as the end of the stream.
+        while self.offset == self.buffer.len() {
+            let mut nonce = lock(&self.received);
+            let frame = match read_frame(&mut self.stream, self.magic) {
+                Ok(frame) => frame,
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(0),
+                Err(err) => return Err(err),
+            };
+            let mut plaintext = vec![0; frame.len()];
+            let len = self
+                .transport
+                .read_message(*nonce, &frame, &mut plaintext)
+                .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+            *nonce += 1;
+            plaintext.truncate(len);
+            (self.buffer, self.offset) = (plaintext, 0);
+        }
+        let len = out.len().min(self.buffer.len() - self.offset);
+        out[..len].copy_from_slice(&self.buffer[self.offset..self.offset + len]);
+        self.offset += len;
+        Ok(len)
+    }
+}
+
+impl Write for SecureStream {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        if bytes.is_empty() {
+            return Ok(0);
+        }
+        let chunk = &bytes[..bytes.len().min(MAX_PLAINTEXT)];
+        let sent = self.sent.clone();
+        self.write_chunk(&mut lock(&sent), chunk)?;
+        Ok(chunk.len())
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let sent = self.sent.clone();
+        let mut nonce = lock(&sent);
+        for chunk in bytes.chunks(MAX_PLAINTEXT) {
+            self.write_chunk(&mut nonce, chunk)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+fn write_frame(stream: &mut TcpStream, magic: [u8; 4], message: &[u8]) -> io::Result<()> {
+    let len = u16::try_from(message.len()).map_err(|_| io::Error::other("Noise message too long"))?;
+    let mut frame = Vec::with_capacity(6 + message.len());
+    frame.extend_from_slice(&magic);
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.extend_from_slice(message);
+    stream.write_all(&frame)
+}
+
+fn read_frame(stream: &mut TcpStream, magic: [u8; 4]) -> io::Result<Vec<u8>> {
+    let mut theirs = [0; 4];
+    stream.read_exact(&mut theirs)?;
+    if theirs != magic {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("peer is on another network (magic {}, expected {})", hex::encode(theirs), hex::encode(magic)),
+        ));
+    }
+    let mut len = [0; 2];
+    stream.read_exact(&mut len)?;
+    let mut frame = vec![0; usize::from(u16::from_be_bytes(len))];
+    stream.read_exact(&mut frame)?;
+    Ok(frame)
+}
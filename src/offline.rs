@@ -0,0 +1,91 @@
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+use crate::blockchain::Blockchain;
+use crate::error::{BlockchainError, Result};
+use crate::file;
+use crate::script::Script;
+use crate::transaction::Transaction;
+use crate::utxo::TxOutput;
+use crate::wallet::UnlockedWallet;
+
+/// A transaction passed between an online machine, which builds it and
+/// later broadcasts it, and an offline one holding the keys, which signs it
+/// without ever touching the network. It carries the outputs the transaction
+/// spends, so the signer needs no copy of the chain to know what it signs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionFile {
+    /// The network the transaction is for; signatures only hold there.
+    pub chain_id: String,
+    pub transaction: Transaction,
+    /// The output each input spends, in input order.
+    pub spent: Vec<TxOutput>,
+}
+
+impl TransactionFile {
+    /// `tx`, as built on `chain`, with the outputs it spends there.
+    pub fn new(chain: &Blockchain, tx: Transaction) -> Result<Self> {
+        let utxos = chain.utxo_set()?;
+        let spent = tx
+            .inputs()
+            .iter()
+            .map(|input| {
+                utxos.get(input).cloned().ok_or_else(|| {
+                    BlockchainError::Validation(format!(
+                        "output {}:{} does not exist or is already spent",
+                        input.txid, input.vout
+                    ))
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(TransactionFile {
+            chain_id: chain.params().chain_id.clone(),
+            transaction: tx,
+            spent,
+        })
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let file: TransactionFile = serde_json::from_slice(&fs::read(path)?)?;
+        if file.spent.len() != file.transaction.inputs().len() {
+            return Err(BlockchainError::Validation(format!(
+                "transaction file lists {} spent outputs for {} inputs",
+                file.spent.len(),
+                file.transaction.inputs().len()
+            )));
+        }
+        Ok(file)
+    }
+
+    /// Writes the file as pretty-printed JSON, replacing it atomically.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        file::write_atomic(path.as_ref(), &serde_json::to_vec_pretty(self)?)
+    }
+
+    /// How many inputs still lack the unlocking script their spent output's
+    /// lock needs.
+    pub fn unsigned_inputs(&self) -> usize {
+        let unlocks = self.transaction.unlocks();
+        self.spent
+            .iter()
+            .enumerate()
+            .filter(|(i, output)| {
+                output.script.is_some() && unlocks.get(*i).is_none_or(|unlock| unlock.ops().is_empty())
+            })
+            .count()
+    }
+
+    pub fn is_signed(&self) -> bool {
+        self.unsigned_inputs() == 0
+    }
+
+    /// Signs every input `wallet` holds the key for, and returns how many
+    /// more inputs are now signed.
+    pub fn sign(&mut self, wallet: &UnlockedWallet) -> usize {
+        let before = self.unsigned_inputs();
+        let locks: Vec<Option<&Script>> = self.spent.iter().map(|output| output.script.as_ref()).collect();
+        self.transaction = wallet.sign_inputs(self.transaction.clone(), &locks, &self.chain_id);
+        before - self.unsigned_inputs()
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::block::Block;
+
+/// Most orphan blocks a node holds; the oldest is dropped to make room.
+pub const MAX_ORPHANS: usize = 100;
+
+/// Blocks received before their parents, e.g. from out-of-order gossip,
+/// held until their ancestry arrives. Only blocks whose stored hash matches
+/// their header and meets its target are kept, so holding one costs its
+/// sender real work; everything else is checked once it connects.
+#[derive(Debug)]
+pub struct OrphanPool {
+    blocks: HashMap<String, Block>,
+    /// Hashes in arrival order, oldest first.
+    arrival: VecDeque<String>,
+    max: usize,
+}
+
+impl Default for OrphanPool {
+    fn default() -> Self {
+        OrphanPool::new(MAX_ORPHANS)
+    }
+}
+
+impl OrphanPool {
+    /// A pool holding at most `max` blocks.
+    pub fn new(max: usize) -> Self {
+        OrphanPool {
+            blocks: HashMap::new(),
+            arrival: VecDeque::new(),
+            max,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    pub fn contains(&self, hash: &str) -> bool {
+        self.blocks.contains_key(hash)
+    }
+
+    /// Holds `block` until its parent arrives, dropping the oldest orphan if
+    /// the pool is full. Returns false, keeping nothing, if the block is
+    /// already held or its proof of work does not check out.
+    pub fn insert(&mut self, block: Block) -> bool {
+        let proven = block.compute_hash() == block.hash() && block.meets_target();
+        if self.max == 0 || self.contains(block.hash()) || !proven {
+            return false;
+        }
+        if self.blocks.len() >= self.max
+            && let Some(oldest) = self.arrival.pop_front()
+        {
+            self.blocks.remove(&oldest);
+        }
+        self.arrival.push_back(block.hash().to_string());
+        self.blocks.insert(block.hash().to_string(), block);
+        true
+    }
+
+    /// The hash of the block to fetch so that the orphan with `hash` can
+    /// connect: the parent of its earliest ancestor in the pool.
+    pub fn missing_ancestor(&self, hash: &str) -> Option<String> {
+        let mut block = self.blocks.get(hash)?;
+        while let Some(parent) = self.blocks.get(block.previous_hash()) {
+            block = parent;
+        }
+        Some(block.previous_hash().to_string())
+    }
+
+    /// Removes and returns the orphans descending from the block with hash
+    /// `parent`, each after its own parent, ready to connect in order.
+    pub fn take_descendants(&mut self, parent: &str) -> Vec<Block> {
+        let mut taken: Vec<Block> = Vec::new();
+        let mut parents = VecDeque::from([parent.to_string()]);
+        while let Some(parent) = parents.pop_front() {
+            let children: Vec<String> = self
+                .arrival
+                .iter()
+                .filter(|hash| self.blocks[*hash].previous_hash() == parent)
+                .cloned()
+                .collect();
+            for hash in children {
+                self.arrival.retain(|held| *held != hash);
+                if let Some(block) = self.blocks.remove(&hash) {
+                    parents.push_back(hash);
+                    taken.push(block);
+                }
+            }
+        }
+        taken
+    }
+}
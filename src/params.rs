@@ -0,0 +1,170 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+
+use crate::amount::Amount;
+use crate::block::INITIAL_BLOCK_VERSION;
+use crate::consensus::ConsensusKind;
+use crate::hash::HashAlgorithm;
+
+pub const DEFAULT_BLOCK_REWARD: Amount = Amount::from_coins(50);
+pub const DEFAULT_DIFFICULTY: usize = 4; // Number of leading zeros for mining
+pub const MAX_DIFFICULTY: usize = 64; // A SHA-256 hex digest has 64 digits
+pub const DEFAULT_CHAIN_ID: &str = "mini-block";
+pub const DEFAULT_ADDRESS_VERSION: u8 = 50; // Encoded addresses start with 'M'
+pub const DEFAULT_MAX_BLOCK_TRANSACTIONS: usize = 1_000; // Coinbase included
+pub const DEFAULT_MAX_BLOCK_SIZE: usize = 1_000_000; // Bytes of serialized block
+pub const DEFAULT_MEDIAN_TIME_SPAN: usize = 11; // Blocks whose median timestamp a new block must exceed
+pub const DEFAULT_MAX_FUTURE_BLOCK_TIME_MS: u64 = 2 * 60 * 1000; // Two minutes
+
+/// A change to the consensus rules that takes effect at a known height,
+/// from which blocks must be of at least `version`. Code guarding a new rule
+/// asks [`ChainParams::is_active`] whether it applies to a block, so a chain
+/// mined before the rule existed stays valid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Upgrade {
+    pub version: u32,
+    pub height: u64,
+}
+
+/// Consensus parameters shared by every node on the same chain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChainParams {
+    /// Identifies the network; the genesis block commits to it.
+    pub chain_id: String,
+    /// Version byte of encoded addresses on this network.
+    pub address_version: u8,
+    /// Amount credited to the miner by each block's coinbase transaction,
+    /// until the first halving.
+    pub block_reward: Amount,
+    /// The block reward halves every this many blocks; 0 keeps it constant.
+    pub halving_interval: u64,
+    /// Timestamp of the genesis block, fixed so that every node derives the
+    /// same genesis block from the same parameters.
+    pub genesis_timestamp: u128,
+    /// Starting proof-of-work target, as the number of leading zero hex digits
+    /// it requires; blocks keep it until the first retarget.
+    pub initial_difficulty: usize,
+    /// Desired average time between blocks, in milliseconds.
+    pub target_block_time_ms: u64,
+    /// Difficulty is recalculated every this many blocks; 0 disables retargeting.
+    pub retarget_interval: u64,
+    /// Balances credited by the genesis block.
+    pub genesis_allocations: BTreeMap<String, Amount>,
+    /// How blocks are produced and agreed on.
+    pub consensus: ConsensusKind,
+    /// Most transactions a block may hold, its coinbase included.
+    pub max_block_transactions: usize,
+    /// Largest serialized size of a block, in bytes.
+    pub max_block_size: usize,
+    /// A block's timestamp must be later than the median timestamp of this
+    /// many blocks before it.
+    pub median_time_span: usize,
+    /// How far ahead of a node's clock a block's timestamp may be, in
+    /// milliseconds.
+    pub max_future_block_time_ms: u64,
+    /// Algorithm block headers are hashed with, from the genesis block on.
+    pub hash_algorithm: HashAlgorithm,
+    /// Scheduled changes to the consensus rules.
+    pub upgrades: Vec<Upgrade>,
+    /// A block's coinbase reward may only be spent by blocks at least this
+    /// many heights above it, so a reorganization can't leave transactions
+    /// spending a reward that no longer exists. Genesis allocations are
+    /// spendable at once. 0, the default, lets rewards be spent right away,
+    /// as chains from before the rule do.
+    pub coinbase_maturity: u64,
+}
+
+impl Default for ChainParams {
+    fn default() -> Self {
+        ChainParams {
+            chain_id: DEFAULT_CHAIN_ID.to_string(),
+            address_version: DEFAULT_ADDRESS_VERSION,
+            block_reward: DEFAULT_BLOCK_REWARD,
+            halving_interval: 0,
+            genesis_timestamp: 1_759_401_237_639,
+            initial_difficulty: DEFAULT_DIFFICULTY,
+            target_block_time_ms: 10_000,
+            retarget_interval: 10,
+            genesis_allocations: BTreeMap::new(),
+            consensus: ConsensusKind::ProofOfWork,
+            max_block_transactions: DEFAULT_MAX_BLOCK_TRANSACTIONS,
+            max_block_size: DEFAULT_MAX_BLOCK_SIZE,
+            median_time_span: DEFAULT_MEDIAN_TIME_SPAN,
+            max_future_block_time_ms: DEFAULT_MAX_FUTURE_BLOCK_TIME_MS,
+            hash_algorithm: HashAlgorithm::default(),
+            upgrades: Vec::new(),
+            coinbase_maturity: 0,
+        }
+    }
+}
+
+impl ChainParams {
+    /// The version the block at `height` must be of at least: that of the
+    /// latest upgrade active by then. Later versions are accepted too, so
+    /// nodes that don't know an upgrade yet keep following the chain.
+    pub fn block_version(&self, height: u64) -> u32 {
+        self.upgrades
+            .iter()
+            .filter(|upgrade| upgrade.height <= height)
+            .map(|upgrade| upgrade.version)
+            .fold(INITIAL_BLOCK_VERSION, u32::max)
+    }
+
+    /// Whether the rules introduced with `version` apply to the block at `height`.
+    pub fn is_active(&self, version: u32, height: u64) -> bool {
+        self.block_version(height) >= version
+    }
+
+    /// What the coinbase of the block at `height` mints on top of the fees:
+    /// the block reward, halved once for each halving interval before it.
+    pub fn block_reward_at(&self, height: u64) -> Amount {
+        match height.checked_div(self.halving_interval) {
+            Some(halvings) if halvings >= u64::from(u64::BITS) => Amount::ZERO,
+            Some(halvings) => Amount::from_units(self.block_reward.units() >> halvings),
+            None => self.block_reward,
+        }
+    }
+
+    /// Height of the first halving after `height`, unless rewards never
+    /// halve or have already run out.
+    pub fn next_halving(&self, height: u64) -> Option<u64> {
+        if self.block_reward_at(height).is_zero() {
+            return None;
+        }
+        let halvings = height.checked_div(self.halving_interval)?;
+        halvings.checked_add(1)?.checked_mul(self.halving_interval)
+    }
+
+    /// Every coin the chain will ever have: the genesis allocations plus
+    /// every block reward, or `None` if rewards never run out (or the total
+    /// overflows).
+    pub fn total_supply(&self) -> Option<Amount> {
+        let allocations = Amount::checked_sum(self.genesis_allocations.values().copied())?;
+        if self.block_reward.is_zero() {
+            return Some(allocations);
+        }
+        let interval = self.halving_interval;
+        if interval == 0 {
+            return None;
+        }
+        // The genesis block mints no reward, so the first era is a block short.
+        let mut total = allocations.checked_add(self.block_reward.checked_mul(interval - 1)?)?;
+        let mut reward = self.block_reward.units() >> 1;
+        while reward > 0 {
+            total = total.checked_add(Amount::from_units(reward).checked_mul(interval)?)?;
+            reward >>= 1;
+        }
+        Some(total)
+    }
+
+    /// Bytes every frame between peers starts with, derived from the chain
+    /// ID, so that a node hangs up on peers of another network before
+    /// trusting anything they send.
+    pub fn network_magic(&self) -> [u8; 4] {
+        let digest = Sha256::digest(self.chain_id.as_bytes());
+        [digest[0], digest[1], digest[2], digest[3]]
+    }
+}
@@ -0,0 +1,87 @@
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::params::ChainParams;
+
+/// A named network with its own genesis, difficulty, address prefix and
+/// default ports, selected with `--chain`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChainProfile {
+    /// The default chain.
+    #[default]
+    Mainnet,
+    /// A public test network with an easier starting difficulty.
+    Testnet,
+    /// A local chain whose blocks need no proof of work, for tests and demos.
+    Regtest,
+}
+
+impl ChainProfile {
+    pub const ALL: [ChainProfile; 3] = [ChainProfile::Mainnet, ChainProfile::Testnet, ChainProfile::Regtest];
+
+    /// Parameters of the network's chain. Mainnet's are the defaults.
+    pub fn params(&self) -> ChainParams {
+        let defaults = ChainParams::default();
+        match self {
+            ChainProfile::Mainnet => defaults,
+            ChainProfile::Testnet => ChainParams {
+                chain_id: "mini-block-testnet".to_string(),
+                address_version: 111, // Encoded addresses start with 'm' or 'n'
+                genesis_timestamp: 1_760_000_000_000,
+                initial_difficulty: 3,
+                coinbase_maturity: 100,
+                ..defaults
+            },
+            ChainProfile::Regtest => ChainParams {
+                chain_id: "mini-block-regtest".to_string(),
+                address_version: 60, // Encoded addresses start with 'R'
+                genesis_timestamp: 1_760_000_000_000,
+                initial_difficulty: 0,
+                retarget_interval: 0,
+                halving_interval: 150,
+                ..defaults
+            },
+        }
+    }
+
+    /// Port peers listen on unless their address names another.
+    pub fn peer_port(&self) -> u16 {
+        match self {
+            ChainProfile::Mainnet => 8333,
+            ChainProfile::Testnet => 18333,
+            ChainProfile::Regtest => 18444,
+        }
+    }
+
+    /// Port the HTTP API is served on unless configured otherwise.
+    pub fn rpc_port(&self) -> u16 {
+        match self {
+            ChainProfile::Mainnet => 8080,
+            ChainProfile::Testnet => 18080,
+            ChainProfile::Regtest => 18443,
+        }
+    }
+}
+
+impl FromStr for ChainProfile {
+    type Err = String;
+
+    fn from_str(name: &str) -> std::result::Result<Self, Self::Err> {
+        ChainProfile::ALL
+            .into_iter()
+            .find(|profile| profile.to_string() == name.to_ascii_lowercase())
+            .ok_or_else(|| format!("unknown chain {} (expected mainnet, testnet or regtest)", name))
+    }
+}
+
+impl fmt::Display for ChainProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ChainProfile::Mainnet => "mainnet",
+            ChainProfile::Testnet => "testnet",
+            ChainProfile::Regtest => "regtest",
+        })
+    }
+}
@@ -0,0 +1,56 @@
+use qrcode::{Color, QrCode};
+use qrcode::render::unicode::Dense1x2;
+use std::fs;
+use std::path::Path;
+
+use crate::error::{BlockchainError, Result};
+
+/// Pixels per module in a PNG from [`write_png`].
+pub const DEFAULT_SCALE: u32 = 8;
+/// Light modules left around the code so scanners can find its edges.
+const QUIET_ZONE: usize = 4;
+
+fn encode(data: &str) -> Result<QrCode> {
+    QrCode::new(data).map_err(|err| BlockchainError::Encoding(format!("cannot fit in a QR code: {}", err)))
+}
+
+/// `data` as a QR code drawn with half-block characters, two rows of
+/// modules per line. Dark modules are drawn as blanks, so the code reads
+/// right on a terminal with light text on a dark background.
+pub fn to_terminal(data: &str) -> Result<String> {
+    Ok(encode(data)?.render::<Dense1x2>().dark_color(Dense1x2::Light).light_color(Dense1x2::Dark).build())
+}
+
+/// Writes `data` as a black-on-white grayscale PNG, `scale` pixels per
+/// module.
+pub fn write_png(data: &str, path: impl AsRef<Path>, scale: u32) -> Result<()> {
+    let code = encode(data)?;
+    let modules = code.to_colors();
+    let width = code.width();
+    let side = width + 2 * QUIET_ZONE;
+    let scale = scale.max(1) as usize;
+    let pixels = side * scale;
+    let mut image = vec![u8::MAX; pixels * pixels];
+    for (i, color) in modules.into_iter().enumerate() {
+        if color == Color::Light {
+            continue;
+        }
+        let (x, y) = ((i % width + QUIET_ZONE) * scale, (i / width + QUIET_ZONE) * scale);
+        for row in y..y + scale {
+            image[row * pixels + x..row * pixels + x + scale].fill(0);
+        }
+    }
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, pixels as u32, pixels as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| {
+            writer.write_image_data(&image)?;
+            writer.finish()
+        })
+        .map_err(|err| BlockchainError::Encoding(format!("cannot write PNG: {}", err)))?;
+    fs::write(path, bytes)?;
+    Ok(())
+}
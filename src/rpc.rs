@@ -0,0 +1,367 @@
+use serde::Deserialize;
+use serde_json::{Value, json};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tracing::{debug, error};
+
+use crate::blockchain::Blockchain;
+use crate::error::Result;
+use crate::fee;
+use crate::mempool::{DEFAULT_BATCH_SIZE, Mempool};
+use crate::network::SharedChain;
+use crate::sync::{lock, read};
+use crate::transaction::Transaction;
+
+pub type SharedMempool = Arc<Mutex<Mempool>>;
+type BlockHook = Arc<dyn Fn(&Blockchain) + Send + Sync>;
+
+const MAX_BODY_BYTES: usize = 1 << 20;
+
+/// OpenAPI description of the endpoints, served at `GET /openapi.json`.
+pub const OPENAPI: &str = include_str!("openapi.json");
+
+/// Single-page block explorer served at `/` by the `explorer` feature.
+#[cfg(feature = "explorer")]
+const EXPLORER_HTML: &str = include_str!("explorer.html");
+
+#[derive(Debug, Deserialize)]
+struct MineRequest {
+    miner: String,
+    #[serde(default = "default_batch_size")]
+    count: usize,
+}
+
+fn default_batch_size() -> usize {
+    DEFAULT_BATCH_SIZE
+}
+
+struct Request {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+enum Body {
+    Json(Value),
+    #[cfg_attr(not(feature = "explorer"), allow(dead_code))]
+    Html(&'static str),
+    /// Prometheus text exposition format.
+    Metrics(String),
+}
+
+struct Response {
+    status: u16,
+    body: Body,
+}
+
+impl Response {
+    fn ok(body: Value) -> Self {
+        Response {
+            status: 200,
+            body: Body::Json(body),
+        }
+    }
+
+    fn error(status: u16, message: impl std::fmt::Display) -> Self {
+        Response {
+            status,
+            body: Body::Json(json!({ "error": message.to_string() })),
+        }
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        422 => "Unprocessable Entity",
+        _ => "Internal Server Error",
+    }
+}
+
+/// Minimal HTTP/1.1 server exposing the chain as JSON:
+///
+/// - `GET /chain` — every block
+/// - `GET /block/{index}` — a single block
+/// - `GET /block/{index}/transactions` — a block's transactions with their IDs
+/// - `GET /balance/{address}` — an address's confirmed balance, its asset
+///   balances, and the sequence number its next transaction must use
+/// - `GET /transaction/{txid}` — a confirmed or pending transaction
+/// - `POST /transaction` — queue `{"sender", "receiver", "amount", "fee"?, "sequence"?}`
+/// - `POST /transaction/validate` — run the checks `POST /transaction` would
+///   on a transaction, without queueing it
+/// - `POST /mine` — mine `{"miner", "count"?}` and return the new block
+/// - `GET /events` — a server-sent event stream of [`NodeEvent`](crate::NodeEvent)s, one JSON
+///   object per `data:` line
+/// - `GET /metrics` — chain height, mempool size, hash rate, peer count and
+///   validation failures for Prometheus
+/// - `GET /fee-estimate/{blocks}` — a suggested fee rate for confirming
+///   within that many blocks
+/// - `GET /openapi.json` — an OpenAPI document describing these endpoints,
+///   which [`HttpClient`](crate::client::HttpClient) wraps for Rust programs
+///
+/// With the `explorer` feature, `GET /` also serves a small web UI for
+/// browsing and searching the chain through these endpoints.
+#[derive(Clone)]
+pub struct RpcServer {
+    chain: SharedChain,
+    mempool: SharedMempool,
+    on_block: BlockHook,
+}
+
+impl RpcServer {
+    pub fn new(chain: SharedChain, mempool: SharedMempool) -> Self {
+        RpcServer {
+            chain,
+            mempool,
+            on_block: Arc::new(|_| {}),
+        }
+    }
+
+    /// Registers a callback run (with the chain locked) after `POST /mine`
+    /// adds a block, e.g. to persist or broadcast it.
+    pub fn with_block_hook(mut self, hook: impl Fn(&Blockchain) + Send + Sync + 'static) -> Self {
+        self.on_block = Arc::new(hook);
+        self
+    }
+
+    /// Serves requests on `addr` until the listener fails, one thread per
+    /// connection.
+    pub fn serve(&self, addr: impl ToSocketAddrs) -> Result<()> {
+        self.accept(TcpListener::bind(addr)?)
+    }
+
+    /// Like [`RpcServer::serve`], but from a background thread, returning
+    /// the address it listens on.
+    pub fn listen(&self, addr: impl ToSocketAddrs) -> Result<SocketAddr> {
+        let listener = TcpListener::bind(addr)?;
+        let local = listener.local_addr()?;
+        let server = self.clone();
+        thread::spawn(move || {
+            if let Err(err) = server.accept(listener) {
+                error!(%err, "HTTP server stopped");
+            }
+        });
+        Ok(local)
+    }
+
+    fn accept(&self, listener: TcpListener) -> Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let server = self.clone();
+            thread::spawn(move || {
+                // Errors here only affect this one client connection.
+                if let Err(err) = server.handle_connection(stream) {
+                    debug!(%err, "HTTP connection failed");
+                }
+            });
+        }
+        Ok(())
+    }
+
+    fn handle_connection(&self, mut stream: TcpStream) -> Result<()> {
+        let response = match read_request(&stream)? {
+            Some(request) if request.method == "GET" && request.path.trim_matches('/') == "events" => {
+                return self.stream_events(stream);
+            }
+            Some(request) => {
+                let response = self.route(&request);
+                debug!(method = request.method, path = request.path, status = response.status, "handled request");
+                response
+            }
+            None => Response::error(413, "request body too large"),
+        };
+        let (content_type, body) = match &response.body {
+            Body::Json(value) => ("application/json", serde_json::to_vec_pretty(value)?),
+            Body::Html(html) => ("text/html; charset=utf-8", html.as_bytes().to_vec()),
+            Body::Metrics(text) => ("text/plain; version=0.0.4; charset=utf-8", text.as_bytes().to_vec()),
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            response.status,
+            reason(response.status),
+            content_type,
+            body.len()
+        )?;
+        stream.write_all(&body)?;
+        Ok(())
+    }
+
+    /// Holds the connection open, writing each event as it is published
+    /// until the client goes away.
+    fn stream_events(&self, mut stream: TcpStream) -> Result<()> {
+        let events = read(&self.chain).subscribe();
+        stream.write_all(
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        )?;
+        for event in events {
+            let data = serde_json::to_string(&event)?;
+            write!(stream, "data: {}\n\n", data)?;
+        }
+        Ok(())
+    }
+
+    fn route(&self, request: &Request) -> Response {
+        let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+        match (request.method.as_str(), segments.as_slice()) {
+            #[cfg(feature = "explorer")]
+            ("GET", [""]) => Response {
+                status: 200,
+                body: Body::Html(EXPLORER_HTML),
+            },
+            ("GET", ["chain"]) => Response::ok(json!(read(&self.chain).blocks())),
+            ("GET", ["block", index]) => match index.parse::<u64>() {
+                Ok(index) => match read(&self.chain).block_by_index(index) {
+                    Some(block) => Response::ok(json!(block)),
+                    None => Response::error(404, format!("no block at index {}", index)),
+                },
+                Err(_) => Response::error(400, "block index must be a number"),
+            },
+            ("GET", ["block", index, "transactions"]) => match index.parse::<u64>() {
+                Ok(index) => match read(&self.chain).block_by_index(index) {
+                    Some(block) => {
+                        let transactions: Vec<Value> = block
+                            .transactions()
+                            .iter()
+                            .map(|tx| json!({ "txid": tx.hash(), "transaction": tx }))
+                            .collect();
+                        Response::ok(json!(transactions))
+                    }
+                    None => Response::error(404, format!("no block at index {}", index)),
+                },
+                Err(_) => Response::error(400, "block index must be a number"),
+            },
+            ("GET", ["balance", address]) => {
+                let chain = read(&self.chain);
+                let balance = chain.balance_of(address);
+                let account = chain.next_sequence(&lock(&self.mempool), address).and_then(|sequence| {
+                    Ok(json!({
+                        "address": address,
+                        "balance": balance,
+                        "assets": chain.asset_balances(address)?,
+                        "next_sequence": sequence,
+                    }))
+                });
+                match account {
+                    Ok(account) => Response::ok(account),
+                    Err(err) => Response::error(500, err),
+                }
+            }
+            ("GET", ["metrics"]) => {
+                let chain = read(&self.chain);
+                let pending = lock(&self.mempool).len();
+                Response {
+                    status: 200,
+                    body: Body::Metrics(chain.metrics().render(chain.latest_block().index(), pending)),
+                }
+            }
+            ("GET", ["openapi.json"]) => {
+                Response::ok(serde_json::from_str(OPENAPI).expect("the OpenAPI document is valid JSON"))
+            }
+            ("GET", ["fee-estimate", blocks]) => match blocks.parse::<u64>() {
+                Ok(blocks) => Response::ok(json!(fee::estimate_fee(&read(&self.chain), &lock(&self.mempool), blocks))),
+                Err(_) => Response::error(400, "target must be a number of blocks"),
+            },
+            ("GET", ["transaction", txid]) if *txid != "validate" => self.transaction(txid),
+            ("POST", ["transaction"]) => self.submit_transaction(&request.body),
+            ("POST", ["transaction", "validate"]) => self.validate_transaction(&request.body),
+            ("POST", ["mine"]) => self.mine(&request.body),
+            (
+                _,
+                ["chain"] | ["block", _] | ["block", _, "transactions"] | ["balance", _] | ["transaction"]
+                | ["transaction", "validate"] | ["transaction", _] | ["mine"] | ["events"] | ["metrics"] | ["openapi.json"]
+                | ["fee-estimate", _],
+            ) => Response::error(405, "method not allowed"),
+            _ => Response::error(404, "unknown endpoint"),
+        }
+    }
+
+    /// Looks a transaction up among the confirmed ones, then the pending ones.
+    fn transaction(&self, txid: &str) -> Response {
+        if let Some((block, tx)) = read(&self.chain).get_transaction(txid) {
+            return Response::ok(json!({ "transaction": tx, "block": block.index(), "confirmed": true }));
+        }
+        match lock(&self.mempool).get(txid) {
+            Some(tx) => Response::ok(json!({ "transaction": tx, "confirmed": false })),
+            None => Response::error(404, format!("no transaction {}", txid)),
+        }
+    }
+
+    fn submit_transaction(&self, body: &[u8]) -> Response {
+        let tx: Transaction = match serde_json::from_slice(body) {
+            Ok(tx) => tx,
+            Err(err) => return Response::error(400, err),
+        };
+        let txid = tx.hash();
+        let chain = read(&self.chain);
+        let mut mempool = lock(&self.mempool);
+        match chain.submit_transaction(&mut mempool, tx) {
+            Ok(()) => Response::ok(json!({ "queued": true, "txid": txid, "pending": mempool.len() })),
+            Err(err) => Response::error(422, err),
+        }
+    }
+
+    /// Answers whether the transaction would be queued, and if not, which
+    /// check it fails, without queuing it.
+    fn validate_transaction(&self, body: &[u8]) -> Response {
+        let tx: Transaction = match serde_json::from_slice(body) {
+            Ok(tx) => tx,
+            Err(err) => return Response::error(400, err),
+        };
+        let txid = tx.hash();
+        match read(&self.chain).validate_transaction(&lock(&self.mempool), &tx) {
+            Ok(()) => Response::ok(json!({ "txid": txid, "valid": true })),
+            Err(err) => Response::ok(json!({ "txid": txid, "valid": false, "check": err.check, "error": err.message })),
+        }
+    }
+
+    fn mine(&self, body: &[u8]) -> Response {
+        let request: MineRequest = match serde_json::from_slice(body) {
+            Ok(request) => request,
+            Err(err) => return Response::error(400, err),
+        };
+        match Blockchain::mine_shared(&self.chain, &self.mempool, request.count, &request.miner) {
+            Ok(block) => {
+                (self.on_block)(&read(&self.chain));
+                Response::ok(json!(block))
+            }
+            Err(err) => Response::error(500, err),
+        }
+    }
+}
+
+/// Reads one request, returning `None` if its body exceeds the size limit.
+fn read_request(stream: &TcpStream) -> Result<Option<Request>> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default().to_string();
+    let path = parts.next().unwrap_or("/").to_string();
+
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.trim().eq_ignore_ascii_case("content-length")
+        {
+            content_length = value.trim().parse().unwrap_or(0);
+        }
+    }
+    if content_length > MAX_BODY_BYTES {
+        return Ok(None);
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(Some(Request { method, path, body }))
+}
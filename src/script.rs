@@ -0,0 +1,245 @@
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::error::{BlockchainError, Result};
+use crate::sigcache::SignatureCache;
+use crate::transaction::Transaction;
+
+/// Most bytes a single push may hold.
+pub const MAX_PUSH_SIZE: usize = 520;
+/// Most operations a script may have.
+pub const MAX_SCRIPT_OPS: usize = 64;
+/// Most items the stack may hold while a script runs.
+pub const MAX_STACK_DEPTH: usize = 32;
+
+/// An instruction of the stack machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    Push(Vec<u8>),
+    /// Duplicates the top item.
+    Dup,
+    /// Replaces the top item with its SHA-256 hash.
+    Hash,
+    /// Pops a public key, then a signature, and pushes whether it signs the
+    /// spending transaction.
+    CheckSig,
+    /// Pops two items and pushes whether they are equal.
+    Equal,
+}
+
+/// A program for a tiny stack machine. A transaction can lock its payment
+/// output with one, on top of naming its receiver; spending the output then
+/// takes an unlocking script, which may only push data, that leaves a true
+/// value on the stack once the locking script has run after it.
+///
+/// Scripts are written as words separated by spaces: `dup`, `hash`,
+/// `checksig`, `equal`, and hex for the bytes to push.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Script(Vec<Op>);
+
+impl Script {
+    pub fn new(ops: Vec<Op>) -> Self {
+        Script(ops)
+    }
+
+    /// Locks an output to the holder of `key`, who unlocks it with
+    /// [`Script::signature`].
+    pub fn pay_to_key(key: &VerifyingKey) -> Self {
+        Script(vec![Op::Push(key.to_bytes().to_vec()), Op::CheckSig])
+    }
+
+    /// Whether this is a [`Script::pay_to_key`] lock, for some key.
+    pub fn is_pay_to_key(&self) -> bool {
+        matches!(self.0.as_slice(), [Op::Push(key), Op::CheckSig] if key.len() == 32)
+    }
+
+    /// Locks an output to whoever reveals the data `hash` is the SHA-256 of,
+    /// by pushing it.
+    pub fn hash_lock(hash: [u8; 32]) -> Self {
+        Script(vec![Op::Hash, Op::Push(hash.to_vec()), Op::Equal])
+    }
+
+    /// Unlocks a [`Script::pay_to_key`] output spent by `tx` on the chain
+    /// with `chain_id`. The signature covers the transaction except for its
+    /// unlocking scripts.
+    pub fn signature(key: &SigningKey, tx: &Transaction, chain_id: &str) -> Self {
+        let signature = key.sign(tx.signature_hash(chain_id).as_bytes());
+        Script(vec![Op::Push(signature.to_bytes().to_vec())])
+    }
+
+    pub fn ops(&self) -> &[Op] {
+        &self.0
+    }
+
+    pub fn is_push_only(&self) -> bool {
+        self.0.iter().all(|op| matches!(op, Op::Push(_)))
+    }
+
+    /// Checks the script is within [`MAX_SCRIPT_OPS`] and [`MAX_PUSH_SIZE`].
+    pub fn check(&self) -> Result<()> {
+        if self.0.len() > MAX_SCRIPT_OPS {
+            return Err(failed(format!("script has more than {} operations", MAX_SCRIPT_OPS)));
+        }
+        if self.0.iter().any(|op| matches!(op, Op::Push(data) if data.len() > MAX_PUSH_SIZE)) {
+            return Err(failed(format!("script pushes more than {} bytes", MAX_PUSH_SIZE)));
+        }
+        Ok(())
+    }
+
+    /// Unambiguous encoding of the script, for hashing into transaction IDs.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for op in &self.0 {
+            match op {
+                Op::Push(data) => {
+                    bytes.push(0);
+                    bytes.extend((data.len() as u32).to_be_bytes());
+                    bytes.extend(data);
+                }
+                Op::Dup => bytes.push(1),
+                Op::Hash => bytes.push(2),
+                Op::CheckSig => bytes.push(3),
+                Op::Equal => bytes.push(4),
+            }
+        }
+        bytes
+    }
+}
+
+/// Runs `unlock` and then `lock` on one stack, succeeding if both are well
+/// formed, `unlock` only pushes data, no operation fails, and the top item
+/// is true: not empty and not all zero bytes. `checksig` verifies signatures
+/// against `message`, the spending transaction's signature hash.
+pub fn verify(unlock: &Script, lock: &Script, message: &[u8]) -> Result<()> {
+    run(unlock, lock, &|key, signature| signature_is_valid(key, signature, message))
+}
+
+/// Like [`verify`] for an input of transaction `txid`, looking signatures up
+/// in `cache` before verifying them and recording the valid ones.
+pub fn verify_cached(unlock: &Script, lock: &Script, message: &[u8], txid: &str, cache: &SignatureCache) -> Result<()> {
+    run(unlock, lock, &|key, signature| {
+        cache.verify(txid, key, signature, || signature_is_valid(key, signature, message))
+    })
+}
+
+/// Runs the scripts with `check_sig` deciding whether a signature is valid
+/// for a key.
+fn run(unlock: &Script, lock: &Script, check_sig: &dyn Fn(&[u8], &[u8]) -> bool) -> Result<()> {
+    unlock.check()?;
+    lock.check()?;
+    if !unlock.is_push_only() {
+        return Err(failed("unlocking script does more than push data"));
+    }
+    let mut stack: Vec<Vec<u8>> = Vec::new();
+    for op in unlock.ops().iter().chain(lock.ops()) {
+        match op {
+            Op::Push(data) => stack.push(data.clone()),
+            Op::Dup => {
+                let top = stack.last().ok_or_else(|| failed("dup on an empty stack"))?.clone();
+                stack.push(top);
+            }
+            Op::Hash => {
+                let top = stack.pop().ok_or_else(|| failed("hash on an empty stack"))?;
+                stack.push(Sha256::digest(top).to_vec());
+            }
+            Op::CheckSig => {
+                let (key, signature) = match (stack.pop(), stack.pop()) {
+                    (Some(key), Some(signature)) => (key, signature),
+                    _ => return Err(failed("checksig needs a key and a signature")),
+                };
+                stack.push(boolean(check_sig(&key, &signature)));
+            }
+            Op::Equal => {
+                let (a, b) = match (stack.pop(), stack.pop()) {
+                    (Some(a), Some(b)) => (a, b),
+                    _ => return Err(failed("equal needs two items")),
+                };
+                stack.push(boolean(a == b));
+            }
+        }
+        if stack.len() > MAX_STACK_DEPTH {
+            return Err(failed(format!("stack grew past {} items", MAX_STACK_DEPTH)));
+        }
+    }
+    match stack.last() {
+        Some(top) if top.iter().any(|&byte| byte != 0) => Ok(()),
+        _ => Err(failed("script did not leave true on the stack")),
+    }
+}
+
+fn signature_is_valid(key: &[u8], signature: &[u8], message: &[u8]) -> bool {
+    let Ok(key) = <[u8; 32]>::try_from(key) else {
+        return false;
+    };
+    match (VerifyingKey::from_bytes(&key), Signature::from_slice(signature)) {
+        (Ok(key), Ok(signature)) => key.verify_strict(message, &signature).is_ok(),
+        _ => false,
+    }
+}
+
+fn boolean(value: bool) -> Vec<u8> {
+    if value { vec![1] } else { Vec::new() }
+}
+
+fn failed(message: impl fmt::Display) -> BlockchainError {
+    BlockchainError::Validation(format!("script failed: {}", message))
+}
+
+impl fmt::Display for Script {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, op) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(" ")?;
+            }
+            match op {
+                // An empty push has no hex digits to write, so it gets a word.
+                Op::Push(data) if data.is_empty() => f.write_str("false")?,
+                Op::Push(data) => f.write_str(&hex::encode(data))?,
+                Op::Dup => f.write_str("dup")?,
+                Op::Hash => f.write_str("hash")?,
+                Op::CheckSig => f.write_str("checksig")?,
+                Op::Equal => f.write_str("equal")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Script {
+    type Err = BlockchainError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let ops = s
+            .split_whitespace()
+            .map(|word| match word {
+                "dup" => Ok(Op::Dup),
+                "hash" => Ok(Op::Hash),
+                "checksig" => Ok(Op::CheckSig),
+                "equal" => Ok(Op::Equal),
+                "false" => Ok(Op::Push(Vec::new())),
+                hex => hex::decode(hex)
+                    .map(Op::Push)
+                    .map_err(|_| BlockchainError::Encoding(format!("unknown script word {}", word))),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Script(ops))
+    }
+}
+
+impl TryFrom<String> for Script {
+    type Error = BlockchainError;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<Script> for String {
+    fn from(script: Script) -> Self {
+        script.to_string()
+    }
+}
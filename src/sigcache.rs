@@ -0,0 +1,104 @@
+use sha2::{Digest, Sha256};
+use std::collections::{HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::sync::lock;
+
+/// Signatures a [`SignatureCache`] remembers before evicting the oldest.
+pub const DEFAULT_CAPACITY: usize = 50_000;
+
+#[derive(Debug)]
+struct Inner {
+    capacity: usize,
+    entries: Mutex<Entries>,
+    hits: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    known: HashSet<[u8; 32]>,
+    /// `known` in the order it was filled, oldest first.
+    order: VecDeque<[u8; 32]>,
+}
+
+/// Signatures already found valid, keyed by the transaction ID and public
+/// key they were checked for, and the signature itself, so checking a block
+/// again (after a reorganization, say) doesn't verify them again. A
+/// transaction's ID covers its unlocking scripts, and the message signed is
+/// derived from the transaction and the chain, so one cache serves one
+/// chain. Clones share the same entries.
+#[derive(Debug, Clone, Default)]
+pub struct SignatureCache(Arc<Inner>);
+
+impl Default for Inner {
+    fn default() -> Self {
+        Inner {
+            capacity: DEFAULT_CAPACITY,
+            entries: Mutex::default(),
+            hits: AtomicU64::new(0),
+        }
+    }
+}
+
+impl SignatureCache {
+    /// A cache of at most `capacity` signatures; 0 caches none.
+    pub fn new(capacity: usize) -> Self {
+        SignatureCache(Arc::new(Inner {
+            capacity,
+            ..Inner::default()
+        }))
+    }
+
+    /// Whether `signature` by `key` is valid for transaction `txid`: true if
+    /// the cache has it, or else whatever `check` finds, which is remembered
+    /// if true.
+    pub fn verify(&self, txid: &str, key: &[u8], signature: &[u8], check: impl FnOnce() -> bool) -> bool {
+        let entry = entry_key(txid, key, signature);
+        if lock(&self.0.entries).known.contains(&entry) {
+            self.0.hits.fetch_add(1, Ordering::Relaxed);
+            return true;
+        }
+        let valid = check();
+        if valid && self.0.capacity > 0 {
+            let mut entries = lock(&self.0.entries);
+            if entries.known.insert(entry) {
+                entries.order.push_back(entry);
+            }
+            while entries.order.len() > self.0.capacity {
+                if let Some(oldest) = entries.order.pop_front() {
+                    entries.known.remove(&oldest);
+                }
+            }
+        }
+        valid
+    }
+
+    pub fn len(&self) -> usize {
+        lock(&self.0.entries).order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Verifications answered from the cache.
+    pub fn hits(&self) -> u64 {
+        self.0.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn clear(&self) {
+        let mut entries = lock(&self.0.entries);
+        entries.known.clear();
+        entries.order.clear();
+    }
+}
+
+fn entry_key(txid: &str, key: &[u8], signature: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for part in [txid.as_bytes(), key, signature] {
+        hasher.update((part.len() as u64).to_be_bytes());
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
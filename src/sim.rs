@@ -0,0 +1,345 @@
+use serde::Serialize;
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::str::FromStr;
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+use crate::amount::{Amount, COIN};
+use crate::ban::BanList;
+use crate::blockchain::Blockchain;
+use crate::error::{BlockchainError, Result};
+use crate::mempool::Mempool;
+use crate::network::{MAX_PEERS, Node};
+use crate::profile::ChainProfile;
+use crate::rpc::SharedMempool;
+use crate::sync::{lock, read};
+use crate::transaction::Transaction;
+
+/// Accounts funded in the simulated chain's genesis block, and what each
+/// starts with.
+const ACCOUNTS: usize = 8;
+const ALLOCATION: Amount = Amount::from_coins(1_000);
+
+/// Fee of every simulated payment.
+const FEE: Amount = Amount::from_coins(1);
+
+/// How long to let the links drain after the partitions heal, before the
+/// block that settles any tie between forks is mined.
+const HEAL_GRACE: Duration = Duration::from_millis(200);
+
+/// Links between these pairs of nodes are cut.
+type Cuts = Arc<Mutex<HashSet<(usize, usize)>>>;
+
+/// Nodes cut off from the rest of the network for rounds `from..to`. They
+/// still reach each other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Partition {
+    pub from: usize,
+    pub to: usize,
+    pub isolated: Vec<usize>,
+}
+
+impl Partition {
+    fn separates(&self, round: usize, a: usize, b: usize) -> bool {
+        (self.from..self.to).contains(&round) && self.isolated.contains(&a) != self.isolated.contains(&b)
+    }
+}
+
+/// Parses `FROM..TO:NODE,NODE`, e.g. `2..5:0,1`.
+impl FromStr for Partition {
+    type Err = BlockchainError;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || BlockchainError::Validation(format!("invalid partition {:?}, expected FROM..TO:NODE,NODE", s));
+        let (rounds, nodes) = s.split_once(':').ok_or_else(invalid)?;
+        let (from, to) = rounds.split_once("..").ok_or_else(invalid)?;
+        let (from, to) = (from.trim().parse().map_err(|_| invalid())?, to.trim().parse().map_err(|_| invalid())?);
+        let isolated = nodes
+            .split(',')
+            .map(|node| node.trim().parse().map_err(|_| invalid()))
+            .collect::<Result<Vec<usize>>>()?;
+        if from >= to {
+            return Err(invalid());
+        }
+        Ok(Partition { from, to, isolated })
+    }
+}
+
+/// What to simulate.
+#[derive(Debug, Clone)]
+pub struct SimConfig {
+    pub nodes: usize,
+    pub rounds: usize,
+    /// Delay of every message between two nodes.
+    pub latency: Duration,
+    /// How long each round of transactions and mining lasts.
+    pub round_time: Duration,
+    pub transactions_per_round: usize,
+    pub partitions: Vec<Partition>,
+    /// Seeds the choice of senders, amounts and miners, so that a run can be
+    /// repeated (up to the timing of the network).
+    pub seed: u64,
+    /// How long the nodes get to agree after the last round.
+    pub settle_timeout: Duration,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        SimConfig {
+            nodes: 4,
+            rounds: 10,
+            latency: Duration::from_millis(20),
+            round_time: Duration::from_millis(100),
+            transactions_per_round: 3,
+            partitions: Vec::new(),
+            seed: 0,
+            settle_timeout: Duration::from_secs(20),
+        }
+    }
+}
+
+/// The outcome of [`run`].
+#[derive(Debug, Clone, Serialize)]
+pub struct SimReport {
+    pub nodes: usize,
+    pub rounds: usize,
+    pub blocks_mined: usize,
+    /// Transactions a node accepted; ones a node refused, e.g. because it
+    /// had not seen an earlier one from the same sender, aren't counted.
+    pub transactions_submitted: usize,
+    /// Each node's height and tip at the end.
+    pub heights: Vec<u64>,
+    pub tips: Vec<String>,
+    /// Whether every node ended on the same tip.
+    pub consistent: bool,
+}
+
+/// A node of the simulation, with its mempool.
+struct SimNode {
+    node: Node,
+    mempool: SharedMempool,
+}
+
+impl SimNode {
+    fn tip(&self) -> (u64, String) {
+        let chain = read(self.node.chain());
+        (chain.height(), chain.latest_block().hash().to_string())
+    }
+}
+
+/// Runs `config.nodes` nodes in this process, each linked to every other
+/// through a relay that delays what it forwards by the latency and holds it
+/// while a partition cuts the link. Each round some of the nodes are given
+/// random payments between funded accounts and one of them mines. After
+/// the last round the partitions heal, a last block is mined to settle ties
+/// between forks, and the nodes get until the settle timeout to agree.
+pub fn run(config: &SimConfig) -> Result<SimReport> {
+    if config.nodes == 0 || config.nodes > MAX_PEERS + 1 {
+        return Err(BlockchainError::Validation(format!(
+            "can simulate 1 to {} nodes, not {}",
+            MAX_PEERS + 1,
+            config.nodes
+        )));
+    }
+    if let Some(node) = config.partitions.iter().flat_map(|p| &p.isolated).find(|node| **node >= config.nodes) {
+        return Err(BlockchainError::Validation(format!("partition names node {}, which does not exist", node)));
+    }
+    let mut params = ChainProfile::Regtest.params();
+    for account in 0..ACCOUNTS {
+        params.genesis_allocations.insert(account_name(account), ALLOCATION);
+    }
+    let nodes = (0..config.nodes)
+        .map(|_| {
+            let chain = Arc::new(RwLock::new(Blockchain::with_params(params.clone())?));
+            let mempool = Arc::new(Mutex::new(Mempool::new()));
+            // Every node connects from 127.0.0.1, so banning one would ban
+            // them all.
+            let node = Node::new(chain)
+                .with_mempool(mempool.clone())
+                .with_ban_list(BanList::new(u32::MAX, Duration::ZERO))
+                .without_discovery();
+            Ok(SimNode { node, mempool })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let cuts: Cuts = Arc::default();
+    for (b, to) in nodes.iter().enumerate() {
+        let addr = to.node.listen("127.0.0.1:0")?;
+        for (a, from) in nodes.iter().enumerate().take(b) {
+            let relay = relay(addr, (a, b), config.latency, cuts.clone())?;
+            from.node.connect(relay)?;
+        }
+    }
+
+    let mut rng = Rng::new(config.seed);
+    let (mut blocks_mined, mut transactions_submitted) = (0, 0);
+    for round in 0..config.rounds {
+        cut(&cuts, config, Some(round));
+        for _ in 0..config.transactions_per_round {
+            let at = &nodes[rng.below(nodes.len())];
+            if submit_payment(at, &mut rng) {
+                transactions_submitted += 1;
+            }
+        }
+        let miner = rng.below(nodes.len());
+        if mine(&nodes[miner], miner) {
+            blocks_mined += 1;
+        }
+        thread::sleep(config.round_time);
+    }
+
+    cut(&cuts, config, None);
+    thread::sleep(config.latency * 2 + HEAL_GRACE);
+    if mine(&nodes[0], 0) {
+        blocks_mined += 1;
+    }
+    let deadline = Instant::now() + config.settle_timeout;
+    let consistent = loop {
+        let tips: HashSet<_> = nodes.iter().map(SimNode::tip).collect();
+        if tips.len() == 1 {
+            break true;
+        }
+        if Instant::now() >= deadline {
+            break false;
+        }
+        thread::sleep(Duration::from_millis(20));
+    };
+    let (heights, tips) = nodes.iter().map(SimNode::tip).unzip();
+    info!(blocks_mined, transactions_submitted, consistent, "simulation finished");
+    Ok(SimReport {
+        nodes: config.nodes,
+        rounds: config.rounds,
+        blocks_mined,
+        transactions_submitted,
+        heights,
+        tips,
+        consistent,
+    })
+}
+
+fn account_name(account: usize) -> String {
+    format!("sim-{}", account)
+}
+
+/// Cuts the links the partitions call for in `round`, or none.
+fn cut(cuts: &Cuts, config: &SimConfig, round: Option<usize>) {
+    let mut cuts = lock(cuts);
+    cuts.clear();
+    let Some(round) = round else { return };
+    for a in 0..config.nodes {
+        for b in a + 1..config.nodes {
+            if config.partitions.iter().any(|partition| partition.separates(round, a, b)) {
+                cuts.insert((a, b));
+            }
+        }
+    }
+}
+
+/// Has `at` queue a random payment between funded accounts that its chain
+/// and mempool say the sender can afford. Returns whether it was queued.
+fn submit_payment(at: &SimNode, rng: &mut Rng) -> bool {
+    let sender = account_name(rng.below(ACCOUNTS));
+    let receiver = account_name(rng.below(ACCOUNTS));
+    let chain = read(at.node.chain());
+    let mut mempool = lock(&at.mempool);
+    let available = chain.balance_of(&sender).saturating_sub(mempool.pending_outgoing(&sender));
+    // Whole coins up to 50, leaving enough for the fee.
+    let affordable = available.saturating_sub(FEE).units() / COIN;
+    if affordable == 0 {
+        return false;
+    }
+    let amount = Amount::from_coins(1 + rng.below(affordable.min(50) as usize) as u32);
+    let queued = chain.next_sequence(&mempool, &sender).and_then(|sequence| {
+        let tx = Transaction::new(&sender, &receiver, amount).with_fee(FEE).with_sequence(sequence);
+        chain.submit_transaction(&mut mempool, tx)
+    });
+    if let Err(err) = &queued {
+        debug!(%err, %sender, "simulated payment refused");
+    }
+    queued.is_ok()
+}
+
+/// Has node `index` mine its pending transactions and announce the block.
+/// Returns whether it mined one.
+fn mine(at: &SimNode, index: usize) -> bool {
+    let miner = format!("sim-miner-{}", index);
+    let block = match Blockchain::mine_shared(at.node.chain(), &at.mempool, usize::MAX, &miner) {
+        Ok(block) => block,
+        Err(err) => {
+            debug!(%err, index, "simulated mining failed");
+            return false;
+        }
+    };
+    at.node.broadcast_block(&block);
+    true
+}
+
+/// Listens for one connection from node `link.0` and forwards it, both
+/// ways, to node `link.1` listening on `to`. Returns the address to dial.
+fn relay(to: SocketAddr, link: (usize, usize), latency: Duration, cuts: Cuts) -> Result<SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    thread::spawn(move || {
+        let Ok((inbound, _)) = listener.accept() else { return };
+        let Ok(outbound) = TcpStream::connect(to) else { return };
+        let (Ok(inbound_copy), Ok(outbound_copy)) = (inbound.try_clone(), outbound.try_clone()) else {
+            return;
+        };
+        forward(inbound, outbound_copy, link, latency, cuts.clone());
+        forward(outbound, inbound_copy, link, latency, cuts);
+    });
+    Ok(addr)
+}
+
+/// Copies what arrives on `from` to `to`, each chunk `latency` after it
+/// arrived and once `link` isn't cut.
+fn forward(mut from: TcpStream, mut to: TcpStream, link: (usize, usize), latency: Duration, cuts: Cuts) {
+    let (sender, receiver) = mpsc::channel::<(Instant, Vec<u8>)>();
+    thread::spawn(move || {
+        let mut buf = [0u8; 16 * 1024];
+        while let Ok(n @ 1..) = from.read(&mut buf) {
+            if sender.send((Instant::now() + latency, buf[..n].to_vec())).is_err() {
+                break;
+            }
+        }
+    });
+    thread::spawn(move || {
+        for (due, bytes) in receiver {
+            thread::sleep(due.saturating_duration_since(Instant::now()));
+            while lock(&cuts).contains(&link) {
+                thread::sleep(Duration::from_millis(10));
+            }
+            if to.write_all(&bytes).is_err() {
+                return;
+            }
+        }
+        let _ = to.shutdown(Shutdown::Write);
+    });
+}
+
+/// SplitMix64, enough to make a run's choices repeatable.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`; `n` must not be zero.
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
@@ -0,0 +1,18 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+use crate::amount::Amount;
+use crate::utxo::UtxoSet;
+
+/// What replaying a chain from genesis through the block at `height` yields:
+/// its unspent outputs and account balances. A pruned chain keeps this in
+/// place of the bodies it discarded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainState {
+    pub height: u64,
+    /// Hash of the block at `height`, so the state can't be applied to a
+    /// different chain.
+    pub hash: String,
+    pub utxos: UtxoSet,
+    pub balances: HashMap<String, Amount>,
+}
@@ -0,0 +1,86 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::slice;
+
+use crate::amount::Amount;
+use crate::blockchain::{self, Blockchain};
+
+/// Figures about a whole chain, from [`stats`]. Averages are over the blocks
+/// mined after genesis, and are zero (or `None`) until there are any.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ChainStats {
+    pub height: u64,
+    /// Confirmed transactions, not counting coinbases.
+    pub transactions: u64,
+    /// Blocks whose transactions were pruned, and so aren't counted.
+    pub pruned_blocks: u64,
+    /// Milliseconds between consecutive mined blocks.
+    pub average_block_interval_ms: Option<f64>,
+    pub average_transactions_per_block: f64,
+    /// Sum of every balance at the tip.
+    pub circulation: Amount,
+    /// What the next block's coinbase mints, before fees.
+    pub block_reward: Amount,
+    /// Height at which the block reward next halves, if it ever does.
+    pub next_halving: Option<u64>,
+    /// Every coin there will ever be, if the block reward runs out.
+    pub total_supply: Option<Amount>,
+    /// The addresses holding the most coins, richest first.
+    pub top_addresses: Vec<(String, Amount)>,
+    pub average_nonce: f64,
+    /// Expected hashes behind each block.
+    pub average_work: f64,
+}
+
+/// Computes [`ChainStats`] in one pass over the chain, listing the `top`
+/// richest addresses. Balances of a pruned chain come from its saved state.
+pub fn stats(chain: &Blockchain, top: usize) -> ChainStats {
+    let pruned = chain.pruned_height().is_some();
+    let params = chain.params();
+    let mut stats = ChainStats {
+        height: chain.height(),
+        block_reward: params.block_reward_at(chain.height() + 1),
+        next_halving: params.next_halving(chain.height() + 1),
+        total_supply: params.total_supply(),
+        ..ChainStats::default()
+    };
+    let mut balances = HashMap::new();
+    let (mut nonces, mut work) = (0f64, 0f64);
+    for block in chain.iter() {
+        if !pruned {
+            blockchain::apply_balances(&mut balances, slice::from_ref(block));
+        }
+        if block.is_pruned() {
+            stats.pruned_blocks += 1;
+        }
+        if block.index() > 0 {
+            stats.transactions += block.transactions().iter().filter(|tx| !tx.is_coinbase()).count() as u64;
+            nonces += block.nonce() as f64;
+            work += block.work() as f64;
+        }
+    }
+    if pruned {
+        balances = chain.balances();
+    }
+
+    let mined = stats.height as f64;
+    if stats.height > 0 {
+        stats.average_transactions_per_block = stats.transactions as f64 / mined;
+        stats.average_nonce = nonces / mined;
+        stats.average_work = work / mined;
+    }
+    // The genesis timestamp is fixed by the chain's parameters, so intervals
+    // start from the first mined block.
+    if let Some(first) = chain.block_by_index(1)
+        && stats.height > 1
+    {
+        let span = chain.latest_block().timestamp().saturating_sub(first.timestamp());
+        stats.average_block_interval_ms = Some(span as f64 / (mined - 1.0));
+    }
+    stats.circulation = balances.values().fold(Amount::ZERO, |total, balance| total.saturating_add(*balance));
+    let mut richest: Vec<(String, Amount)> = balances.into_iter().filter(|(_, balance)| !balance.is_zero()).collect();
+    richest.sort_by(|(a, x), (b, y)| y.cmp(x).then_with(|| a.cmp(b)));
+    richest.truncate(top);
+    stats.top_addresses = richest;
+    stats
+}
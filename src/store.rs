@@ -0,0 +1,536 @@
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use tracing::{debug, debug_span, warn};
+
+use crate::block::Block;
+use crate::error::{BlockchainError, Result};
+use crate::file;
+use crate::index::ChainIndex;
+use crate::params::ChainParams;
+use crate::state::ChainState;
+
+/// Persistent block storage that appends blocks one at a time instead of
+/// rewriting the whole chain.
+pub trait ChainStore {
+    fn append_block(&mut self, block: &Block) -> Result<()>;
+    fn block_by_height(&self, height: u64) -> Result<Option<Block>>;
+    fn block_by_hash(&self, hash: &str) -> Result<Option<Block>>;
+    /// Number of stored blocks, i.e. the height of the next block to append.
+    fn len(&self) -> Result<u64>;
+    /// Removes every block at height `len` and above.
+    fn truncate(&mut self, len: u64) -> Result<()>;
+    fn params(&self) -> Result<Option<ChainParams>>;
+    fn set_params(&mut self, params: &ChainParams) -> Result<()>;
+    /// The state saved by the last [`ChainStore::prune`], if any.
+    fn state(&self) -> Result<Option<ChainState>>;
+    /// Saves `state` and replaces the blocks up to its height with their
+    /// pruned form, freeing the space their transactions took.
+    fn prune(&mut self, state: &ChainState) -> Result<()>;
+    /// The last state saved by [`ChainStore::save_snapshot`], if any. It may
+    /// be from a block no longer on the chain, which callers must check.
+    fn snapshot(&self) -> Result<Option<ChainState>>;
+    fn save_snapshot(&mut self, state: &ChainState) -> Result<()>;
+    /// The index last saved by [`ChainStore::save_index`], if any. Like a
+    /// snapshot, it may be behind or off the stored chain.
+    fn index(&self) -> Result<Option<ChainIndex>>;
+    fn save_index(&mut self, index: &ChainIndex) -> Result<()>;
+
+    fn is_empty(&self) -> Result<bool> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Loads every block in height order.
+    fn load_blocks(&self) -> Result<Vec<Block>> {
+        let len = self.len()?;
+        let mut blocks = Vec::with_capacity(len as usize);
+        for height in 0..len {
+            let block = self.block_by_height(height)?.ok_or_else(|| {
+                BlockchainError::Storage(format!("block at height {} is missing", height))
+            })?;
+            blocks.push(block);
+        }
+        Ok(blocks)
+    }
+}
+
+/// [`ChainStore`] backed by a sled database directory. Clones share the
+/// same underlying database.
+#[derive(Clone)]
+pub struct SledStore {
+    db: sled::Db,
+    blocks: sled::Tree,
+    hashes: sled::Tree,
+}
+
+const PARAMS_KEY: &[u8] = b"params";
+const STATE_KEY: &[u8] = b"state";
+const SNAPSHOT_KEY: &[u8] = b"snapshot";
+const INDEX_KEY: &[u8] = b"index";
+
+impl SledStore {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let db = sled::open(path)?;
+        let blocks = db.open_tree("blocks")?;
+        let hashes = db.open_tree("hashes")?;
+        Ok(SledStore { db, blocks, hashes })
+    }
+
+    fn decode_height(bytes: &[u8]) -> Result<u64> {
+        let bytes: [u8; 8] = bytes
+            .try_into()
+            .map_err(|_| BlockchainError::Storage("corrupt height index entry".to_string()))?;
+        Ok(u64::from_be_bytes(bytes))
+    }
+}
+
+impl ChainStore for SledStore {
+    fn append_block(&mut self, block: &Block) -> Result<()> {
+        let expected = self.len()?;
+        if block.index() != expected {
+            return Err(BlockchainError::Storage(format!(
+                "cannot append block #{} to a store holding {} blocks",
+                block.index(),
+                expected
+            )));
+        }
+        let key = block.index().to_be_bytes();
+        self.blocks.insert(key, serde_json::to_vec(block)?)?;
+        self.hashes.insert(block.hash().as_bytes(), &key)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn block_by_height(&self, height: u64) -> Result<Option<Block>> {
+        match self.blocks.get(height.to_be_bytes())? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn block_by_hash(&self, hash: &str) -> Result<Option<Block>> {
+        match self.hashes.get(hash.as_bytes())? {
+            Some(height) => self.block_by_height(SledStore::decode_height(&height)?),
+            None => Ok(None),
+        }
+    }
+
+    fn len(&self) -> Result<u64> {
+        match self.blocks.last()? {
+            Some((key, _)) => Ok(SledStore::decode_height(&key)? + 1),
+            None => Ok(0),
+        }
+    }
+
+    fn truncate(&mut self, len: u64) -> Result<()> {
+        for height in len..self.len()? {
+            if let Some(bytes) = self.blocks.remove(height.to_be_bytes())? {
+                let block: Block = serde_json::from_slice(&bytes)?;
+                self.hashes.remove(block.hash().as_bytes())?;
+            }
+        }
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn params(&self) -> Result<Option<ChainParams>> {
+        match self.db.get(PARAMS_KEY)? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn set_params(&mut self, params: &ChainParams) -> Result<()> {
+        self.db.insert(PARAMS_KEY, serde_json::to_vec(params)?)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn state(&self) -> Result<Option<ChainState>> {
+        match self.db.get(STATE_KEY)? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn prune(&mut self, state: &ChainState) -> Result<()> {
+        self.db.insert(STATE_KEY, serde_json::to_vec(state)?)?;
+        for height in 0..=state.height {
+            if let Some(mut block) = self.block_by_height(height)?
+                && !block.is_pruned()
+            {
+                block.prune();
+                self.blocks.insert(height.to_be_bytes(), serde_json::to_vec(&block)?)?;
+            }
+        }
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn snapshot(&self) -> Result<Option<ChainState>> {
+        match self.db.get(SNAPSHOT_KEY)? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn save_snapshot(&mut self, state: &ChainState) -> Result<()> {
+        self.db.insert(SNAPSHOT_KEY, serde_json::to_vec(state)?)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn index(&self) -> Result<Option<ChainIndex>> {
+        match self.db.get(INDEX_KEY)? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn save_index(&mut self, index: &ChainIndex) -> Result<()> {
+        self.db.insert(INDEX_KEY, serde_json::to_vec(index)?)?;
+        self.db.flush()?;
+        Ok(())
+    }
+}
+
+/// Bytes before each record's payload: its length and checksum.
+const RECORD_HEADER_LEN: u64 = 8;
+
+/// A record of a [`LogStore`]. The chain parameters, if set, are the first,
+/// followed by the state of a pruned chain.
+#[derive(Serialize, Deserialize)]
+enum LogRecord {
+    Params(ChainParams),
+    Block(Block),
+    State(ChainState),
+}
+
+/// [`ChainStore`] backed by a single append-only file holding one
+/// length-prefixed, checksummed record per block. Clones share the same file.
+///
+/// Blocks are only ever appended (or cut off the end when the chain
+/// reorganizes), so a crash can at worst leave a partially written last
+/// record, which [`LogStore::open`] discards. Pruning rewrites the log into a
+/// new file that then replaces it. Snapshots and the index are kept beside
+/// the log, in files named after it with `.snapshot` and `.index` appended.
+#[derive(Clone)]
+pub struct LogStore {
+    inner: Arc<Mutex<BlockLog>>,
+}
+
+struct BlockLog {
+    path: PathBuf,
+    file: File,
+    params: Option<ChainParams>,
+    state: Option<ChainState>,
+    /// File offset of each block's record, by height.
+    offsets: Vec<u64>,
+    heights: HashMap<String, u64>,
+    /// Offset just past the last complete record.
+    end: u64,
+    recovered: u64,
+}
+
+fn record_checksum(payload: &[u8]) -> [u8; 4] {
+    let mut checksum = [0u8; 4];
+    checksum.copy_from_slice(&Sha256::digest(payload)[..4]);
+    checksum
+}
+
+impl LogStore {
+    /// Opens (or creates) the log at `path`, truncating a partially written
+    /// record left at its end by a crash.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let _span = debug_span!("open_log", path = %path.display()).entered();
+        let log = BlockLog::open(path)?;
+        if log.recovered > 0 {
+            warn!(bytes = log.recovered, "discarded an unfinished write at the end of the log");
+        }
+        debug!(blocks = log.offsets.len(), "opened log");
+        Ok(LogStore {
+            inner: Arc::new(Mutex::new(log)),
+        })
+    }
+
+    /// Number of bytes of an incomplete last record dropped when the log was opened.
+    pub fn recovered_bytes(&self) -> u64 {
+        self.lock().recovered
+    }
+
+    fn lock(&self) -> MutexGuard<'_, BlockLog> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Reads the file beside the log with `suffix` appended, if it exists
+    /// and can be read, warning about the unreadable `what`.
+    fn read_sibling<T: DeserializeOwned>(&self, suffix: &str, what: &str) -> Result<Option<T>> {
+        let path = self.lock().sibling(suffix);
+        let bytes = match fs::read(&path) {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        match serde_json::from_slice(&bytes) {
+            Ok(value) => Ok(Some(value)),
+            Err(err) => {
+                warn!(path = %path.display(), %err, "ignoring an unreadable {}", what);
+                Ok(None)
+            }
+        }
+    }
+}
+
+impl BlockLog {
+    fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+        let mut log = BlockLog {
+            path: path.to_path_buf(),
+            file,
+            params: None,
+            state: None,
+            offsets: Vec::new(),
+            heights: HashMap::new(),
+            end: 0,
+            recovered: 0,
+        };
+        log.recover()?;
+        Ok(log)
+    }
+
+    /// The log's path with `suffix` appended.
+    fn sibling(&self, suffix: &str) -> PathBuf {
+        file::with_suffix(&self.path, suffix)
+    }
+
+    /// Reads the record at `offset`, or `None` if the log ends partway
+    /// through it (or it fails its checksum and nothing follows it).
+    fn read_record(&mut self, offset: u64, len: u64) -> Result<Option<(LogRecord, u64)>> {
+        if len - offset < RECORD_HEADER_LEN {
+            return Ok(None);
+        }
+        let mut header = [0u8; RECORD_HEADER_LEN as usize];
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.read_exact(&mut header)?;
+        let size = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        let next = offset + RECORD_HEADER_LEN + size;
+        if next > len {
+            return Ok(None);
+        }
+        let mut payload = vec![0u8; size as usize];
+        self.file.read_exact(&mut payload)?;
+        if record_checksum(&payload) != header[4..] {
+            if next == len {
+                return Ok(None);
+            }
+            return Err(BlockchainError::Storage(format!("corrupt record at offset {}", offset)));
+        }
+        Ok(Some((serde_json::from_slice(&payload)?, next)))
+    }
+
+    /// Indexes every complete record and cuts off anything after the last one.
+    fn recover(&mut self) -> Result<()> {
+        let len = self.file.metadata()?.len();
+        let mut offset = 0;
+        while let Some((record, next)) = self.read_record(offset, len)? {
+            match record {
+                LogRecord::Params(params) if offset == 0 => self.params = Some(params),
+                LogRecord::Params(_) => {
+                    return Err(BlockchainError::Storage(format!(
+                        "unexpected chain parameters at offset {}",
+                        offset
+                    )));
+                }
+                LogRecord::State(state) if self.offsets.is_empty() => self.state = Some(state),
+                LogRecord::State(_) => {
+                    return Err(BlockchainError::Storage(format!("unexpected chain state at offset {}", offset)));
+                }
+                LogRecord::Block(block) => {
+                    if block.index() != self.offsets.len() as u64 {
+                        return Err(BlockchainError::Storage(format!(
+                            "block #{} found where block #{} was expected",
+                            block.index(),
+                            self.offsets.len()
+                        )));
+                    }
+                    self.heights.insert(block.hash().to_string(), block.index());
+                    self.offsets.push(offset);
+                }
+            }
+            offset = next;
+        }
+        if offset < len {
+            self.file.set_len(offset)?;
+            self.file.sync_all()?;
+            self.recovered = len - offset;
+        }
+        self.end = offset;
+        Ok(())
+    }
+
+    /// Writes a record with a single write and waits for it to reach the disk.
+    fn append(&mut self, record: &LogRecord) -> Result<u64> {
+        let payload = serde_json::to_vec(record)?;
+        let size = u32::try_from(payload.len())
+            .map_err(|_| BlockchainError::Storage("record is too large for the log".to_string()))?;
+        let mut bytes = Vec::with_capacity(RECORD_HEADER_LEN as usize + payload.len());
+        bytes.extend_from_slice(&size.to_le_bytes());
+        bytes.extend_from_slice(&record_checksum(&payload));
+        bytes.extend_from_slice(&payload);
+        self.file.write_all(&bytes)?;
+        self.file.sync_data()?;
+        let offset = self.end;
+        self.end += bytes.len() as u64;
+        Ok(offset)
+    }
+
+    fn cut(&mut self, offset: u64) -> Result<()> {
+        self.file.set_len(offset)?;
+        self.file.sync_all()?;
+        self.end = offset;
+        Ok(())
+    }
+
+    fn block_at(&mut self, height: u64) -> Result<Option<Block>> {
+        let Some(&offset) = self.offsets.get(height as usize) else {
+            return Ok(None);
+        };
+        let end = self.end;
+        match self.read_record(offset, end)? {
+            Some((LogRecord::Block(block), _)) => Ok(Some(block)),
+            _ => Err(BlockchainError::Storage(format!("block at height {} is unreadable", height))),
+        }
+    }
+}
+
+impl ChainStore for LogStore {
+    fn append_block(&mut self, block: &Block) -> Result<()> {
+        let mut log = self.lock();
+        let expected = log.offsets.len() as u64;
+        if block.index() != expected {
+            return Err(BlockchainError::Storage(format!(
+                "cannot append block #{} to a store holding {} blocks",
+                block.index(),
+                expected
+            )));
+        }
+        let offset = log.append(&LogRecord::Block(block.clone()))?;
+        log.offsets.push(offset);
+        log.heights.insert(block.hash().to_string(), block.index());
+        Ok(())
+    }
+
+    fn block_by_height(&self, height: u64) -> Result<Option<Block>> {
+        self.lock().block_at(height)
+    }
+
+    fn block_by_hash(&self, hash: &str) -> Result<Option<Block>> {
+        let mut log = self.lock();
+        match log.heights.get(hash).copied() {
+            Some(height) => log.block_at(height),
+            None => Ok(None),
+        }
+    }
+
+    fn len(&self) -> Result<u64> {
+        Ok(self.lock().offsets.len() as u64)
+    }
+
+    fn truncate(&mut self, len: u64) -> Result<()> {
+        let mut log = self.lock();
+        let Some(&offset) = log.offsets.get(len as usize) else {
+            return Ok(());
+        };
+        log.cut(offset)?;
+        debug!(from = log.offsets.len(), to = len, "truncated log");
+        log.offsets.truncate(len as usize);
+        log.heights.retain(|_, height| *height < len);
+        Ok(())
+    }
+
+    fn params(&self) -> Result<Option<ChainParams>> {
+        Ok(self.lock().params.clone())
+    }
+
+    /// Parameters go at the very start of the log, so they can only be
+    /// changed while it holds no blocks.
+    fn set_params(&mut self, params: &ChainParams) -> Result<()> {
+        let mut log = self.lock();
+        if log.params.as_ref() == Some(params) {
+            return Ok(());
+        }
+        if !log.offsets.is_empty() {
+            return Err(BlockchainError::Storage(
+                "cannot change the parameters of a log that already holds blocks".to_string(),
+            ));
+        }
+        log.cut(0)?;
+        log.append(&LogRecord::Params(params.clone()))?;
+        log.params = Some(params.clone());
+        log.state = None;
+        Ok(())
+    }
+
+    fn state(&self) -> Result<Option<ChainState>> {
+        Ok(self.lock().state.clone())
+    }
+
+    /// Writes the parameters, `state` and every block (pruned up to the
+    /// state's height) to a new file, then moves it over the log.
+    fn prune(&mut self, state: &ChainState) -> Result<()> {
+        let mut log = self.lock();
+        let compacted = log.sibling(".compact");
+        let _ = fs::remove_file(&compacted);
+        let mut new = BlockLog::open(&compacted)?;
+        if let Some(params) = &log.params {
+            new.append(&LogRecord::Params(params.clone()))?;
+        }
+        new.append(&LogRecord::State(state.clone()))?;
+        for height in 0..log.offsets.len() as u64 {
+            let mut block = log
+                .block_at(height)?
+                .ok_or_else(|| BlockchainError::Storage(format!("block at height {} is missing", height)))?;
+            if height <= state.height {
+                block.prune();
+            }
+            new.append(&LogRecord::Block(block))?;
+        }
+        drop(new);
+        fs::rename(&compacted, &log.path)?;
+        let path = log.path.clone();
+        *log = BlockLog::open(&path)?;
+        debug!(height = state.height, bytes = log.end, "pruned log");
+        Ok(())
+    }
+
+    /// A snapshot that can't be read is ignored, since the chain can always
+    /// be replayed without it.
+    fn snapshot(&self) -> Result<Option<ChainState>> {
+        self.read_sibling(".snapshot", "snapshot")
+    }
+
+    fn save_snapshot(&mut self, state: &ChainState) -> Result<()> {
+        let path = self.lock().sibling(".snapshot");
+        file::write_atomic(&path, &serde_json::to_vec(state)?)?;
+        debug!(height = state.height, "saved snapshot");
+        Ok(())
+    }
+
+    /// An index that can't be read is ignored, since it can be rebuilt.
+    fn index(&self) -> Result<Option<ChainIndex>> {
+        self.read_sibling(".index", "index")
+    }
+
+    fn save_index(&mut self, index: &ChainIndex) -> Result<()> {
+        let path = self.lock().sibling(".index");
+        file::write_atomic(&path, &serde_json::to_vec(index)?)?;
+        debug!(height = index.tip().map(|(_, height)| height), "saved index");
+        Ok(())
+    }
+}
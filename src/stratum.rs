@@ -0,0 +1,438 @@
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, info_span, warn};
+
+use crate::block::{BlockHeader, BlockTemplate};
+use crate::blockchain::Blockchain;
+use crate::error::{BlockchainError, Result};
+use crate::events::ChainEvent;
+use crate::mempool::DEFAULT_BATCH_SIZE;
+use crate::miner::Miner;
+use crate::network::SharedChain;
+use crate::rpc::SharedMempool;
+use crate::sync::{lock, read, write};
+use crate::target::Target;
+
+/// Leading zero hex digits a share needs unless the block target is easier.
+pub const DEFAULT_SHARE_DIFFICULTY: usize = 3;
+/// How often the server checks whether the tip moved under the current job.
+const TIP_POLL: Duration = Duration::from_millis(200);
+/// Jobs are rebuilt this often anyway, to pick up new transactions.
+const JOB_REFRESH: Duration = Duration::from_secs(30);
+/// Why shares for a replaced job are rejected.
+const STALE: &str = "stale job";
+
+type BlockHook = Arc<dyn Fn(&Blockchain) + Send + Sync>;
+
+/// Messages between a [`StratumServer`] and its workers, sent as one JSON
+/// object per line.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data")]
+pub enum StratumMessage {
+    /// A worker's first message, naming it in the server's share counts.
+    Subscribe { worker: String },
+    /// Work for a worker, replacing its previous job. Sent on subscribing and
+    /// whenever the server builds a new block template.
+    Job(Job),
+    /// A nonce for job `job` whose hash meets the job's share target.
+    Submit { job: u64, nonce: u64 },
+    /// Reply to `Submit` for a share that counted, with the hash of the
+    /// block it found, if it met the block target too.
+    Accepted { job: u64, block: Option<String> },
+    /// Reply to `Submit` for a share that didn't count.
+    Rejected { job: u64, reason: String },
+}
+
+/// A block header to search nonces for.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: u64,
+    pub header: BlockHeader,
+    /// Hashes meeting this target are shares; the header's own target makes
+    /// a block.
+    pub share_bits: u32,
+    /// Where the worker starts searching, so workers don't repeat each
+    /// other's nonces.
+    pub nonce_start: u64,
+}
+
+/// Shares and blocks a worker has had accepted.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct WorkerStats {
+    pub name: String,
+    pub shares: u64,
+    pub blocks: u64,
+}
+
+struct Worker {
+    id: u64,
+    stream: Arc<Mutex<TcpStream>>,
+    stats: WorkerStats,
+}
+
+/// The server's current job and connected workers.
+#[derive(Default)]
+struct Pool {
+    job: Option<(u64, BlockTemplate)>,
+    next_job: u64,
+    next_worker: u64,
+    /// Nonces already submitted for the current job.
+    submitted: HashSet<u64>,
+    workers: Vec<Worker>,
+}
+
+fn send(stream: &Mutex<TcpStream>, message: &StratumMessage) -> Result<()> {
+    let mut line = serde_json::to_vec(message)?;
+    line.push(b'\n');
+    lock(stream).write_all(&line)?;
+    Ok(())
+}
+
+/// Hands out block templates to workers on other machines and accepts the
+/// blocks they find, so several machines can mine for one node.
+///
+/// Every worker gets the same template, paying the server's payout address,
+/// with its own random starting nonce. Workers submit every hash meeting the
+/// easier share target, which shows how much each contributes; shares that
+/// also meet the block target are added to the chain. A new job goes out
+/// whenever the tip changes, and every [`JOB_REFRESH`] to take in new
+/// transactions.
+#[derive(Clone)]
+pub struct StratumServer {
+    chain: SharedChain,
+    mempool: SharedMempool,
+    payout: String,
+    share_difficulty: usize,
+    pool: Arc<Mutex<Pool>>,
+    on_block: BlockHook,
+}
+
+impl StratumServer {
+    /// A server whose blocks pay `payout`.
+    pub fn new(chain: SharedChain, mempool: SharedMempool, payout: impl Into<String>) -> Self {
+        StratumServer {
+            chain,
+            mempool,
+            payout: payout.into(),
+            share_difficulty: DEFAULT_SHARE_DIFFICULTY,
+            pool: Arc::new(Mutex::new(Pool::default())),
+            on_block: Arc::new(|_| {}),
+        }
+    }
+
+    /// Requires shares to start with `digits` zero hex digits.
+    pub fn with_share_difficulty(mut self, digits: usize) -> Self {
+        self.share_difficulty = digits;
+        self
+    }
+
+    /// Registers a callback run (with the chain locked) after a worker's
+    /// block is added, e.g. to persist or broadcast it.
+    pub fn with_block_hook(mut self, hook: impl Fn(&Blockchain) + Send + Sync + 'static) -> Self {
+        self.on_block = Arc::new(hook);
+        self
+    }
+
+    /// Accepts workers on `addr` in a background thread, and keeps their job
+    /// up to date with the chain in another.
+    pub fn listen(&self, addr: impl ToSocketAddrs) -> Result<SocketAddr> {
+        self.new_job()?;
+        let listener = TcpListener::bind(addr)?;
+        let local = listener.local_addr()?;
+        let server = self.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let server = server.clone();
+                thread::spawn(move || {
+                    if let Err(err) = server.serve_worker(stream) {
+                        debug!(%err, "worker connection failed");
+                    }
+                });
+            }
+        });
+        let server = self.clone();
+        thread::spawn(move || server.watch_tip());
+        Ok(local)
+    }
+
+    pub fn workers(&self) -> Vec<WorkerStats> {
+        lock(&self.pool).workers.iter().map(|worker| worker.stats.clone()).collect()
+    }
+
+    /// Builds a template on the current tip and sends it to every worker.
+    fn new_job(&self) -> Result<()> {
+        let template = {
+            let chain = read(&self.chain);
+            let mempool = lock(&self.mempool);
+            chain.block_template(&mempool, DEFAULT_BATCH_SIZE, &self.payout)?
+        };
+        let mut pool = lock(&self.pool);
+        let id = pool.next_job;
+        pool.next_job += 1;
+        pool.job = Some((id, template));
+        pool.submitted.clear();
+        for worker in &pool.workers {
+            if let Err(err) = send(&worker.stream, &self.job_message(&pool)) {
+                debug!(%err, "failed to send a job");
+            }
+        }
+        debug!(id, "new mining job");
+        Ok(())
+    }
+
+    /// The current job, with a fresh starting nonce.
+    fn job_message(&self, pool: &Pool) -> StratumMessage {
+        let (id, template) = pool.job.as_ref().expect("the server builds a job before accepting workers");
+        let header = template.header().clone();
+        StratumMessage::Job(Job {
+            id: *id,
+            share_bits: self.share_target(&header).to_bits(),
+            header,
+            nonce_start: OsRng.next_u64(),
+        })
+    }
+
+    /// The easier of the share difficulty and the block target, as rounded
+    /// by the compact form jobs carry it in.
+    fn share_target(&self, header: &BlockHeader) -> Target {
+        Target::from_bits(Target::from_leading_zeros(self.share_difficulty).max(header.target()).to_bits())
+    }
+
+    fn watch_tip(&self) {
+        let mut refreshed = Instant::now();
+        loop {
+            thread::sleep(TIP_POLL);
+            let tip = read(&self.chain).latest_block().hash().to_string();
+            let stale = lock(&self.pool)
+                .job
+                .as_ref()
+                .is_none_or(|(_, template)| template.header().previous_hash() != tip);
+            if stale || refreshed.elapsed() >= JOB_REFRESH {
+                if let Err(err) = self.new_job() {
+                    warn!(%err, "failed to build a mining job");
+                }
+                refreshed = Instant::now();
+            }
+        }
+    }
+
+    fn serve_worker(&self, stream: TcpStream) -> Result<()> {
+        let addr = stream.peer_addr()?;
+        let _span = info_span!("worker", %addr).entered();
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let Ok(StratumMessage::Subscribe { worker: name }) = serde_json::from_str(&line) else {
+            let _ = stream.shutdown(Shutdown::Both);
+            return Err(BlockchainError::Validation(format!("worker {} did not subscribe", addr)));
+        };
+        let stream = Arc::new(Mutex::new(stream));
+        let id = {
+            let mut pool = lock(&self.pool);
+            let id = pool.next_worker;
+            pool.next_worker += 1;
+            send(&stream, &self.job_message(&pool))?;
+            let stats = WorkerStats {
+                name: name.clone(),
+                ..WorkerStats::default()
+            };
+            pool.workers.push(Worker {
+                id,
+                stream: Arc::clone(&stream),
+                stats,
+            });
+            id
+        };
+        info!(%name, "worker subscribed");
+        let served = self.read_submissions(id, &stream, reader);
+        lock(&self.pool).workers.retain(|worker| worker.id != id);
+        info!(%name, "worker disconnected");
+        served
+    }
+
+    fn read_submissions(&self, worker: u64, stream: &Mutex<TcpStream>, reader: BufReader<TcpStream>) -> Result<()> {
+        for line in reader.lines() {
+            let reply = match serde_json::from_str(&line?)? {
+                StratumMessage::Submit { job, nonce } => match self.submit(worker, job, nonce) {
+                    Ok(block) => StratumMessage::Accepted { job, block },
+                    Err(reason) => StratumMessage::Rejected { job, reason },
+                },
+                _ => continue,
+            };
+            send(stream, &reply)?;
+        }
+        Ok(())
+    }
+
+    /// Checks a share and, if it solves the block, adds the block to the
+    /// chain, returning its hash.
+    fn submit(&self, worker: u64, job: u64, nonce: u64) -> std::result::Result<Option<String>, String> {
+        let block = {
+            let mut pool = lock(&self.pool);
+            let Some((current, template)) = &pool.job else {
+                return Err("no job".to_string());
+            };
+            if *current != job {
+                return Err(STALE.to_string());
+            }
+            let block = template.complete(nonce);
+            if !self.share_target(block.header()).is_met_by(block.hash()) && !block.meets_target() {
+                return Err("share does not meet the share target".to_string());
+            }
+            if !pool.submitted.insert(nonce) {
+                return Err("duplicate share".to_string());
+            }
+            if let Some(worker) = pool.workers.iter_mut().find(|entry| entry.id == worker) {
+                worker.stats.shares += 1;
+            }
+            block
+        };
+        if !block.meets_target() {
+            return Ok(None);
+        }
+
+        let hash = block.hash().to_string();
+        {
+            let mut chain = write(&self.chain);
+            let events = chain.accept_block(block.clone()).map_err(|err| err.to_string())?;
+            if events.iter().any(|event| matches!(event, ChainEvent::BlockConnected(_))) {
+                let mut mempool = lock(&self.mempool);
+                mempool.remove_batch(&block.transactions()[1..]);
+                mempool.expire(chain.height());
+                drop(mempool);
+                (self.on_block)(&chain);
+            }
+        }
+        if let Some(worker) = lock(&self.pool).workers.iter_mut().find(|entry| entry.id == worker) {
+            worker.stats.blocks += 1;
+        }
+        info!(%hash, index = block.index(), "worker found a block");
+        if let Err(err) = self.new_job() {
+            warn!(%err, "failed to build a mining job");
+        }
+        Ok(Some(hash))
+    }
+}
+
+/// Mines for a [`StratumServer`], searching each job it sends for shares
+/// with its [`Miner`] until the server disconnects or the miner's cancel
+/// token fires.
+pub struct StratumWorker {
+    name: String,
+    miner: Miner,
+}
+
+impl StratumWorker {
+    pub fn new(name: impl Into<String>, miner: Miner) -> Self {
+        StratumWorker {
+            name: name.into(),
+            miner,
+        }
+    }
+
+    /// Mines for the server at `addr`, returning the shares and blocks it
+    /// accepted.
+    pub fn run(&self, addr: impl ToSocketAddrs) -> Result<WorkerStats> {
+        let stream = TcpStream::connect(addr)?;
+        let writer = Mutex::new(stream.try_clone()?);
+        send(&writer, &StratumMessage::Subscribe { worker: self.name.clone() })?;
+        let (messages, received) = mpsc::channel();
+        let reader = BufReader::new(stream.try_clone()?);
+        thread::spawn(move || {
+            for line in reader.lines() {
+                let Ok(Ok(message)) = line.map(|line| serde_json::from_str::<StratumMessage>(&line)) else {
+                    break;
+                };
+                if messages.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut stats = WorkerStats {
+            name: self.name.clone(),
+            ..WorkerStats::default()
+        };
+        let cancel = self.miner.cancel_token().clone();
+        // The job being searched, and the nonce to search from.
+        let mut current: Option<(Job, u64)> = None;
+        let mut search: Option<thread::JoinHandle<Result<(BlockHeader, String)>>> = None;
+        let stop_search = |search: &mut Option<thread::JoinHandle<_>>| {
+            if let Some(handle) = search.take() {
+                cancel.cancel();
+                let _ = handle.join();
+                cancel.reset();
+            }
+        };
+        loop {
+            if cancel.is_cancelled() {
+                break;
+            }
+            if search.is_none()
+                && let Some((job, start)) = &current
+            {
+                let miner = self.miner.clone().with_nonce_start({
+                    let start = *start;
+                    move |_| start
+                });
+                let (header, target) = (job.header.clone(), Target::from_bits(job.share_bits));
+                search = Some(thread::spawn(move || miner.mine_to(header, target)));
+            }
+            match received.recv_timeout(Duration::from_millis(20)) {
+                Ok(StratumMessage::Job(job)) => {
+                    stop_search(&mut search);
+                    debug!(id = job.id, index = job.header.index(), "new job");
+                    let start = job.nonce_start;
+                    current = Some((job, start));
+                }
+                Ok(StratumMessage::Accepted { job, block }) => {
+                    stats.shares += 1;
+                    match block {
+                        Some(hash) => {
+                            stats.blocks += 1;
+                            info!(job, %hash, "found a block");
+                        }
+                        None => debug!(job, "share accepted"),
+                    }
+                }
+                // Shares found just before a new job arrives are expected to be stale.
+                Ok(StratumMessage::Rejected { job, reason }) if reason == STALE => debug!(job, "stale share"),
+                Ok(StratumMessage::Rejected { job, reason }) => warn!(job, %reason, "share rejected"),
+                Ok(_) => {}
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    stop_search(&mut search);
+                    info!("server disconnected");
+                    break;
+                }
+            }
+            if search.as_ref().is_some_and(|handle| handle.is_finished())
+                && let Some(handle) = search.take()
+                && let Some((job, start)) = &mut current
+            {
+                match handle.join() {
+                    Ok(Ok((header, _))) => {
+                        send(&writer, &StratumMessage::Submit { job: job.id, nonce: header.nonce() })?;
+                        *start = header.nonce().wrapping_add(1);
+                    }
+                    Ok(Err(BlockchainError::Cancelled)) => break,
+                    Ok(Err(err)) => {
+                        // Nothing left to search until the next job.
+                        warn!(%err, "job exhausted");
+                        current = None;
+                    }
+                    Err(_) => return Err(BlockchainError::Mining("mining thread panicked".to_string())),
+                }
+            }
+        }
+        let _ = stream.shutdown(Shutdown::Both);
+        Ok(stats)
+    }
+}
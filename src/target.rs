@@ -0,0 +1,119 @@
+use std::fmt;
+
+mod u256 {
+    // The macro's expansion trips lints that are not ours to fix.
+    #![allow(clippy::all)]
+
+    uint::construct_uint! {
+        /// 256-bit unsigned integer, big enough for a SHA-256 hash.
+        pub(super) struct U256(4);
+    }
+}
+
+use u256::U256;
+
+/// What [`Target::to_bits`] encodes [`Target::MAX`] as: its top two bytes,
+/// as the top bit of three would be read as a sign.
+const MAX_BITS: u32 = 0x2100_ffff;
+
+/// A proof-of-work target: a block is valid if its hash, read as a 256-bit
+/// big-endian number, does not exceed the target. Headers carry it in the
+/// compact "bits" form used by Bitcoin, which keeps the top three bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Target(U256);
+
+impl Target {
+    /// The easiest possible target, met by every hash.
+    pub const MAX: Target = Target(U256::MAX);
+
+    /// The target met by hashes starting with `digits` zero hex digits,
+    /// which takes about 16^digits attempts to hit.
+    pub fn from_leading_zeros(digits: usize) -> Target {
+        match digits.checked_mul(4).and_then(|bits| 256usize.checked_sub(bits)) {
+            Some(256) => Target::MAX,
+            Some(bits) => Target((U256::one() << bits) - 1),
+            None => Target(U256::zero()),
+        }
+    }
+
+    /// Decodes the compact form: the top byte is the length of the target
+    /// in bytes and the low 23 bits its most significant digits. Negative
+    /// targets decode as zero and overflowing ones as [`Target::MAX`], as
+    /// does the form `MAX` itself rounds down to, so it survives the trip.
+    pub fn from_bits(bits: u32) -> Target {
+        if bits == MAX_BITS {
+            return Target::MAX;
+        }
+        let size = bits >> 24;
+        let mantissa = U256::from(bits & 0x007f_ffff);
+        if bits & 0x0080_0000 != 0 || mantissa.is_zero() {
+            return Target(U256::zero());
+        }
+        if size <= 3 {
+            return Target(mantissa >> (8 * (3 - size) as usize));
+        }
+        let shift = 8 * (size - 3) as usize;
+        if mantissa.bits() + shift > 256 {
+            return Target::MAX;
+        }
+        Target(mantissa << shift)
+    }
+
+    /// Encodes the target in compact form, rounding it down to three
+    /// significant bytes.
+    pub fn to_bits(&self) -> u32 {
+        let mut size = self.0.bits().div_ceil(8) as u32;
+        let mut mantissa = if size <= 3 {
+            self.0.low_u32() << (8 * (3 - size))
+        } else {
+            (self.0 >> (8 * (size - 3) as usize)).low_u32()
+        };
+        // The mantissa's top bit is a sign bit, so move it into the next byte.
+        if mantissa & 0x0080_0000 != 0 {
+            mantissa >>= 8;
+            size += 1;
+        }
+        mantissa | (size << 24)
+    }
+
+    /// Whether `hash`, a 64-digit hex SHA-256 digest, meets the target.
+    pub fn is_met_by(&self, hash: &str) -> bool {
+        hash.len() == 64 && U256::from_str_radix(hash, 16).is_ok_and(|value| value <= self.0)
+    }
+
+    /// Whether a SHA-256 digest meets the target.
+    pub fn is_met_by_digest(&self, digest: &[u8; 32]) -> bool {
+        U256::from_big_endian(digest) <= self.0
+    }
+
+    /// Expected number of hashes needed to meet the target, 2^256 / (target + 1),
+    /// saturating at `u128::MAX`.
+    pub fn work(&self) -> u128 {
+        if self.0.is_zero() {
+            return u128::MAX;
+        }
+        if self.0 == U256::MAX {
+            return 1;
+        }
+        // 2^256 does not fit, but (2^256 - target - 1) / (target + 1) + 1 is equal.
+        let work = (!self.0 / (self.0 + 1)) + 1;
+        if work.bits() > 128 { u128::MAX } else { work.as_u128() }
+    }
+
+    /// The target multiplied by `numerator / denominator`, capped at `limit`.
+    pub fn scale(&self, numerator: u128, denominator: u128, limit: Target) -> Target {
+        let (numerator, denominator) = (U256::from(numerator), U256::from(denominator.max(1)));
+        let scaled = match self.0.checked_mul(numerator) {
+            Some(product) => product / denominator,
+            None => (self.0 / denominator).saturating_mul(numerator),
+        };
+        Target(scaled.min(limit.0))
+    }
+}
+
+impl fmt::Display for Target {
+    /// Formats as 64 hex digits, comparable with block hashes.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(self.0.to_big_endian()))
+    }
+}
@@ -0,0 +1,540 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::amount::Amount;
+use crate::error::{BlockchainError, Result};
+use crate::script::Script;
+use crate::utxo::{OutPoint, TxOutput};
+
+/// Sender used by coinbase transactions, which mint the block reward.
+pub const COINBASE_SENDER: &str = "COINBASE";
+
+/// Lock times below this are block heights; from it on, timestamps in
+/// milliseconds.
+pub const LOCK_TIME_THRESHOLD: u64 = 500_000_000;
+
+/// Longest memo a transaction may carry, in bytes.
+pub const MAX_MEMO_LEN: usize = 80;
+
+/// A transfer of `amount` from `sender` to `receiver`, paying an optional
+/// `fee` to the miner that confirms it.
+///
+/// In the account model `inputs` is empty and the sender's balance is simply
+/// debited `amount + fee`. A UTXO-style transaction additionally names the
+/// previous outputs it spends; their total must equal `amount + change + fee`,
+/// with `change` paid back to the sender as a second output.
+///
+/// Account-model transactions also carry the sender's `sequence` number:
+/// each sender's first transfer uses 0 and every later one the next number,
+/// so a transaction already confirmed can't be replayed.
+///
+/// A transaction naming an `asset` moves that token instead of the native
+/// coin, or with `issue` set creates it; see [`Asset`](crate::Asset).
+///
+/// One transaction may pay several receivers: `payments` lists any after
+/// the first, each paid by an output of its own, and the sender is debited
+/// their total.
+///
+/// A nonzero `lock_time` keeps the transaction out of blocks until the chain
+/// reaches that height or time; see [`Transaction::is_final`].
+///
+/// A `memo` of up to [`MAX_MEMO_LEN`] bytes is committed to by the hash but
+/// otherwise ignored, so the chain can timestamp arbitrary data. JSON carries
+/// it in hex.
+///
+/// The payment output may be locked with a [`Script`], and UTXO-style
+/// transactions carry one unlocking script per input for the locked outputs
+/// they spend.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Transaction {
+    sender: String,
+    receiver: String,
+    amount: Amount,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    payments: Vec<Payment>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    inputs: Vec<OutPoint>,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    change: Amount,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    fee: Amount,
+    /// Height of the block a coinbase belongs to, so that otherwise identical
+    /// coinbases in different blocks have distinct hashes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    height: Option<u64>,
+    #[serde(default, skip_serializing_if = "is_zero_u64")]
+    sequence: u64,
+    #[serde(default, skip_serializing_if = "is_zero_u64")]
+    lock_time: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    asset: Option<String>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    issue: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "hex_memo")]
+    memo: Option<Vec<u8>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    lock: Option<Script>,
+    /// By input; inputs past the end have empty unlocking scripts.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    unlocks: Vec<Script>,
+}
+
+/// A further receiver of a transaction and the amount paid to it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Payment {
+    pub receiver: String,
+    pub amount: Amount,
+}
+
+fn is_zero(value: &Amount) -> bool {
+    value.is_zero()
+}
+
+fn is_zero_u64(value: &u64) -> bool {
+    *value == 0
+}
+
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// Memos in hex, since JSON has no byte strings.
+mod hex_memo {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(memo: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error> {
+        memo.as_ref().map(hex::encode).serialize(serializer)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|memo| hex::decode(memo).map_err(serde::de::Error::custom))
+            .transpose()
+    }
+}
+
+impl Transaction {
+    pub fn new(sender: impl Into<String>, receiver: impl Into<String>, amount: Amount) -> Self {
+        Transaction {
+            sender: sender.into(),
+            receiver: receiver.into(),
+            amount,
+            payments: Vec::new(),
+            inputs: Vec::new(),
+            change: Amount::ZERO,
+            fee: Amount::ZERO,
+            height: None,
+            sequence: 0,
+            lock_time: 0,
+            asset: None,
+            issue: false,
+            memo: None,
+            lock: None,
+            unlocks: Vec::new(),
+        }
+    }
+
+    /// A UTXO-style transaction spending `inputs`, which must all belong to
+    /// `sender` and add up to `amount + change` (plus any fee).
+    pub fn spending(
+        sender: impl Into<String>,
+        receiver: impl Into<String>,
+        amount: Amount,
+        inputs: Vec<OutPoint>,
+        change: Amount,
+    ) -> Self {
+        Transaction {
+            inputs,
+            change,
+            ..Transaction::new(sender, receiver, amount)
+        }
+    }
+
+    /// Issues `amount` of `asset` to `issuer`, creating the asset if it does
+    /// not exist yet.
+    pub fn issue(issuer: impl Into<String>, asset: impl Into<String>, amount: Amount) -> Self {
+        let issuer = issuer.into();
+        Transaction {
+            issue: true,
+            ..Transaction::new(issuer.clone(), issuer, amount).with_asset(asset)
+        }
+    }
+
+    /// Also pays `amount` to `receiver`, in an output after the earlier ones.
+    pub fn with_payment(mut self, receiver: impl Into<String>, amount: Amount) -> Self {
+        self.payments.push(Payment {
+            receiver: receiver.into(),
+            amount,
+        });
+        self
+    }
+
+    /// Sends `amount` of `asset` rather than of the native coin.
+    pub fn with_asset(mut self, asset: impl Into<String>) -> Self {
+        self.asset = Some(asset.into());
+        self
+    }
+
+    /// Sets the fee offered to the miner.
+    pub fn with_fee(mut self, fee: Amount) -> Self {
+        self.fee = fee;
+        self
+    }
+
+    /// Sets the sender's sequence number; see [`Transaction::is_sequenced`].
+    pub fn with_sequence(mut self, sequence: u64) -> Self {
+        self.sequence = sequence;
+        self
+    }
+
+    /// Sets the height (below [`LOCK_TIME_THRESHOLD`]) or time (from it on)
+    /// before which the transaction can't be mined.
+    pub fn with_lock_time(mut self, lock_time: u64) -> Self {
+        self.lock_time = lock_time;
+        self
+    }
+
+    /// Attaches up to [`MAX_MEMO_LEN`] bytes of data.
+    pub fn with_memo(mut self, memo: impl Into<Vec<u8>>) -> Self {
+        self.memo = Some(memo.into());
+        self
+    }
+
+    /// Locks the payment output with `script`.
+    pub fn with_lock(mut self, script: Script) -> Self {
+        self.lock = Some(script);
+        self
+    }
+
+    /// Sets the unlocking scripts of the inputs, in order. They are not
+    /// covered by [`Transaction::signature_hash`], so can be added last.
+    pub fn with_unlocks(mut self, unlocks: Vec<Script>) -> Self {
+        self.unlocks = unlocks;
+        self
+    }
+
+    pub fn coinbase(miner: impl Into<String>, reward: Amount, height: u64) -> Self {
+        Transaction {
+            height: Some(height),
+            ..Transaction::new(COINBASE_SENDER, miner, reward)
+        }
+    }
+
+    /// Rebuilds a transaction field by field, e.g. from an export.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn from_parts(
+        sender: String,
+        receiver: String,
+        amount: Amount,
+        payments: Vec<Payment>,
+        inputs: Vec<OutPoint>,
+        change: Amount,
+        fee: Amount,
+        height: Option<u64>,
+        sequence: u64,
+        lock_time: u64,
+        asset: Option<String>,
+        issue: bool,
+        memo: Option<Vec<u8>>,
+        lock: Option<Script>,
+        unlocks: Vec<Script>,
+    ) -> Self {
+        Transaction {
+            sender,
+            receiver,
+            amount,
+            payments,
+            inputs,
+            change,
+            fee,
+            height,
+            sequence,
+            lock_time,
+            asset,
+            issue,
+            memo,
+            lock,
+            unlocks,
+        }
+    }
+
+    pub fn is_coinbase(&self) -> bool {
+        self.sender == COINBASE_SENDER
+    }
+
+    pub fn sender(&self) -> &str {
+        &self.sender
+    }
+
+    pub fn receiver(&self) -> &str {
+        &self.receiver
+    }
+
+    pub fn amount(&self) -> Amount {
+        self.amount
+    }
+
+    /// The receivers paid after the first.
+    pub fn payments(&self) -> &[Payment] {
+        &self.payments
+    }
+
+    /// Every receiver and the amount paid to it, the first receiver first.
+    pub fn recipients(&self) -> impl Iterator<Item = (&str, Amount)> {
+        std::iter::once((self.receiver.as_str(), self.amount))
+            .chain(self.payments.iter().map(|payment| (payment.receiver.as_str(), payment.amount)))
+    }
+
+    /// What the transaction pays `address` across all its receivers.
+    pub fn amount_to(&self, address: &str) -> Amount {
+        self.recipients()
+            .filter(|(receiver, _)| *receiver == address)
+            .fold(Amount::ZERO, |total, (_, amount)| total.saturating_add(amount))
+    }
+
+    /// The amount paid to all receivers together. Fails if the sum
+    /// overflows.
+    pub fn total_amount(&self) -> Result<Amount> {
+        self.payments
+            .iter()
+            .try_fold(self.amount, |total, payment| total.try_add(payment.amount, "transaction payments"))
+    }
+
+    /// The sender, unless the transaction is a coinbase, and every receiver,
+    /// each once.
+    pub fn parties(&self) -> Vec<&str> {
+        let mut parties: Vec<&str> = Vec::new();
+        let sender = (!self.is_coinbase()).then_some(self.sender.as_str());
+        for party in sender.into_iter().chain(self.recipients().map(|(receiver, _)| receiver)) {
+            if !parties.contains(&party) {
+                parties.push(party);
+            }
+        }
+        parties
+    }
+
+    pub fn inputs(&self) -> &[OutPoint] {
+        &self.inputs
+    }
+
+    pub fn change(&self) -> Amount {
+        self.change
+    }
+
+    pub fn fee(&self) -> Amount {
+        self.fee
+    }
+
+    /// What the sender gives up in the native coin: the amounts sent plus the
+    /// fee, or just the fee if the transaction moves an asset. Fails if the
+    /// sum overflows.
+    pub fn cost(&self) -> Result<Amount> {
+        match self.asset {
+            Some(_) => Ok(self.fee),
+            None => self.total_amount()?.try_add(self.fee, "transaction amount plus fee"),
+        }
+    }
+
+    /// Serialized size in bytes, the denominator of the fee rate.
+    pub fn size(&self) -> usize {
+        serde_json::to_vec(self).map_or(0, |bytes| bytes.len())
+    }
+
+    /// Block height committed to by a coinbase; `None` for other transactions.
+    pub fn height(&self) -> Option<u64> {
+        self.height
+    }
+
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    pub fn lock_time(&self) -> u64 {
+        self.lock_time
+    }
+
+    /// Whether the transaction may go in a block at `height` stamped
+    /// `timestamp`: its lock time is 0, or a height or time the block has
+    /// reached.
+    pub fn is_final(&self, height: u64, timestamp: u128) -> bool {
+        match self.lock_time {
+            0 => true,
+            lock_time if lock_time < LOCK_TIME_THRESHOLD => height >= lock_time,
+            lock_time => timestamp >= u128::from(lock_time),
+        }
+    }
+
+    /// The asset moved or issued; `None` for the native coin.
+    pub fn asset(&self) -> Option<&str> {
+        self.asset.as_deref()
+    }
+
+    pub fn is_issue(&self) -> bool {
+        self.issue
+    }
+
+    pub fn memo(&self) -> Option<&[u8]> {
+        self.memo.as_deref()
+    }
+
+    /// Fails if the memo is longer than [`MAX_MEMO_LEN`].
+    pub(crate) fn check_memo(&self) -> Result<()> {
+        if self.memo().is_some_and(|memo| memo.len() > MAX_MEMO_LEN) {
+            return Err(BlockchainError::Validation(format!(
+                "memo is longer than {} bytes",
+                MAX_MEMO_LEN
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn lock(&self) -> Option<&Script> {
+        self.lock.as_ref()
+    }
+
+    pub fn unlocks(&self) -> &[Script] {
+        &self.unlocks
+    }
+
+    /// Whether the transaction is ordered by its sender's sequence number:
+    /// true for account-model transfers. Coinbases are unique by height and
+    /// UTXO-style transactions by the outputs they spend.
+    pub fn is_sequenced(&self) -> bool {
+        !self.is_coinbase() && self.inputs.is_empty()
+    }
+
+    /// Native-coin outputs created by this transaction: the payment to each
+    /// receiver in order, only the first locked by any script, followed by
+    /// any change returned to the sender. Asset transactions create none.
+    pub fn outputs(&self) -> Vec<TxOutput> {
+        if self.asset.is_some() {
+            return Vec::new();
+        }
+        let mut outputs = vec![TxOutput {
+            owner: self.receiver.clone(),
+            amount: self.amount,
+            script: self.lock.clone(),
+        }];
+        outputs.extend(self.payments.iter().map(|payment| TxOutput {
+            owner: payment.receiver.clone(),
+            amount: payment.amount,
+            script: None,
+        }));
+        if !self.change.is_zero() {
+            outputs.push(TxOutput {
+                owner: self.sender.clone(),
+                amount: self.change,
+                script: None,
+            });
+        }
+        outputs
+    }
+
+    /// The transaction ID: SHA-256 of the transaction's fields, each
+    /// length-prefixed so that different field splits can never collide.
+    pub fn hash(&self) -> String {
+        self.digest(true)
+    }
+
+    /// What signatures in unlocking scripts sign: the ID the transaction
+    /// would have without its unlocking scripts, bound to the chain with
+    /// `chain_id` so that it can't be replayed on another network.
+    pub fn signature_hash(&self, chain_id: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update((chain_id.len() as u64).to_be_bytes());
+        hasher.update(chain_id);
+        hasher.update(self.digest(false));
+        format!("{:x}", hasher.finalize())
+    }
+
+    fn digest(&self, with_unlocks: bool) -> String {
+        let mut hasher = Sha256::new();
+        for field in [&self.sender, &self.receiver] {
+            hasher.update((field.len() as u64).to_be_bytes());
+            hasher.update(field);
+        }
+        hasher.update(self.amount.units().to_be_bytes());
+        // Optional fields only enter the hash when set, so older transactions
+        // keep their hashes, and each after a tag of its own, so no two can be
+        // mistaken for one another.
+        if !self.inputs.is_empty() {
+            hasher.update((self.inputs.len() as u64).to_be_bytes());
+            for input in &self.inputs {
+                hasher.update((input.txid.len() as u64).to_be_bytes());
+                hasher.update(&input.txid);
+                hasher.update(input.vout.to_be_bytes());
+            }
+            hasher.update(self.change.units().to_be_bytes());
+        }
+        if !self.fee.is_zero() {
+            hasher.update(b"f");
+            hasher.update(self.fee.units().to_be_bytes());
+        }
+        if let Some(height) = self.height {
+            hasher.update(b"h");
+            hasher.update(height.to_be_bytes());
+        }
+        if self.sequence > 0 {
+            hasher.update(b"s");
+            hasher.update(self.sequence.to_be_bytes());
+        }
+        if self.lock_time > 0 {
+            hasher.update(b"t");
+            hasher.update(self.lock_time.to_be_bytes());
+        }
+        if let Some(asset) = &self.asset {
+            hasher.update(if self.issue { b"i" } else { b"a" });
+            hasher.update((asset.len() as u64).to_be_bytes());
+            hasher.update(asset);
+        } else if self.issue {
+            hasher.update(b"i");
+        }
+        if let Some(memo) = &self.memo {
+            hasher.update(b"m");
+            hasher.update((memo.len() as u64).to_be_bytes());
+            hasher.update(memo);
+        }
+        if let Some(lock) = &self.lock {
+            let bytes = lock.to_bytes();
+            hasher.update(b"l");
+            hasher.update((bytes.len() as u64).to_be_bytes());
+            hasher.update(bytes);
+        }
+        if !self.payments.is_empty() {
+            hasher.update(b"p");
+            hasher.update((self.payments.len() as u64).to_be_bytes());
+            for payment in &self.payments {
+                hasher.update((payment.receiver.len() as u64).to_be_bytes());
+                hasher.update(&payment.receiver);
+                hasher.update(payment.amount.units().to_be_bytes());
+            }
+        }
+        if with_unlocks && !self.unlocks.is_empty() {
+            hasher.update(b"u");
+            hasher.update((self.unlocks.len() as u64).to_be_bytes());
+            for unlock in &self.unlocks {
+                let bytes = unlock.to_bytes();
+                hasher.update((bytes.len() as u64).to_be_bytes());
+                hasher.update(bytes);
+            }
+        }
+        format!("{:x}", hasher.finalize())
+    }
+}
+
+/// A lock time in words: `block #N` or `time T`.
+pub fn describe_lock_time(lock_time: u64) -> String {
+    if lock_time < LOCK_TIME_THRESHOLD {
+        format!("block #{}", lock_time)
+    } else {
+        format!("time {}", lock_time)
+    }
+}
+
+/// A memo as quoted text if it is printable UTF-8, otherwise as `0x` and hex.
+pub fn describe_memo(memo: &[u8]) -> String {
+    match std::str::from_utf8(memo) {
+        Ok(text) if !text.chars().any(char::is_control) => format!("{:?}", text),
+        _ => format!("0x{}", hex::encode(memo)),
+    }
+}
@@ -0,0 +1,397 @@
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::amount::Amount;
+use crate::asset::{self, Asset};
+use crate::block::Block;
+use crate::error::{BlockchainError, Result};
+use crate::script::{self, Script};
+use crate::sigcache::SignatureCache;
+use crate::transaction::Transaction;
+use crate::validation::{TxCheck, TxError};
+
+/// Reference to one output of an earlier transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct OutPoint {
+    pub txid: String,
+    pub vout: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxOutput {
+    pub owner: String,
+    pub amount: Amount,
+    /// Further condition on spending the output; see [`Script`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script: Option<Script>,
+}
+
+/// The set of outputs created on the chain that have not been spent yet.
+///
+/// Every confirmed native-coin transaction creates outputs, but only
+/// UTXO-style transactions (those with inputs) consume them. The set also
+/// remembers the ID of every transaction applied to it, so none can be
+/// confirmed twice, the next sequence number of every account-model sender,
+/// and the issued assets with their balances.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(from = "StoredUtxoSet", into = "StoredUtxoSet")]
+pub struct UtxoSet {
+    outputs: HashMap<OutPoint, TxOutput>,
+    txids: HashSet<String>,
+    sequences: HashMap<String, u64>,
+    assets: BTreeMap<String, Asset>,
+    /// Heights of the coinbase transactions after genesis whose reward is
+    /// unspent, by ID.
+    coinbases: HashMap<String, u64>,
+}
+
+/// Serialized form of a [`UtxoSet`]: outputs as a list, since their keys
+/// are not strings.
+#[derive(Serialize, Deserialize)]
+struct StoredUtxoSet {
+    outputs: Vec<(OutPoint, TxOutput)>,
+    txids: Vec<String>,
+    sequences: HashMap<String, u64>,
+    #[serde(default)]
+    assets: BTreeMap<String, Asset>,
+    #[serde(default)]
+    coinbases: HashMap<String, u64>,
+}
+
+impl From<StoredUtxoSet> for UtxoSet {
+    fn from(stored: StoredUtxoSet) -> Self {
+        UtxoSet {
+            outputs: stored.outputs.into_iter().collect(),
+            txids: stored.txids.into_iter().collect(),
+            sequences: stored.sequences,
+            assets: stored.assets,
+            coinbases: stored.coinbases,
+        }
+    }
+}
+
+impl From<UtxoSet> for StoredUtxoSet {
+    fn from(utxos: UtxoSet) -> Self {
+        StoredUtxoSet {
+            outputs: utxos.outputs.into_iter().collect(),
+            txids: utxos.txids.into_iter().collect(),
+            sequences: utxos.sequences,
+            assets: utxos.assets,
+            coinbases: utxos.coinbases,
+        }
+    }
+}
+
+impl UtxoSet {
+    pub fn new() -> Self {
+        UtxoSet::default()
+    }
+
+    /// Replays `blocks` of the chain with `chain_id` in order, failing on
+    /// the first invalid spend.
+    pub fn from_blocks(blocks: &[Block], chain_id: &str) -> Result<Self> {
+        let mut utxos = UtxoSet::new();
+        for block in blocks {
+            utxos.apply_block(block, chain_id)?;
+        }
+        Ok(utxos)
+    }
+
+    pub fn get(&self, outpoint: &OutPoint) -> Option<&TxOutput> {
+        self.outputs.get(outpoint)
+    }
+
+    pub fn len(&self) -> usize {
+        self.outputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+
+    /// Unspent outputs owned by `owner`, in no particular order.
+    pub fn outputs_for<'a>(&'a self, owner: &'a str) -> impl Iterator<Item = (&'a OutPoint, &'a TxOutput)> + 'a {
+        self.outputs.iter().filter(move |(_, output)| output.owner == owner)
+    }
+
+    /// Whether a transaction with this ID has been applied.
+    pub fn contains_transaction(&self, txid: &str) -> bool {
+        self.txids.contains(txid)
+    }
+
+    /// The sequence number `sender`'s next account-model transaction must use.
+    pub fn next_sequence(&self, sender: &str) -> u64 {
+        self.sequences.get(sender).copied().unwrap_or_default()
+    }
+
+    pub fn asset(&self, name: &str) -> Option<&Asset> {
+        self.assets.get(name)
+    }
+
+    pub fn assets(&self) -> &BTreeMap<String, Asset> {
+        &self.assets
+    }
+
+    /// How much of each asset `address` holds, leaving out empty balances.
+    pub fn asset_balances(&self, address: &str) -> BTreeMap<String, Amount> {
+        self.assets
+            .iter()
+            .map(|(name, asset)| (name.clone(), asset.balance_of(address)))
+            .filter(|&(_, balance)| !balance.is_zero())
+            .collect()
+    }
+
+    /// Checks the asset side of a transaction: an issuance must come from the
+    /// asset's issuer, and a transfer must not send more of it than the
+    /// sender holds beyond `pending`. Assets only move in account-model
+    /// transactions without scripts.
+    pub fn check_asset(&self, tx: &Transaction, pending: Amount) -> std::result::Result<(), TxError> {
+        let invalid = |message: String| TxError::new(TxCheck::Asset, message);
+        let Some(name) = tx.asset() else {
+            if tx.is_issue() {
+                return Err(invalid("issuance names no asset".to_string()));
+            }
+            return Ok(());
+        };
+        asset::validate_name(name).map_err(|err| TxError::from_error(TxCheck::Asset, err))?;
+        if tx.is_coinbase() || !tx.inputs().is_empty() || tx.lock().is_some() {
+            return Err(invalid(format!(
+                "asset {} can only move in account-model transactions without scripts",
+                name
+            )));
+        }
+        if !tx.payments().is_empty() {
+            return Err(invalid(format!("asset {} can only be sent to one receiver", name)));
+        }
+        let amount = tx.amount();
+        let asset = self.assets.get(name);
+        if tx.is_issue() {
+            if let Some(asset) = asset
+                && asset.issuer != tx.sender()
+            {
+                return Err(invalid(format!(
+                    "asset {} was issued by {}, not {}",
+                    name,
+                    asset.issuer,
+                    tx.sender()
+                )));
+            }
+            if asset.map_or(Amount::ZERO, |asset| asset.supply).checked_add(amount).is_none() {
+                return Err(invalid(format!("supply of asset {} would overflow", name)));
+            }
+            return Ok(());
+        }
+        let asset = asset.ok_or_else(|| invalid(format!("unknown asset {}", name)))?;
+        let available = asset.balance_of(tx.sender()).saturating_sub(pending);
+        if amount > available {
+            return Err(invalid(format!(
+                "insufficient {} balance: {} has {} available but tried to send {}",
+                name,
+                tx.sender(),
+                available,
+                amount
+            )));
+        }
+        Ok(())
+    }
+
+    /// Checks that the transaction has not been applied before, that an
+    /// account-model transaction uses its sender's next sequence number,
+    /// that any asset it names may move (see [`UtxoSet::check_asset`]), and
+    /// that every input exists, is owned by the sender, is spent only once,
+    /// and is unlocked if it has a script, by signatures made for the chain
+    /// with `chain_id`, and that together they cover exactly `amount + change`.
+    pub fn check_transaction(&self, tx: &Transaction, chain_id: &str) -> std::result::Result<(), TxError> {
+        self.check_transaction_with(tx, chain_id, &SignatureCache::new(0))
+    }
+
+    /// Like [`UtxoSet::check_transaction`], taking signatures found valid
+    /// before from `signatures` and recording new ones there.
+    pub fn check_transaction_with(
+        &self,
+        tx: &Transaction,
+        chain_id: &str,
+        signatures: &SignatureCache,
+    ) -> std::result::Result<(), TxError> {
+        let txid = tx.hash();
+        if self.contains_transaction(&txid) {
+            return Err(TxError::new(
+                TxCheck::Duplicate,
+                format!("transaction {} is already in the chain", txid),
+            ));
+        }
+        if !tx.is_coinbase() && tx.height().is_some() {
+            return Err(TxError::new(TxCheck::Coinbase, "only coinbase transactions commit to a height"));
+        }
+        if tx.is_sequenced() {
+            let expected = self.next_sequence(tx.sender());
+            if tx.sequence() != expected {
+                return Err(TxError::new(
+                    TxCheck::Sequence,
+                    format!(
+                        "transaction from {} has sequence {}, expected {}",
+                        tx.sender(),
+                        tx.sequence(),
+                        expected
+                    ),
+                ));
+            }
+        }
+        tx.check_memo().map_err(|err| TxError::from_error(TxCheck::Memo, err))?;
+        if let Some(lock) = tx.lock() {
+            lock.check().map_err(|err| TxError::from_error(TxCheck::Script, err))?;
+        }
+        if tx.unlocks().len() > tx.inputs().len() {
+            return Err(TxError::new(TxCheck::Script, "transaction has more unlocking scripts than inputs"));
+        }
+        self.check_asset(tx, Amount::ZERO)?;
+        if tx.inputs().is_empty() {
+            return Ok(());
+        }
+        let overflow = |err| TxError::from_error(TxCheck::Fee, err);
+        let signature_hash = tx.signature_hash(chain_id);
+        let mut seen = HashSet::new();
+        let mut total = Amount::ZERO;
+        for (i, input) in tx.inputs().iter().enumerate() {
+            if !seen.insert(input) {
+                return Err(TxError::new(
+                    TxCheck::Inputs,
+                    format!("transaction spends output {}:{} twice", input.txid, input.vout),
+                ));
+            }
+            let output = self.get(input).ok_or_else(|| {
+                TxError::new(
+                    TxCheck::Inputs,
+                    format!("output {}:{} does not exist or is already spent", input.txid, input.vout),
+                )
+            })?;
+            if output.owner != tx.sender() {
+                return Err(TxError::new(
+                    TxCheck::Inputs,
+                    format!(
+                        "output {}:{} belongs to {}, not {}",
+                        input.txid,
+                        input.vout,
+                        output.owner,
+                        tx.sender()
+                    ),
+                ));
+            }
+            if let Some(lock) = &output.script {
+                let unlock = tx.unlocks().get(i).cloned().unwrap_or_default();
+                script::verify_cached(&unlock, lock, signature_hash.as_bytes(), &txid, signatures).map_err(|err| {
+                    TxError::new(TxCheck::Script, format!("output {}:{}: {}", input.txid, input.vout, err))
+                })?;
+            }
+            total = total.try_add(output.amount, "inputs").map_err(overflow)?;
+        }
+        let spent = tx.cost().and_then(|cost| cost.try_add(tx.change(), "outputs")).map_err(overflow)?;
+        if total != spent {
+            return Err(TxError::new(
+                TxCheck::Inputs,
+                format!("inputs total {} but outputs total {}", total, spent),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Runs the scripts of every input of `transactions` that spends an
+    /// output in the set, in parallel, recording the valid signatures in
+    /// `signatures` so that checking the transactions one by one afterwards
+    /// finds them there. Failures are left for that check to report.
+    pub fn verify_signatures(&self, transactions: &[Transaction], chain_id: &str, signatures: &SignatureCache) {
+        transactions.par_iter().filter(|tx| !tx.inputs().is_empty()).for_each(|tx| {
+            let (txid, signature_hash) = (tx.hash(), tx.signature_hash(chain_id));
+            for (i, input) in tx.inputs().iter().enumerate() {
+                if let Some(lock) = self.get(input).and_then(|output| output.script.as_ref()) {
+                    let unlock = tx.unlocks().get(i).cloned().unwrap_or_default();
+                    let _ = script::verify_cached(&unlock, lock, signature_hash.as_bytes(), &txid, signatures);
+                }
+            }
+        });
+    }
+
+    /// Height of the block whose coinbase created `outpoint`, unless it is
+    /// some other output or a genesis allocation.
+    pub fn coinbase_height(&self, outpoint: &OutPoint) -> Option<u64> {
+        self.coinbases.get(&outpoint.txid).copied()
+    }
+
+    /// Whether a block at `height` may spend `outpoint` under a
+    /// [coinbase maturity](crate::ChainParams::coinbase_maturity) of `maturity`.
+    pub fn is_mature(&self, outpoint: &OutPoint, height: u64, maturity: u64) -> bool {
+        self.coinbase_height(outpoint).is_none_or(|mined| height >= mined.saturating_add(maturity))
+    }
+
+    /// Checks that a block at `height` may spend every input of `tx` under a
+    /// coinbase maturity of `maturity`.
+    pub fn check_maturity(&self, tx: &Transaction, height: u64, maturity: u64) -> std::result::Result<(), TxError> {
+        match tx.inputs().iter().find(|input| !self.is_mature(input, height, maturity)) {
+            Some(input) => {
+                let mined = self.coinbase_height(input).unwrap_or_default();
+                Err(TxError::new(
+                    TxCheck::Maturity,
+                    format!(
+                        "output {}:{} is the reward of block #{} and can't be spent before block #{}",
+                        input.txid,
+                        input.vout,
+                        mined,
+                        mined.saturating_add(maturity)
+                    ),
+                ))
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Spends the transaction's inputs and adds its outputs. Callers must
+    /// run [`UtxoSet::check_transaction`] first.
+    pub fn apply_transaction(&mut self, tx: &Transaction) {
+        for input in tx.inputs() {
+            self.outputs.remove(input);
+            self.coinbases.remove(&input.txid);
+        }
+        let txid = tx.hash();
+        if let Some(height) = tx.height().filter(|&height| tx.is_coinbase() && height > 0) {
+            self.coinbases.insert(txid.clone(), height);
+        }
+        for (vout, output) in tx.outputs().into_iter().enumerate() {
+            let outpoint = OutPoint {
+                txid: txid.clone(),
+                vout: vout as u32,
+            };
+            self.outputs.insert(outpoint, output);
+        }
+        self.txids.insert(txid);
+        if tx.is_sequenced() {
+            *self.sequences.entry(tx.sender().to_string()).or_default() += 1;
+        }
+        if let Some(name) = tx.asset() {
+            let asset = self.assets.entry(name.to_string()).or_insert_with(|| Asset {
+                issuer: tx.sender().to_string(),
+                ..Asset::default()
+            });
+            // Checked not to overflow the supply, which bounds every balance.
+            let amount = tx.amount();
+            if tx.is_issue() {
+                asset.supply = asset.supply.saturating_add(amount);
+            } else if let Some(balance) = asset.balances.get_mut(tx.sender()) {
+                *balance = balance.saturating_sub(amount);
+            }
+            let balance = asset.balances.entry(tx.receiver().to_string()).or_default();
+            *balance = balance.saturating_add(amount);
+        }
+    }
+
+    /// Checks and applies every transaction in `block`, so a block cannot
+    /// spend the same output twice either.
+    pub fn apply_block(&mut self, block: &Block, chain_id: &str) -> Result<()> {
+        for tx in block.transactions() {
+            self.check_transaction(tx, chain_id)
+                .map_err(|err| BlockchainError::Validation(format!("block #{}: {}", block.index(), err)))?;
+            self.apply_transaction(tx);
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::error::BlockchainError;
+
+/// The rule a block broke.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Check {
+    Height,
+    Hash,
+    MerkleRoot,
+    Difficulty,
+    ProofOfWork,
+    /// The block was produced by an address the consensus engine did not elect.
+    Producer,
+    Link,
+    /// The block's version is below the one an upgrade requires at its height.
+    Version,
+    Genesis,
+    Coinbase,
+    /// The block exceeds the chain's transaction count or size limit.
+    Size,
+    /// The block's timestamp is not after the median of recent blocks, or is
+    /// too far in the future.
+    Timestamp,
+    /// The block's hash differs from a checkpoint at its height.
+    Checkpoint,
+    /// The block's transactions were discarded but no saved state covers it,
+    /// or it differs from the block the saved state was taken at.
+    Pruned,
+    /// A transaction spends more than its sender holds, or a coinbase mints
+    /// more than it may; see [`audit`](crate::audit::audit).
+    Conservation,
+    Transaction,
+}
+
+/// One broken rule, with the expected and actual values where there are any.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Violation {
+    pub block: u64,
+    pub check: Check,
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub actual: Option<String>,
+}
+
+impl Violation {
+    pub fn new(block: u64, check: Check, message: impl Into<String>) -> Self {
+        Violation {
+            block,
+            check,
+            message: message.into(),
+            expected: None,
+            actual: None,
+        }
+    }
+
+    pub fn expected(mut self, value: impl fmt::Display) -> Self {
+        self.expected = Some(value.to_string());
+        self
+    }
+
+    pub fn actual(mut self, value: impl fmt::Display) -> Self {
+        self.actual = Some(value.to_string());
+        self
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "block #{}: {}", self.block, self.message)?;
+        match (&self.expected, &self.actual) {
+            (Some(expected), Some(actual)) => write!(f, " (expected {}, found {})", expected, actual),
+            (Some(expected), None) => write!(f, " (expected {})", expected),
+            (None, Some(actual)) => write!(f, " (found {})", actual),
+            (None, None) => Ok(()),
+        }
+    }
+}
+
+/// Every violation found in a chain, in block order.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ValidationReport {
+    pub blocks_checked: usize,
+    pub violations: Vec<Violation>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.violations.is_empty()
+    }
+}
+
+/// The rule a transaction broke.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TxCheck {
+    /// Coinbase transactions only come from mining.
+    Coinbase,
+    /// The transaction is already in the chain or pending.
+    Duplicate,
+    Address,
+    /// The transaction could never fit in a block.
+    Size,
+    Memo,
+    Sequence,
+    Asset,
+    /// A script is ill-formed, or an unlocking script fails to satisfy the
+    /// output it spends, as with a bad signature.
+    Script,
+    /// An input is missing, spent, someone else's or spent twice, or the
+    /// inputs don't add up to the outputs.
+    Inputs,
+    Balance,
+    /// An input spends a coinbase reward that is not yet deep enough in the
+    /// chain; see [`ChainParams::coinbase_maturity`](crate::ChainParams::coinbase_maturity).
+    Maturity,
+    /// The amounts overflow, or the fee is too low for the mempool to take it.
+    Fee,
+    /// An output is below the mempool's dust limit.
+    Dust,
+    /// The chain's state needed to check the transaction could not be read.
+    State,
+}
+
+/// Why a transaction was rejected; see
+/// [`Blockchain::validate_transaction`](crate::Blockchain::validate_transaction).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TxError {
+    pub check: TxCheck,
+    pub message: String,
+}
+
+impl TxError {
+    pub fn new(check: TxCheck, message: impl Into<String>) -> Self {
+        TxError {
+            check,
+            message: message.into(),
+        }
+    }
+
+    /// Files `err`, from a check that fails with a [`BlockchainError`], under `check`.
+    pub fn from_error(check: TxCheck, err: BlockchainError) -> Self {
+        match err {
+            BlockchainError::Validation(message) => TxError::new(check, message),
+            other => TxError::new(TxCheck::State, other.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for TxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for TxError {}
+
+impl From<TxError> for BlockchainError {
+    fn from(err: TxError) -> Self {
+        BlockchainError::Validation(err.message)
+    }
+}
@@ -0,0 +1,393 @@
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use argon2::{Algorithm, Argon2, Params, Version};
+use ed25519_dalek::SigningKey;
+use rand_core::{OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::address;
+use crate::error::{BlockchainError, Result};
+use crate::file;
+use crate::hd;
+use crate::params::DEFAULT_ADDRESS_VERSION;
+use crate::script::Script;
+use crate::transaction::Transaction;
+use crate::utxo::UtxoSet;
+
+const WALLET_VERSION: u32 = 2;
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 12;
+
+/// Argon2id settings used to turn the password into the encryption key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct KdfParams {
+    salt: String,
+    memory_kib: u32,
+    iterations: u32,
+    parallelism: u32,
+}
+
+impl KdfParams {
+    fn generate() -> Self {
+        let mut salt = [0u8; SALT_LEN];
+        OsRng.fill_bytes(&mut salt);
+        KdfParams {
+            salt: hex::encode(salt),
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            parallelism: Params::DEFAULT_P_COST,
+        }
+    }
+
+    fn derive_key(&self, password: &str) -> Result<[u8; 32]> {
+        let salt = decode_hex("salt", &self.salt)?;
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, Some(32))
+            .map_err(|err| BlockchainError::Wallet(format!("invalid key derivation settings: {}", err)))?;
+        let mut key = [0u8; 32];
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(password.as_bytes(), &salt, &mut key)
+            .map_err(|err| BlockchainError::Wallet(format!("key derivation failed: {}", err)))?;
+        Ok(key)
+    }
+}
+
+/// On-disk layout: addresses in the clear so they can be listed without the
+/// password, secret keys only inside the AES-256-GCM ciphertext.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WalletFile {
+    version: u32,
+    /// Network address version the addresses are encoded with.
+    #[serde(default = "default_address_version")]
+    address_version: u8,
+    addresses: Vec<String>,
+    /// Addresses followed without their keys.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    watched: Vec<String>,
+    kdf: KdfParams,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// What the ciphertext holds. Deterministic wallets also keep their mnemonic
+/// so further keys can be derived; version 1 wallets held only the key list.
+#[derive(Serialize, Deserialize)]
+struct Secrets {
+    keys: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mnemonic: Option<String>,
+}
+
+fn default_address_version() -> u8 {
+    DEFAULT_ADDRESS_VERSION
+}
+
+/// Adds `address` to `watched` unless it is already there; fails if it is
+/// not a valid address or is one of the wallet's own.
+fn add_watched(watched: &mut Vec<String>, own: &[String], version: u8, address: &str) -> Result<bool> {
+    address::validate(address, version)?;
+    if own.iter().any(|own| own == address) {
+        return Err(BlockchainError::Wallet(format!("{} is already in the wallet with its key", address)));
+    }
+    if watched.iter().any(|watched| watched == address) {
+        return Ok(false);
+    }
+    watched.push(address.to_string());
+    Ok(true)
+}
+
+/// One key encrypted under the wallet's password, small enough to print as
+/// a QR code for a paper backup.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KeyBackup {
+    pub address: String,
+    kdf: KdfParams,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl KeyBackup {
+    /// Decrypts the key; fails if the password is wrong or the key does not
+    /// belong to the backup's address.
+    pub fn decrypt(&self, password: &str) -> Result<SigningKey> {
+        let plaintext = decrypt(&self.kdf.derive_key(password)?, &self.nonce, &self.ciphertext)?;
+        let bytes: [u8; 32] = plaintext
+            .try_into()
+            .map_err(|_| BlockchainError::Wallet("backed up key has the wrong length".to_string()))?;
+        let key = SigningKey::from_bytes(&bytes);
+        let (version, _) = address::decode(&self.address)?;
+        if address::from_public_key(version, &key.verifying_key()) != self.address {
+            return Err(BlockchainError::Wallet(format!("backed up key is not the key of {}", self.address)));
+        }
+        Ok(key)
+    }
+}
+
+fn decrypt(key: &[u8; 32], nonce: &str, ciphertext: &str) -> Result<Vec<u8>> {
+    let nonce = decode_hex("nonce", nonce)?;
+    if nonce.len() != NONCE_LEN {
+        return Err(BlockchainError::Wallet("wallet nonce has the wrong length".to_string()));
+    }
+    let ciphertext = decode_hex("ciphertext", ciphertext)?;
+    Aes256Gcm::new(&(*key).into())
+        .decrypt(Nonce::from_slice(&nonce), ciphertext.as_slice())
+        .map_err(|_| BlockchainError::Wallet("wrong password or corrupted wallet".to_string()))
+}
+
+/// Encrypts `plaintext` under a fresh nonce, returning the nonce and
+/// ciphertext in hex.
+fn encrypt(key: &[u8; 32], plaintext: &[u8]) -> Result<(String, String)> {
+    let mut nonce = [0u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+    let ciphertext = Aes256Gcm::new(&(*key).into())
+        .encrypt(Nonce::from_slice(&nonce), plaintext)
+        .map_err(|_| BlockchainError::Wallet("encryption failed".to_string()))?;
+    Ok((hex::encode(nonce), hex::encode(ciphertext)))
+}
+
+fn decode_hex(field: &str, value: &str) -> Result<Vec<u8>> {
+    hex::decode(value).map_err(|_| BlockchainError::Wallet(format!("wallet {} is not valid hex", field)))
+}
+
+/// A wallet file as read from disk, with its keys still encrypted.
+#[derive(Debug, Clone)]
+pub struct Wallet {
+    file: WalletFile,
+}
+
+impl Wallet {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let file: WalletFile = file::read_with_backup(path.as_ref(), |data| Ok(serde_json::from_slice(data)?))?;
+        if !(1..=WALLET_VERSION).contains(&file.version) {
+            return Err(BlockchainError::Wallet(format!(
+                "unsupported wallet version {}",
+                file.version
+            )));
+        }
+        Ok(Wallet { file })
+    }
+
+    pub fn addresses(&self) -> &[String] {
+        &self.file.addresses
+    }
+
+    /// Watch-only addresses: followed, but with no key to sign for them.
+    pub fn watched(&self) -> &[String] {
+        &self.file.watched
+    }
+
+    /// Starts watching `address`, returning false if it already was. Needs
+    /// no password, since watched addresses are kept in the clear.
+    pub fn watch(&mut self, address: &str) -> Result<bool> {
+        add_watched(&mut self.file.watched, &self.file.addresses, self.file.address_version, address)
+    }
+
+    /// Writes the wallet file back, keeping the previous one as
+    /// `<path>.bak`. The keys stay encrypted as they were.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        file::replace_with_backup(path.as_ref(), &serde_json::to_vec_pretty(&self.file)?)
+    }
+
+    /// Decrypts the secret keys; fails if the password is wrong.
+    pub fn unlock(&self, password: &str) -> Result<UnlockedWallet> {
+        let key = self.file.kdf.derive_key(password)?;
+        let plaintext = decrypt(&key, &self.file.nonce, &self.file.ciphertext)?;
+        let secrets: Secrets = match self.file.version {
+            1 => Secrets {
+                keys: serde_json::from_slice(&plaintext)?,
+                mnemonic: None,
+            },
+            _ => serde_json::from_slice(&plaintext)?,
+        };
+        let mut keys = Vec::with_capacity(secrets.keys.len());
+        for secret in &secrets.keys {
+            let bytes: [u8; 32] = decode_hex("key", secret)?
+                .try_into()
+                .map_err(|_| BlockchainError::Wallet("wallet key has the wrong length".to_string()))?;
+            keys.push(SigningKey::from_bytes(&bytes));
+        }
+        let seed = secrets.mnemonic.as_deref().map(hd::seed_from_mnemonic).transpose()?;
+        Ok(UnlockedWallet {
+            kdf: self.file.kdf.clone(),
+            address_version: self.file.address_version,
+            key,
+            keys,
+            mnemonic: secrets.mnemonic,
+            seed,
+            watched: self.file.watched.clone(),
+        })
+    }
+}
+
+/// A wallet whose secret keys are decrypted in memory.
+pub struct UnlockedWallet {
+    kdf: KdfParams,
+    address_version: u8,
+    /// Encryption key derived from the password, reused when saving.
+    key: [u8; 32],
+    keys: Vec<SigningKey>,
+    mnemonic: Option<String>,
+    seed: Option<[u8; 64]>,
+    watched: Vec<String>,
+}
+
+impl UnlockedWallet {
+    /// Starts an empty wallet protected by `password`, whose keys are
+    /// generated at random and whose addresses use `address_version`.
+    pub fn create(password: &str, address_version: u8) -> Result<Self> {
+        let kdf = KdfParams::generate();
+        let key = kdf.derive_key(password)?;
+        Ok(UnlockedWallet {
+            kdf,
+            address_version,
+            key,
+            keys: Vec::new(),
+            mnemonic: None,
+            seed: None,
+            watched: Vec::new(),
+        })
+    }
+
+    /// Starts an empty hierarchical deterministic wallet whose keys are all
+    /// derived from the mnemonic `phrase`, so the phrase alone restores them.
+    pub fn from_mnemonic(password: &str, phrase: &str, address_version: u8) -> Result<Self> {
+        let seed = hd::seed_from_mnemonic(phrase)?;
+        Ok(UnlockedWallet {
+            mnemonic: Some(phrase.to_string()),
+            seed: Some(seed),
+            ..UnlockedWallet::create(password, address_version)?
+        })
+    }
+
+    fn address_of(&self, key: &SigningKey) -> String {
+        address::from_public_key(self.address_version, &key.verifying_key())
+    }
+
+    /// The recovery phrase of a deterministic wallet.
+    pub fn mnemonic(&self) -> Option<&str> {
+        self.mnemonic.as_deref()
+    }
+
+    /// Adds a key and returns its address: the next derived key for a
+    /// deterministic wallet, otherwise a random one.
+    pub fn generate_key(&mut self) -> String {
+        let key = match &self.seed {
+            Some(seed) => hd::account_key(seed, self.keys.len() as u32),
+            None => SigningKey::generate(&mut OsRng),
+        };
+        let address = self.address_of(&key);
+        self.keys.push(key);
+        address
+    }
+
+    /// Re-derives the keys of a restored deterministic wallet: keeps deriving
+    /// until `gap_limit` addresses in a row are unused, then keeps every key
+    /// up to the last used one (and always at least one). Returns the number
+    /// of keys kept.
+    pub fn restore_keys(&mut self, is_used: impl Fn(&str) -> bool, gap_limit: usize) -> usize {
+        let mut keep = 1;
+        let mut unused = 0;
+        while unused < gap_limit {
+            let address = self.generate_key();
+            if is_used(&address) {
+                keep = self.keys.len();
+                unused = 0;
+            } else {
+                unused += 1;
+            }
+        }
+        self.keys.truncate(keep);
+        keep
+    }
+
+    pub fn addresses(&self) -> Vec<String> {
+        self.keys.iter().map(|key| self.address_of(key)).collect()
+    }
+
+    /// Watch-only addresses; see [`Wallet::watched`].
+    pub fn watched(&self) -> &[String] {
+        &self.watched
+    }
+
+    /// Starts watching `address`, returning false if it already was.
+    pub fn watch(&mut self, address: &str) -> Result<bool> {
+        let own = self.addresses();
+        add_watched(&mut self.watched, &own, self.address_version, address)
+    }
+
+    pub fn is_watch_only(&self, address: &str) -> bool {
+        self.watched.iter().any(|watched| watched == address)
+    }
+
+    /// The signing key for `address`, if this wallet holds it.
+    /// Watch-only addresses have none.
+    pub fn signing_key(&self, address: &str) -> Option<&SigningKey> {
+        self.keys.iter().find(|key| self.address_of(key) == address)
+    }
+
+    /// The key for `address` encrypted under the wallet's password, if this
+    /// wallet holds it.
+    pub fn backup_key(&self, address: &str) -> Result<KeyBackup> {
+        let key = self.signing_key(address).ok_or_else(|| {
+            BlockchainError::Wallet(format!("the wallet holds no key for {}", address))
+        })?;
+        let (nonce, ciphertext) = encrypt(&self.key, &key.to_bytes())?;
+        Ok(KeyBackup {
+            address: address.to_string(),
+            kdf: self.kdf.clone(),
+            nonce,
+            ciphertext,
+        })
+    }
+
+    /// Signs each input of `tx` spending an output in `utxos` locked to one of
+    /// this wallet's keys with [`Script::pay_to_key`], for the chain with
+    /// `chain_id`. Other inputs keep their unlocking scripts.
+    pub fn sign(&self, tx: Transaction, utxos: &UtxoSet, chain_id: &str) -> Transaction {
+        let locks: Vec<Option<&Script>> =
+            tx.inputs().iter().map(|input| utxos.get(input).and_then(|output| output.script.as_ref())).collect();
+        self.sign_inputs(tx, &locks, chain_id)
+    }
+
+    /// Like [`UnlockedWallet::sign`], given the lock of the output each input
+    /// spends rather than the set holding them, as an offline signer is.
+    pub fn sign_inputs(&self, tx: Transaction, locks: &[Option<&Script>], chain_id: &str) -> Transaction {
+        let mut unlocks: Vec<Script> = (0..tx.inputs().len())
+            .map(|i| {
+                locks
+                    .get(i)
+                    .copied()
+                    .flatten()
+                    .and_then(|lock| self.keys.iter().find(|key| *lock == Script::pay_to_key(&key.verifying_key())))
+                    .map_or_else(
+                        || tx.unlocks().get(i).cloned().unwrap_or_default(),
+                        |key| Script::signature(key, &tx, chain_id),
+                    )
+            })
+            .collect();
+        while unlocks.last().is_some_and(|unlock| unlock.ops().is_empty()) {
+            unlocks.pop();
+        }
+        tx.with_unlocks(unlocks)
+    }
+
+    /// Encrypts the keys under a fresh nonce and writes the wallet file,
+    /// keeping the previous one as `<path>.bak`.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let secrets = Secrets {
+            keys: self.keys.iter().map(|key| hex::encode(key.to_bytes())).collect(),
+            mnemonic: self.mnemonic.clone(),
+        };
+        let (nonce, ciphertext) = encrypt(&self.key, &serde_json::to_vec(&secrets)?)?;
+        let file = WalletFile {
+            version: WALLET_VERSION,
+            address_version: self.address_version,
+            addresses: self.addresses(),
+            watched: self.watched.clone(),
+            kdf: self.kdf.clone(),
+            nonce,
+            ciphertext,
+        };
+        file::replace_with_backup(path.as_ref(), &serde_json::to_vec_pretty(&file)?)
+    }
+}
@@ -0,0 +1,83 @@
+use mini_block::amount::COIN;
+use mini_block::batch;
+use mini_block::export::{self, ExportFormat};
+use mini_block::{Amount, Blockchain, ChainParams, Mempool, Transaction};
+
+#[test]
+fn amounts_parse_and_print_as_decimal_coins() {
+    for (text, units) in [("12", 12 * COIN), ("12.5", 1_250_000_000), (".5", COIN / 2), ("0.00000001", 1), ("0", 0)] {
+        assert_eq!(text.parse::<Amount>().unwrap(), Amount::from_units(units), "{}", text);
+    }
+    let printed: Vec<String> = [12 * COIN, 1_250_000_000, 1, 0, u64::MAX]
+        .into_iter()
+        .map(|units| Amount::from_units(units).to_string())
+        .collect();
+    assert_eq!(printed, ["12", "12.5", "0.00000001", "0", "184467440737.09551615"]);
+    assert_eq!(Amount::MAX.to_string().parse::<Amount>().unwrap(), Amount::MAX);
+
+    for bad in ["", ".", "5.", "-1", "+1", "1.000000001", "1e3", "1,5", "184467440737.09551616"] {
+        assert!(bad.parse::<Amount>().is_err(), "{:?}", bad);
+    }
+}
+
+#[test]
+fn arithmetic_on_amounts_is_checked() {
+    let one = Amount::from_coins(1);
+    assert_eq!(Amount::MAX.checked_add(Amount::from_units(1)), None);
+    assert_eq!(Amount::ZERO.checked_sub(one), None);
+    assert_eq!(Amount::MAX.saturating_add(one), Amount::MAX);
+    assert_eq!(Amount::checked_sum([one, one, one]), Some(Amount::from_coins(3)));
+    assert_eq!(Amount::checked_sum([Amount::MAX, one]), None);
+    assert!(Amount::MAX.try_add(one, "the total").unwrap_err().to_string().contains("the total overflows"));
+
+    let tx = Transaction::new("alice", "bob", Amount::MAX).with_fee(one);
+    assert!(tx.cost().is_err());
+    let mut params = ChainParams {
+        initial_difficulty: 1,
+        ..ChainParams::default()
+    };
+    params.genesis_allocations.insert("alice".to_string(), Amount::from_coins(100));
+    let chain = Blockchain::with_params(params).unwrap();
+    let err = chain.submit_transaction(&mut Mempool::new(), tx).unwrap_err();
+    assert!(err.to_string().contains("overflows"), "{}", err);
+}
+
+#[test]
+fn text_formats_carry_decimal_strings_and_binary_formats_units() {
+    let amount: Amount = "12.5".parse().unwrap();
+    assert_eq!(serde_json::to_string(&amount).unwrap(), r#""12.5""#);
+    assert_eq!(serde_json::from_str::<Amount>(r#""12.5""#).unwrap(), amount);
+    // Whole coins may be written as numbers, as config files used to.
+    assert_eq!(serde_json::from_str::<Amount>("12").unwrap(), Amount::from_coins(12));
+    assert!(serde_json::from_str::<Amount>("-1").is_err());
+    assert_eq!(bincode::deserialize::<u64>(&bincode::serialize(&amount).unwrap()).unwrap(), amount.units());
+}
+
+#[test]
+fn fractional_amounts_survive_batches_and_exports() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("batch.csv");
+    std::fs::write(&path, "sender,receiver,amount,fee\nalice,bob,2.5,0.001\n").unwrap();
+    let entry = batch::read_batch(&path).unwrap().remove(0).1.unwrap();
+    assert_eq!((entry.amount.to_string(), entry.fee.to_string()), ("2.5".to_string(), "0.001".to_string()));
+
+    let mut params = ChainParams {
+        initial_difficulty: 1,
+        ..ChainParams::default()
+    };
+    params.genesis_allocations.insert("alice".to_string(), Amount::from_coins(10));
+    let mut chain = Blockchain::with_params(params).unwrap();
+    let mut mempool = Mempool::new();
+    let tx = Transaction::new("alice", "bob", entry.amount).with_fee(entry.fee);
+    chain.submit_transaction(&mut mempool, tx).unwrap();
+    chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+    assert_eq!(chain.balance_of("alice").to_string(), "7.499");
+
+    for format in [ExportFormat::Json, ExportFormat::Csv, ExportFormat::Cbor, ExportFormat::Bincode] {
+        let path = dir.path().join(format!("chain.{:?}", format));
+        export::export(&chain, &path, format).unwrap();
+        let imported = export::import(&path, format).unwrap();
+        assert_eq!(imported.balance_of("bob"), entry.amount, "{:?}", format);
+        assert_eq!(imported.latest_block().hash(), chain.latest_block().hash(), "{:?}", format);
+    }
+}
@@ -0,0 +1,35 @@
+use mini_block::{Amount, Blockchain, ChainParams, Mempool, Transaction, anchor};
+
+#[test]
+fn anchored_files_are_found_in_the_first_block_recording_them() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("contract.txt");
+    std::fs::write(&path, "signed by both parties").unwrap();
+    let digest = anchor::hash_file(&path).unwrap();
+
+    let params = ChainParams {
+        initial_difficulty: 1,
+        ..ChainParams::default()
+    };
+    let mut chain = Blockchain::with_params(params).unwrap();
+    let mut mempool = Mempool::new();
+    chain.mine_pending(&mut mempool, 10, "notary").unwrap();
+    assert!(anchor::find(&chain, &digest).is_none());
+
+    let tx = anchor::transaction("notary", digest);
+    assert_eq!(anchor::digest_of(&tx), Some(digest));
+    assert_eq!(anchor::digest_of(&Transaction::new("notary", "notary", Amount::ZERO).with_memo(digest)), None);
+    chain.submit_transaction(&mut mempool, tx.clone()).unwrap();
+    chain.mine_pending(&mut mempool, 10, "notary").unwrap();
+    // Anchoring the same digest again doesn't move the proof to a later block.
+    let again = anchor::transaction("notary", digest).with_sequence(1);
+    chain.submit_transaction(&mut mempool, again).unwrap();
+    chain.mine_pending(&mut mempool, 10, "notary").unwrap();
+
+    let (block, found) = anchor::find(&chain, &digest).unwrap();
+    assert_eq!((block.index(), found.hash()), (2, tx.hash()));
+    assert_eq!(chain.balance_of("notary"), chain.params().block_reward.checked_mul(3).unwrap());
+
+    std::fs::write(&path, "signed by one party").unwrap();
+    assert!(anchor::find(&chain, &anchor::hash_file(&path).unwrap()).is_none());
+}
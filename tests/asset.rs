@@ -0,0 +1,79 @@
+use mini_block::audit;
+use mini_block::validation::Check;
+use mini_block::{Amount, Block, Blockchain, ChainParams, Mempool, Transaction};
+
+/// A chain where alice issued 100 GOLD and sent 40 of it to bob.
+fn gold_chain() -> (Blockchain, Mempool) {
+    let mut params = ChainParams {
+        initial_difficulty: 1,
+        ..ChainParams::default()
+    };
+    params.genesis_allocations.insert("alice".to_string(), Amount::from_coins(50));
+    let mut chain = Blockchain::with_params(params).unwrap();
+    let mut mempool = Mempool::new();
+    let issue = Transaction::issue("alice", "GOLD", Amount::from_coins(100)).with_fee(Amount::from_coins(1));
+    chain.submit_transaction(&mut mempool, issue).unwrap();
+    chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+    let transfer = Transaction::new("alice", "bob", Amount::from_coins(40))
+        .with_asset("GOLD")
+        .with_fee(Amount::from_coins(2))
+        .with_sequence(1);
+    chain.submit_transaction(&mut mempool, transfer).unwrap();
+    chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+    (chain, mempool)
+}
+
+#[test]
+fn assets_are_issued_and_transferred_separately_from_the_coin() {
+    let (chain, mut mempool) = gold_chain();
+    assert!(chain.is_chain_valid());
+    assert_eq!(chain.asset_balances("alice").unwrap(), [("GOLD".to_string(), Amount::from_coins(60))].into());
+    assert_eq!(chain.asset_balances("bob").unwrap(), [("GOLD".to_string(), Amount::from_coins(40))].into());
+    // Only the fees came out of alice's coins, and bob received none.
+    assert_eq!(chain.balance_of("alice"), Amount::from_coins(47));
+    assert_eq!(chain.balance_of("bob"), Amount::ZERO);
+    let gold = chain.utxo_set().unwrap().asset("GOLD").cloned().unwrap();
+    assert_eq!((gold.issuer.as_str(), gold.supply), ("alice", Amount::from_coins(100)));
+
+    let overspend = Transaction::new("bob", "carol", Amount::from_coins(41)).with_asset("GOLD");
+    let err = chain.submit_transaction(&mut mempool, overspend).unwrap_err();
+    assert!(err.to_string().contains("insufficient GOLD balance"), "{}", err);
+    let spend = Transaction::new("bob", "carol", Amount::from_coins(30)).with_asset("GOLD");
+    chain.submit_transaction(&mut mempool, spend).unwrap();
+    let overspend = Transaction::new("bob", "carol", Amount::from_coins(11)).with_asset("GOLD").with_sequence(1);
+    let err = chain.submit_transaction(&mut mempool, overspend).unwrap_err();
+    assert!(err.to_string().contains("10 available"), "{}", err);
+
+    let reissue = Transaction::issue("bob", "GOLD", Amount::from_coins(5)).with_sequence(1);
+    let err = chain.submit_transaction(&mut mempool, reissue).unwrap_err();
+    assert!(err.to_string().contains("issued by alice"), "{}", err);
+    let silver = Transaction::new("bob", "carol", Amount::from_coins(1)).with_asset("SILVER").with_sequence(1);
+    let err = chain.submit_transaction(&mut mempool, silver);
+    assert!(err.unwrap_err().to_string().contains("unknown asset"));
+}
+
+#[test]
+fn blocks_moving_assets_without_cover_are_rejected_and_flagged() {
+    let (chain, _) = gold_chain();
+    let report = audit::audit(&chain).unwrap();
+    assert!(report.is_balanced(), "{:?}", report.violations);
+    let gold = &report.assets["GOLD"];
+    assert_eq!((gold.issued, gold.supply()), (Amount::from_coins(100), Amount::from_coins(100)));
+    assert_eq!(gold.holdings["bob"], Amount::from_coins(40));
+    assert_eq!(report.fees, Amount::from_coins(3));
+
+    let tip = chain.latest_block();
+    let index = tip.index() + 1;
+    let transactions = vec![
+        Transaction::coinbase("miner", chain.params().block_reward, index),
+        Transaction::new("bob", "carol", Amount::from_coins(50)).with_asset("GOLD"),
+    ];
+    let (timestamp, previous) = (tip.timestamp() + 1, tip.hash().to_string());
+    let block = Block::mine_at(chain.miner(), index, timestamp, transactions, previous, chain.next_bits()).unwrap();
+    let forged = Blockchain::from_blocks([chain.blocks(), &[block]].concat(), chain.params().clone()).unwrap();
+    let report = forged.validate_detailed();
+    assert!(report.violations.iter().any(|violation| violation.check == Check::Transaction));
+    let audited = audit::audit(&forged).unwrap();
+    assert!(!audited.is_balanced());
+    assert!(audited.violations.iter().any(|violation| violation.message.contains("sends more GOLD")));
+}
@@ -0,0 +1,66 @@
+use mini_block::audit;
+use mini_block::validation::Check;
+use mini_block::{Amount, Block, Blockchain, ChainParams, Mempool, Transaction};
+
+fn funded_chain() -> Blockchain {
+    let mut params = ChainParams {
+        initial_difficulty: 1,
+        ..ChainParams::default()
+    };
+    params.genesis_allocations.insert("alice".to_string(), Amount::from_coins(100));
+    let mut chain = Blockchain::with_params(params).unwrap();
+    let mut mempool = Mempool::new();
+    let spend =
+        chain.build_utxo_transaction(&mempool, "alice", "bob", Amount::from_coins(30), Amount::from_coins(2)).unwrap();
+    chain.submit_transaction(&mut mempool, spend).unwrap();
+    let transfer = Transaction::new("bob", "carol", Amount::from_coins(1)).with_fee(Amount::from_coins(1));
+    chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+    chain.submit_transaction(&mut mempool, transfer).unwrap();
+    chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+    chain
+}
+
+/// `chain` plus a block holding `transactions` after a coinbase of `coinbase`.
+fn with_block(chain: &Blockchain, coinbase: Amount, transactions: Vec<Transaction>) -> Blockchain {
+    let tip = chain.latest_block();
+    let index = tip.index() + 1;
+    let mut all = vec![Transaction::coinbase("miner", coinbase, index)];
+    all.extend(transactions);
+    let block =
+        Block::mine_at(chain.miner(), index, tip.timestamp() + 1, all, tip.hash().to_string(), chain.next_bits()).unwrap();
+    Blockchain::from_blocks([chain.blocks(), &[block]].concat(), chain.params().clone()).unwrap()
+}
+
+#[test]
+fn honest_chains_balance() {
+    let chain = funded_chain();
+    let report = audit::audit(&chain).unwrap();
+    assert!(report.is_balanced(), "{:?}", report.violations);
+    let issued = chain.params().block_reward.checked_mul(2).unwrap();
+    assert_eq!(
+        (report.allocated, report.issued, report.fees),
+        (Amount::from_coins(100), issued, Amount::from_coins(3))
+    );
+    assert_eq!(Some(report.supply), Amount::checked_sum(chain.balances().into_values()));
+    for (address, flows) in &report.addresses {
+        assert_eq!(flows.balance(), chain.balance_of(address), "{}", address);
+    }
+    assert_eq!(report.addresses["bob"].fees, Amount::from_coins(1));
+}
+
+#[test]
+fn coins_created_outside_the_coinbase_are_flagged() {
+    let chain = funded_chain();
+    let reward = chain.params().block_reward;
+
+    let overspent = with_block(&chain, reward, vec![Transaction::new("mallory", "bob", Amount::from_coins(50))]);
+    let report = audit::audit(&overspent).unwrap();
+    assert!(!report.is_balanced());
+    assert_eq!(report.violations.len(), 1);
+    assert_eq!((report.violations[0].block, report.violations[0].check), (3, Check::Conservation));
+
+    let overpaid = with_block(&chain, reward.checked_add(Amount::from_units(1)).unwrap(), Vec::new());
+    let report = audit::audit(&overpaid).unwrap();
+    assert_eq!(report.violations.len(), 1);
+    assert!(report.violations[0].message.contains("coinbase"), "{}", report.violations[0]);
+}
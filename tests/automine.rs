@@ -0,0 +1,46 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use mini_block::automine::AutoMiner;
+use mini_block::{Amount, Blockchain, ChainParams, Mempool, Transaction};
+
+#[test]
+fn blocks_are_mined_on_schedule_while_transactions_are_pending() {
+    let mut params = ChainParams {
+        initial_difficulty: 1,
+        ..ChainParams::default()
+    };
+    params.genesis_allocations.insert("alice".to_string(), Amount::from_coins(10));
+    let chain = Arc::new(RwLock::new(Blockchain::with_params(params).unwrap()));
+    let mempool = Arc::new(Mutex::new(Mempool::new()));
+    let hooked = Arc::new(AtomicU64::new(0));
+    let counter = Arc::clone(&hooked);
+    let miner = AutoMiner::new(Arc::clone(&chain), Arc::clone(&mempool), "miner", Duration::from_millis(10))
+        .with_block_hook(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+    assert!(miner.tick().unwrap().is_none());
+    let payment = Transaction::new("alice", "bob", Amount::from_coins(2)).with_fee(Amount::from_coins(1));
+    chain.read().unwrap().submit_transaction(&mut mempool.lock().unwrap(), payment).unwrap();
+    let block = miner.tick().unwrap().unwrap();
+    assert_eq!((block.index(), block.transactions().len()), (1, 2));
+    assert!(mempool.lock().unwrap().is_empty());
+    assert_eq!(chain.read().unwrap().balance_of("bob"), Amount::from_coins(2));
+    assert_eq!(hooked.load(Ordering::SeqCst), 1);
+
+    // With empty blocks on, the chain grows with nothing pending.
+    let running = miner.with_empty_blocks(true).start();
+    let deadline = Instant::now() + Duration::from_secs(20);
+    while chain.read().unwrap().height() < 4 && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    running.stop();
+    let height = chain.read().unwrap().height();
+    assert!(height >= 4, "{}", height);
+    assert_eq!(hooked.load(Ordering::SeqCst), height);
+    thread::sleep(Duration::from_millis(50));
+    assert_eq!(chain.read().unwrap().height(), height);
+}
@@ -0,0 +1,151 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use mini_block::ban::Offense;
+use mini_block::network::{Handshake, MAX_MESSAGE_RATE, Message, Node, PROTOCOL_VERSION};
+use mini_block::noise::SecureStream;
+use mini_block::{BanList, Block, ChainParams, Mempool, NodeKey};
+
+mod common;
+
+const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+/// Handshake nonces, distinct for every raw peer.
+static NONCES: AtomicU64 = AtomicU64::new(1);
+
+/// A peer driven by hand, to misbehave.
+struct RawPeer {
+    stream: SecureStream,
+    reader: BufReader<SecureStream>,
+}
+
+impl RawPeer {
+    /// Connects to the node at `addr`, or returns `None` if it hangs up
+    /// instead of completing the handshakes.
+    fn connect(addr: SocketAddr) -> Option<Self> {
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        let magic = ChainParams::default().network_magic();
+        let stream = SecureStream::handshake(stream, &NodeKey::generate(), true, magic).ok()?;
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut line = String::new();
+        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+            return None;
+        }
+        let Message::Hello(theirs) = serde_json::from_str(&line).unwrap() else { panic!("expected a handshake") };
+        let mut peer = RawPeer { stream, reader };
+        let ours = Handshake {
+            version: PROTOCOL_VERSION,
+            nonce: NONCES.fetch_add(1, Ordering::Relaxed),
+            height: 0,
+            listen_port: None,
+            light: false,
+            ..theirs
+        };
+        peer.send(&Message::Hello(ours));
+        Some(peer)
+    }
+
+    fn send(&mut self, message: &Message) {
+        self.send_line(&serde_json::to_string(message).unwrap());
+    }
+
+    fn send_line(&mut self, line: &str) {
+        // Writes fail once the node has hung up.
+        let _ = writeln!(self.stream, "{}", line);
+    }
+
+    /// Whether the node closes the connection, skipping what it sends first.
+    fn is_dropped(&mut self) -> bool {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match self.reader.read_line(&mut line) {
+                Ok(0) | Err(_) => return true,
+                Ok(_) => {}
+            }
+        }
+    }
+}
+
+#[test]
+fn scores_add_up_to_bans_that_run_out() {
+    let mut bans = BanList::new(50, Duration::from_millis(100));
+    assert!(!bans.punish(LOCALHOST, Offense::MalformedMessage));
+    assert!(!bans.punish(LOCALHOST, Offense::Flooding));
+    assert_eq!(bans.score(LOCALHOST), 30);
+    assert!(!bans.is_banned(LOCALHOST));
+    assert!(bans.punish(LOCALHOST, Offense::UnexpectedMessage));
+    assert!(bans.is_banned(LOCALHOST));
+    assert_eq!(bans.score(LOCALHOST), 0);
+    assert_eq!(bans.bans().len(), 1);
+    thread::sleep(Duration::from_millis(150));
+    assert!(!bans.is_banned(LOCALHOST) && bans.bans().is_empty());
+}
+
+#[test]
+fn peers_sending_invalid_blocks_are_banned() {
+    let node = Node::new(Arc::new(RwLock::new(common::chain(1, &[]))));
+    let addr = node.listen("127.0.0.1:0").unwrap();
+    let mut other = common::chain(1, &[]);
+    other.mine_pending(&mut Mempool::new(), 10, "miner").unwrap();
+    let valid = other.latest_block().clone();
+    let forged = Block::from_parts(valid.header().clone(), "00".repeat(32), valid.transactions().to_vec());
+
+    let mut peer = RawPeer::connect(addr).unwrap();
+    peer.send(&Message::NewBlock(forged.clone()));
+    assert!(common::wait_until(|| node.peer_scores().first().is_some_and(|(_, score)| *score == 50)));
+    peer.send(&Message::NewBlock(forged));
+    assert!(peer.is_dropped());
+    assert!(common::wait_until(|| node.peer_count() == 0));
+    assert_eq!(node.bans().iter().map(|(ip, _)| *ip).collect::<Vec<_>>(), [LOCALHOST]);
+    assert!(RawPeer::connect(addr).is_none());
+    assert_eq!(node.chain().read().unwrap().height(), 0);
+}
+
+#[test]
+fn flooding_and_malformed_messages_are_punished() {
+    let bans = BanList::new(30, Duration::from_millis(300));
+    let node = Node::new(Arc::new(RwLock::new(common::chain(1, &[])))).with_ban_list(bans);
+    let addr = node.listen("127.0.0.1:0").unwrap();
+
+    // A malformed line costs the connection but not, on its own, a ban.
+    let mut peer = RawPeer::connect(addr).unwrap();
+    peer.send_line("{not json");
+    assert!(peer.is_dropped());
+    let mut peer = RawPeer::connect(addr).unwrap();
+    for _ in 0..=MAX_MESSAGE_RATE {
+        peer.send(&Message::GetPeers);
+    }
+    assert!(peer.is_dropped());
+    assert!(RawPeer::connect(addr).is_none());
+
+    thread::sleep(Duration::from_millis(300));
+    assert!(RawPeer::connect(addr).is_some());
+}
+
+#[test]
+fn peers_sending_oversized_messages_are_banned() {
+    let limit = 1 << 16;
+    let penalty = Offense::OversizedMessage.penalty();
+    let bans = BanList::new(penalty, Duration::from_secs(60));
+    let node = Node::new(Arc::new(RwLock::new(common::chain(1, &[]))))
+        .with_max_message_bytes(limit)
+        .with_ban_list(bans);
+    let addr = node.listen("127.0.0.1:0").unwrap();
+    let mut peer = RawPeer::connect(addr).unwrap();
+    // Frames that never end the line, until the node hangs up.
+    let chunk = vec![b'x'; 1 << 12];
+    for _ in 0..=limit / chunk.len() {
+        if peer.stream.write_all(&chunk).is_err() {
+            break;
+        }
+    }
+    assert!(peer.is_dropped());
+    assert!(common::wait_until(|| node.peer_count() == 0));
+    assert_eq!(node.bans().iter().map(|(ip, _)| *ip).collect::<Vec<_>>(), [LOCALHOST]);
+}
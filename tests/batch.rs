@@ -0,0 +1,47 @@
+use mini_block::Amount;
+use mini_block::batch::{self, BatchEntry};
+
+fn entry(sender: &str, receiver: &str, amount: u32, fee: u32) -> BatchEntry {
+    BatchEntry {
+        sender: sender.to_string(),
+        receiver: receiver.to_string(),
+        amount: Amount::from_coins(amount),
+        fee: Amount::from_coins(fee),
+    }
+}
+
+#[test]
+fn csv_batches_report_bad_rows_by_line() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("batch.csv");
+    std::fs::write(&path, "sender,receiver,amount,fee\nalice,bob,10,1\nalice, carol ,abc,0\nbob,dave,5\na,b,1,2,3\n").unwrap();
+    let entries = batch::read_batch(&path).unwrap();
+    let lines: Vec<u64> = entries.iter().map(|(line, _)| *line).collect();
+    assert_eq!(lines, [2, 3, 4, 5]);
+    assert_eq!(entries[0].1.as_ref().unwrap(), &entry("alice", "bob", 10, 1));
+    let err = entries[1].1.as_ref().unwrap_err().to_string();
+    assert!(err.contains("amount"), "{}", err);
+    // The fee may be left out.
+    assert_eq!(entries[2].1.as_ref().unwrap(), &entry("bob", "dave", 5, 0));
+    assert!(entries[3].1.is_err());
+}
+
+#[test]
+fn json_batches_report_bad_entries_by_position() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("batch.json");
+    std::fs::write(
+        &path,
+        r#"[{"sender": "alice", "receiver": "bob", "amount": 4}, {"sender": "alice", "amount": 1}]"#,
+    )
+    .unwrap();
+    let entries = batch::read_batch(&path).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].0, 1);
+    assert_eq!(entries[0].1.as_ref().unwrap(), &entry("alice", "bob", 4, 0));
+    assert_eq!(entries[1].0, 2);
+    assert!(entries[1].1.is_err());
+
+    std::fs::write(&path, "not json").unwrap();
+    assert!(batch::read_batch(&path).is_err());
+}
@@ -0,0 +1,134 @@
+use mini_block::client::HttpClient;
+use mini_block::rpc::RpcServer;
+use mini_block::{Amount, Blockchain, BlockchainError, ChainParams, Mempool, Miner, NodeEvent, Transaction, TxCheck};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::{Arc, Mutex, RwLock, mpsc};
+use std::thread;
+use std::time::Duration;
+
+fn serve() -> (SocketAddr, HttpClient) {
+    let params = ChainParams {
+        initial_difficulty: 1,
+        ..ChainParams::default()
+    };
+    let chain = Arc::new(RwLock::new(Blockchain::with_params(params).unwrap()));
+    let server = RpcServer::new(chain, Arc::new(Mutex::new(Mempool::new())));
+    let addr = server.listen("127.0.0.1:0").unwrap();
+    (addr, HttpClient::new(addr.to_string()).with_timeout(Duration::from_secs(10)))
+}
+
+#[test]
+fn clients_call_every_endpoint() {
+    let (_, client) = serve();
+    let mut events = client.events().unwrap();
+    let block = client.mine("alice", None).unwrap();
+    assert_eq!(block.index(), 1);
+    assert!(matches!(events.next().unwrap().unwrap(), NodeEvent::BlockMined(mined) if mined.hash() == block.hash()));
+
+    let tx = Transaction::new("alice", "bob", Amount::from_coins(10)).with_fee(Amount::from_coins(1));
+    let validated = client.validate_transaction(&tx).unwrap();
+    assert_eq!((validated.valid, validated.check), (true, None));
+    let broke = client.validate_transaction(&Transaction::new("carol", "bob", Amount::from_coins(10))).unwrap();
+    assert_eq!((broke.valid, broke.check), (false, Some(TxCheck::Balance)));
+    assert!(broke.error.unwrap().contains("insufficient balance"));
+    let submitted = client.submit_transaction(&tx).unwrap();
+    assert_eq!((submitted.txid.as_str(), submitted.pending), (tx.hash().as_str(), 1));
+    assert!(!client.transaction(&tx.hash()).unwrap().confirmed);
+    match client.submit_transaction(&Transaction::new("carol", "bob", Amount::from_coins(10))).unwrap_err() {
+        BlockchainError::Rpc { status, message } => assert!(status == 422 && !message.is_empty(), "{}", message),
+        err => panic!("unexpected error {}", err),
+    }
+
+    let block = client.mine("alice", Some(10)).unwrap();
+    assert_eq!(client.chain().unwrap().len(), 3);
+    assert_eq!(client.block(2).unwrap().hash(), block.hash());
+    let transactions = client.block_transactions(2).unwrap();
+    assert_eq!(transactions[1].txid, tx.hash());
+    let status = client.transaction(&tx.hash()).unwrap();
+    assert_eq!((status.confirmed, status.block), (true, Some(2)));
+    let account = client.balance("bob").unwrap();
+    assert_eq!((account.balance, account.next_sequence), (Amount::from_coins(10), 0));
+    assert!(client.metrics().unwrap().contains("mini_block_chain_height 2"));
+    let estimate = client.fee_estimate(1).unwrap();
+    assert_eq!((estimate.target_blocks, estimate.blocks_sampled, estimate.fee_rate), (1, 2, 0));
+    assert!(matches!(client.block(9), Err(BlockchainError::Rpc { status: 404, .. })));
+}
+
+#[test]
+fn queries_are_answered_while_a_block_is_mined() {
+    let params = ChainParams {
+        initial_difficulty: 1,
+        ..ChainParams::default()
+    };
+    let mut elsewhere = Blockchain::with_params(params.clone()).unwrap();
+    let mut blockchain = Blockchain::with_params(params).unwrap();
+    // The miner announces each search and waits to be let go on with it.
+    let (started, searching) = mpsc::channel();
+    let (release, released) = mpsc::channel();
+    let (started, released) = (Mutex::new(started), Mutex::new(released));
+    blockchain.set_miner(Miner::new(1).with_nonce_start(move |header| {
+        started.lock().unwrap().send(header.index()).unwrap();
+        released.lock().unwrap().recv().unwrap();
+        0
+    }));
+    let chain = Arc::new(RwLock::new(blockchain));
+    let server = RpcServer::new(Arc::clone(&chain), Arc::new(Mutex::new(Mempool::new())));
+    let addr = server.listen("127.0.0.1:0").unwrap();
+    let client = HttpClient::new(addr.to_string()).with_timeout(Duration::from_secs(10));
+    let mining = thread::spawn({
+        let client = client.clone();
+        move || client.mine("alice", None)
+    });
+
+    assert_eq!(searching.recv().unwrap(), 1);
+    assert_eq!(client.chain().unwrap().len(), 1);
+    assert_eq!(client.balance("alice").unwrap().balance, Amount::ZERO);
+    // A block from elsewhere lands meanwhile, so the miner starts over on it.
+    elsewhere.mine_pending(&mut Mempool::new(), 10, "bob").unwrap();
+    chain.write().unwrap().accept_block(elsewhere.latest_block().clone()).unwrap();
+    release.send(()).unwrap();
+    assert_eq!(searching.recv().unwrap(), 2);
+    release.send(()).unwrap();
+
+    let block = mining.join().unwrap().unwrap();
+    assert_eq!(block.index(), 2);
+    assert_eq!(block.previous_hash(), elsewhere.latest_block().hash());
+    assert_eq!(client.balance("alice").unwrap().balance, chain.read().unwrap().params().block_reward);
+}
+
+#[test]
+fn the_openapi_document_covers_the_served_endpoints() {
+    let (addr, client) = serve();
+    let document = client.openapi().unwrap();
+    assert_eq!(document["info"]["version"], env!("CARGO_PKG_VERSION"));
+    let paths = document["paths"].as_object().unwrap();
+    assert_eq!(paths.len(), 12);
+    for (path, operations) in paths {
+        let path = path.replace("{index}", "0").replace("{address}", "alice").replace("{txid}", "none");
+        let path = path.replace("{blocks}", "1");
+        for method in operations.as_object().unwrap().keys() {
+            let (status, body) = request(addr, &method.to_uppercase(), &path);
+            // Placeholder parameters and empty bodies may be refused, but
+            // never as an unknown endpoint or method.
+            assert!(status != 405 && !body.contains("unknown endpoint"), "{} {}: {}", method, path, status);
+        }
+    }
+    assert_eq!(request(addr, "GET", "/transaction/validate").0, 405);
+}
+
+/// Sends a bodiless request, returning the status and, unless it succeeded,
+/// the body.
+fn request(addr: SocketAddr, method: &str, path: &str) -> (u16, String) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "{} {} HTTP/1.1\r\nContent-Length: 0\r\n\r\n", method, path).unwrap();
+    let mut reader = BufReader::new(stream);
+    let mut status_line = String::new();
+    reader.read_line(&mut status_line).unwrap();
+    let status = status_line.split_whitespace().nth(1).unwrap().parse().unwrap();
+    let mut body = String::new();
+    if status != 200 {
+        reader.read_to_string(&mut body).unwrap();
+    }
+    (status, body)
+}
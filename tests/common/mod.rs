@@ -0,0 +1,37 @@
+//! Helpers shared by the integration tests that run nodes. Each test crate
+//! uses its own share of them.
+#![allow(dead_code)]
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use mini_block::{Amount, Blockchain, ChainParams};
+
+/// A genesis allocation, so transactions from alice can be queued at once.
+pub const FUNDED_ALICE: &[(&str, Amount)] = &[("alice", Amount::from_coins(100))];
+
+/// A fresh chain mined at `difficulty`, whose genesis block allocates
+/// `allocations`.
+pub fn chain(difficulty: usize, allocations: &[(&str, Amount)]) -> Blockchain {
+    let mut params = ChainParams {
+        initial_difficulty: difficulty,
+        ..ChainParams::default()
+    };
+    for (address, amount) in allocations {
+        params.genesis_allocations.insert(address.to_string(), *amount);
+    }
+    Blockchain::with_params(params).unwrap()
+}
+
+/// Polls `condition` until it holds, for up to 20 seconds, returning
+/// whether it did.
+pub fn wait_until(condition: impl Fn() -> bool) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(20);
+    while Instant::now() < deadline {
+        if condition() {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    false
+}
@@ -0,0 +1,75 @@
+use std::sync::{Arc, Mutex, RwLock};
+
+use mini_block::compact::CompactBlock;
+use mini_block::network::Node;
+use mini_block::{Amount, Mempool, Transaction};
+
+mod common;
+
+fn payments() -> Vec<Transaction> {
+    (0..3)
+        .map(|sequence| {
+            Transaction::new("alice", "bob", Amount::from_coins(10)).with_fee(Amount::from_coins(1)).with_sequence(sequence)
+        })
+        .collect()
+}
+
+#[test]
+fn compact_blocks_are_rebuilt_from_the_mempool_and_the_missing_transactions() {
+    let mut chain = common::chain(1, common::FUNDED_ALICE);
+    let mut mempool = Mempool::new();
+    for tx in payments() {
+        chain.submit_transaction(&mut mempool, tx).unwrap();
+    }
+    let pending = mempool.clone();
+    chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+    let block = chain.latest_block().clone();
+    let compact = CompactBlock::new(&block);
+    assert_eq!(compact.hash(), block.hash());
+    assert_eq!(compact.prefilled.len(), 1);
+
+    let rebuilt = compact.reconstruct(Some(&pending));
+    assert!(rebuilt.missing().is_empty());
+    let rebuilt = rebuilt.into_block().unwrap();
+    assert_eq!((rebuilt.hash(), rebuilt.merkle_root()), (block.hash(), block.merkle_root()));
+    assert!(rebuilt.has_valid_merkle_root());
+
+    // Without the mempool, only the coinbase is there.
+    let mut partial = compact.reconstruct(None);
+    assert_eq!(partial.missing().len(), block.transactions().len() - 1);
+    let missing: Vec<_> = partial.missing().iter().map(|position| block.transactions()[*position].clone()).collect();
+    assert!(partial.fill(missing[1..].to_vec()).is_err());
+    partial.fill(missing).unwrap();
+    assert_eq!(partial.into_block().unwrap().merkle_root(), block.merkle_root());
+}
+
+#[test]
+fn peers_rebuild_announced_blocks_with_or_without_the_transactions() {
+    let mempool = Arc::new(Mutex::new(Mempool::new()));
+    let miner = Node::new(Arc::new(RwLock::new(common::chain(1, common::FUNDED_ALICE)))).with_mempool(mempool.clone());
+    let addr = miner.listen("127.0.0.1:0").unwrap();
+    let relay_pool = Arc::new(Mutex::new(Mempool::new()));
+    let relay =
+        Node::new(Arc::new(RwLock::new(common::chain(1, common::FUNDED_ALICE)))).with_mempool(relay_pool.clone());
+    let bare = Node::new(Arc::new(RwLock::new(common::chain(1, common::FUNDED_ALICE))));
+    relay.connect(addr).unwrap();
+    bare.connect(addr).unwrap();
+    assert!(common::wait_until(|| miner.peer_count() == 2));
+
+    for tx in payments() {
+        let chain = miner.chain().read().unwrap();
+        chain.submit_transaction(&mut mempool.lock().unwrap(), tx).unwrap();
+    }
+    assert!(common::wait_until(|| relay_pool.lock().unwrap().len() == 3));
+    let block = {
+        let mut chain = miner.chain().write().unwrap();
+        chain.mine_pending(&mut mempool.lock().unwrap(), 10, "miner").unwrap();
+        chain.latest_block().clone()
+    };
+    miner.broadcast_block(&block);
+
+    for node in [&relay, &bare] {
+        assert!(common::wait_until(|| node.chain().read().unwrap().height() == 1));
+        assert_eq!(node.chain().read().unwrap().balance_of("bob"), Amount::from_coins(30));
+    }
+}
@@ -0,0 +1,592 @@
+use mini_block::validation::{Check, TxCheck};
+use mini_block::transaction::{MAX_MEMO_LEN, describe_memo};
+use mini_block::{
+    Amount, Block, BlockHeader, Blockchain, ChainEvent, ChainParams, ChainStore, LogStore, ManualClock, Mempool,
+    Metrics, Miner, OutPoint, Target, Transaction, Upgrade,
+};
+use std::time::Duration;
+
+const START: u64 = 1_800_000_000_000;
+
+fn chain_on(clock: &ManualClock) -> Blockchain {
+    let params = ChainParams {
+        initial_difficulty: 1,
+        retarget_interval: 0,
+        median_time_span: 3,
+        ..ChainParams::default()
+    };
+    let mut chain = Blockchain::with_params(params).unwrap();
+    chain.set_miner(Miner::new(1).with_clock(clock.clone()));
+    for _ in 0..4 {
+        clock.advance(1000);
+        chain.add_block("miner", Vec::new()).unwrap();
+    }
+    chain
+}
+
+/// The next block on `chain`, stamped `timestamp`.
+fn next_block(chain: &Blockchain, timestamp: u128) -> Block {
+    let tip = chain.latest_block();
+    let index = tip.index() + 1;
+    let coinbase = Transaction::coinbase("miner", chain.params().block_reward, index);
+    Block::mine_at(chain.miner(), index, timestamp, vec![coinbase], tip.hash().to_string(), chain.next_bits()).unwrap()
+}
+
+#[test]
+fn blocks_must_be_later_than_the_median_of_recent_blocks() {
+    let clock = ManualClock::new(START);
+    let mut chain = chain_on(&clock);
+    // The last three blocks are at START + 2s, 3s and 4s.
+    let median = u128::from(START) + 3000;
+    let err = chain.accept_block(next_block(&chain, median)).unwrap_err();
+    assert!(err.to_string().contains("median"), "{}", err);
+    chain.accept_block(next_block(&chain, median + 1)).unwrap();
+    assert!(chain.is_chain_valid());
+}
+
+#[test]
+fn blocks_may_not_be_too_far_in_the_future() {
+    let clock = ManualClock::new(START);
+    let mut chain = chain_on(&clock);
+    let limit = u128::from(START) + 4000 + u128::from(chain.params().max_future_block_time_ms);
+    let err = chain.accept_block(next_block(&chain, limit + 1)).unwrap_err();
+    assert!(err.to_string().contains("future"), "{}", err);
+    chain.accept_block(next_block(&chain, limit)).unwrap();
+
+    // A block once too far ahead becomes valid as the clock catches up.
+    let early = next_block(&chain, limit + 60_000);
+    let mut later = Blockchain::from_blocks([chain.blocks(), &[early]].concat(), chain.params().clone()).unwrap();
+    later.set_miner(Miner::new(1).with_clock(clock.clone()));
+    assert!(!later.is_chain_valid());
+    clock.advance(60_000);
+    assert!(later.is_chain_valid());
+}
+
+#[test]
+fn mining_stays_after_the_median_when_the_clock_stands_still() {
+    let clock = ManualClock::new(START);
+    let mut chain = chain_on(&clock);
+    for _ in 0..5 {
+        chain.add_block("miner", Vec::new()).unwrap();
+    }
+    assert!(chain.validate_detailed().is_valid());
+}
+
+#[test]
+fn validation_resumes_after_the_last_validated_block() {
+    let clock = ManualClock::new(START);
+    let mut chain = chain_on(&clock);
+    assert_eq!(chain.validated_height(), None);
+    assert_eq!(chain.validate_detailed().blocks_checked, 5);
+    assert_eq!(chain.validated_height(), Some(4));
+
+    clock.advance(1000);
+    chain.add_block("miner", Vec::new()).unwrap();
+    assert_eq!(chain.validate_detailed().blocks_checked, 1);
+    assert_eq!(chain.validated_height(), Some(5));
+    chain.clear_validation_cache();
+    assert_eq!(chain.validate_detailed().blocks_checked, 6);
+
+    // Once the validated tip is reorganized away, everything is checked again.
+    let mut fork = Blockchain::from_blocks(chain.blocks()[..4].to_vec(), chain.params().clone()).unwrap();
+    fork.set_miner(chain.miner().clone());
+    for _ in 0..3 {
+        clock.advance(1000);
+        fork.add_block("rival", Vec::new()).unwrap();
+    }
+    assert!(!chain.replace_chain(fork.blocks().to_vec()).unwrap().is_empty());
+    assert_eq!(chain.validate_detailed().blocks_checked, 7);
+    chain.validate_full().unwrap();
+    assert_eq!(chain.validated_height(), Some(6));
+}
+
+#[test]
+fn headers_are_checked_before_any_body() {
+    let clock = ManualClock::new(START);
+    let mut chain = chain_on(&clock);
+    let mut fork = Blockchain::from_blocks(chain.blocks()[..2].to_vec(), chain.params().clone()).unwrap();
+    fork.set_miner(chain.miner().clone());
+    for _ in 0..4 {
+        clock.advance(1000);
+        fork.add_block("rival", Vec::new()).unwrap();
+    }
+    let headers: Vec<BlockHeader> = fork.blocks()[2..].iter().map(|block| block.header().clone()).collect();
+    chain.validate_headers(&headers).unwrap();
+    let err = chain.validate_headers(&headers[1..]).unwrap_err();
+    assert!(err.to_string().contains("does not build on our chain"), "{}", err);
+    let err = chain.validate_headers(&[headers[0].clone(), headers[2].clone()]).unwrap_err();
+    assert!(err.to_string().contains("does not follow"), "{}", err);
+
+    // The last three blocks are at START + 2s, 3s and 4s.
+    let stale = next_block(&chain, u128::from(START) + 3000);
+    let err = chain.validate_headers(&[stale.header().clone()]).unwrap_err();
+    assert!(err.to_string().contains("median"), "{}", err);
+
+    // A tip without its proof of work is caught by its header, before the
+    // fork's transactions are replayed.
+    let tip = fork.latest_block();
+    let mut header = tip.header().clone();
+    while header.target().is_met_by(&header.compute_hash()) {
+        header.set_nonce(header.nonce() + 1);
+    }
+    let forged = Block::from_parts(header.clone(), header.compute_hash(), tip.transactions().to_vec());
+    let mut blocks = fork.blocks().to_vec();
+    *blocks.last_mut().unwrap() = forged;
+    let err = chain.replace_chain(blocks).unwrap_err();
+    assert!(err.to_string().contains("header does not meet its proof-of-work target"), "{}", err);
+    assert!(!chain.replace_chain(fork.blocks().to_vec()).unwrap().is_empty());
+}
+
+#[test]
+fn accepted_blocks_reorganize_onto_the_heaviest_branch() {
+    let clock = ManualClock::new(START);
+    let mut chain = chain_on(&clock);
+    let mut fork = Blockchain::from_blocks(chain.blocks()[..3].to_vec(), chain.params().clone()).unwrap();
+    fork.set_miner(chain.miner().clone());
+    for _ in 0..3 {
+        clock.advance(1000);
+        fork.add_block("rival", Vec::new()).unwrap();
+    }
+    let (old_tip, work) = (chain.latest_block().hash().to_string(), chain.cumulative_work());
+    let reward = chain.params().block_reward;
+    let miner = chain.balance_of("miner");
+
+    // A branch with no more work than ours is only stored.
+    let branch = &fork.blocks()[3..];
+    let events = chain.accept_block(branch[0].clone()).unwrap();
+    assert!(matches!(events.as_slice(), [ChainEvent::SideBlockStored(_)]));
+    let events = chain.accept_block(branch[1].clone()).unwrap();
+    assert!(matches!(events.as_slice(), [ChainEvent::SideBlockStored(_)]));
+    assert_eq!((chain.latest_block().hash(), chain.cumulative_work()), (old_tip.as_str(), work));
+    assert!(chain.knows_block(branch[1].hash()));
+
+    // The block that makes it heavier rolls back our tip, newest first.
+    let events = chain.accept_block(branch[2].clone()).unwrap();
+    let rolled_back: Vec<u64> = events
+        .iter()
+        .filter_map(|event| match event {
+            ChainEvent::BlockRolledBack(block) => Some(block.index()),
+            _ => None,
+        })
+        .collect();
+    let connected = events.iter().filter(|event| matches!(event, ChainEvent::BlockConnected(_))).count();
+    assert_eq!((rolled_back, connected), (vec![4, 3], 3));
+    assert_eq!(chain.latest_block().hash(), fork.latest_block().hash());
+    assert!(chain.cumulative_work() > work);
+    assert_eq!(chain.balance_of("miner"), miner.checked_sub(reward.checked_mul(2).unwrap()).unwrap());
+    assert_eq!(chain.balance_of("rival"), fork.balance_of("rival"));
+    assert!(chain.knows_block(&old_tip));
+    assert!(chain.is_chain_valid());
+
+    // Blocks whose parent we have never seen are turned away.
+    let mut detached = Blockchain::from_blocks(fork.blocks()[..1].to_vec(), chain.params().clone()).unwrap();
+    detached.set_miner(chain.miner().clone());
+    for _ in 0..2 {
+        clock.advance(1000);
+        detached.add_block("stranger", Vec::new()).unwrap();
+    }
+    let err = chain.accept_block(detached.latest_block().clone()).unwrap_err();
+    assert!(err.to_string().contains("unknown parent"), "{}", err);
+}
+
+#[test]
+fn checkpoints_block_deep_reorganizations() {
+    let clock = ManualClock::new(START);
+    let mut chain = chain_on(&clock);
+    let mut fork = Blockchain::from_blocks(chain.blocks()[..2].to_vec(), chain.params().clone()).unwrap();
+    fork.set_miner(Miner::new(1).with_clock(clock.clone()));
+    for _ in 0..5 {
+        clock.advance(1000);
+        fork.add_block("rival", Vec::new()).unwrap();
+    }
+
+    let checkpoints = [(2, chain.blocks()[2].hash().to_string())].into();
+    chain.set_checkpoints(checkpoints);
+    assert!(chain.is_chain_valid());
+    let err = chain.replace_chain(fork.blocks().to_vec()).unwrap_err();
+    assert!(err.to_string().contains("checkpoint"), "{}", err);
+
+    fork.set_checkpoints(chain.checkpoints().clone());
+    let report = fork.validate_detailed();
+    assert!(report.violations.iter().any(|violation| violation.check == Check::Checkpoint));
+
+    chain.set_checkpoints(Default::default());
+    assert!(!chain.replace_chain(fork.blocks().to_vec()).unwrap().is_empty());
+}
+
+#[test]
+fn pruned_chains_keep_their_state() {
+    let clock = ManualClock::new(START);
+    let mut chain = chain_on(&clock);
+    let full = chain.blocks().to_vec();
+    let balances = chain.balances();
+    chain.set_prune_depth(Some(2)).unwrap();
+    assert_eq!(chain.pruned_height(), Some(2));
+    assert!(chain.blocks()[..=2].iter().all(Block::is_pruned));
+    assert!(!chain.blocks()[3].is_pruned());
+    assert_eq!(chain.balances(), balances);
+    assert!(chain.is_chain_valid());
+
+    clock.advance(1000);
+    chain.add_block("miner", Vec::new()).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let mut store = LogStore::open(dir.path().join("chain.log")).unwrap();
+    chain.persist(&mut store).unwrap();
+    let mut reopened = Blockchain::open_store(&mut LogStore::open(dir.path().join("chain.log")).unwrap()).unwrap();
+    reopened.set_miner(chain.miner().clone());
+    assert_eq!(reopened.pruned_height(), Some(2));
+    assert_eq!(reopened.balances(), chain.balances());
+    assert_eq!(reopened.utxo_set().unwrap().len(), chain.utxo_set().unwrap().len());
+    assert!(reopened.is_chain_valid());
+
+    // Without the saved state the pruned blocks can't be checked.
+    let stripped = Blockchain::from_blocks(chain.blocks().to_vec(), chain.params().clone()).unwrap();
+    let report = stripped.validate_detailed();
+    assert!(report.violations.iter().any(|violation| violation.check == Check::Pruned));
+
+    let mut fork = Blockchain::from_blocks(full[..2].to_vec(), chain.params().clone()).unwrap();
+    fork.set_miner(chain.miner().clone());
+    for _ in 0..6 {
+        clock.advance(1000);
+        fork.add_block("rival", Vec::new()).unwrap();
+    }
+    let err = chain.replace_chain(fork.blocks().to_vec()).unwrap_err();
+    assert!(err.to_string().contains("pruned"), "{}", err);
+}
+
+#[test]
+fn snapshots_are_restored_on_load() {
+    let clock = ManualClock::new(START);
+    let mut chain = chain_on(&clock);
+    chain.set_snapshot_interval(3);
+    for _ in 0..3 {
+        clock.advance(1000);
+        chain.add_block("miner", Vec::new()).unwrap();
+    }
+    assert_eq!(chain.snapshot_height(), Some(5));
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("chain.log");
+    let mut store = LogStore::open(&path).unwrap();
+    chain.persist(&mut store).unwrap();
+
+    // Balances come from the snapshot, not a replay from genesis.
+    let mut forged = store.snapshot().unwrap().unwrap();
+    forged.balances.insert("ghost".to_string(), Amount::from_coins(7));
+    store.save_snapshot(&forged).unwrap();
+    let reopened = Blockchain::open_store(&mut LogStore::open(&path).unwrap()).unwrap();
+    assert_eq!(reopened.snapshot_height(), Some(5));
+    assert_eq!(reopened.balance_of("ghost"), Amount::from_coins(7));
+    assert_eq!(reopened.balance_of("miner"), chain.balance_of("miner"));
+
+    // A snapshot of a block no longer on the chain is ignored.
+    store.truncate(5).unwrap();
+    let reopened = Blockchain::open_store(&mut LogStore::open(&path).unwrap()).unwrap();
+    assert_eq!(reopened.snapshot_height(), None);
+    assert_eq!(reopened.balance_of("ghost"), Amount::ZERO);
+}
+
+#[test]
+fn locked_transactions_wait_in_the_mempool() {
+    let clock = ManualClock::new(START);
+    let mut chain = chain_on(&clock);
+    let mut mempool = Mempool::new();
+    // Blocks 5 and 6 come first; the escrow can go in block 7 at the earliest.
+    let escrow = Transaction::new("miner", "seller", Amount::from_coins(30)).with_lock_time(7);
+    assert!(!escrow.is_final(6, u128::MAX));
+    chain.submit_transaction(&mut mempool, escrow.clone()).unwrap();
+    for _ in 0..2 {
+        clock.advance(1000);
+        chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+        assert_eq!(mempool.len(), 1);
+    }
+
+    // A block including it early is rejected.
+    let tip = chain.latest_block();
+    let coinbase = Transaction::coinbase("miner", chain.params().block_reward, 7);
+    let transactions = vec![coinbase, escrow.clone().with_lock_time(8)];
+    let timestamp = u128::from(START) + 7000;
+    let early =
+        Block::mine_at(chain.miner(), 7, timestamp, transactions, tip.hash().to_string(), chain.next_bits()).unwrap();
+    let err = chain.accept_block(early).unwrap_err();
+    assert!(err.to_string().contains("locked until block #8"), "{}", err);
+
+    clock.advance(1000);
+    assert_eq!(chain.mine_pending(&mut mempool, 10, "miner").unwrap(), 1);
+    assert!(mempool.is_empty());
+    assert_eq!(chain.balance_of("seller"), Amount::from_coins(30));
+    assert!(chain.is_chain_valid());
+
+    // Time locks compare against the block timestamp.
+    let locked = Transaction::new("miner", "seller", Amount::from_coins(1)).with_lock_time(START + 60_000);
+    assert!(!locked.is_final(100, u128::from(START) + 59_999));
+    assert!(locked.is_final(0, u128::from(START) + 60_000));
+}
+
+#[test]
+fn blocks_may_not_spend_more_than_a_sender_has() {
+    let clock = ManualClock::new(START);
+    let mut chain = chain_on(&clock);
+    let tip = chain.latest_block().clone();
+    let index = tip.index() + 1;
+    let coinbase = Transaction::coinbase("miner", chain.params().block_reward, index);
+    let overdraft = Transaction::new("mallory", "bob", Amount::from_units(1_000_000));
+    let timestamp = u128::from(START) + 5000;
+    let block = Block::mine_at(
+        chain.miner(),
+        index,
+        timestamp,
+        vec![coinbase.clone(), overdraft],
+        tip.hash().to_string(),
+        chain.next_bits(),
+    )
+    .unwrap();
+    let err = chain.accept_block(block).unwrap_err();
+    assert!(err.to_string().contains("insufficient balance: mallory has 0"), "{}", err);
+    assert_eq!(chain.balance_of("bob"), Amount::ZERO);
+
+    // Funds received earlier in the same block may be passed on.
+    let reward = chain.balance_of("miner");
+    let funded = vec![
+        coinbase,
+        Transaction::new("miner", "mallory", reward),
+        Transaction::new("mallory", "bob", reward),
+    ];
+    let block =
+        Block::mine_at(chain.miner(), index, timestamp, funded, tip.hash().to_string(), chain.next_bits()).unwrap();
+    chain.accept_block(block).unwrap();
+    assert_eq!(chain.balance_of("bob"), reward);
+    assert!(chain.is_chain_valid());
+}
+
+#[test]
+fn fees_and_heights_hash_apart() {
+    let clock = ManualClock::new(START);
+    let mut chain = chain_on(&clock);
+    let mut mempool = Mempool::new();
+    let paid = Transaction::new("miner", "bob", Amount::from_coins(1)).with_fee(Amount::from_units(7));
+    let mut json = serde_json::to_value(&paid).unwrap();
+    json.as_object_mut().unwrap().remove("fee");
+    json["height"] = 7.into();
+    let numbered: Transaction = serde_json::from_value(json).unwrap();
+    assert_eq!(numbered.height(), Some(7));
+    assert_ne!(numbered.hash(), paid.hash());
+    let chain_id = chain.params().chain_id.clone();
+    assert_ne!(numbered.signature_hash(&chain_id), paid.signature_hash(&chain_id));
+
+    // Only coinbases commit to a height.
+    let err = chain.submit_transaction(&mut mempool, numbered.clone()).unwrap_err();
+    assert!(err.to_string().contains("only coinbase"), "{}", err);
+    let tip = chain.latest_block().clone();
+    let transactions = vec![Transaction::coinbase("miner", chain.params().block_reward, 5), numbered];
+    let timestamp = u128::from(START) + 5000;
+    let block = Block::mine_at(chain.miner(), 5, timestamp, transactions, tip.hash().to_string(), chain.next_bits());
+    let err = chain.accept_block(block.unwrap()).unwrap_err();
+    assert!(err.to_string().contains("only coinbase"), "{}", err);
+}
+
+#[test]
+fn memos_are_committed_to_and_bounded() {
+    let clock = ManualClock::new(START);
+    let mut chain = chain_on(&clock);
+    let mut mempool = Mempool::new();
+    let plain = Transaction::new("miner", "notary", Amount::from_coins(1));
+    let stamped = plain.clone().with_memo("sha256:9f86d0");
+    assert_ne!(stamped.hash(), plain.hash());
+    assert_ne!(stamped.hash(), plain.clone().with_memo("sha256:9f86d1").hash());
+    assert_eq!(describe_memo(stamped.memo().unwrap()), "\"sha256:9f86d0\"");
+    assert_eq!(describe_memo(&[0, 0xff]), "0x00ff");
+
+    let oversized = plain.with_memo(vec![b'x'; MAX_MEMO_LEN + 1]);
+    let err = chain.submit_transaction(&mut mempool, oversized).unwrap_err();
+    assert!(err.to_string().contains("memo is longer"), "{}", err);
+    chain.submit_transaction(&mut mempool, stamped.clone()).unwrap();
+    clock.advance(1000);
+    chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+    let (_, mined) = chain.get_transaction(&stamped.hash()).unwrap();
+    assert_eq!(mined.memo(), Some(&b"sha256:9f86d0"[..]));
+    assert!(chain.is_chain_valid());
+}
+
+#[test]
+fn the_easiest_target_survives_compact_encoding() {
+    let bits = Target::MAX.to_bits();
+    assert_eq!(Target::from_bits(bits), Target::MAX);
+    assert!(Target::from_bits(bits).is_met_by(&"ff".repeat(32)));
+    // The targets just below keep their rounded form.
+    let below = Target::from_leading_zeros(1);
+    assert_eq!(Target::from_bits(below.to_bits()).to_bits(), below.to_bits());
+    assert!(!Target::from_bits(below.to_bits()).is_met_by(&"ff".repeat(32)));
+}
+
+#[test]
+fn retargeting_moves_the_target_by_less_than_a_hex_digit() {
+    let clock = ManualClock::new(START);
+    let params = ChainParams {
+        initial_difficulty: 2,
+        retarget_interval: 4,
+        target_block_time_ms: 1000,
+        median_time_span: 3,
+        ..ChainParams::default()
+    };
+    let mut chain = Blockchain::with_params(params).unwrap();
+    chain.set_miner(Miner::new(1).with_clock(clock.clone()));
+    for _ in 0..3 {
+        clock.advance(1500);
+        chain.add_block("miner", Vec::new()).unwrap();
+    }
+    // Blocks came half again as slowly as intended, so the target eases by 3/2.
+    let initial = chain.latest_block().target();
+    let taken = chain.latest_block().timestamp() - chain.blocks()[1].timestamp();
+    assert_eq!(taken, 3000);
+    let eased = Target::from_bits(chain.next_bits());
+    assert_eq!(eased, Target::from_bits(initial.scale(3, 2, Target::MAX).to_bits()));
+    assert!(initial < eased && eased < Target::from_leading_zeros(1));
+    let work = initial.work();
+    assert!(eased.work() < work && eased.work() > work / 2);
+
+    clock.advance(1500);
+    chain.add_block("miner", Vec::new()).unwrap();
+    assert_eq!(chain.latest_block().target(), eased);
+    assert_eq!(chain.cumulative_work(), chain.iter().map(|block| block.target().work()).sum::<u128>());
+    assert!(chain.is_chain_valid());
+
+    // Hashes are compared as numbers, not by their leading zeros.
+    let one_zero = format!("01{}", "0".repeat(62));
+    assert!(eased.is_met_by(&one_zero) && !initial.is_met_by(&one_zero));
+    assert!(eased.is_met_by(&eased.to_string()));
+    assert!(!eased.is_met_by(&increment_hex(&eased.to_string())));
+}
+
+/// `hex` plus one, as a hex number of the same width.
+fn increment_hex(hex: &str) -> String {
+    let mut digits: Vec<u32> = hex.chars().map(|c| c.to_digit(16).unwrap()).collect();
+    for digit in digits.iter_mut().rev() {
+        *digit = (*digit + 1) % 16;
+        if *digit != 0 {
+            break;
+        }
+    }
+    digits.into_iter().map(|d| char::from_digit(d, 16).unwrap()).collect()
+}
+
+#[test]
+fn searches_with_a_timestamp_refresh_restamp_the_header() {
+    let clock = ManualClock::new(START + 60_000);
+    let bits = Target::from_leading_zeros(3).to_bits();
+    let header = BlockHeader::new(1, u128::from(START), "ab".repeat(32), "cd".repeat(32), bits);
+    let miner = Miner::new(1).with_clock(clock.clone());
+    let (kept, _) = miner.mine(header.clone()).unwrap();
+    assert_eq!(kept.timestamp(), u128::from(START));
+    // The first solution lies beyond the nonces tried before the first refresh.
+    assert!(kept.nonce() > 1024, "{}", kept.nonce());
+
+    let (restamped, hash) = miner.with_timestamp_refresh(Duration::ZERO).mine(header).unwrap();
+    assert!(restamped.timestamp() >= u128::from(START + 60_000));
+    assert_eq!(restamped.compute_hash(), hash);
+    assert!(restamped.target().is_met_by(&hash));
+}
+
+#[test]
+fn calibration_measures_the_hash_rate_without_touching_the_miner() {
+    let metrics = Metrics::new();
+    let miner = Miner::new(2).with_metrics(metrics.clone());
+    let progress = miner.calibrate(Duration::from_millis(100));
+    assert!(progress.elapsed >= Duration::from_millis(100));
+    assert!(progress.hashes > 0 && progress.hash_rate() > 0.0);
+    assert_eq!(metrics.hashes(), 0);
+    assert!(!miner.cancel_token().is_cancelled());
+}
+
+#[test]
+fn difficulties_beyond_the_digest_length_cannot_be_met() {
+    let impossible = Target::from_leading_zeros(64);
+    assert_eq!(Target::from_leading_zeros(65), impossible);
+    assert_eq!(Target::from_leading_zeros(usize::MAX), impossible);
+    assert!(!impossible.is_met_by(&format!("{}1", "0".repeat(63))));
+}
+
+#[test]
+fn upgrades_require_newer_blocks_from_their_height() {
+    let clock = ManualClock::new(START);
+    let legacy = chain_on(&clock);
+    let params = ChainParams {
+        upgrades: vec![Upgrade { version: 2, height: 3 }],
+        ..legacy.params().clone()
+    };
+    assert_eq!((params.block_version(2), params.block_version(3)), (1, 2));
+    assert!(!params.is_active(2, 2) && params.is_active(2, 3));
+
+    // Blocks mined before the upgrade keep their hashes; only the ones at
+    // and above its height fall short.
+    let reloaded = Blockchain::from_blocks(legacy.blocks().to_vec(), params.clone()).unwrap();
+    let report = reloaded.validate_detailed();
+    let outdated: Vec<u64> = report
+        .violations
+        .iter()
+        .filter(|violation| violation.check == Check::Version)
+        .map(|violation| violation.block)
+        .collect();
+    assert_eq!(outdated, [3, 4]);
+
+    let mut upgraded = Blockchain::from_blocks(legacy.blocks()[..3].to_vec(), params).unwrap();
+    upgraded.set_miner(legacy.miner().clone());
+    clock.advance(1000);
+    let stale = next_block(&upgraded, u128::from(START) + 5000);
+    let err = upgraded.accept_block(stale).unwrap_err();
+    assert!(err.to_string().contains("version"), "{}", err);
+    upgraded.add_block("miner", Vec::new()).unwrap();
+    assert_eq!(upgraded.blocks().iter().map(Block::version).collect::<Vec<_>>(), [1, 1, 1, 2]);
+    assert!(upgraded.is_chain_valid());
+}
+
+#[test]
+fn coinbase_rewards_are_spent_only_once_mature() {
+    let mut params = ChainParams {
+        initial_difficulty: 1,
+        coinbase_maturity: 3,
+        ..ChainParams::default()
+    };
+    params.genesis_allocations.insert("alice".to_string(), Amount::from_coins(10));
+    let mut chain = Blockchain::with_params(params).unwrap();
+    let mut mempool = Mempool::new();
+    chain.add_block("miner", Vec::new()).unwrap();
+    let reward = chain.params().block_reward;
+    let coinbase = OutPoint {
+        txid: chain.latest_block().transactions()[0].hash(),
+        vout: 0,
+    };
+    assert_eq!(chain.immature_balance_of("miner"), reward);
+    assert_eq!(chain.spendable_balance_of("miner"), Amount::ZERO);
+
+    let spend = Transaction::spending("miner", "bob", reward, vec![coinbase.clone()], Amount::ZERO);
+    let err = chain.validate_transaction(&mempool, &spend).unwrap_err();
+    assert_eq!(err.check, TxCheck::Maturity, "{}", err);
+    let pay = Transaction::new("miner", "bob", Amount::from_coins(1));
+    assert_eq!(chain.validate_transaction(&mempool, &pay).unwrap_err().check, TxCheck::Balance);
+    assert!(chain.build_utxo_transaction(&mempool, "miner", "bob", Amount::from_coins(1), Amount::ZERO).is_err());
+    // Genesis allocations need no waiting.
+    chain.submit_transaction(&mut mempool, Transaction::new("alice", "bob", Amount::from_coins(1))).unwrap();
+
+    // Nor may a block from a peer spend the reward early.
+    let tip = chain.latest_block().clone();
+    let index = tip.index() + 1;
+    let transactions = vec![Transaction::coinbase("miner", reward, index), spend.clone()];
+    let bits = chain.next_bits();
+    let early = Block::mine_at(chain.miner(), index, tip.timestamp() + 1, transactions, tip.hash().to_string(), bits);
+    let err = chain.accept_block(early.unwrap()).unwrap_err();
+    assert!(err.to_string().contains("can't be spent before block #4"), "{}", err);
+    // Or spend it from the account balance, where this block's own reward
+    // is no readier.
+    let transactions = vec![Transaction::coinbase("miner", reward, index), pay.clone()];
+    let early = Block::mine_at(chain.miner(), index, tip.timestamp() + 1, transactions, tip.hash().to_string(), bits);
+    let err = chain.accept_block(early.unwrap()).unwrap_err();
+    assert!(err.to_string().contains("insufficient balance: miner has 0"), "{}", err);
+
+    chain.add_block("miner", Vec::new()).unwrap();
+    chain.add_block("miner", Vec::new()).unwrap();
+    assert_eq!(chain.spendable_balance_of("miner"), reward);
+    chain.submit_transaction(&mut mempool, spend).unwrap();
+    chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+    assert_eq!(chain.balance_of("bob"), reward.checked_add(Amount::from_coins(1)).unwrap());
+    assert!(chain.is_chain_valid());
+}
@@ -0,0 +1,45 @@
+#![cfg(unix)]
+
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixListener;
+use std::thread;
+
+use mini_block::control::{self, ControlRequest, ControlResponse, ControlSocket};
+
+#[test]
+fn commands_round_trip_over_the_control_socket() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("node.sock");
+    let socket = ControlSocket::bind(&path).unwrap();
+    assert_eq!(path.metadata().unwrap().permissions().mode() & 0o777, 0o600);
+    assert!(ControlSocket::bind(&path).unwrap_err().to_string().contains("already running"));
+
+    let daemon = thread::spawn(move || {
+        let (request, reply) = socket.accept().unwrap();
+        let output = format!("ran {}\n", request.args.join(" "));
+        reply.send(&ControlResponse { output, ok: request.json }).unwrap();
+        socket
+    });
+    let request = ControlRequest {
+        args: vec!["balance".to_string(), "alice".to_string()],
+        json: true,
+        password: None,
+    };
+    let response = control::send(&path, &request).unwrap();
+    assert_eq!(response, ControlResponse { output: "ran balance alice\n".to_string(), ok: true });
+
+    drop(daemon.join().unwrap());
+    assert!(!path.exists());
+    assert!(control::send(&path, &request).unwrap_err().to_string().contains("no daemon"));
+}
+
+#[test]
+fn stale_sockets_are_replaced() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("node.sock");
+    // A socket file whose daemon is gone.
+    drop(UnixListener::bind(&path).unwrap());
+    assert!(path.exists());
+    let socket = ControlSocket::bind(&path).unwrap();
+    assert_eq!(socket.path(), path);
+}
@@ -0,0 +1,129 @@
+use mini_block::export::{self, ExportFormat};
+use mini_block::{Amount, Blockchain, ChainParams, Mempool, Script, Transaction};
+use std::path::Path;
+
+fn sample_chain() -> Blockchain {
+    let mut params = ChainParams {
+        initial_difficulty: 1,
+        ..ChainParams::default()
+    };
+    params.genesis_allocations.insert("alice".to_string(), Amount::from_coins(100));
+    let mut chain = Blockchain::with_params(params).unwrap();
+    let mut mempool = Mempool::new();
+
+    // A locked output, so scripts are carried through the export too.
+    let spend = chain
+        .build_utxo_transaction(&mempool, "alice", "bob", Amount::from_coins(30), Amount::from_coins(2))
+        .unwrap()
+        .with_lock(Script::hash_lock([7; 32]));
+    chain.submit_transaction(&mut mempool, spend).unwrap();
+    chain
+        .submit_transaction(
+            &mut mempool,
+            Transaction::new("alice", "carol", Amount::from_coins(5)).with_fee(Amount::from_coins(1)).with_memo([0, 0xff, b'a']),
+        )
+        .unwrap();
+    chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+    // A block holding only its coinbase.
+    chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+    chain
+}
+
+fn assert_round_trip(format: ExportFormat, file: &str) {
+    let chain = sample_chain();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join(file);
+    export::export(&chain, &path, format).unwrap();
+    assert_eq!(ExportFormat::from_path(Path::new(file)), format);
+
+    let mut imported = export::import(&path, format).unwrap();
+    if format == ExportFormat::Csv {
+        imported = Blockchain::from_blocks(imported.blocks().to_vec(), chain.params().clone()).unwrap();
+    }
+    let hashes = |chain: &Blockchain| chain.iter().map(|block| block.hash().to_string()).collect::<Vec<_>>();
+    assert_eq!(hashes(&imported), hashes(&chain));
+    for (ours, theirs) in chain.iter().zip(imported.iter()) {
+        assert_eq!(ours.header(), theirs.header());
+        let txids = |block: &mini_block::Block| block.transactions().iter().map(Transaction::hash).collect::<Vec<_>>();
+        assert_eq!(txids(ours), txids(theirs));
+        let memos = |block: &mini_block::Block| block.transactions().iter().map(|tx| tx.memo().map(<[u8]>::to_vec)).collect::<Vec<_>>();
+        assert_eq!(memos(ours), memos(theirs));
+    }
+    assert_eq!(imported.params(), chain.params());
+    imported.validate().unwrap();
+}
+
+#[test]
+fn json_round_trip() {
+    assert_round_trip(ExportFormat::Json, "chain.json");
+}
+
+#[test]
+fn cbor_round_trip() {
+    assert_round_trip(ExportFormat::Cbor, "chain.cbor");
+}
+
+#[test]
+fn bincode_round_trip() {
+    assert_round_trip(ExportFormat::Bincode, "chain.bin");
+}
+
+#[test]
+fn csv_round_trip() {
+    assert_round_trip(ExportFormat::Csv, "chain.csv");
+}
+
+#[test]
+fn csv_import_uses_default_params() {
+    let chain = sample_chain();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("chain.csv");
+    export::export(&chain, &path, ExportFormat::Csv).unwrap();
+    let imported = export::import(&path, ExportFormat::Csv).unwrap();
+    assert_eq!(imported.params(), &ChainParams::default());
+}
+
+#[test]
+fn corrupt_binary_files_are_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("chain.bin");
+    export::export(&sample_chain(), &path, ExportFormat::Bincode).unwrap();
+    let mut bytes = std::fs::read(&path).unwrap();
+    // The chain ID's length, claiming far more bytes than the file has.
+    bytes[..8].copy_from_slice(&u64::MAX.to_le_bytes());
+    std::fs::write(&path, &bytes).unwrap();
+    assert!(export::import(&path, ExportFormat::Bincode).is_err());
+
+    std::fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+    for format in [ExportFormat::Cbor, ExportFormat::Bincode] {
+        assert!(export::import(&path, format).is_err());
+    }
+}
+
+#[test]
+fn unknown_format_is_rejected() {
+    assert!("xml".parse::<ExportFormat>().is_err());
+    assert_eq!("cbor".parse::<ExportFormat>(), Ok(ExportFormat::Cbor));
+}
+
+#[test]
+fn saves_keep_a_backup_to_fall_back_on() {
+    let chain = sample_chain();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("blockchain.json");
+    let backup = dir.path().join("blockchain.json.bak");
+    chain.save_to_file(&path).unwrap();
+    assert!(!backup.exists());
+
+    let shorter = Blockchain::from_blocks(chain.blocks()[..2].to_vec(), chain.params().clone()).unwrap();
+    shorter.save_to_file(&path).unwrap();
+    assert_eq!(Blockchain::load_from_file(&backup).unwrap().height(), chain.height());
+    assert_eq!(Blockchain::load_from_file(&path).unwrap().height(), 1);
+    assert!(!dir.path().join("blockchain.json.tmp").exists());
+
+    // A torn write is survived by the backup; without one the error surfaces.
+    std::fs::write(&path, "{\"blocks\": [").unwrap();
+    assert_eq!(Blockchain::load_from_file(&path).unwrap().height(), chain.height());
+    std::fs::remove_file(&backup).unwrap();
+    assert!(Blockchain::load_from_file(&path).is_err());
+}
@@ -0,0 +1,62 @@
+#![cfg(feature = "grpc")]
+
+use mini_block::grpc::GrpcServer;
+use mini_block::grpc::proto::get_block_request::Block as BlockKey;
+use mini_block::grpc::proto::node_client::NodeClient;
+use mini_block::grpc::proto::{self, GetBalanceRequest, GetBlockRequest, GetChainInfoRequest, MineRequest};
+use mini_block::{Amount, Blockchain, ChainParams, Mempool, Transaction};
+use std::sync::{Arc, Mutex, RwLock};
+use tonic::Code;
+
+#[test]
+fn grpc_clients_read_the_chain_and_mine() {
+    let params = ChainParams {
+        initial_difficulty: 1,
+        ..ChainParams::default()
+    };
+    // Amounts go over gRPC in base units.
+    let reward = params.block_reward.units();
+    let chain = Arc::new(RwLock::new(Blockchain::with_params(params).unwrap()));
+    let mempool = Arc::new(Mutex::new(Mempool::new()));
+    let mined = Arc::new(Mutex::new(0));
+    let counter = Arc::clone(&mined);
+    let server = GrpcServer::new(Arc::clone(&chain), Arc::clone(&mempool))
+        .with_block_hook(move |_| *counter.lock().unwrap() += 1);
+    let addr = server.listen("127.0.0.1:0").unwrap();
+
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    runtime.block_on(async {
+        let mut client = NodeClient::connect(format!("http://{}", addr)).await.unwrap();
+        let block = client.mine(MineRequest { miner: "alice".to_string(), count: 0 }).await.unwrap().into_inner();
+        assert_eq!(block.header.as_ref().unwrap().index, 1);
+
+        let tx =
+            Transaction::new("alice", "bob", Amount::from_coins(10)).with_fee(Amount::from_coins(1)).with_memo(b"hi".to_vec());
+        let submitted =
+            client.submit_transaction(proto::Transaction::from(&tx)).await.unwrap().into_inner();
+        assert_eq!((submitted.txid.as_str(), submitted.pending), (tx.hash().as_str(), 1));
+        let overdrawn = proto::Transaction::from(&Transaction::new("carol", "bob", Amount::from_coins(10)));
+        let err = client.submit_transaction(overdrawn).await.unwrap_err();
+        assert_eq!(err.code(), Code::InvalidArgument);
+
+        client.mine(MineRequest { miner: "alice".to_string(), count: 10 }).await.unwrap();
+        let info = client.get_chain_info(GetChainInfoRequest {}).await.unwrap().into_inner();
+        assert_eq!((info.height, info.pending), (2, 0));
+        let tip = client
+            .get_block(GetBlockRequest { block: Some(BlockKey::Hash(info.tip.clone())) })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(tip.header.unwrap().index, 2);
+        assert_eq!(tip.transactions[1].memo.as_deref(), Some(&b"hi"[..]));
+        assert_eq!(Transaction::try_from(tip.transactions[1].clone()).unwrap().hash(), tx.hash());
+        let missing = client.get_block(GetBlockRequest { block: Some(BlockKey::Height(9)) }).await.unwrap_err();
+        assert_eq!(missing.code(), Code::NotFound);
+
+        let balance =
+            client.get_balance(GetBalanceRequest { address: "alice".to_string() }).await.unwrap().into_inner();
+        assert_eq!((balance.balance, balance.next_sequence), (2 * reward - Amount::from_coins(10).units(), 1));
+    });
+    assert_eq!(*mined.lock().unwrap(), 2);
+    assert_eq!(chain.read().unwrap().height(), 2);
+}
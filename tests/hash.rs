@@ -0,0 +1,73 @@
+use mini_block::export::{self, ExportFormat};
+use mini_block::{Block, BlockHeader, Blockchain, GenesisConfig, HashAlgorithm, Mempool, Transaction};
+
+const ALGORITHMS: [HashAlgorithm; 3] = [HashAlgorithm::Sha256, HashAlgorithm::Sha3_256, HashAlgorithm::Blake3];
+
+fn chain_hashed_with(algorithm: HashAlgorithm) -> Blockchain {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("genesis.toml");
+    let name = serde_json::to_value(algorithm).unwrap();
+    let toml = format!("chain_id = \"hashed\"\ndifficulty = 1\nhash_algorithm = {}\n", name);
+    std::fs::write(&path, toml).unwrap();
+    let params = GenesisConfig::load(&path).unwrap().params();
+    assert_eq!(params.hash_algorithm, algorithm);
+    let mut chain = Blockchain::with_params(params).unwrap();
+    for _ in 0..3 {
+        chain.mine_pending(&mut Mempool::new(), 10, "miner").unwrap();
+    }
+    chain
+}
+
+#[test]
+fn algorithms_match_their_published_digests() {
+    let digests = ALGORITHMS.map(|algorithm| hex::encode(algorithm.digest(b"abc")));
+    assert_eq!(
+        digests,
+        [
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            "3a985da74fe225b2045c172d6bd390bd855f086e3e9d525b46bfe24511431532",
+            "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85",
+        ]
+    );
+    let header = BlockHeader::new(1, 1_800_000_000_000, "ab".repeat(32), "cd".repeat(32), 0x2100ffff);
+    let hashes = ALGORITHMS.map(|algorithm| header.clone().with_algorithm(algorithm).compute_hash());
+    assert!(hashes[0] != hashes[1] && hashes[1] != hashes[2] && hashes[0] != hashes[2]);
+}
+
+#[test]
+fn chains_reject_blocks_hashed_with_another_algorithm() {
+    let chains = ALGORITHMS.map(chain_hashed_with);
+    for (chain, algorithm) in chains.iter().zip(ALGORITHMS) {
+        assert!(chain.iter().all(|block| block.header().algorithm() == algorithm));
+        assert!(chain.is_chain_valid());
+    }
+    assert_ne!(chains[0].blocks()[0].hash(), chains[2].blocks()[0].hash());
+
+    // A BLAKE3 chain's blocks under SHA-256 parameters don't validate either.
+    let mixed = Blockchain::from_blocks(chains[2].blocks().to_vec(), chains[0].params().clone());
+    assert!(mixed.is_err() || !mixed.unwrap().is_chain_valid());
+
+    let [_, _, mut chain] = chains;
+    let tip = chain.latest_block();
+    let index = tip.index() + 1;
+    let coinbase = Transaction::coinbase("miner", chain.params().block_reward, index);
+    let timestamp = tip.timestamp() + 1000;
+    let sha256 =
+        Block::mine_at(chain.miner(), index, timestamp, vec![coinbase], tip.hash().to_string(), chain.next_bits()).unwrap();
+    let err = chain.accept_block(sha256).unwrap_err();
+    assert!(err.to_string().contains("another algorithm"), "{}", err);
+}
+
+#[test]
+fn exports_keep_the_hash_algorithm() {
+    let chain = chain_hashed_with(HashAlgorithm::Sha3_256);
+    let dir = tempfile::tempdir().unwrap();
+    for (format, file) in [(ExportFormat::Bincode, "chain.bin"), (ExportFormat::Csv, "chain.csv")] {
+        let path = dir.path().join(file);
+        export::export(&chain, &path, format).unwrap();
+        let imported = export::import(&path, format).unwrap();
+        let imported = Blockchain::from_blocks(imported.blocks().to_vec(), chain.params().clone()).unwrap();
+        assert_eq!(imported.latest_block().hash(), chain.latest_block().hash());
+        assert!(imported.is_chain_valid());
+    }
+}
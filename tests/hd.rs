@@ -0,0 +1,89 @@
+use mini_block::hd;
+use mini_block::params::DEFAULT_ADDRESS_VERSION;
+use mini_block::UnlockedWallet;
+
+/// Mnemonic→seed vectors from the BIP39 reference, all with passphrase "TREZOR".
+const BIP39_VECTORS: &[(&str, &str)] = &[
+    (
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+        concat!(
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e5349553",
+            "1f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04"
+        ),
+    ),
+    (
+        "legal winner thank year wave sausage worth useful legal winner thank yellow",
+        concat!(
+            "2e8905819b8723fe2c1d161860e5ee1830318dbf49a83bd451cfb8440c28bd6f",
+            "a457fe1296106559a3c80937a1c1069be3a3a5bd381ee6260e8d9739fce1f607"
+        ),
+    ),
+    (
+        "zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo zoo vote",
+        concat!(
+            "dd48c104698c30cfe2b6142103248622fb7bb0ff692eebb00089b32d22484e16",
+            "13912f0a5b694407be899ffd31ed3992c456cdf60f5d4564b8ba3f05a69890ad"
+        ),
+    ),
+];
+
+/// SLIP-0010 ed25519 test vector 1: private keys along m/0'/1'/2'/2'/1000000000'.
+const SLIP10_VECTOR_1: &[&str] = &[
+    "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7",
+    "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3",
+    "b1d0bad404bf35da785a64ca1ac54b2617211d2777696fbffaf208f746ae84f2",
+    "92a5b23c0b8a99e37d07df3fb9966917f5d06e02ddbd909c7e184371463e9fc9",
+    "30d1dc7e5fc04c31219ab25a27ae00b50f6fd66622f6e9c913253d6511d1e662",
+    "8f94d394a8e8fd6b1bc2f3f49f5c47e385281d5c17e65324b0f62483e37e8793",
+];
+
+/// SLIP-0010 ed25519 test vector 2: private keys along m/0'/2147483647'/1'/2147483646'/2'.
+const SLIP10_VECTOR_2: &[&str] = &[
+    "171cb88b1b3c1db25add599712e36245d75bc65a1a5c9e18d76f9f2b1eab4012",
+    "1559eb2bbec5790b0c65d8693e4d0875b1747f4970ae8b650486ed7470845635",
+    "ea4f5bfe8694d8bb74b7b59404632fd5968b774ed545e810de9c32a4fb4192f4",
+    "3757c7577170179c7868353ada796c839135b3d30554bbb74a4b1e4a5a58505c",
+    "5837736c89570de861ebc173b1086da4f505d4adb387c6a1b1342d5e4ac9ec72",
+    "551d333177df541ad876a60ea71f00447931c0a9da16f227c11ea080d7391b8d",
+];
+
+fn check_derivation(seed: &str, path: &[u32], keys: &[&str]) {
+    let seed = hex::decode(seed).unwrap();
+    for (depth, expected) in keys.iter().enumerate() {
+        let key = hd::derive_key(&seed, &path[..depth]);
+        assert_eq!(hex::encode(key.to_bytes()), *expected, "at depth {}", depth);
+    }
+}
+
+#[test]
+fn mnemonics_give_the_published_bip39_seeds() {
+    for (phrase, seed) in BIP39_VECTORS {
+        assert_eq!(hex::encode(hd::seed_with_passphrase(phrase, "TREZOR").unwrap()), *seed);
+    }
+    assert!(hd::seed_from_mnemonic("abandon abandon abandon").is_err());
+}
+
+#[test]
+fn keys_match_the_published_slip10_vectors() {
+    check_derivation("000102030405060708090a0b0c0d0e0f", &[0, 1, 2, 2, 1_000_000_000], SLIP10_VECTOR_1);
+    check_derivation(
+        concat!(
+            "fffcf9f6f3f0edeae7e4e1dedbd8d5d2cfccc9c6c3c0bdbab7b4b1aeaba8a5a2",
+            "9f9c999693908d8a8784817e7b7875726f6c696663605d5a5754514e4b484542"
+        ),
+        &[0, 2_147_483_647, 1, 2_147_483_646, 2],
+        SLIP10_VECTOR_2,
+    );
+}
+
+#[test]
+fn restoring_from_the_mnemonic_gives_the_same_addresses() {
+    let phrase = hd::generate_mnemonic(12).unwrap();
+    let mut wallet = UnlockedWallet::from_mnemonic("password", &phrase, DEFAULT_ADDRESS_VERSION).unwrap();
+    let addresses: Vec<String> = (0..3).map(|_| wallet.generate_key()).collect();
+    assert_eq!(wallet.mnemonic(), Some(phrase.as_str()));
+
+    let mut restored = UnlockedWallet::from_mnemonic("other password", &phrase, DEFAULT_ADDRESS_VERSION).unwrap();
+    let again: Vec<String> = (0..3).map(|_| restored.generate_key()).collect();
+    assert_eq!(again, addresses);
+}
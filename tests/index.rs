@@ -0,0 +1,197 @@
+use mini_block::index::{self, Direction, TxLocation};
+use mini_block::{
+    Amount, Blockchain, ChainIndex, ChainParams, ChainStore, LogStore, ManualClock, Mempool, Miner, SledStore,
+    Transaction,
+};
+use std::sync::{Arc, Mutex};
+
+#[test]
+fn history_follows_an_address_across_reorganizations() {
+    let params = ChainParams {
+        initial_difficulty: 1,
+        ..ChainParams::default()
+    };
+    let reward = params.block_reward;
+    let clock = ManualClock::new(1_800_000_000_000);
+    let mut chain = Blockchain::with_params(params).unwrap();
+    chain.set_miner(Miner::new(1).with_clock(clock.clone()));
+    let mut mempool = Mempool::new();
+    chain.mine_pending(&mut mempool, 10, "alice").unwrap();
+    let base = Blockchain::from_blocks(chain.blocks().to_vec(), chain.params().clone()).unwrap();
+    let payment = Transaction::new("alice", "bob", Amount::from_coins(10)).with_fee(Amount::from_coins(1));
+    chain.submit_transaction(&mut mempool, payment).unwrap();
+    let to_self =
+        Transaction::new("alice", "alice", Amount::from_coins(5)).with_fee(Amount::from_coins(2)).with_sequence(1);
+    chain.submit_transaction(&mut mempool, to_self).unwrap();
+    clock.advance(1000);
+    chain.mine_pending(&mut mempool, 10, "carol").unwrap();
+
+    let history = index::history(&chain, "alice");
+    let summary: Vec<_> = history.iter().map(|entry| (entry.height, entry.direction, entry.balance)).collect();
+    assert_eq!(
+        summary,
+        [
+            (1, Direction::Mined, reward),
+            (2, Direction::Sent, reward.checked_sub(Amount::from_coins(11)).unwrap()),
+            (2, Direction::ToSelf, reward.checked_sub(Amount::from_coins(13)).unwrap()),
+        ]
+    );
+    assert_eq!((history[1].counterparty.as_str(), history[1].fee), ("bob", Amount::from_coins(1)));
+    let bob = index::history(&chain, "bob");
+    assert_eq!(bob.len(), 1);
+    let bob = &bob[0];
+    assert_eq!(
+        (bob.direction, bob.counterparty.as_str(), bob.fee, bob.balance),
+        (Direction::Received, "alice", Amount::ZERO, Amount::from_coins(10))
+    );
+
+    // A heavier fork without alice's transactions takes them out of the index.
+    let mut fork = base;
+    fork.set_miner(chain.miner().clone());
+    for _ in 0..2 {
+        clock.advance(1000);
+        fork.mine_pending(&mut Mempool::new(), 10, "dave").unwrap();
+    }
+    assert!(!chain.replace_chain(fork.blocks().to_vec()).unwrap().is_empty());
+    assert!(index::history(&chain, "bob").is_empty());
+    assert_eq!(index::history(&chain, "alice").len(), 1);
+    let dave: Vec<_> = chain.transactions_for_address("dave").map(|(block, _)| block.index()).collect();
+    assert_eq!(dave, [2, 3]);
+    assert_eq!(chain.transactions_for_address("carol").count(), 0);
+}
+
+#[test]
+fn stores_keep_the_index_and_reindex_rebuilds_it() {
+    let dir = tempfile::tempdir().unwrap();
+    let params = ChainParams {
+        initial_difficulty: 1,
+        ..ChainParams::default()
+    };
+    let mut log = LogStore::open(dir.path().join("chain.log")).unwrap();
+    let mut sled = SledStore::open(dir.path().join("sled")).unwrap();
+    for store in [&mut log as &mut dyn ChainStore, &mut sled] {
+        let mut chain = Blockchain::open_store_with(store, params.clone()).unwrap();
+        let mut mempool = Mempool::new();
+        chain.mine_pending(&mut mempool, 10, "alice").unwrap();
+        chain.submit_transaction(&mut mempool, Transaction::new("alice", "bob", Amount::from_coins(10))).unwrap();
+        chain.mine_pending(&mut mempool, 10, "alice").unwrap();
+        chain.persist(store).unwrap();
+        assert_eq!(store.index().unwrap().as_ref(), Some(chain.index()));
+
+        let (block, tx) = chain.transactions_for_address("bob").next().unwrap();
+        assert_eq!(chain.index().height_of(block.hash()), Some(2));
+        assert_eq!(chain.index().transaction(&tx.hash()), Some(TxLocation { height: 2, position: 1 }));
+        assert_eq!(chain.get_transaction(&tx.hash()).unwrap().0.hash(), block.hash());
+        assert_eq!(chain.index().tip(), Some((block.hash(), 2)));
+
+        // A saved index that fell behind is caught up when the chain is opened.
+        let mut behind = ChainIndex::build(&chain.blocks()[..2]);
+        store.save_index(&behind).unwrap();
+        let reopened = Blockchain::open_store(store).unwrap();
+        assert_eq!(reopened.index(), chain.index());
+        assert!(behind.catch_up(reopened.blocks()));
+        assert_eq!(&behind, chain.index());
+        assert!(!behind.catch_up(reopened.blocks()));
+        assert_eq!(store.index().unwrap().as_ref(), Some(chain.index()));
+
+        let mut reopened = reopened;
+        store.save_index(&ChainIndex::default()).unwrap();
+        reopened.reindex(store).unwrap();
+        assert_eq!(store.index().unwrap().as_ref(), Some(chain.index()));
+    }
+}
+
+#[test]
+fn callbacks_follow_new_blocks_reorganizations_and_transactions() {
+    let params = ChainParams {
+        initial_difficulty: 1,
+        ..ChainParams::default()
+    };
+    let clock = ManualClock::new(1_800_000_000_000);
+    let miner = Miner::new(1).with_clock(clock.clone());
+    let mut chain = Blockchain::with_params(params).unwrap();
+    chain.set_miner(miner.clone());
+    let base = Blockchain::from_blocks(chain.blocks().to_vec(), chain.params().clone()).unwrap();
+    let log: Arc<Mutex<Vec<String>>> = Arc::default();
+    let record = |log: &Arc<Mutex<Vec<String>>>| {
+        let log = Arc::clone(log);
+        move |entry: String| log.lock().unwrap().push(entry)
+    };
+    let added = chain.on_block_added({
+        let record = record(&log);
+        move |block| record(format!("added {}", block.index()))
+    });
+    chain.on_reorg({
+        let record = record(&log);
+        move |rolled_back, connected| record(format!("reorg -{} +{}", rolled_back.len(), connected.len()))
+    });
+    chain.on_tx_accepted({
+        let record = record(&log);
+        move |txid, tx| record(format!("tx to {} {}", tx.receiver(), txid == tx.hash()))
+    });
+
+    let mut mempool = Mempool::new();
+    chain.mine_pending(&mut mempool, 10, "alice").unwrap();
+    chain.submit_transaction(&mut mempool, Transaction::new("alice", "bob", Amount::from_coins(1))).unwrap();
+    clock.advance(1000);
+    chain.mine_pending(&mut mempool, 10, "carol").unwrap();
+    let mut fork = base;
+    fork.set_miner(miner);
+    for _ in 0..3 {
+        clock.advance(1000);
+        fork.mine_pending(&mut Mempool::new(), 10, "dave").unwrap();
+    }
+    chain.replace_chain(fork.blocks().to_vec()).unwrap();
+    clock.advance(1000);
+    fork.mine_pending(&mut Mempool::new(), 10, "dave").unwrap();
+    chain.accept_block(fork.latest_block().clone()).unwrap();
+    assert_eq!(*log.lock().unwrap(), ["added 1", "tx to bob true", "added 2", "reorg -2 +3", "added 4"]);
+
+    assert!(chain.event_bus().remove(added));
+    assert!(!chain.event_bus().remove(added));
+    clock.advance(1000);
+    chain.mine_pending(&mut mempool, 10, "alice").unwrap();
+    assert_eq!(log.lock().unwrap().len(), 5);
+}
+
+#[test]
+fn one_transaction_can_pay_several_receivers() {
+    let mut params = ChainParams {
+        initial_difficulty: 1,
+        ..ChainParams::default()
+    };
+    params.genesis_allocations.insert("alice".to_string(), Amount::from_coins(20));
+    let mut chain = Blockchain::with_params(params).unwrap();
+    let mut mempool = Mempool::new();
+    let single = Transaction::new("alice", "bob", Amount::from_coins(5)).with_fee(Amount::from_coins(1));
+    let batch = single.clone().with_payment("bob", Amount::from_coins(3)).with_payment("carol", Amount::from_coins(2));
+    assert_ne!(batch.hash(), single.hash());
+    assert_ne!(batch.signature_hash("main"), single.signature_hash("main"));
+    assert_eq!(batch.cost().unwrap(), Amount::from_coins(11));
+    assert_eq!(batch.amount_to("bob"), Amount::from_coins(8));
+    assert_eq!(batch.parties(), ["alice", "bob", "carol"]);
+
+    let too_much =
+        Transaction::new("alice", "bob", Amount::from_coins(15)).with_payment("carol", Amount::from_coins(6));
+    assert!(chain.submit_transaction(&mut mempool, too_much).is_err());
+    let txid = batch.hash();
+    chain.submit_transaction(&mut mempool, batch).unwrap();
+    chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+    let balances: Vec<_> = ["alice", "bob", "carol"].iter().map(|address| chain.balance_of(address)).collect();
+    assert_eq!(balances, [Amount::from_coins(9), Amount::from_coins(8), Amount::from_coins(2)]);
+    let utxos = chain.utxo_set().unwrap();
+    let carol: Vec<_> = utxos.outputs_for("carol").map(|(outpoint, output)| (outpoint.vout, output.amount)).collect();
+    assert_eq!(carol, [(2, Amount::from_coins(2))]);
+
+    let sent = index::history(&chain, "alice").pop().unwrap();
+    assert_eq!((sent.direction, sent.counterparty.as_str()), (Direction::Sent, "bob, carol"));
+    assert_eq!(sent.amount, Amount::from_coins(10));
+    let received = index::history(&chain, "bob").pop().unwrap();
+    assert_eq!(received.txid, txid);
+    assert_eq!((received.amount, received.balance), (Amount::from_coins(8), Amount::from_coins(8)));
+    assert_eq!(chain.index().address("carol").len(), 1);
+
+    let issue = Transaction::issue("alice", "GOLD", Amount::from_coins(5)).with_payment("bob", Amount::from_coins(1));
+    let err = chain.submit_transaction(&mut mempool, issue.with_sequence(1)).unwrap_err();
+    assert!(err.to_string().contains("one receiver"), "{}", err);
+}
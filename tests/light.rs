@@ -0,0 +1,90 @@
+use std::sync::{Arc, RwLock};
+
+use mini_block::network::{LightClient, Node};
+use mini_block::{
+    Amount, BlockHeader, Blockchain, ChainParams, HeaderChain, ManualClock, Mempool, Miner, Target, Transaction,
+};
+
+const START: u64 = 1_800_000_000_000;
+
+/// A chain of five blocks where alice paid bob in block #2, and that
+/// payment's ID.
+fn paid_chain() -> (Blockchain, String) {
+    let mut params = ChainParams {
+        initial_difficulty: 1,
+        retarget_interval: 2,
+        ..ChainParams::default()
+    };
+    params.genesis_allocations.insert("alice".to_string(), Amount::from_coins(100));
+    let clock = ManualClock::new(START);
+    let mut chain = Blockchain::with_params(params).unwrap();
+    chain.set_miner(Miner::new(1).with_clock(clock.clone()));
+    let mut mempool = Mempool::new();
+    let payment = Transaction::new("alice", "bob", Amount::from_coins(30)).with_fee(Amount::from_coins(1));
+    let txid = payment.hash();
+    for height in 1..5 {
+        if height == 2 {
+            chain.submit_transaction(&mut mempool, payment.clone()).unwrap();
+        }
+        clock.advance(1000);
+        chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+    }
+    (chain, txid)
+}
+
+#[test]
+fn header_chains_check_headers_and_transaction_proofs() {
+    let (chain, txid) = paid_chain();
+    let mut headers = HeaderChain::new(chain.params().clone()).unwrap();
+    assert_eq!(headers.genesis_hash(), chain.blocks()[0].hash());
+    let received: Vec<_> = chain.blocks()[1..].iter().map(|block| block.header().clone()).collect();
+    assert_eq!(headers.extend(received.clone()).unwrap(), 4);
+    assert_eq!(headers.extend(received[2..].to_vec()).unwrap(), 0);
+    assert_eq!((headers.height(), headers.cumulative_work()), (4, chain.cumulative_work()));
+
+    let proof = chain.transaction_proof(&txid).unwrap();
+    assert_eq!(proof.block_hash, chain.blocks()[2].hash());
+    assert_eq!(headers.verify(&proof).unwrap(), 3);
+    let mut forged = proof.clone();
+    forged.transaction = Transaction::new("alice", "bob", Amount::from_coins(300)).with_fee(Amount::from_coins(1));
+    assert!(headers.verify(&forged).unwrap_err().to_string().contains("different transaction"));
+    forged.proof.tx_hash = forged.transaction.hash();
+    assert!(headers.verify(&forged).unwrap_err().to_string().contains("Merkle root"));
+    assert!(chain.transaction_proof("missing").is_none());
+
+    // A header claiming an easier target than the chain requires is refused.
+    let mut fresh = HeaderChain::new(chain.params().clone()).unwrap();
+    let first = &received[0];
+    let (root, genesis) = (first.merkle_root().to_string(), headers.genesis_hash().to_string());
+    let easy = BlockHeader::new(1, first.timestamp(), root, genesis, Target::MAX.to_bits());
+    assert!(fresh.extend(vec![easy]).unwrap_err().to_string().contains("wrong target"));
+    assert_eq!(fresh.height(), 0);
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("headers.json");
+    headers.save(&path).unwrap();
+    let loaded = HeaderChain::load(&path, chain.params().clone()).unwrap();
+    assert_eq!(loaded.tip(), headers.tip());
+    assert!(HeaderChain::load(&path, ChainParams::default()).is_err());
+}
+
+#[test]
+fn light_clients_sync_headers_and_proofs_from_full_nodes() {
+    let (chain, txid) = paid_chain();
+    let params = chain.params().clone();
+    let node = Node::new(Arc::new(RwLock::new(chain)));
+    let addr = node.listen("127.0.0.1:0").unwrap();
+
+    let mut headers = HeaderChain::new(params.clone()).unwrap();
+    let mut client = LightClient::connect(addr, &headers).unwrap();
+    assert_eq!(client.sync(&mut headers).unwrap(), 4);
+    assert_eq!(client.sync(&mut headers).unwrap(), 0);
+    let proof = client.fetch_proof(&txid).unwrap().unwrap();
+    assert_eq!(headers.verify(&proof).unwrap(), 3);
+    assert!(client.fetch_proof("missing").unwrap().is_none());
+    // The node counts the client as a peer but never syncs from it.
+    assert_eq!(node.peer_count(), 1);
+
+    let other = HeaderChain::new(ChainParams::default()).unwrap();
+    assert!(LightClient::connect(addr, &other).is_err());
+}
@@ -0,0 +1,403 @@
+use mini_block::fee;
+use mini_block::{
+    Amount, Block, Blockchain, ChainProfile, Mempool, MempoolLimits, OutPoint, Transaction, TxCheck,
+};
+use std::sync::{Arc, Mutex};
+
+mod common;
+
+/// A block from elsewhere on top of `chain` holding `transactions`.
+fn next_block(chain: &Blockchain, transactions: Vec<Transaction>) -> Block {
+    let tip = chain.latest_block();
+    let index = tip.index() + 1;
+    let coinbase = Transaction::coinbase("miner", chain.params().block_reward, index);
+    let transactions = [vec![coinbase], transactions].concat();
+    Block::mine_at(chain.miner(), index, tip.timestamp() + 1, transactions, tip.hash().to_string(), chain.next_bits())
+        .unwrap()
+}
+
+#[test]
+fn saved_mempools_load_back_in_order() {
+    let mut chain = Blockchain::with_params(ChainProfile::Regtest.params()).unwrap();
+    let mut mempool = Mempool::new();
+    chain.mine_pending(&mut mempool, 10, "alice").unwrap();
+    for (sequence, receiver) in ["bob", "carol"].into_iter().enumerate() {
+        let tx = Transaction::new("alice", receiver, Amount::from_coins(5)).with_sequence(sequence as u64);
+        chain.submit_transaction(&mut mempool, tx).unwrap();
+    }
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("mempool.json");
+    assert!(Mempool::load(&path).unwrap().is_empty());
+    mempool.save(&path).unwrap();
+    assert!(!dir.path().join("mempool.json.tmp").exists());
+
+    let saved = Mempool::load(&path).unwrap();
+    let hashes = |txs: &[Transaction]| txs.iter().map(Transaction::hash).collect::<Vec<_>>();
+    assert_eq!(hashes(&saved), hashes(&mempool.iter().cloned().collect::<Vec<_>>()));
+    let mut restored = Mempool::new();
+    for tx in saved {
+        chain.submit_transaction(&mut restored, tx).unwrap();
+    }
+    chain.mine_pending(&mut restored, 10, "miner").unwrap();
+    assert_eq!(chain.balance_of("carol"), Amount::from_coins(5));
+}
+
+#[test]
+fn persisted_mempools_are_saved_on_every_change() {
+    let mut chain = Blockchain::with_params(ChainProfile::Regtest.params()).unwrap();
+    let mut mempool = Mempool::new();
+    chain.mine_pending(&mut mempool, 10, "alice").unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("mempool.json");
+    mempool.persist_to(&path);
+
+    let tx = Transaction::new("alice", "bob", Amount::from_coins(5));
+    chain.submit_transaction(&mut mempool, tx.clone()).unwrap();
+    let saved = Mempool::load(&path).unwrap();
+    assert_eq!(saved.iter().map(Transaction::hash).collect::<Vec<_>>(), [tx.hash()]);
+    // Mined transactions leave the file too.
+    chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+    assert!(Mempool::load(&path).unwrap().is_empty());
+}
+
+#[test]
+fn full_mempools_evict_the_lowest_fee_rates() {
+    let mut chain = Blockchain::with_params(ChainProfile::Regtest.params()).unwrap();
+    for miner in ["alice", "bob", "carol", "dave"] {
+        chain.mine_pending(&mut Mempool::new(), 10, miner).unwrap();
+    }
+    let limits = MempoolLimits {
+        max_transactions: 2,
+        ..MempoolLimits::default()
+    };
+    let mut mempool = Mempool::with_limits(limits);
+    let cheap = Transaction::new("alice", "erin", Amount::from_coins(1)).with_fee(Amount::from_coins(1));
+    let cheap_next =
+        Transaction::new("alice", "erin", Amount::from_coins(1)).with_fee(Amount::from_coins(9)).with_sequence(1);
+    chain.submit_transaction(&mut mempool, cheap.clone()).unwrap();
+    chain.submit_transaction(&mut mempool, cheap_next).unwrap();
+
+    // A fee rate below everything it would have to evict is not enough.
+    let err =
+        chain.submit_transaction(&mut mempool, Transaction::new("bob", "erin", Amount::from_coins(1))).unwrap_err();
+    assert!(err.to_string().contains("mempool is full"), "{}", err);
+    assert_eq!(mempool.len(), 2);
+
+    // Evicting alice's first transaction takes her later one with it.
+    let generous = Transaction::new("carol", "erin", Amount::from_coins(1)).with_fee(Amount::from_coins(5));
+    chain.submit_transaction(&mut mempool, generous.clone()).unwrap();
+    let pending: Vec<_> = mempool.iter().map(Transaction::hash).collect();
+    assert_eq!(pending, [generous.hash()]);
+    assert_eq!(mempool.stats().fees, Amount::from_coins(5));
+    let middling = Transaction::new("dave", "erin", Amount::from_coins(1)).with_fee(Amount::from_coins(2));
+    chain.submit_transaction(&mut mempool, middling).unwrap();
+    assert_eq!(mempool.len(), 2);
+}
+
+#[test]
+fn pending_transactions_expire_after_enough_blocks() {
+    let mut chain = Blockchain::with_params(ChainProfile::Regtest.params()).unwrap();
+    chain.mine_pending(&mut Mempool::new(), 10, "alice").unwrap();
+    let limits = MempoolLimits {
+        expiry_blocks: 3,
+        ..MempoolLimits::default()
+    };
+    let mut mempool = Mempool::with_limits(limits);
+    // Locked until block #10, so mining leaves it pending.
+    let locked = Transaction::new("alice", "bob", Amount::from_coins(1)).with_lock_time(10);
+    chain.submit_transaction(&mut mempool, locked).unwrap();
+    let follower = Transaction::new("alice", "bob", Amount::from_coins(1)).with_sequence(1);
+    for _ in 0..2 {
+        chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+    }
+    chain.submit_transaction(&mut mempool, follower).unwrap();
+    assert_eq!(mempool.stats().oldest_height, Some(1));
+    // The follower can't apply once the locked transaction before it expires.
+    chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+    assert!(mempool.is_empty());
+    assert_eq!(chain.balance_of("bob"), Amount::ZERO);
+}
+
+#[test]
+fn expiring_utxo_spends_leave_the_senders_account_transactions() {
+    let limits = MempoolLimits {
+        expiry_blocks: 3,
+        ..MempoolLimits::default()
+    };
+    let mut mempool = Mempool::with_limits(limits);
+    let input = OutPoint {
+        txid: "ab".repeat(32),
+        vout: 0,
+    };
+    let spend = Transaction::spending("alice", "bob", Amount::from_coins(1), vec![input], Amount::ZERO);
+    mempool.insert(spend.clone(), 0).unwrap();
+    for sequence in 0..2 {
+        let tx = Transaction::new("alice", "carol", Amount::from_coins(1)).with_sequence(sequence);
+        mempool.insert(tx, 2).unwrap();
+    }
+    let expired = mempool.expire(3);
+    assert_eq!(expired.iter().map(Transaction::hash).collect::<Vec<_>>(), [spend.hash()]);
+    assert_eq!(mempool.len(), 2);
+}
+
+#[test]
+fn restored_transactions_keep_their_age() {
+    let mut chain = Blockchain::with_params(ChainProfile::Regtest.params()).unwrap();
+    chain.mine_pending(&mut Mempool::new(), 10, "alice").unwrap();
+    let limits = MempoolLimits {
+        expiry_blocks: 2,
+        ..MempoolLimits::default()
+    };
+    let mut mempool = Mempool::with_limits(limits);
+    let locked = Transaction::new("alice", "bob", Amount::from_coins(1)).with_lock_time(10);
+    chain.submit_transaction(&mut mempool, locked).unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("mempool.json");
+    mempool.save(&path).unwrap();
+
+    let saved = Mempool::load_entries(&path).unwrap();
+    assert_eq!(saved.iter().map(|(_, queued_at)| *queued_at).collect::<Vec<_>>(), [Some(1)]);
+    chain.mine_pending(&mut Mempool::new(), 10, "miner").unwrap();
+    let (tx, queued_at) = saved[0].clone();
+    let mut restored = Mempool::with_limits(limits);
+    chain.restore_transaction(&mut restored, tx.clone(), queued_at.unwrap()).unwrap();
+    assert_eq!(restored.entries().map(|(_, queued_at)| queued_at).collect::<Vec<_>>(), [1]);
+
+    chain.mine_pending(&mut Mempool::new(), 10, "miner").unwrap();
+    let err = chain.restore_transaction(&mut Mempool::with_limits(limits), tx, 1).unwrap_err();
+    assert!(err.to_string().contains("expired"), "{}", err);
+}
+
+#[test]
+fn blocks_from_elsewhere_drop_the_pending_transactions_they_invalidate() {
+    let mut params = ChainProfile::Regtest.params();
+    params.genesis_allocations.insert("alice".to_string(), Amount::from_coins(100));
+    params.genesis_allocations.insert("dave".to_string(), Amount::from_coins(100));
+    let mut ours = Blockchain::with_params(params.clone()).unwrap();
+    let mut theirs = Blockchain::with_params(params).unwrap();
+    let mut mempool = Mempool::new();
+    let conflicting = Transaction::new("alice", "bob", Amount::from_coins(5)).with_sequence(0);
+    let unrelated = Transaction::new("dave", "bob", Amount::from_coins(5)).with_sequence(0);
+    ours.submit_transaction(&mut mempool, conflicting.clone()).unwrap();
+    ours.submit_transaction(&mut mempool, unrelated.clone()).unwrap();
+    assert!(ours.drop_invalid_pending(&mut mempool).is_empty());
+
+    // Another node mines a payment from alice using the same sequence.
+    let mut elsewhere = Mempool::new();
+    let confirmed_elsewhere = Transaction::new("alice", "carol", Amount::from_coins(5)).with_sequence(0);
+    theirs.submit_transaction(&mut elsewhere, confirmed_elsewhere).unwrap();
+    theirs.mine_pending(&mut elsewhere, 10, "miner").unwrap();
+    ours.accept_block(theirs.latest_block().clone()).unwrap();
+
+    let dropped: Vec<_> = ours.drop_invalid_pending(&mut mempool).iter().map(Transaction::hash).collect();
+    assert_eq!(dropped, [conflicting.hash()]);
+    assert!(mempool.contains(&unrelated.hash()));
+}
+
+#[test]
+fn transactions_validate_without_being_queued() {
+    let mut chain = Blockchain::with_params(ChainProfile::Regtest.params()).unwrap();
+    chain.mine_pending(&mut Mempool::new(), 10, "alice").unwrap();
+    let limits = MempoolLimits {
+        max_transactions: 1,
+        ..MempoolLimits::default()
+    };
+    let mut mempool = Mempool::with_limits(limits);
+    let check = |mempool: &Mempool, tx: &Transaction| chain.validate_transaction(mempool, tx).map_err(|err| err.check);
+
+    let tx = Transaction::new("alice", "bob", Amount::from_coins(5)).with_fee(Amount::from_coins(1));
+    assert_eq!(check(&mempool, &tx), Ok(()));
+    assert!(mempool.is_empty());
+    let overdraft = Transaction::new("alice", "bob", chain.balance_of("alice")).with_fee(Amount::from_coins(1));
+    assert_eq!(check(&mempool, &overdraft), Err(TxCheck::Balance));
+    let skipped = Transaction::new("alice", "bob", Amount::from_coins(5)).with_sequence(3);
+    assert_eq!(check(&mempool, &skipped), Err(TxCheck::Sequence));
+    assert_eq!(check(&mempool, &Transaction::coinbase("alice", Amount::from_coins(5), 1)), Err(TxCheck::Coinbase));
+    let missing = OutPoint {
+        txid: "00".repeat(32),
+        vout: 0,
+    };
+    let spend = Transaction::spending("alice", "bob", Amount::from_coins(1), vec![missing], Amount::ZERO);
+    assert_eq!(check(&mempool, &spend), Err(TxCheck::Inputs));
+
+    chain.submit_transaction(&mut mempool, tx.clone()).unwrap();
+    assert_eq!(check(&mempool, &tx), Err(TxCheck::Duplicate));
+    // The mempool is full, and the newcomer pays no more than what it holds.
+    let cheap = Transaction::new("alice", "carol", Amount::from_coins(1)).with_sequence(1);
+    let err = chain.validate_transaction(&mempool, &cheap).unwrap_err();
+    assert_eq!(err.check, TxCheck::Fee);
+    let submitted = chain.submit_transaction(&mut mempool, cheap).unwrap_err();
+    assert_eq!(submitted.to_string(), format!("validation failed: {}", err));
+}
+
+#[test]
+fn pending_transactions_are_replaced_by_higher_fee_versions() {
+    let mut chain = Blockchain::with_params(ChainProfile::Regtest.params()).unwrap();
+    let mut mempool = Mempool::new();
+    chain.mine_pending(&mut mempool, 10, "alice").unwrap();
+    let replaced = Arc::new(Mutex::new(Vec::new()));
+    let seen = Arc::clone(&replaced);
+    chain.on_tx_replaced(move |txid, original, _| seen.lock().unwrap().push((txid.to_string(), original.to_string())));
+    let first = Transaction::new("alice", "bob", Amount::from_coins(20)).with_fee(Amount::from_coins(1));
+    let next = Transaction::new("alice", "carol", Amount::from_coins(20)).with_sequence(1);
+    chain.submit_transaction(&mut mempool, first.clone()).unwrap();
+    chain.submit_transaction(&mut mempool, next.clone()).unwrap();
+
+    // The fee must go up, and the sender still afford everything pending.
+    let same_fee = Transaction::new("alice", "bob", Amount::from_coins(19)).with_fee(Amount::from_coins(1));
+    for cheaper in [same_fee, first.clone().with_fee(Amount::from_units(50_000_000))] {
+        let err = chain.validate_transaction(&mempool, &cheaper).unwrap_err();
+        assert_eq!(err.check, TxCheck::Fee, "{}", err);
+    }
+    let greedy = Transaction::new("alice", "bob", Amount::from_coins(29)).with_fee(Amount::from_coins(2));
+    assert_eq!(chain.validate_transaction(&mempool, &greedy).unwrap_err().check, TxCheck::Balance);
+
+    let bumped = first.clone().with_fee(Amount::from_coins(3));
+    chain.submit_transaction(&mut mempool, bumped.clone()).unwrap();
+    let pending: Vec<String> = mempool.iter().map(Transaction::hash).collect();
+    assert_eq!(pending.len(), 2);
+    assert!(pending.contains(&bumped.hash()) && pending.contains(&next.hash()));
+    assert_eq!(*replaced.lock().unwrap(), [(bumped.hash(), first.hash())]);
+    assert_eq!(chain.next_sequence(&mempool, "alice").unwrap(), 2);
+
+    chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+    assert_eq!(chain.balance_of("bob"), Amount::from_coins(20));
+    assert!(chain.get_transaction(&first.hash()).is_none());
+}
+
+#[test]
+fn fees_are_estimated_from_recent_blocks_and_the_pending_transactions() {
+    let mut chain = Blockchain::with_params(ChainProfile::Regtest.params()).unwrap();
+    let mut mempool = Mempool::new();
+    for _ in 0..3 {
+        chain.mine_pending(&mut mempool, 10, "alice").unwrap();
+    }
+    let estimate = fee::estimate_fee(&chain, &mempool, 1);
+    assert_eq!((estimate.blocks_sampled, estimate.fee_rate, estimate.fee), (3, 0, Amount::ZERO));
+    assert!(estimate.typical_size > 0);
+
+    // A block's worth pending: outbid it to make the next block, or wait one more.
+    for sequence in 0..10 {
+        let tx = Transaction::new("alice", "bob", Amount::from_coins(1)).with_sequence(sequence);
+        chain.submit_transaction(&mut mempool, tx.with_fee(Amount::from_units(1_000_000 + sequence))).unwrap();
+    }
+    let lowest = mempool.iter().map(|tx| tx.fee().units() / tx.size() as u64).min().unwrap();
+    let estimate = fee::estimate_fee(&chain, &mempool, 1);
+    assert_eq!((estimate.mempool_rate, estimate.pending), (lowest + 1, 10));
+    assert_eq!(estimate.fee_rate, lowest + 1);
+    assert_eq!(fee::estimate_fee(&chain, &mempool, 2).fee_rate, 0);
+
+    // Once mined, only one recent block in four was full, which is enough to
+    // wait out over two blocks but not one.
+    chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+    let confirmed = &chain.latest_block().transactions()[1..];
+    let marginal = confirmed.iter().map(|tx| tx.fee().units().div_ceil(tx.size() as u64)).min().unwrap();
+    let estimate = fee::estimate_fee(&chain, &mempool, 1);
+    assert_eq!((estimate.history_rate, estimate.mempool_rate, estimate.blocks_sampled), (marginal, 0, 4));
+    assert_eq!(estimate.fee, estimate.fee_for(estimate.typical_size));
+    assert!(confirmed.iter().any(|tx| tx.size() == estimate.typical_size));
+    assert_eq!(fee::estimate_fee(&chain, &mempool, 2).fee_rate, 0);
+}
+
+#[test]
+fn relay_policy_refuses_low_fees_and_dust_but_blocks_may_hold_them() {
+    let mut chain = Blockchain::with_params(ChainProfile::Regtest.params()).unwrap();
+    let mut elsewhere = Blockchain::with_params(ChainProfile::Regtest.params()).unwrap();
+    let mut mempool = Mempool::with_limits(MempoolLimits {
+        min_fee_rate: 10,
+        dust_limit: "0.001".parse().unwrap(),
+        ..MempoolLimits::default()
+    });
+    chain.mine_pending(&mut mempool, 10, "alice").unwrap();
+    elsewhere.accept_block(chain.latest_block().clone()).unwrap();
+
+    let free = Transaction::new("alice", "bob", Amount::from_coins(1));
+    assert_eq!(chain.validate_transaction(&mempool, &free).unwrap_err().check, TxCheck::Fee);
+    let minimum = mempool.limits().min_fee_for(1_000);
+    let dust = Transaction::new("alice", "bob", Amount::from_units(1_000)).with_fee(minimum);
+    assert_eq!(chain.validate_transaction(&mempool, &dust).unwrap_err().check, TxCheck::Dust);
+    // Data-only transactions create no output, so carry no dust.
+    let memo = Transaction::new("alice", "alice", Amount::ZERO).with_memo("hello").with_fee(minimum);
+    chain.validate_transaction(&mempool, &memo).unwrap();
+
+    // Change too small to keep is left to the miner.
+    let fee = Amount::from_units(99_950_000);
+    let tx = chain.build_utxo_transaction(&mempool, "alice", "bob", Amount::from_coins(49), fee).unwrap();
+    assert_eq!((tx.change(), tx.fee()), (Amount::ZERO, Amount::from_coins(1)));
+    chain.submit_transaction(&mut mempool, tx).unwrap();
+
+    // A block from a node with no such policy is still valid here.
+    let mut open = Mempool::new();
+    elsewhere.submit_transaction(&mut open, free).unwrap();
+    elsewhere.submit_transaction(&mut open, dust.with_sequence(1)).unwrap();
+    elsewhere.mine_pending(&mut open, 10, "miner").unwrap();
+    chain.accept_block(elsewhere.latest_block().clone()).unwrap();
+    assert_eq!(chain.balance_of("bob"), Amount::from_units(100_001_000));
+}
+
+#[test]
+fn outputs_cannot_be_spent_twice() {
+    let mut chain = common::chain(1, common::FUNDED_ALICE);
+    let mut mempool = Mempool::new();
+    let check = |chain: &Blockchain, mempool: &Mempool, tx: &Transaction| {
+        chain.validate_transaction(mempool, tx).map_err(|err| err.check)
+    };
+
+    let pay = chain.build_utxo_transaction(&mempool, "alice", "bob", Amount::from_coins(30), Amount::ZERO).unwrap();
+    let inputs = pay.inputs().to_vec();
+    let rival = Transaction::spending("alice", "carol", Amount::from_coins(30), inputs.clone(), pay.change());
+    assert_eq!(check(&chain, &mempool, &rival), Ok(()));
+    // Both spend the same output, so a block may hold only one of them.
+    let err = chain.accept_block(next_block(&chain, vec![pay.clone(), rival.clone()])).unwrap_err();
+    assert!(err.to_string().contains("already spent"), "{}", err);
+    chain.submit_transaction(&mut mempool, pay.clone()).unwrap();
+    assert_eq!(check(&chain, &mempool, &rival), Err(TxCheck::Inputs));
+
+    chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+    assert_eq!(chain.balance_of("bob"), Amount::from_coins(30));
+    assert_eq!(chain.balance_of("alice"), Amount::from_coins(70));
+    let utxos = chain.utxo_set().unwrap();
+    assert!(inputs.iter().all(|input| utxos.get(input).is_none()));
+    let err = chain.validate_transaction(&mempool, &rival).unwrap_err();
+    assert_eq!(err.check, TxCheck::Inputs);
+    assert!(err.to_string().contains("already spent"), "{}", err);
+    let output = OutPoint {
+        txid: pay.hash(),
+        vout: 0,
+    };
+    let twice = Transaction::spending("bob", "carol", Amount::from_coins(60), vec![output; 2], Amount::ZERO);
+    let err = chain.validate_transaction(&mempool, &twice).unwrap_err();
+    assert!(err.to_string().contains("twice"), "{}", err);
+}
+
+#[test]
+fn account_transactions_cannot_be_replayed() {
+    let mut chain = common::chain(1, common::FUNDED_ALICE);
+    let mut mempool = Mempool::new();
+    let check = |chain: &Blockchain, mempool: &Mempool, tx: &Transaction| {
+        chain.validate_transaction(mempool, tx).map_err(|err| err.check)
+    };
+    let pay = Transaction::new("alice", "bob", Amount::from_coins(10));
+    assert_eq!(pay.sequence(), 0);
+    chain.submit_transaction(&mut mempool, pay.clone()).unwrap();
+    assert_eq!(chain.next_sequence(&mempool, "alice").unwrap(), 1);
+    chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+    assert_eq!(chain.next_sequence(&mempool, "alice").unwrap(), 1);
+
+    // Re-broadcasting the mined transaction, or another one reusing its
+    // sequence, is refused, and so is a block carrying either.
+    assert_eq!(check(&chain, &mempool, &pay), Err(TxCheck::Duplicate));
+    let reuse = Transaction::new("alice", "bob", Amount::from_coins(11));
+    assert_eq!(check(&chain, &mempool, &reuse), Err(TxCheck::Sequence));
+    assert!(chain.accept_block(next_block(&chain, vec![pay.clone()])).is_err());
+    let err = chain.accept_block(next_block(&chain, vec![reuse])).unwrap_err();
+    assert!(err.to_string().contains("expected 1"), "{}", err);
+    let skipped = Transaction::new("alice", "bob", Amount::from_coins(10)).with_sequence(2);
+    assert_eq!(check(&chain, &mempool, &skipped), Err(TxCheck::Sequence));
+    assert_eq!(chain.balance_of("bob"), Amount::from_coins(10));
+
+    let next = Transaction::new("alice", "bob", Amount::from_coins(10)).with_sequence(1);
+    chain.accept_block(next_block(&chain, vec![next])).unwrap();
+    assert_eq!(chain.balance_of("bob"), Amount::from_coins(20));
+    assert_eq!(chain.next_sequence(&mempool, "alice").unwrap(), 2);
+}
@@ -0,0 +1,95 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use mini_block::network::Node;
+use mini_block::noise::SecureStream;
+use mini_block::{Blockchain, ChainParams, Mempool, NodeKey};
+
+mod common;
+
+#[test]
+fn node_keys_are_saved_and_reloaded() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("node.key");
+    let key = NodeKey::load_or_generate(&path).unwrap();
+    assert_eq!(key.public_key().len(), 64);
+    assert_eq!(NodeKey::load_or_generate(&path).unwrap().public_key(), key.public_key());
+    assert_ne!(NodeKey::generate().public_key(), key.public_key());
+    assert!(NodeKey::from_hex("abcd").is_err());
+}
+
+#[test]
+fn peers_sync_over_encrypted_connections_and_learn_each_others_keys() {
+    let mut ahead = common::chain(1, &[]);
+    ahead.mine_pending(&mut Mempool::new(), 10, "miner").unwrap();
+    let (seed_key, joiner_key) = (NodeKey::generate(), NodeKey::generate());
+    let seed = Node::new(Arc::new(RwLock::new(ahead))).with_key(seed_key.clone());
+    let addr = seed.listen("127.0.0.1:0").unwrap();
+    let joiner = Node::new(Arc::new(RwLock::new(common::chain(1, &[])))).with_key(joiner_key.clone());
+    joiner.connect(addr).unwrap();
+
+    assert!(common::wait_until(|| joiner.chain().read().unwrap().height() == 1));
+    assert_eq!(joiner.peer_keys()[0].1, seed_key.public_key());
+    assert!(common::wait_until(|| seed.peer_keys().first().is_some_and(|(_, key)| *key == joiner_key.public_key())));
+}
+
+#[test]
+fn allowlists_refuse_unknown_keys() {
+    let friend = NodeKey::generate();
+    let node = Node::new(Arc::new(RwLock::new(common::chain(1, &[])))).with_allowlist([friend.public_key()]);
+    let addr = node.listen("127.0.0.1:0").unwrap();
+
+    let stranger = Node::new(Arc::new(RwLock::new(common::chain(1, &[]))));
+    assert!(stranger.connect(addr).is_err());
+    assert_eq!((node.peer_count(), stranger.peer_count()), (0, 0));
+
+    let friendly = Node::new(Arc::new(RwLock::new(common::chain(1, &[])))).with_key(friend);
+    friendly.connect(addr).unwrap();
+    assert!(common::wait_until(|| node.peer_count() == 1));
+}
+
+#[test]
+fn peers_on_other_networks_are_dropped_at_the_handshake() {
+    let node = Node::new(Arc::new(RwLock::new(common::chain(1, &[]))));
+    let addr = node.listen("127.0.0.1:0").unwrap();
+    let params = ChainParams {
+        chain_id: "testnet".to_string(),
+        initial_difficulty: 1,
+        ..ChainParams::default()
+    };
+    assert_ne!(params.network_magic(), ChainParams::default().network_magic());
+    let magic = params.network_magic();
+    let stranger = Node::new(Arc::new(RwLock::new(Blockchain::with_params(params).unwrap())));
+    assert!(stranger.connect(addr).is_err());
+    assert_eq!((node.peer_count(), stranger.peer_count()), (0, 0));
+
+    // The side that reads the first foreign frame says why.
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let dialed = listener.local_addr().unwrap();
+    let dialer = thread::spawn(move || {
+        SecureStream::handshake(TcpStream::connect(dialed).unwrap(), &NodeKey::generate(), true, magic).is_err()
+    });
+    let (stream, _) = listener.accept().unwrap();
+    let err = SecureStream::handshake(stream, &NodeKey::generate(), false, ChainParams::default().network_magic())
+        .err()
+        .unwrap();
+    assert!(err.to_string().contains("another network"), "{}", err);
+    assert!(dialer.join().unwrap());
+}
+
+#[test]
+fn plaintext_peers_are_dropped() {
+    let node = Node::new(Arc::new(RwLock::new(common::chain(1, &[]))));
+    let addr = node.listen("127.0.0.1:0").unwrap();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    writeln!(stream, r#"{{"type":"GetPeers"}}"#).unwrap();
+    stream.shutdown(std::net::Shutdown::Write).unwrap();
+    let mut reply = Vec::new();
+    let _ = stream.read_to_end(&mut reply);
+    assert!(!String::from_utf8_lossy(&reply).contains("Hello"));
+    assert_eq!(node.peer_count(), 0);
+}
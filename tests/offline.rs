@@ -0,0 +1,79 @@
+use mini_block::offline::TransactionFile;
+use mini_block::params::DEFAULT_ADDRESS_VERSION;
+use mini_block::{Amount, Blockchain, ChainParams, Mempool, Script, Transaction, UnlockedWallet, Wallet};
+
+#[test]
+fn transactions_built_online_are_signed_offline_and_broadcast() {
+    let mut cold = UnlockedWallet::create("password", DEFAULT_ADDRESS_VERSION).unwrap();
+    let address = cold.generate_key();
+    let key = cold.signing_key(&address).unwrap().verifying_key();
+    let mut params = ChainParams {
+        initial_difficulty: 1,
+        ..ChainParams::default()
+    };
+    params.genesis_allocations.insert("alice".to_string(), Amount::from_coins(100));
+    let mut chain = Blockchain::with_params(params).unwrap();
+    let mut mempool = Mempool::new();
+    let deposit =
+        Transaction::new("alice", address.as_str(), Amount::from_coins(40)).with_lock(Script::pay_to_key(&key));
+    chain.submit_transaction(&mut mempool, deposit).unwrap();
+    chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+
+    // The online machine has no keys, but knows which outputs need one.
+    let tx = chain
+        .build_unsigned_transaction(&mempool, &address, "bob", Amount::from_coins(25), Amount::from_coins(1))
+        .unwrap();
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("tx.json");
+    TransactionFile::new(&chain, tx).unwrap().save(&path).unwrap();
+
+    let mut file = TransactionFile::load(&path).unwrap();
+    assert_eq!((file.unsigned_inputs(), file.spent[0].amount), (1, Amount::from_coins(40)));
+    let mut other = UnlockedWallet::create("password", DEFAULT_ADDRESS_VERSION).unwrap();
+    other.generate_key();
+    assert_eq!(file.sign(&other), 0);
+    assert!(chain.validate_transaction(&mempool, &file.transaction).is_err());
+    assert_eq!(file.sign(&cold), 1);
+    assert!(file.is_signed());
+    file.save(&path).unwrap();
+
+    let signed = TransactionFile::load(&path).unwrap();
+    chain.submit_transaction(&mut mempool, signed.transaction).unwrap();
+    chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+    assert_eq!(chain.balance_of("bob"), Amount::from_coins(25));
+    assert_eq!(chain.balance_of(&address), Amount::from_coins(14));
+}
+
+#[test]
+fn watched_addresses_are_kept_without_keys() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("wallet.json");
+    let mut hot = UnlockedWallet::create("password", DEFAULT_ADDRESS_VERSION).unwrap();
+    let own = hot.generate_key();
+    hot.save(&path).unwrap();
+    let mut cold = UnlockedWallet::create("password", DEFAULT_ADDRESS_VERSION).unwrap();
+    let address = cold.generate_key();
+
+    // Watching needs no password.
+    let mut wallet = Wallet::load(&path).unwrap();
+    assert!(wallet.watch(&address).unwrap());
+    assert!(!wallet.watch(&address).unwrap());
+    assert!(wallet.watch(&own).is_err());
+    let mut foreign = UnlockedWallet::create("password", DEFAULT_ADDRESS_VERSION + 1).unwrap();
+    assert!(wallet.watch(&foreign.generate_key()).is_err());
+    wallet.save(&path).unwrap();
+
+    let mut unlocked = Wallet::load(&path).unwrap().unlock("password").unwrap();
+    assert_eq!((unlocked.addresses(), unlocked.watched()), (vec![own.clone()], &[address.clone()][..]));
+    assert!(unlocked.is_watch_only(&address) && !unlocked.is_watch_only(&own));
+    assert!(unlocked.signing_key(&address).is_none());
+    unlocked.generate_key();
+    unlocked.save(&path).unwrap();
+    assert_eq!(Wallet::load(&path).unwrap().watched(), std::slice::from_ref(&address));
+
+    let key = cold.signing_key(&address).unwrap().verifying_key();
+    let lock = Script::pay_to_key(&key);
+    let tx = Transaction::new(address.as_str(), "bob", Amount::from_coins(1));
+    let signed = unlocked.sign_inputs(tx.clone(), &[Some(&lock)], "regtest");
+    assert_eq!(signed.hash(), tx.hash());
+}
@@ -0,0 +1,77 @@
+use std::sync::{Arc, RwLock};
+
+use mini_block::network::Node;
+use mini_block::{Block, Blockchain, Mempool, OrphanPool};
+
+mod common;
+
+/// Blocks 1 to `count` of a fresh chain.
+fn mined(count: usize) -> Vec<Block> {
+    let mut chain = common::chain(1, &[]);
+    for _ in 0..count {
+        chain.mine_pending(&mut Mempool::new(), 10, "miner").unwrap();
+    }
+    chain.blocks()[1..].to_vec()
+}
+
+#[test]
+fn orphans_are_released_in_order_once_their_ancestry_arrives() {
+    let blocks = mined(4);
+    let mut pool = OrphanPool::default();
+    assert!(pool.insert(blocks[3].clone()));
+    assert!(pool.insert(blocks[1].clone()));
+    assert!(pool.insert(blocks[2].clone()));
+    assert!(!pool.insert(blocks[2].clone()));
+    assert_eq!(pool.len(), 3);
+    assert_eq!(pool.missing_ancestor(blocks[3].hash()).as_deref(), Some(blocks[0].hash()));
+    assert_eq!(pool.missing_ancestor(blocks[0].hash()), None);
+
+    assert!(pool.take_descendants(blocks[1].hash()).len() == 2 && pool.len() == 1);
+    let mut pool = OrphanPool::default();
+    for block in blocks.iter().rev() {
+        pool.insert(block.clone());
+    }
+    let genesis = common::chain(1, &[]).blocks()[0].hash().to_string();
+    let released: Vec<u64> = pool.take_descendants(&genesis).iter().map(Block::index).collect();
+    assert_eq!(released, [1, 2, 3, 4]);
+    assert!(pool.is_empty());
+}
+
+#[test]
+fn orphan_pools_are_bounded_and_hold_only_proven_work() {
+    let blocks = mined(3);
+    let mut pool = OrphanPool::new(2);
+    for block in &blocks {
+        assert!(pool.insert(block.clone()));
+    }
+    assert_eq!(pool.len(), 2);
+    assert!(!pool.contains(blocks[0].hash()));
+
+    let mut forged: serde_json::Value = serde_json::to_value(&blocks[0]).unwrap();
+    forged["hash"] = serde_json::json!("00".repeat(32));
+    let forged: Block = serde_json::from_value(forged).unwrap();
+    assert!(!OrphanPool::default().insert(forged));
+}
+
+#[test]
+fn nodes_fetch_the_missing_parent_of_a_gossiped_block() {
+    let ours = common::chain(1, &[]);
+    let theirs = Blockchain::from_blocks(ours.blocks().to_vec(), ours.params().clone()).unwrap();
+    let a = Node::new(Arc::new(RwLock::new(ours)));
+    let b = Node::new(Arc::new(RwLock::new(theirs)));
+    let addr = b.listen("127.0.0.1:0").unwrap();
+    a.connect(addr).unwrap();
+    assert!(common::wait_until(|| a.peer_count() == 1 && b.peer_count() == 1));
+
+    let tip = {
+        let mut chain = a.chain().write().unwrap();
+        for _ in 0..2 {
+            chain.mine_pending(&mut Mempool::new(), 10, "miner").unwrap();
+        }
+        chain.latest_block().clone()
+    };
+    // Only the second block is gossiped, as if the first got lost.
+    a.broadcast_block(&tip);
+    assert!(common::wait_until(|| b.chain().read().unwrap().latest_block().hash() == tip.hash()));
+    assert_eq!(b.orphan_count(), 0);
+}
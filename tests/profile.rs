@@ -0,0 +1,31 @@
+use mini_block::address;
+use mini_block::{Blockchain, ChainProfile, Mempool};
+
+#[test]
+fn profiles_are_distinct_networks() {
+    let genesis: Vec<String> = ChainProfile::ALL
+        .iter()
+        .map(|profile| Blockchain::with_params(profile.params()).unwrap().latest_block().hash().to_string())
+        .collect();
+    assert_eq!(genesis[0], Blockchain::new().unwrap().latest_block().hash());
+    assert!(genesis[0] != genesis[1] && genesis[1] != genesis[2] && genesis[0] != genesis[2]);
+
+    for (profile, prefixes) in ChainProfile::ALL.iter().zip(["M", "mn", "R"]) {
+        assert_eq!(profile.to_string().parse::<ChainProfile>().unwrap(), *profile);
+        for payload in [[0u8; address::HASH_LEN], [0xff; address::HASH_LEN]] {
+            let encoded = address::encode(profile.params().address_version, &payload);
+            assert!(prefixes.contains(&encoded[..1]), "{} address {}", profile, encoded);
+        }
+    }
+    assert!("devnet".parse::<ChainProfile>().is_err());
+}
+
+#[test]
+fn regtest_blocks_need_no_work() {
+    let mut chain = Blockchain::with_params(ChainProfile::Regtest.params()).unwrap();
+    for _ in 0..20 {
+        chain.mine_pending(&mut Mempool::new(), 10, "miner").unwrap();
+    }
+    assert_eq!(chain.height(), 20);
+    assert!(chain.is_chain_valid());
+}
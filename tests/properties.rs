@@ -0,0 +1,228 @@
+use mini_block::export::{self, ExportFormat};
+use mini_block::{
+    Amount, Block, BlockHeader, Blockchain, ChainParams, LogStore, ManualClock, Mempool, Target, Transaction,
+};
+use proptest::prelude::*;
+use proptest::sample::Index;
+use serde_json::Value;
+
+const ACCOUNTS: [&str; 4] = ["alice", "bob", "carol", "dave"];
+
+/// Something done to a chain through its public API.
+#[derive(Debug, Clone)]
+enum Action {
+    Transfer { from: usize, to: usize, amount: u32, fee: u32 },
+    Spend { from: usize, to: usize, amount: u32, fee: u32 },
+    Mine { miner: usize, max: usize },
+}
+
+fn action() -> impl Strategy<Value = Action> {
+    let account = 0..ACCOUNTS.len();
+    prop_oneof![
+        3 => (account.clone(), account.clone(), 1..80u32, 0..5u32)
+            .prop_map(|(from, to, amount, fee)| Action::Transfer { from, to, amount, fee }),
+        2 => (account.clone(), account.clone(), 1..80u32, 0..5u32)
+            .prop_map(|(from, to, amount, fee)| Action::Spend { from, to, amount, fee }),
+        1 => (account, 1..5usize).prop_map(|(miner, max)| Action::Mine { miner, max }),
+    ]
+}
+
+fn params() -> ChainParams {
+    let mut params = ChainParams {
+        initial_difficulty: 1,
+        retarget_interval: 4,
+        ..ChainParams::default()
+    };
+    params.genesis_allocations.insert("alice".to_string(), Amount::from_coins(200));
+    params.genesis_allocations.insert("bob".to_string(), Amount::from_coins(100));
+    params
+}
+
+/// Applies `actions`, ignoring transactions the chain refuses, then mines
+/// whatever is still pending. Blocks are a second apart on a manual clock, so
+/// the same actions always build the same chain.
+fn build_chain(actions: &[Action]) -> Blockchain {
+    let mut chain = Blockchain::with_params(params()).unwrap();
+    let clock = ManualClock::new(params().genesis_timestamp as u64);
+    chain.set_miner(chain.miner().clone().with_clock(clock.clone()));
+    let mut mempool = Mempool::new();
+    for action in actions {
+        clock.advance(1000);
+        match *action {
+            Action::Transfer { from, to, amount, fee } => {
+                let sequence = chain.next_sequence(&mempool, ACCOUNTS[from]).unwrap();
+                let tx = Transaction::new(ACCOUNTS[from], ACCOUNTS[to], Amount::from_coins(amount))
+                    .with_fee(Amount::from_coins(fee))
+                    .with_sequence(sequence);
+                let _ = chain.submit_transaction(&mut mempool, tx);
+            }
+            Action::Spend { from, to, amount, fee } => {
+                let (amount, fee) = (Amount::from_coins(amount), Amount::from_coins(fee));
+                if let Ok(tx) = chain.build_utxo_transaction(&mempool, ACCOUNTS[from], ACCOUNTS[to], amount, fee) {
+                    let _ = chain.submit_transaction(&mut mempool, tx);
+                }
+            }
+            Action::Mine { miner, max } => {
+                chain.mine_pending(&mut mempool, max, ACCOUNTS[miner]).unwrap();
+            }
+        }
+    }
+    while !mempool.is_empty() {
+        clock.advance(1000);
+        chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+    }
+    chain
+}
+
+fn hashes(blocks: &[Block]) -> Vec<String> {
+    blocks.iter().map(|block| block.hash().to_string()).collect()
+}
+
+fn txids(blocks: &[Block]) -> Vec<Vec<String>> {
+    blocks
+        .iter()
+        .map(|block| block.transactions().iter().map(Transaction::hash).collect())
+        .collect()
+}
+
+/// Paths to every scalar field of the serialized blocks.
+fn fields(value: &Value, path: &mut Vec<String>, out: &mut Vec<Vec<String>>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                path.push(key.clone());
+                fields(value, path, out);
+                path.pop();
+            }
+        }
+        Value::Array(items) => {
+            for (i, value) in items.iter().enumerate() {
+                path.push(i.to_string());
+                fields(value, path, out);
+                path.pop();
+            }
+        }
+        _ => out.push(path.clone()),
+    }
+}
+
+fn field_mut<'a>(mut value: &'a mut Value, path: &[String]) -> &'a mut Value {
+    for key in path {
+        value = match value {
+            Value::Array(items) => &mut items[key.parse::<usize>().unwrap()],
+            value => &mut value[key.as_str()],
+        };
+    }
+    value
+}
+
+/// Changes a value to a different one of the same type.
+fn mutate(value: &mut Value) {
+    match value {
+        Value::Number(n) => *value = (n.as_u64().unwrap() ^ 1).into(),
+        Value::String(s) => {
+            let last = if s.ends_with('0') { '1' } else { '0' };
+            s.pop();
+            s.push(last);
+        }
+        other => panic!("unexpected field {}", other),
+    }
+}
+
+#[test]
+fn chains_are_deterministic_on_a_manual_clock() {
+    let actions = [
+        Action::Transfer { from: 0, to: 2, amount: 50, fee: 1 },
+        Action::Spend { from: 1, to: 3, amount: 40, fee: 2 },
+        Action::Mine { miner: 2, max: 4 },
+        Action::Transfer { from: 2, to: 0, amount: 10, fee: 0 },
+    ];
+    let first = build_chain(&actions);
+    assert_eq!(first.blocks().len(), 3);
+    assert_eq!(hashes(first.blocks()), hashes(build_chain(&actions).blocks()));
+
+    // A nonce-start hook changes where the search begins, not what is valid.
+    let mut chain = Blockchain::with_params(params()).unwrap();
+    chain.set_miner(
+        chain
+            .miner()
+            .clone()
+            .with_clock(ManualClock::new(params().genesis_timestamp as u64 + 1000))
+            .with_nonce_start(|header| header.index() * 1_000_000),
+    );
+    chain.mine_pending(&mut Mempool::new(), 10, "miner").unwrap();
+    assert!(chain.latest_block().header().nonce() >= 1_000_000);
+    assert!(chain.is_chain_valid());
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(48))]
+
+    #[test]
+    fn chains_built_through_the_api_are_valid(actions in prop::collection::vec(action(), 0..40)) {
+        let chain = build_chain(&actions);
+        let report = chain.validate_detailed();
+        prop_assert!(report.is_valid(), "{:?}", report.violations);
+
+        // Another node accepts the same blocks one at a time.
+        let mut replica = Blockchain::with_params(params()).unwrap();
+        for block in &chain.blocks()[1..] {
+            replica.accept_block(block.clone()).unwrap();
+        }
+        prop_assert_eq!(hashes(replica.blocks()), hashes(chain.blocks()));
+        prop_assert_eq!(replica.balances(), chain.balances());
+    }
+
+    #[test]
+    fn any_single_field_mutation_invalidates_the_chain(
+        actions in prop::collection::vec(action(), 0..20),
+        pick in any::<Index>(),
+    ) {
+        let chain = build_chain(&actions);
+        let mut value = serde_json::to_value(chain.blocks()).unwrap();
+        let mut paths = Vec::new();
+        fields(&value, &mut Vec::new(), &mut paths);
+        let path = pick.get(&paths);
+        mutate(field_mut(&mut value, path));
+
+        let blocks: Vec<Block> = serde_json::from_value(value).unwrap();
+        let mutated = Blockchain::from_blocks(blocks, params()).unwrap();
+        prop_assert!(!mutated.is_chain_valid(), "mutating {:?} went unnoticed", path);
+    }
+
+    #[test]
+    fn header_hashers_agree_with_full_hashes(index: u64, timestamp: u128, nonce: u64, zeros in 0..4usize) {
+        let target = Target::from_leading_zeros(zeros);
+        let mut header = BlockHeader::new(index, timestamp, "ab".repeat(32), "cd".repeat(32), target.to_bits());
+        let digest = header.hasher().hash(nonce);
+        header.set_nonce(nonce);
+        prop_assert_eq!(digest, header.hash_bytes());
+        prop_assert_eq!(hex::encode(digest), header.compute_hash());
+        prop_assert_eq!(target.is_met_by_digest(&digest), target.is_met_by(&header.compute_hash()));
+    }
+
+    #[test]
+    fn serialization_round_trips_preserve_hashes(actions in prop::collection::vec(action(), 0..20)) {
+        let chain = build_chain(&actions);
+        let blocks = chain.blocks();
+        let dir = tempfile::tempdir().unwrap();
+        let mut decoded = vec![serde_json::from_str::<Blockchain>(&serde_json::to_string(&chain).unwrap()).unwrap()];
+        for format in [ExportFormat::Json, ExportFormat::Cbor, ExportFormat::Bincode] {
+            let path = dir.path().join("chain");
+            export::export(&chain, &path, format).unwrap();
+            decoded.push(export::import(&path, format).unwrap());
+        }
+        let mut store = LogStore::open(dir.path().join("chain.log")).unwrap();
+        chain.persist(&mut store).unwrap();
+        decoded.push(Blockchain::open_store(&mut store).unwrap());
+
+        for decoded in decoded.iter().map(Blockchain::blocks) {
+            prop_assert_eq!(hashes(decoded), hashes(blocks));
+            prop_assert_eq!(txids(decoded), txids(blocks));
+            for block in decoded {
+                prop_assert_eq!(block.header().compute_hash(), block.hash());
+            }
+            prop_assert!(Blockchain::from_blocks(decoded.to_vec(), params()).unwrap().is_chain_valid());
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use mini_block::params::DEFAULT_ADDRESS_VERSION;
+use mini_block::{KeyBackup, UnlockedWallet, qr};
+
+#[test]
+fn addresses_and_key_backups_render_as_qr_codes() {
+    let mut wallet = UnlockedWallet::create("password", DEFAULT_ADDRESS_VERSION).unwrap();
+    let address = wallet.generate_key();
+    let drawn = qr::to_terminal(&address).unwrap();
+    // Two rows of modules per line, so a square code is twice as wide as it is tall.
+    let lines: Vec<usize> = drawn.lines().map(|line| line.chars().count()).collect();
+    assert!(lines.iter().all(|width| *width == lines.len() * 2 - 1), "{:?}", lines);
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("address.png");
+    qr::write_png(&address, &path, 3).unwrap();
+    let decoder = png::Decoder::new(std::io::BufReader::new(std::fs::File::open(&path).unwrap()));
+    let info = decoder.read_info().unwrap().info().clone();
+    assert_eq!(info.width, info.height);
+    assert_eq!(info.width % 3, 0);
+
+    // The backup survives the trip through JSON, which is what the code holds.
+    let backup = wallet.backup_key(&address).unwrap();
+    let backup: KeyBackup = serde_json::from_str(&serde_json::to_string(&backup).unwrap()).unwrap();
+    assert_eq!(backup.decrypt("password").unwrap().to_bytes(), wallet.signing_key(&address).unwrap().to_bytes());
+    assert!(backup.decrypt("wrong").is_err());
+    assert!(wallet.backup_key("bob").is_err());
+}
@@ -0,0 +1,65 @@
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::Duration;
+
+use mini_block::network::Node;
+use mini_block::{Amount, Mempool, Transaction};
+
+mod common;
+
+type Shared<T> = Arc<Mutex<T>>;
+
+/// A node relaying transactions through its own mempool.
+fn node() -> (Node, Shared<Mempool>) {
+    let mempool = Arc::new(Mutex::new(Mempool::new()));
+    let node = Node::new(Arc::new(RwLock::new(common::chain(1, common::FUNDED_ALICE)))).with_mempool(mempool.clone());
+    (node, mempool)
+}
+
+fn submit(node: &Node, mempool: &Shared<Mempool>, tx: Transaction) {
+    let chain = node.chain().read().unwrap();
+    chain.submit_transaction(&mut mempool.lock().unwrap(), tx).unwrap();
+}
+
+#[test]
+fn transactions_are_relayed_across_the_network_and_mined_anywhere() {
+    let (hub, hub_pool) = node();
+    let addr = hub.listen("127.0.0.1:0").unwrap();
+    let (sender, sender_pool) = node();
+    let (miner, miner_pool) = node();
+    sender.connect(addr).unwrap();
+    miner.connect(addr).unwrap();
+    assert!(common::wait_until(|| hub.peer_count() == 2));
+
+    // The sender and miner only reach each other through the hub.
+    let tx = Transaction::new("alice", "bob", Amount::from_coins(30)).with_fee(Amount::from_coins(1));
+    let txid = tx.hash();
+    submit(&sender, &sender_pool, tx);
+    assert!(common::wait_until(|| miner_pool.lock().unwrap().contains(&txid)));
+    assert!(hub_pool.lock().unwrap().contains(&txid));
+
+    let mut chain = miner.chain().write().unwrap();
+    chain.mine_pending(&mut miner_pool.lock().unwrap(), 10, "miner").unwrap();
+    assert!(chain.get_transaction(&txid).is_some());
+    assert_eq!(chain.balance_of("bob"), Amount::from_coins(30));
+}
+
+#[test]
+fn new_peers_are_sent_the_pending_transactions() {
+    let (seed, seed_pool) = node();
+    let addr = seed.listen("127.0.0.1:0").unwrap();
+    let tx = Transaction::new("alice", "bob", Amount::from_coins(30));
+    let txid = tx.hash();
+    submit(&seed, &seed_pool, tx);
+
+    let (joiner, joiner_pool) = node();
+    joiner.connect(addr).unwrap();
+    assert!(common::wait_until(|| joiner_pool.lock().unwrap().contains(&txid)));
+
+    // A node without a mempool ignores the announcements.
+    let bystander = Node::new(Arc::new(RwLock::new(common::chain(1, common::FUNDED_ALICE))));
+    bystander.connect(addr).unwrap();
+    assert!(common::wait_until(|| seed.peer_count() == 2));
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!((seed.peer_count(), bystander.peer_count()), (2, 1));
+}
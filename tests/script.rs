@@ -0,0 +1,208 @@
+use ed25519_dalek::SigningKey;
+use mini_block::script::{self, MAX_STACK_DEPTH, Op};
+use mini_block::params::DEFAULT_ADDRESS_VERSION;
+use mini_block::{
+    Amount, Blockchain, ChainParams, Mempool, OutPoint, Script, SignatureCache, Transaction, UnlockedWallet,
+};
+use sha2::{Digest, Sha256};
+use std::cell::Cell;
+
+fn run(unlock: &str, lock: &str) -> mini_block::Result<()> {
+    script::verify(&unlock.parse().unwrap(), &lock.parse().unwrap(), b"message")
+}
+
+#[test]
+fn scripts_round_trip_through_text() {
+    let text = "dup hash 00ff equal false checksig";
+    let script: Script = text.parse().unwrap();
+    assert_eq!(script.ops()[2], Op::Push(vec![0, 0xff]));
+    assert_eq!(script.ops()[4], Op::Push(Vec::new()));
+    assert_eq!(script.to_string(), text);
+    assert_eq!(serde_json::to_string(&script).unwrap(), format!("\"{}\"", text));
+    assert!("dup nope".parse::<Script>().is_err());
+    assert!("abc".parse::<Script>().is_err());
+}
+
+#[test]
+fn the_interpreter_leaves_true_only_when_conditions_hold() {
+    assert!(run("01", "").is_ok());
+    assert!(run("00", "").is_err());
+    assert!(run("", "").is_err());
+    assert!(run("0102", "dup equal").is_ok());
+    assert!(run("01 02", "equal").is_err());
+    assert!(run("", "dup").is_err());
+    assert!(run("01", "equal").is_err());
+    // Unlocking scripts may only push.
+    assert!(run("01 dup", "equal").is_err());
+
+    let preimage = b"open sesame";
+    let lock = Script::hash_lock(Sha256::digest(preimage).into());
+    let unlock = |data: &[u8]| Script::new(vec![Op::Push(data.to_vec())]);
+    assert!(script::verify(&unlock(preimage), &lock, b"").is_ok());
+    assert!(script::verify(&unlock(b"open sesame!"), &lock, b"").is_err());
+
+    let too_deep = vec!["dup"; MAX_STACK_DEPTH].join(" ");
+    assert!(run("01", &too_deep).is_err());
+}
+
+#[test]
+fn checksig_verifies_signatures_over_the_spending_transaction() {
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let lock = Script::pay_to_key(&key.verifying_key());
+    let tx = Transaction::new("bob", "carol", Amount::from_coins(5));
+    let signature = Script::signature(&key, &tx, "main");
+    assert!(script::verify(&signature, &lock, tx.signature_hash("main").as_bytes()).is_ok());
+
+    let other = Transaction::new("bob", "carol", Amount::from_coins(6));
+    assert!(script::verify(&signature, &lock, other.signature_hash("main").as_bytes()).is_err());
+    let forged = Script::signature(&SigningKey::from_bytes(&[8; 32]), &tx, "main");
+    assert!(script::verify(&forged, &lock, tx.signature_hash("main").as_bytes()).is_err());
+    // Signatures only hold on the chain they were made for.
+    assert!(script::verify(&signature, &lock, tx.signature_hash("test").as_bytes()).is_err());
+    // Unlocking scripts are not signed, but are part of the ID.
+    let unlocked = tx.clone().with_unlocks(vec![signature]);
+    assert_eq!(unlocked.signature_hash("main"), tx.signature_hash("main"));
+    assert_ne!(unlocked.hash(), tx.hash());
+}
+
+#[test]
+fn locked_outputs_need_their_unlocking_script_on_chain() {
+    let mut params = ChainParams {
+        initial_difficulty: 1,
+        ..ChainParams::default()
+    };
+    params.genesis_allocations.insert("alice".to_string(), Amount::from_coins(100));
+    let mut chain = Blockchain::with_params(params).unwrap();
+    let mut mempool = Mempool::new();
+    let key = SigningKey::from_bytes(&[7; 32]);
+
+    let pay = chain
+        .build_utxo_transaction(&mempool, "alice", "bob", Amount::from_coins(40), Amount::ZERO)
+        .unwrap()
+        .with_lock(Script::pay_to_key(&key.verifying_key()));
+    let locked = OutPoint {
+        txid: pay.hash(),
+        vout: 0,
+    };
+    chain.submit_transaction(&mut mempool, pay).unwrap();
+    chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+    // The wallet-style builder leaves locked outputs alone.
+    assert!(chain.build_utxo_transaction(&mempool, "bob", "carol", Amount::from_coins(1), Amount::ZERO).is_err());
+
+    let spend = Transaction::spending("bob", "carol", Amount::from_coins(40), vec![locked], Amount::ZERO);
+    let err = chain.submit_transaction(&mut mempool, spend.clone()).unwrap_err();
+    assert!(err.to_string().contains("script failed"), "{}", err);
+    let chain_id = chain.params().chain_id.clone();
+    let wrong_key = Script::signature(&SigningKey::from_bytes(&[8; 32]), &spend, &chain_id);
+    assert!(chain.submit_transaction(&mut mempool, spend.clone().with_unlocks(vec![wrong_key])).is_err());
+    let other_network = Script::signature(&key, &spend, "testnet");
+    assert!(chain.submit_transaction(&mut mempool, spend.clone().with_unlocks(vec![other_network])).is_err());
+
+    let signature = Script::signature(&key, &spend, &chain_id);
+    chain.submit_transaction(&mut mempool, spend.with_unlocks(vec![signature])).unwrap();
+    chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+    assert_eq!(chain.balance_of("carol"), Amount::from_coins(40));
+    assert!(chain.is_chain_valid());
+}
+
+#[test]
+fn valid_signatures_are_cached_with_the_oldest_evicted() {
+    let cache = SignatureCache::new(2);
+    let checks = Cell::new(0);
+    let check = |valid| {
+        checks.set(checks.get() + 1);
+        valid
+    };
+    assert!(cache.verify("tx1", b"key", b"sig", || check(true)));
+    assert!(cache.verify("tx1", b"key", b"sig", || check(true)));
+    assert_eq!((checks.get(), cache.hits()), (1, 1));
+    // Another signature by the same key for the same transaction is checked.
+    assert!(!cache.verify("tx1", b"key", b"forged", || check(false)));
+    assert!(!cache.verify("tx1", b"key", b"forged", || check(false)));
+    assert_eq!((checks.get(), cache.len()), (3, 1));
+
+    assert!(cache.verify("tx2", b"key", b"sig", || check(true)));
+    assert!(cache.verify("tx3", b"key", b"sig", || check(true)));
+    assert_eq!(cache.len(), 2);
+    assert!(cache.verify("tx1", b"key", b"sig", || check(true)));
+    assert_eq!(checks.get(), 6);
+    cache.clear();
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn revalidating_a_chain_reuses_its_verified_signatures() {
+    let mut params = ChainParams {
+        initial_difficulty: 1,
+        ..ChainParams::default()
+    };
+    params.genesis_allocations.insert("alice".to_string(), Amount::from_coins(100));
+    let mut chain = Blockchain::with_params(params).unwrap();
+    let mut mempool = Mempool::new();
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let chain_id = chain.params().chain_id.clone();
+    let mut spends = Vec::new();
+    for _ in 0..3 {
+        let pay = chain
+            .build_utxo_transaction(&mempool, "alice", "bob", Amount::from_coins(10), Amount::ZERO)
+            .unwrap()
+            .with_lock(Script::pay_to_key(&key.verifying_key()));
+        let locked = OutPoint {
+            txid: pay.hash(),
+            vout: 0,
+        };
+        chain.submit_transaction(&mut mempool, pay).unwrap();
+        chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+        spends.push(Transaction::spending("bob", "carol", Amount::from_coins(10), vec![locked], Amount::ZERO));
+    }
+    for spend in spends {
+        let signature = Script::signature(&key, &spend, &chain_id);
+        chain.submit_transaction(&mut mempool, spend.with_unlocks(vec![signature])).unwrap();
+    }
+    assert_eq!(chain.signature_cache().len(), 3);
+    chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+
+    let hits = chain.signature_cache().hits();
+    chain.validate_full().unwrap();
+    // Each is looked up by the parallel pass and again by the check after it.
+    assert_eq!(chain.signature_cache().hits(), hits + 6);
+    assert_eq!(chain.balance_of("carol"), Amount::from_coins(30));
+
+    // A chain without them verifies every signature, in parallel.
+    let reloaded = Blockchain::from_blocks(chain.blocks().to_vec(), chain.params().clone()).unwrap();
+    reloaded.validate().unwrap();
+    assert_eq!((reloaded.signature_cache().len(), reloaded.signature_cache().hits()), (3, 3));
+}
+
+#[test]
+fn wallets_sign_the_outputs_locked_to_their_keys() {
+    let mut wallet = UnlockedWallet::create("password", DEFAULT_ADDRESS_VERSION).unwrap();
+    let address = wallet.generate_key();
+    let key = wallet.signing_key(&address).unwrap().verifying_key();
+    let mut params = ChainParams {
+        initial_difficulty: 1,
+        ..ChainParams::default()
+    };
+    params.genesis_allocations.insert("alice".to_string(), Amount::from_coins(100));
+    let mut chain = Blockchain::with_params(params).unwrap();
+    let mut mempool = Mempool::new();
+    let locked =
+        Transaction::new("alice", address.as_str(), Amount::from_coins(30)).with_lock(Script::pay_to_key(&key));
+    let plain = Transaction::new("alice", address.as_str(), Amount::from_coins(5)).with_sequence(1);
+    chain.submit_transaction(&mut mempool, locked).unwrap();
+    chain.submit_transaction(&mut mempool, plain).unwrap();
+    chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+
+    let tx = chain
+        .build_transaction_for_key(&mempool, &address, &key, "carol", Amount::from_coins(32), Amount::from_coins(1))
+        .unwrap();
+    assert_eq!((tx.inputs().len(), tx.change()), (2, Amount::from_coins(2)));
+    assert!(chain.validate_transaction(&mempool, &tx).is_err());
+    let signed = wallet.sign(tx, &chain.utxo_set().unwrap(), &chain.params().chain_id);
+    // Only the locked output, spent first as the larger, needs a signature.
+    assert_eq!(signed.unlocks().len(), 1);
+    chain.submit_transaction(&mut mempool, signed).unwrap();
+    chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+    assert_eq!(chain.balance_of("carol"), Amount::from_coins(32));
+    assert_eq!(chain.balance_of(&address), Amount::from_coins(2));
+}
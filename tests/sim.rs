@@ -0,0 +1,33 @@
+use std::time::Duration;
+
+use mini_block::sim::{self, Partition, SimConfig};
+
+#[test]
+fn partitions_are_parsed_from_round_ranges_and_node_lists() {
+    let partition: Partition = "2..5:0, 1".parse().unwrap();
+    assert_eq!(partition, Partition { from: 2, to: 5, isolated: vec![0, 1] });
+    for invalid in ["2..5", "5..2:0", "2-5:0", "2..5:", "2..5:a"] {
+        assert!(invalid.parse::<Partition>().is_err(), "{}", invalid);
+    }
+}
+
+#[test]
+fn nodes_agree_once_a_partition_heals() {
+    let config = SimConfig {
+        nodes: 3,
+        rounds: 6,
+        latency: Duration::from_millis(10),
+        round_time: Duration::from_millis(50),
+        partitions: vec!["1..4:0".parse().unwrap()],
+        seed: 7,
+        ..SimConfig::default()
+    };
+    let report = sim::run(&config).unwrap();
+    assert!(report.consistent, "{:?}", report);
+    assert_eq!(report.blocks_mined, 7);
+    assert!(report.transactions_submitted > 0);
+    assert!(report.heights.iter().all(|height| *height == report.heights[0]));
+
+    let too_many = SimConfig { nodes: 20, ..SimConfig::default() };
+    assert!(sim::run(&too_many).is_err());
+}
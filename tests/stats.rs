@@ -0,0 +1,126 @@
+use mini_block::{Amount, Block, BlockHeader, Blockchain, ChainParams, ManualClock, Mempool, Miner, Transaction, stats};
+
+#[test]
+fn stats_summarize_the_chain() {
+    let params = ChainParams {
+        initial_difficulty: 1,
+        ..ChainParams::default()
+    };
+    let reward = params.block_reward;
+    let clock = ManualClock::new(1_800_000_000_000);
+    let mut chain = Blockchain::with_params(params).unwrap();
+    chain.set_miner(Miner::new(1).with_clock(clock.clone()));
+    let empty = stats::stats(&chain, 5);
+    assert_eq!((empty.height, empty.average_block_interval_ms, empty.average_nonce), (0, None, 0.0));
+
+    let mut mempool = Mempool::new();
+    chain.mine_pending(&mut mempool, 10, "alice").unwrap();
+    for sequence in 0..3 {
+        let tx =
+            Transaction::new("alice", "bob", Amount::from_coins(5)).with_fee(Amount::from_coins(1)).with_sequence(sequence);
+        chain.submit_transaction(&mut mempool, tx).unwrap();
+    }
+    clock.advance(4000);
+    chain.mine_pending(&mut mempool, 10, "carol").unwrap();
+    clock.advance(2000);
+    chain.mine_pending(&mut mempool, 10, "carol").unwrap();
+
+    let stats = stats::stats(&chain, 2);
+    assert_eq!((stats.height, stats.transactions, stats.pruned_blocks), (3, 3, 0));
+    assert_eq!(stats.average_block_interval_ms, Some(3000.0));
+    assert_eq!(stats.average_transactions_per_block, 1.0);
+    assert_eq!(stats.circulation, reward.checked_mul(3).unwrap());
+    let carol = reward.checked_mul(2).and_then(|mined| mined.checked_add(Amount::from_coins(3))).unwrap();
+    let alice = reward.checked_sub(Amount::from_coins(18)).unwrap();
+    assert_eq!(stats.top_addresses, [("carol".to_string(), carol), ("alice".to_string(), alice)]);
+    let nonces: u64 = chain.iter().skip(1).map(|block| block.nonce()).sum();
+    assert_eq!(stats.average_nonce, nonces as f64 / 3.0);
+    assert!(stats.average_work >= 1.0);
+}
+
+#[test]
+fn block_rewards_halve_on_schedule() {
+    let params = ChainParams {
+        initial_difficulty: 1,
+        halving_interval: 4,
+        ..ChainParams::default()
+    };
+    let reward = params.block_reward;
+    assert_eq!(params.block_reward_at(3), reward);
+    assert_eq!(params.block_reward_at(4), Amount::from_coins(25));
+    assert_eq!(params.block_reward_at(9), "12.5".parse().unwrap());
+    assert_eq!(params.block_reward_at(4 * 64), Amount::ZERO);
+    assert_eq!((params.next_halving(1), params.next_halving(4)), (Some(4), Some(8)));
+    assert_eq!(params.next_halving(4 * 40), None);
+    assert_eq!(params.total_supply(), Some("349.99999956".parse().unwrap()));
+    assert_eq!(ChainParams::default().total_supply(), None);
+
+    let mut chain = Blockchain::with_params(params).unwrap();
+    let mut mempool = Mempool::new();
+    for _ in 0..5 {
+        chain.mine_pending(&mut mempool, 10, "alice").unwrap();
+    }
+    assert_eq!(chain.balance_of("alice"), Amount::from_coins(3 * 50 + 2 * 25));
+    let stats = stats::stats(&chain, 1);
+    assert_eq!((stats.block_reward, stats.next_halving), (Amount::from_coins(25), Some(8)));
+    assert!(chain.is_chain_valid());
+
+    // A block claiming the reward from before the halving is invalid.
+    let tip = chain.latest_block();
+    let coinbase = Transaction::coinbase("alice", reward, tip.index() + 1);
+    let bits = chain.next_bits();
+    let (index, timestamp) = (tip.index() + 1, tip.timestamp() + 1);
+    let block = Block::mine_at(chain.miner(), index, timestamp, vec![coinbase], tip.hash().to_string(), bits).unwrap();
+    let err = chain.accept_block(block).unwrap_err();
+    assert!(err.to_string().contains("block reward plus fees"), "{}", err);
+}
+
+#[test]
+fn blocks_account_for_their_subsidy_fees_and_transfers() {
+    let params = ChainParams {
+        initial_difficulty: 1,
+        ..ChainParams::default()
+    };
+    let reward = params.block_reward;
+    let mut chain = Blockchain::with_params(params).unwrap();
+    let mut mempool = Mempool::new();
+    chain.mine_pending(&mut mempool, 10, "alice").unwrap();
+    let pay = Transaction::new("alice", "bob", Amount::from_coins(5)).with_payment("carol", Amount::from_coins(2));
+    chain.submit_transaction(&mut mempool, pay.with_fee(Amount::from_coins(1))).unwrap();
+    let tx = Transaction::new("alice", "bob", Amount::from_coins(3)).with_fee(Amount::from_coins(2)).with_sequence(1);
+    chain.submit_transaction(&mut mempool, tx).unwrap();
+    chain.mine_pending(&mut mempool, 10, "miner").unwrap();
+
+    let block = chain.latest_block();
+    let totals = *block.totals();
+    assert_eq!((totals.fees, totals.transferred), (Amount::from_coins(3), Amount::from_coins(10)));
+    assert_eq!((totals.subsidy(), totals.coinbase), (reward, reward.checked_add(Amount::from_coins(3)).unwrap()));
+    assert_eq!(totals.size, block.size());
+    // Totals are worked out again from the transactions, and only stored
+    // once those are gone.
+    let json = serde_json::to_string(block).unwrap();
+    assert!(!json.contains("totals"));
+    assert_eq!(*serde_json::from_str::<Block>(&json).unwrap().totals(), totals);
+    let mut pruned = block.clone();
+    pruned.prune();
+    let pruned: Block = serde_json::from_str(&serde_json::to_string(&pruned).unwrap()).unwrap();
+    assert_eq!((pruned.transactions().len(), *pruned.totals()), (0, totals));
+}
+
+#[test]
+fn block_sizes_count_the_serialized_bytes() {
+    let header = BlockHeader::new(1, 1000, "ab".repeat(32), "cd".repeat(32), 0);
+    let coinbase = Transaction::coinbase("miner", Amount::from_coins(50), 1);
+    let block = Block::from_parts(header, "ef".repeat(32), vec![coinbase]);
+    let json = format!(
+        concat!(
+            r#"{{"index":1,"timestamp":1000,"transactions":[{{"sender":"COINBASE","receiver":"miner","amount":"50","#,
+            r#""height":1}}],"merkle_root":"{}","previous_hash":"{}","hash":"{}","nonce":0,"bits":0}}"#,
+        ),
+        "ab".repeat(32),
+        "cd".repeat(32),
+        "ef".repeat(32),
+    );
+    assert_eq!(block.totals().size, json.len());
+    assert_eq!(block.totals().size, block.size());
+}
@@ -0,0 +1,98 @@
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+
+use mini_block::stratum::{StratumMessage, StratumServer, StratumWorker};
+use mini_block::{Amount, Blockchain, CancelToken, ChainParams, ConsensusKind, Mempool, Miner, Transaction};
+
+mod common;
+
+#[test]
+fn templates_become_blocks_once_their_nonce_is_found() {
+    let mut chain = common::chain(1, &[]);
+    let mut mempool = Mempool::new();
+    chain.mine_pending(&mut mempool, 10, "alice").unwrap();
+    let payment = Transaction::new("alice", "bob", Amount::from_coins(5)).with_fee(Amount::from_coins(1));
+    chain.submit_transaction(&mut mempool, payment).unwrap();
+
+    let template = chain.block_template(&mempool, 10, "pool").unwrap();
+    assert_eq!(template.header().previous_hash(), chain.latest_block().hash());
+    assert_eq!(template.transactions().len(), 2);
+    let (header, _) = Miner::new(1).mine(template.header().clone()).unwrap();
+    chain.accept_block(template.complete(header.nonce())).unwrap();
+    assert_eq!((chain.height(), chain.balance_of("bob")), (2, Amount::from_coins(5)));
+    assert_eq!(chain.balance_of("pool"), chain.params().block_reward.checked_add(Amount::from_coins(1)).unwrap());
+
+    let staked = Blockchain::with_params(ChainParams {
+        consensus: ConsensusKind::ProofOfStake { min_stake: Amount::from_coins(1) },
+        ..ChainParams::default()
+    })
+    .unwrap();
+    assert!(staked.block_template(&Mempool::new(), 10, "pool").is_err());
+}
+
+#[test]
+fn workers_mine_blocks_for_the_server() {
+    let chain = Arc::new(RwLock::new(common::chain(1, &[])));
+    let mempool = Arc::new(Mutex::new(Mempool::new()));
+    let server = StratumServer::new(Arc::clone(&chain), mempool, "pool").with_share_difficulty(0);
+    let addr = server.listen("127.0.0.1:0").unwrap();
+
+    let cancel = CancelToken::new();
+    let worker = StratumWorker::new("laptop", Miner::new(2).with_cancel_token(cancel.clone()));
+    let mining = thread::spawn(move || worker.run(addr));
+    let found = || server.workers().first().map_or(0, |worker| worker.blocks);
+    assert!(common::wait_until(|| found() >= 3));
+    let counted = server.workers();
+    assert_eq!(counted[0].name, "laptop");
+    assert!(counted[0].shares >= counted[0].blocks);
+    cancel.cancel();
+    // Replies still in flight when the worker stops go uncounted on its side.
+    let stats = mining.join().unwrap().unwrap();
+    assert!(stats.blocks > 0 && stats.shares >= stats.blocks, "{:?}", stats);
+
+    let chain = chain.read().unwrap();
+    assert!(chain.height() >= 3 && chain.is_chain_valid());
+    assert_eq!(chain.balance_of("pool"), chain.params().block_reward.checked_mul(chain.height()).unwrap());
+}
+
+#[test]
+fn servers_reject_stale_and_invalid_shares() {
+    let chain = Arc::new(RwLock::new(common::chain(2, &[])));
+    let server = StratumServer::new(Arc::clone(&chain), Arc::new(Mutex::new(Mempool::new())), "pool")
+        .with_share_difficulty(60);
+    let addr = server.listen("127.0.0.1:0").unwrap();
+    let mut stream = TcpStream::connect(addr).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut exchange = |message: &StratumMessage| {
+        let mut line = serde_json::to_string(message).unwrap();
+        line.push('\n');
+        stream.write_all(line.as_bytes()).unwrap();
+        let mut reply = String::new();
+        reader.read_line(&mut reply).unwrap();
+        serde_json::from_str::<StratumMessage>(&reply).unwrap()
+    };
+    let StratumMessage::Job(job) = exchange(&StratumMessage::Subscribe { worker: "cheat".to_string() }) else {
+        panic!("expected a job");
+    };
+    assert_eq!(job.header.previous_hash(), chain.read().unwrap().latest_block().hash());
+
+    let rejected = |message| match message {
+        StratumMessage::Rejected { reason, .. } => reason,
+        other => panic!("expected a rejection, got {:?}", other),
+    };
+    let stale = StratumMessage::Submit { job: job.id + 1, nonce: 0 };
+    assert_eq!(rejected(exchange(&stale)), "stale job");
+    // Nothing meets a share target this hard, so only block hashes count.
+    let hash_with = |nonce| {
+        let mut header = job.header.clone();
+        header.set_nonce(nonce);
+        header.compute_hash()
+    };
+    let weak = (0..).find(|&nonce| !hash_with(nonce).starts_with("00")).unwrap();
+    let invalid = StratumMessage::Submit { job: job.id, nonce: weak };
+    assert!(rejected(exchange(&invalid)).contains("target"));
+    assert_eq!(server.workers()[0].shares, 0);
+    assert_eq!(chain.read().unwrap().height(), 0);
+}
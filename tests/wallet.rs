@@ -0,0 +1,56 @@
+use mini_block::address::{self, HASH_LEN};
+use mini_block::params::DEFAULT_ADDRESS_VERSION;
+use mini_block::validation::TxCheck;
+use mini_block::{Amount, Blockchain, ChainParams, Mempool, Transaction, UnlockedWallet, Wallet};
+
+#[test]
+fn wallet_files_keep_keys_encrypted_under_the_password() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("wallet.json");
+    let mut wallet = UnlockedWallet::create("correct horse", DEFAULT_ADDRESS_VERSION).unwrap();
+    let addresses = [wallet.generate_key(), wallet.generate_key()];
+    wallet.save(&path).unwrap();
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    for address in &addresses {
+        let secret = hex::encode(wallet.signing_key(address).unwrap().to_bytes());
+        assert!(!contents.contains(&secret));
+    }
+    // Addresses can be listed without the password, but keys need it.
+    let locked = Wallet::load(&path).unwrap();
+    assert_eq!(locked.addresses(), addresses);
+    assert!(locked.unlock("wrong horse").is_err());
+    let unlocked = locked.unlock("correct horse").unwrap();
+    for address in &addresses {
+        let key = unlocked.signing_key(address).unwrap();
+        assert_eq!(key.to_bytes(), wallet.signing_key(address).unwrap().to_bytes());
+    }
+}
+
+#[test]
+fn addresses_with_a_mistyped_character_are_rejected() {
+    let good = address::encode(DEFAULT_ADDRESS_VERSION, &[7u8; HASH_LEN]);
+    assert!(address::validate(&good, DEFAULT_ADDRESS_VERSION).is_ok());
+    let mut chars: Vec<char> = good.chars().collect();
+    chars[10] = if chars[10] == '2' { '3' } else { '2' };
+    let typo: String = chars.into_iter().collect();
+
+    let err = address::decode(&typo).unwrap_err();
+    assert!(err.to_string().contains("bad checksum"), "{}", err);
+    assert!(address::validate(&typo, DEFAULT_ADDRESS_VERSION).is_err());
+    let mut wallet = UnlockedWallet::create("password", DEFAULT_ADDRESS_VERSION).unwrap();
+    assert!(wallet.watch(&typo).is_err());
+
+    // Nor can coins be sent to it.
+    let mut params = ChainParams {
+        initial_difficulty: 1,
+        ..ChainParams::default()
+    };
+    params.genesis_allocations.insert("alice".to_string(), Amount::from_coins(100));
+    let chain = Blockchain::with_params(params).unwrap();
+    let mempool = Mempool::new();
+    let pay = Transaction::new("alice", typo, Amount::from_coins(1));
+    assert_eq!(chain.validate_transaction(&mempool, &pay).unwrap_err().check, TxCheck::Address);
+    let pay = Transaction::new("alice", good, Amount::from_coins(1));
+    assert!(chain.validate_transaction(&mempool, &pay).is_ok());
+}